@@ -27,7 +27,8 @@ fn length_check(len: usize, error_empty: Error, error_too_long: Error) -> Result
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+// Ordered by the normalized string first, the offsets only follow from it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct InnerJid {
     pub(crate) normalized: String,
     pub(crate) at: Option<NonZeroU16>,
@@ -29,8 +29,15 @@
 //!   `@example.com` or `user@example.com/`
 //! - stringprep error: some characters were invalid according to the stringprep algorithm, such as
 //!   mixing left-to-write and right-to-left characters
+//!
+//! All three types implement `Eq`, `Hash` and `Ord` on their normalized form, so they can be used
+//! as keys in a `HashMap` or a `BTreeSet`, and sort like their string representation. A bare JID
+//! never equals a full JID, even one with the same node and domain: key collections of accounts,
+//! such as a roster, on [`BareJid`] (using [`Jid::to_bare`] if needed), and collections of
+//! resources, such as received presences, on [`FullJid`] or [`Jid`].
 
 use core::num::NonZeroU16;
+use std::cmp::Ordering;
 use std::convert::TryFrom;
 use std::fmt;
 use std::str::FromStr;
@@ -88,6 +95,21 @@ impl fmt::Display for Jid {
     }
 }
 
+impl PartialOrd for Jid {
+    fn partial_cmp(&self, other: &Jid) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Jid {
+    fn cmp(&self, other: &Jid) -> Ordering {
+        // Sort bare and full JIDs together, by their string representation.
+        let (Jid::Bare(BareJid { inner }) | Jid::Full(FullJid { inner })) = self;
+        let (Jid::Bare(BareJid { inner: other }) | Jid::Full(FullJid { inner: other })) = other;
+        inner.cmp(other)
+    }
+}
+
 impl Jid {
     /// Constructs a Jabber ID from a string. This is of the form
     /// `node`@`domain`/`resource`, where node and resource parts are optional.
@@ -277,7 +299,7 @@ impl PartialEq<BareJid> for Jid {
 /// Unlike a [`BareJid`], it always contains a resource, and should only be used when you are
 /// certain there is no case where a resource can be missing.  Otherwise, use a [`Jid`] or
 /// [`BareJid`].
-#[derive(Clone, PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FullJid {
     inner: InnerJid,
 }
@@ -290,7 +312,7 @@ pub struct FullJid {
 ///
 /// Unlike a [`FullJid`], it can’t contain a resource, and should only be used when you are certain
 /// there is no case where a resource can be set.  Otherwise, use a [`Jid`] or [`FullJid`].
-#[derive(Clone, PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BareJid {
     inner: InnerJid,
 }
@@ -670,7 +692,7 @@ impl From<BareJid> for Node {
 mod tests {
     use super::*;
 
-    use std::collections::HashMap;
+    use std::collections::{BTreeSet, HashMap};
 
     macro_rules! assert_size (
         ($t:ty, $sz:expr) => (
@@ -812,6 +834,28 @@ mod tests {
         let _map: HashMap<Jid, String> = HashMap::new();
     }
 
+    #[test]
+    fn ordering() {
+        let set: BTreeSet<Jid> = ["b@c", "a@c/d", "a@c", "B@c/e"]
+            .iter()
+            .map(|jid| Jid::new(jid).unwrap())
+            .collect();
+        let sorted: Vec<String> = set.iter().map(Jid::to_string).collect();
+        assert_eq!(sorted, ["a@c", "a@c/d", "b@c", "b@c/e"]);
+
+        // Same node and domain, but a bare JID is a different key.
+        let bare = Jid::new("a@c").unwrap();
+        let full = Jid::new("a@c/d").unwrap();
+        assert_ne!(bare, full);
+        assert_eq!(bare, Jid::Bare(full.to_bare()));
+
+        let set: BTreeSet<BareJid> = ["b@c", "a@c", "a@C"]
+            .iter()
+            .map(|jid| BareJid::new(jid).unwrap())
+            .collect();
+        assert_eq!(set.len(), 2);
+    }
+
     #[test]
     fn invalid_jids() {
         assert_eq!(BareJid::from_str(""), Err(Error::DomainEmpty));
@@ -0,0 +1,69 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Measures the heap used by a roster’s worth of cached presences, as a
+//! server component would keep them.
+//!
+//! Run with `cargo run --release --example footprint`.
+
+use minidom::Element;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const CONTACTS: usize = 1000;
+const RESOURCES: usize = 3;
+
+fn presence(contact: usize, resource: usize) -> String {
+    format!(
+        "<presence xmlns='jabber:client' from='contact{}@example.org/res{}' to='user@example.org/laptop' id='pres-{}-{}' xml:lang='en'>\
+            <show>away</show>\
+            <status>Out for lunch</status>\
+            <priority>5</priority>\
+            <c xmlns='http://jabber.org/protocol/caps' hash='sha-1' node='https://example.org/client' ver='QgayPKawpkPSDYmwT/WM94uAlu0='/>\
+            <x xmlns='vcard-temp:x:update'><photo>01b87fcd030b72895ff8e88db57ec525450f000d</photo></x>\
+            <delay xmlns='urn:xmpp:delay' from='example.org' stamp='2002-09-10T23:41:07Z'/>\
+        </presence>",
+        contact, resource, contact, resource
+    )
+}
+
+fn main() {
+    let sources: Vec<String> = (0..CONTACTS)
+        .flat_map(|contact| (0..RESOURCES).map(move |resource| presence(contact, resource)))
+        .collect();
+
+    let before = LIVE.load(Ordering::Relaxed);
+    let cache: Vec<Element> = sources
+        .iter()
+        .map(|source| source.parse().unwrap())
+        .collect();
+    let after = LIVE.load(Ordering::Relaxed);
+
+    let bytes = after - before;
+    println!(
+        "{} cached presences: {} bytes on the heap, {} bytes per presence",
+        cache.len(),
+        bytes,
+        bytes / cache.len()
+    );
+}
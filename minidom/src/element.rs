@@ -14,21 +14,27 @@
 
 use crate::convert::IntoAttributeValue;
 use crate::error::{Error, Result};
+use crate::interned;
 use crate::namespaces::NSChoice;
 use crate::node::Node;
 use crate::prefixes::{Namespace, Prefix, Prefixes};
 use crate::tree_builder::TreeBuilder;
 
-use std::collections::{btree_map, BTreeMap};
+use std::collections::BTreeMap;
 use std::convert::{TryFrom, TryInto};
 use std::io::{BufRead, Write};
+use std::iter::FromIterator;
 use std::sync::Arc;
 
 use std::borrow::Cow;
 use std::str;
 
 use rxml::writer::{Encoder, Item, TrackNamespace};
+#[cfg(feature = "async")]
+use rxml::{AsyncDriver, AsyncEventReadExt};
 use rxml::{EventRead, Lexer, PullDriver, RawParser, XmlVersion};
+#[cfg(feature = "async")]
+use tokio::io::AsyncBufRead;
 
 use std::str::FromStr;
 
@@ -115,14 +121,23 @@ pub fn escape(raw: &[u8]) -> Cow<[u8]> {
     }
 }
 
+/// Whether `c` matches the Char production of XML 1.0.
+fn is_xml_char(c: char) -> bool {
+    match c {
+        '\t' | '\n' | '\r' => true,
+        '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => false,
+        _ => true,
+    }
+}
+
 #[derive(Clone, Eq, Debug)]
 /// A struct representing a DOM Element.
 pub struct Element {
     name: String,
-    namespace: String,
+    namespace: Cow<'static, str>,
     /// Namespace declarations
     pub prefixes: Prefixes,
-    attributes: BTreeMap<String, String>,
+    attributes: Attributes,
     children: Vec<Node>,
 }
 
@@ -163,12 +178,12 @@ impl Element {
         name: String,
         namespace: String,
         prefixes: P,
-        attributes: BTreeMap<String, String>,
+        attributes: Attributes,
         children: Vec<Node>,
     ) -> Element {
         Element {
             name,
-            namespace,
+            namespace: interned::namespace(namespace),
             prefixes: prefixes.into(),
             attributes,
             children,
@@ -199,7 +214,7 @@ impl Element {
                 name.as_ref().to_string(),
                 namespace.into(),
                 None,
-                BTreeMap::new(),
+                Attributes::default(),
                 Vec::new(),
             ),
         }
@@ -224,11 +239,18 @@ impl Element {
             name.into(),
             namespace.into(),
             None,
-            BTreeMap::new(),
+            Attributes::default(),
             Vec::new(),
         )
     }
 
+    /// Frees the spare capacity of the attributes and children, once the
+    /// element has been fully parsed.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.attributes.shrink_to_fit();
+        self.children.shrink_to_fit();
+    }
+
     /// Returns a reference to the local name of this element (that is, without a possible prefix).
     pub fn name(&self) -> &str {
         &self.name
@@ -236,15 +258,81 @@ impl Element {
 
     /// Returns a reference to the namespace of this element.
     pub fn ns(&self) -> String {
-        self.namespace.clone()
+        self.namespace.clone().into_owned()
+    }
+
+    /// Returns the namespace declarations made directly on this element, as a map from prefix
+    /// (`None` for the default namespace) to namespace URI.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let elem: Element = "<root xmlns='ns1' xmlns:foo='ns2'/>".parse().unwrap();
+    ///
+    /// let namespaces = elem.declared_namespaces();
+    /// assert_eq!(namespaces.len(), 2);
+    /// assert_eq!(namespaces[&None], "ns1");
+    /// assert_eq!(namespaces[&Some(String::from("foo"))], "ns2");
+    /// ```
+    pub fn declared_namespaces(&self) -> &BTreeMap<Prefix, Namespace> {
+        self.prefixes.declared_prefixes()
+    }
+
+    /// Returns the namespace the given prefix (`None` for the default namespace) is bound to by
+    /// a declaration on this very element, if any.
+    ///
+    /// This only looks at this element, not at its ancestors: elements don’t keep a reference to
+    /// their parent, so a prefix declared higher up in the tree gives `None` here.  To resolve a
+    /// prefix in scope, call this method on each ancestor in turn, starting from the innermost
+    /// one.  The reserved `xml` prefix is always bound.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let elem: Element = "<root xmlns='ns1' xmlns:foo='ns2'><child xmlns='ns1'/></root>".parse().unwrap();
+    ///
+    /// assert_eq!(elem.namespace_for_prefix(None), Some("ns1"));
+    /// assert_eq!(elem.namespace_for_prefix(Some("foo")), Some("ns2"));
+    /// assert_eq!(elem.namespace_for_prefix(Some("xml")), Some("http://www.w3.org/XML/1998/namespace"));
+    /// assert_eq!(elem.namespace_for_prefix(Some("bar")), None);
+    ///
+    /// // Only declared on the root.
+    /// let child = elem.get_child("child", "ns1").unwrap();
+    /// assert_eq!(child.namespace_for_prefix(Some("foo")), None);
+    /// ```
+    pub fn namespace_for_prefix(&self, prefix: Option<&str>) -> Option<&str> {
+        if prefix == Some("xml") {
+            return Some("http://www.w3.org/XML/1998/namespace");
+        }
+        self.prefixes
+            .get(&prefix.map(String::from))
+            .map(String::as_str)
     }
 
     /// Returns a reference to the value of the given attribute, if it exists, else `None`.
     pub fn attr(&self, name: &str) -> Option<&str> {
-        if let Some(value) = self.attributes.get(name) {
-            return Some(value);
-        }
-        None
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    /// Parses the value of the given attribute, if it exists, else returns `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let elem: Element = "<reference xmlns=\"ns1\" begin=\"72\" end=\"-1\" />".parse().unwrap();
+    ///
+    /// assert_eq!(elem.attr_parse::<u16>("begin"), Some(Ok(72)));
+    /// assert!(elem.attr_parse::<u16>("end").unwrap().is_err());
+    /// assert_eq!(elem.attr_parse::<u16>("type"), None);
+    /// ```
+    pub fn attr_parse<T: FromStr>(&self, name: &str) -> Option<std::result::Result<T, T::Err>> {
+        self.attr(name).map(str::parse)
     }
 
     /// Returns an iterator over the attributes of this element.
@@ -349,6 +437,25 @@ impl Element {
         Err(Error::EndOfDocument)
     }
 
+    /// Parse a document from an `AsyncBufRead`, e.g. a file or a socket,
+    /// without buffering more of it than the parser needs.
+    ///
+    /// Like `from_reader`, this returns as soon as the root element is
+    /// closed, leaving anything after it unread.
+    #[cfg(feature = "async")]
+    pub async fn from_async_reader<R: AsyncBufRead + Unpin>(reader: R) -> Result<Element> {
+        let mut tree_builder = TreeBuilder::new();
+        let mut driver = AsyncDriver::wrap(reader, Lexer::new(), RawParser::new());
+        while let Some(event) = driver.read().await? {
+            tree_builder.process_event(event)?;
+
+            if let Some(root) = tree_builder.root.take() {
+                return Ok(root);
+            }
+        }
+        Err(Error::EndOfDocument)
+    }
+
     /// Parse a document from a `BufRead`, allowing Prefixes to be specified. Useful to provide
     /// knowledge of namespaces that would have been declared on parent elements not present in the
     /// reader.
@@ -379,18 +486,77 @@ impl Element {
     }
 
     /// Output the document to an `ItemWriter`
+    ///
+    /// Fails with `Error::InvalidChar`, before writing anything, if a text
+    /// node or an attribute value contains a character XML 1.0 doesn’t allow.
     pub fn to_writer<W: Write>(&self, writer: &mut ItemWriter<W>) -> Result<()> {
+        self.check_chars()?;
         self.write_to_inner(writer)
     }
 
     /// Output the document to an `ItemWriter`
     pub fn to_writer_decl<W: Write>(&self, writer: &mut ItemWriter<W>) -> Result<()> {
+        self.check_chars()?;
         writer
             .write(Item::XmlDeclaration(XmlVersion::V1_0))
             .unwrap(); // TODO: error return
         self.write_to_inner(writer)
     }
 
+    /// Checks that the text nodes and attribute values of this element and of
+    /// its descendants only contain characters allowed in XML 1.0, which
+    /// excludes most of the C0 control characters.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::{Element, Error};
+    ///
+    /// let elem = Element::builder("body", "jabber:client")
+    ///     .append("Hello\u{1b}[1mworld")
+    ///     .build();
+    /// assert!(matches!(elem.check_chars(), Err(Error::InvalidChar('\u{1b}'))));
+    /// ```
+    pub fn check_chars(&self) -> Result<()> {
+        let invalid = self
+            .attrs()
+            .map(|(_, value)| value)
+            .chain(self.texts())
+            .flat_map(str::chars)
+            .find(|c| !is_xml_char(*c));
+        if let Some(c) = invalid {
+            return Err(Error::InvalidChar(c));
+        }
+        self.children().try_for_each(Element::check_chars)
+    }
+
+    /// Removes the characters not allowed in XML 1.0 from the text nodes and
+    /// attribute values of this element and of its descendants, for when
+    /// sending a mangled text is better than failing.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem = Element::builder("body", "jabber:client")
+    ///     .append("Hello\u{1b}[1mworld")
+    ///     .build();
+    /// elem.strip_invalid_chars();
+    /// assert_eq!(elem.text(), "Hello[1mworld");
+    /// ```
+    pub fn strip_invalid_chars(&mut self) {
+        for (_, value) in self.attrs_mut() {
+            value.retain(is_xml_char);
+        }
+        for node in self.nodes_mut() {
+            match node {
+                Node::Text(text) => text.retain(is_xml_char),
+                Node::Element(child) => child.strip_invalid_chars(),
+            }
+        }
+    }
+
     /// Like `write_to()` but without the `<?xml?>` prelude
     pub fn write_to_inner<W: Write>(&self, writer: &mut ItemWriter<W>) -> Result<()> {
         for (prefix, namespace) in self.prefixes.declared_prefixes() {
@@ -403,7 +569,7 @@ impl Element {
         let namespace = if self.namespace.len() == 0 {
             None
         } else {
-            Some(Arc::new(self.namespace.clone().try_into()?))
+            Some(Arc::new(self.namespace.clone().into_owned().try_into()?))
         };
         writer.write(Item::ElementHeadStart(namespace, (*self.name).try_into()?))?;
 
@@ -484,6 +650,35 @@ impl Element {
         }
     }
 
+    /// Returns an iterator over references to every child element of this element, each paired
+    /// with the `xml:lang` in scope for it.
+    ///
+    /// An `Element` doesn’t know its parent, so `inherited` is the language in scope where this
+    /// element appears, e.g. the one of the stream; the language yielded along with a child is
+    /// the one to pass when iterating over its own children. An empty `xml:lang` means that no
+    /// language is in scope.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let elem: Element = r#"<message xmlns="jabber:client" xml:lang="fr"><body>Salut</body><body xml:lang="en">Hi</body></message>"#.parse().unwrap();
+    ///
+    /// let mut iter = elem.children_with_lang(Some("de"));
+    /// let (body, lang) = iter.next().unwrap();
+    /// assert_eq!((body.text().as_str(), lang), ("Salut", Some("fr")));
+    /// let (body, lang) = iter.next().unwrap();
+    /// assert_eq!((body.text().as_str(), lang), ("Hi", Some("en")));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn children_with_lang<'a>(&'a self, inherited: Option<&'a str>) -> ChildrenWithLang<'a> {
+        ChildrenWithLang {
+            children: self.children(),
+            lang: in_scope_lang(self, inherited),
+        }
+    }
+
     /// Returns an iterator over mutable references to every child element of this element.
     #[inline]
     pub fn children_mut(&mut self) -> ChildrenMut {
@@ -604,6 +799,29 @@ impl Element {
         self.texts().fold(String::new(), |ret, new| ret + new)
     }
 
+    /// Returns the concatenation of all text nodes in the `Element`, without
+    /// leading and trailing whitespace, e.g. to parse a number out of a
+    /// pretty-printed element.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let elem: Element = "<node xmlns=\"ns1\">\n  -1\n</node>".parse().unwrap();
+    ///
+    /// assert_eq!(elem.text_trimmed(), "-1");
+    /// ```
+    pub fn text_trimmed(&self) -> String {
+        let text = self.text();
+        let trimmed = text.trim();
+        if trimmed.len() == text.len() {
+            text
+        } else {
+            trimmed.to_owned()
+        }
+    }
+
     /// Returns a reference to the first child element with the specific name and namespace, if it
     /// exists in the direct descendants of this `Element`, else returns `None`.
     ///
@@ -709,6 +927,27 @@ impl Element {
         self.children.remove(idx).into_element()
     }
 
+    /// Keeps only the child elements for which `f` returns `true`, in their original order;
+    /// text nodes are left untouched.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem: Element = r#"<node xmlns="ns">a<a /><b xmlns="other_ns" />b<c /></node>"#.parse().unwrap();
+    /// elem.retain_children(|child| !child.has_ns("other_ns"));
+    /// assert_eq!(elem.children().count(), 2);
+    /// assert!(!elem.has_child("b", "other_ns"));
+    /// assert_eq!(elem.text(), "ab");
+    /// ```
+    pub fn retain_children<F: FnMut(&Element) -> bool>(&mut self, mut f: F) {
+        self.children.retain(|node| match node {
+            Node::Element(elem) => f(elem),
+            Node::Text(_) => true,
+        });
+    }
+
     /// Remove the leading nodes up to the first child element and
     /// return it
     pub fn unshift_child(&mut self) -> Option<Element> {
@@ -740,6 +979,31 @@ impl<'a> Iterator for Children<'a> {
     }
 }
 
+/// The `xml:lang` in scope for `elem`, given the one in scope where it appears.
+fn in_scope_lang<'a>(elem: &'a Element, inherited: Option<&'a str>) -> Option<&'a str> {
+    match elem.attr("xml:lang") {
+        Some("") => None,
+        Some(lang) => Some(lang),
+        None => inherited,
+    }
+}
+
+/// An iterator over references to child elements of an `Element`, paired with the `xml:lang` in
+/// scope for each of them.
+pub struct ChildrenWithLang<'a> {
+    children: Children<'a>,
+    lang: Option<&'a str>,
+}
+
+impl<'a> Iterator for ChildrenWithLang<'a> {
+    type Item = (&'a Element, Option<&'a str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let child = self.children.next()?;
+        Some((child, in_scope_lang(child, self.lang)))
+    }
+}
+
 /// An iterator over mutable references to child elements of an `Element`.
 pub struct ChildrenMut<'a> {
     iter: slice::IterMut<'a, Node>,
@@ -800,9 +1064,65 @@ pub type Nodes<'a> = slice::Iter<'a, Node>;
 /// An iterator over mutable references to all child nodes of an `Element`.
 pub type NodesMut<'a> = slice::IterMut<'a, Node>;
 
+/// The attributes of an `Element`, sorted by name.
+///
+/// A sorted `Vec` needs a single allocation for the handful of attributes an
+/// element usually carries, where a `BTreeMap` would allocate a node sized for
+/// eleven of them, and the most common names are borrowed from a static table.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Attributes(Vec<(Cow<'static, str>, String)>);
+
+impl Attributes {
+    fn position(&self, name: &str) -> std::result::Result<usize, usize> {
+        self.0.binary_search_by(|(key, _)| (**key).cmp(name))
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&String> {
+        let index = self.position(name).ok()?;
+        Some(&self.0[index].1)
+    }
+
+    pub(crate) fn get_mut(&mut self, name: &str) -> Option<&mut String> {
+        let index = self.position(name).ok()?;
+        Some(&mut self.0[index].1)
+    }
+
+    /// Sets the value of an attribute, replacing the previous one if any.
+    pub(crate) fn insert<S: AsRef<str> + Into<String>>(&mut self, name: S, value: String) {
+        match self.position(name.as_ref()) {
+            Ok(index) => self.0[index].1 = value,
+            Err(index) => self
+                .0
+                .insert(index, (interned::attribute_name(name), value)),
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.0.shrink_to_fit();
+    }
+
+    fn iter(&self) -> slice::Iter<'_, (Cow<'static, str>, String)> {
+        self.0.iter()
+    }
+
+    fn iter_mut(&mut self) -> slice::IterMut<'_, (Cow<'static, str>, String)> {
+        self.0.iter_mut()
+    }
+}
+
+impl FromIterator<(String, String)> for Attributes {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        let mut attributes = Attributes::default();
+        for (name, value) in iter {
+            attributes.insert(name, value);
+        }
+        attributes
+    }
+}
+
 /// An iterator over the attributes of an `Element`.
 pub struct Attrs<'a> {
-    iter: btree_map::Iter<'a, String, String>,
+    iter: slice::Iter<'a, (Cow<'static, str>, String)>,
 }
 
 impl<'a> Iterator for Attrs<'a> {
@@ -815,14 +1135,16 @@ impl<'a> Iterator for Attrs<'a> {
 
 /// An iterator over the attributes of an `Element`, with the values mutable.
 pub struct AttrsMut<'a> {
-    iter: btree_map::IterMut<'a, String, String>,
+    iter: slice::IterMut<'a, (Cow<'static, str>, String)>,
 }
 
 impl<'a> Iterator for AttrsMut<'a> {
     type Item = (&'a str, &'a mut String);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|(x, y)| (x.as_ref(), y))
+        self.iter
+            .next()
+            .map(|&mut (ref x, ref mut y)| (x.as_ref(), y))
     }
 }
 
@@ -872,6 +1194,24 @@ impl ElementBuilder {
         self
     }
 
+    /// Appends `node` into the tree only if `cond` is true.
+    pub fn append_if<T: Into<Node>>(self, cond: bool, node: T) -> ElementBuilder {
+        if cond {
+            self.append(node)
+        } else {
+            self
+        }
+    }
+
+    /// Appends the node in `node` into the tree, if any; `append_opt(None)`
+    /// appends nothing.
+    pub fn append_opt<T: Into<Node>>(self, node: Option<T>) -> ElementBuilder {
+        match node {
+            Some(node) => self.append(node),
+            None => self,
+        }
+    }
+
     /// Builds the `Element`.
     pub fn build(self) -> Element {
         self.root
@@ -884,13 +1224,11 @@ mod tests {
 
     #[test]
     fn test_element_new() {
-        use std::iter::FromIterator;
-
         let elem = Element::new(
             "name".to_owned(),
             "namespace".to_owned(),
             (None, "namespace".to_owned()),
-            BTreeMap::from_iter(vec![("name".to_string(), "value".to_string())].into_iter()),
+            Attributes::from_iter(vec![("name".to_string(), "value".to_string())].into_iter()),
             Vec::new(),
         );
 
@@ -900,6 +1238,18 @@ mod tests {
         assert_eq!(elem.attr("inexistent"), None);
     }
 
+    #[test]
+    fn test_builder_conditional_appends() {
+        let elem = Element::builder("foo", "ns1")
+            .append_if(true, Element::bare("bar", "ns1"))
+            .append_if(false, Element::bare("baz", "ns1"))
+            .append_opt(Some(Element::bare("qux", "ns1")))
+            .append_opt(None::<Element>)
+            .build();
+        let names: Vec<_> = elem.children().map(|child| child.name()).collect();
+        assert_eq!(names, ["bar", "qux"]);
+    }
+
     #[test]
     fn test_from_reader_simple() {
         let xml = b"<foo xmlns='ns1'></foo>";
@@ -946,6 +1296,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_namespace_for_prefix() {
+        let xml = b"<foo xmlns='ns1' xmlns:a='ns2'><bar xmlns:b='ns3'/></foo>";
+        let elem = Element::from_reader(&xml[..]).unwrap();
+        assert_eq!(elem.declared_namespaces().len(), 2);
+        assert_eq!(elem.namespace_for_prefix(Some("a")), Some("ns2"));
+
+        // Only the declarations made on the child itself are kept there.
+        let child = elem.get_child("bar", "ns1").unwrap();
+        assert_eq!(child.declared_namespaces().len(), 1);
+        assert_eq!(child.namespace_for_prefix(Some("b")), Some("ns3"));
+        assert_eq!(child.namespace_for_prefix(Some("a")), None);
+        assert_eq!(child.namespace_for_prefix(None), None);
+    }
+
     #[test]
     fn parses_spectest_xml() {
         // From: https://gitlab.com/lumi/minidom-rs/issues/8
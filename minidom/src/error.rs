@@ -32,16 +32,21 @@ pub enum Error {
 
     /// An error which is returned when a prefixed is defined twice
     DuplicatePrefix,
+
+    /// An error which is returned when serializing a text or an attribute
+    /// value containing a character XML 1.0 doesn’t allow
+    InvalidChar(char),
 }
 
 impl StdError for Error {
-    fn cause(&self) -> Option<&dyn StdError> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match self {
             Error::XmlError(e) => Some(e),
             Error::EndOfDocument => None,
             Error::InvalidPrefix => None,
             Error::MissingNamespace => None,
             Error::DuplicatePrefix => None,
+            Error::InvalidChar(_) => None,
         }
     }
 }
@@ -56,6 +61,7 @@ impl std::fmt::Display for Error {
             Error::InvalidPrefix => write!(fmt, "the prefix is invalid"),
             Error::MissingNamespace => write!(fmt, "the XML element is missing a namespace",),
             Error::DuplicatePrefix => write!(fmt, "the prefix is already defined"),
+            Error::InvalidChar(c) => write!(fmt, "the character {:?} is not allowed in XML", c),
         }
     }
 }
@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Static tables of the strings found on most XMPP elements, so that those
+//! don’t need an allocation of their own in every element.
+
+use std::borrow::Cow;
+
+/// Common attribute names, sorted.
+const ATTRIBUTE_NAMES: &[&str] = &[
+    "affiliation",
+    "by",
+    "category",
+    "code",
+    "from",
+    "hash",
+    "id",
+    "jid",
+    "name",
+    "node",
+    "role",
+    "stamp",
+    "subscription",
+    "to",
+    "type",
+    "var",
+    "ver",
+    "xml:lang",
+];
+
+/// Common namespaces, sorted.
+const NAMESPACES: &[&str] = &[
+    "http://etherx.jabber.org/streams",
+    "http://jabber.org/protocol/caps",
+    "http://jabber.org/protocol/chatstates",
+    "http://jabber.org/protocol/disco#info",
+    "http://jabber.org/protocol/disco#items",
+    "http://jabber.org/protocol/muc",
+    "http://jabber.org/protocol/muc#user",
+    "http://jabber.org/protocol/pubsub",
+    "http://jabber.org/protocol/pubsub#event",
+    "jabber:client",
+    "jabber:component:accept",
+    "jabber:iq:roster",
+    "jabber:server",
+    "jabber:x:data",
+    "urn:ietf:params:xml:ns:xmpp-stanzas",
+    "urn:xmpp:carbons:2",
+    "urn:xmpp:delay",
+    "urn:xmpp:forward:0",
+    "urn:xmpp:hints",
+    "urn:xmpp:idle:1",
+    "urn:xmpp:mam:2",
+    "urn:xmpp:receipts",
+    "urn:xmpp:sid:0",
+    "vcard-temp:x:update",
+];
+
+fn lookup(table: &'static [&'static str], s: &str) -> Option<&'static str> {
+    table.binary_search(&s).ok().map(|index| table[index])
+}
+
+/// Returns a borrowed copy of `name` if it is a common attribute name.
+pub(crate) fn attribute_name<S: AsRef<str> + Into<String>>(name: S) -> Cow<'static, str> {
+    match lookup(ATTRIBUTE_NAMES, name.as_ref()) {
+        Some(interned) => Cow::Borrowed(interned),
+        None => Cow::Owned(name.into()),
+    }
+}
+
+/// Returns a borrowed copy of `namespace` if it is a common namespace.
+pub(crate) fn namespace<S: AsRef<str> + Into<String>>(namespace: S) -> Cow<'static, str> {
+    match lookup(NAMESPACES, namespace.as_ref()) {
+        Some(interned) => Cow::Borrowed(interned),
+        None => Cow::Owned(namespace.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sorted() {
+        assert!(ATTRIBUTE_NAMES.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(NAMESPACES.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_interned() {
+        assert!(matches!(attribute_name("id"), Cow::Borrowed("id")));
+        assert!(matches!(
+            attribute_name(String::from("xml:lang")),
+            Cow::Borrowed("xml:lang")
+        ));
+        assert!(matches!(attribute_name("foo"), Cow::Owned(_)));
+        assert!(matches!(
+            namespace("jabber:client"),
+            Cow::Borrowed("jabber:client")
+        ));
+        assert!(matches!(namespace("jabber:clients"), Cow::Owned(_)));
+    }
+}
@@ -78,6 +78,7 @@
 pub mod convert;
 pub mod element;
 pub mod error;
+mod interned;
 mod namespaces;
 pub mod node;
 mod prefixes;
@@ -87,7 +88,7 @@ pub mod tree_builder;
 mod tests;
 
 pub use convert::IntoAttributeValue;
-pub use element::{Children, ChildrenMut, Element, ElementBuilder};
+pub use element::{Children, ChildrenMut, ChildrenWithLang, Element, ElementBuilder};
 pub use error::{Error, Result};
 pub use namespaces::NSChoice;
 pub use node::Node;
@@ -40,6 +40,23 @@ fn reader_works() {
     );
 }
 
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_reader_works() {
+    // A tiny buffer, so that the document is read in many chunks.
+    let reader = tokio::io::BufReader::with_capacity(4, TEST_STRING);
+    assert_eq!(
+        Element::from_async_reader(reader).await.unwrap(),
+        build_test_tree()
+    );
+
+    let truncated = &TEST_STRING[..TEST_STRING.len() - 4];
+    assert!(matches!(
+        Element::from_async_reader(truncated).await,
+        Err(Error::XmlError(_))
+    ));
+}
+
 #[test]
 fn reader_deduplicate_prefixes() {
     // The reader shouldn't complain that "child" doesn't have a namespace. It should reuse the
@@ -316,6 +333,72 @@ fn get_child_works() {
     );
 }
 
+#[test]
+fn remove_child_works() {
+    let mut root = build_test_tree();
+    assert_eq!(root.remove_child("child", "inexistent_ns"), None);
+    let child = root.remove_child("child", "child_ns").unwrap();
+    assert_eq!(child.attr("d"), Some("e"));
+    assert!(!root.has_child("child", "child_ns"));
+    assert!(root.has_child("child", "root_ns"));
+
+    let mut root = build_test_tree();
+    root.retain_children(|child| child.is("child", "child_ns"));
+    let mut iter = root.children();
+    assert!(iter.next().unwrap().is("child", "child_ns"));
+    assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn children_with_lang_works() {
+    let root: Element = r#"<message xmlns="jabber:client"><html xmlns="http://jabber.org/protocol/xhtml-im" xml:lang="fr"><body><p>Salut</p><p xml:lang="en">Hi</p><p xml:lang="">?</p></body></html><body>Hallo</body></message>"#.parse().unwrap();
+
+    let mut children = root.children_with_lang(Some("de"));
+    let (html, html_lang) = children.next().unwrap();
+    assert_eq!(html.name(), "html");
+    assert_eq!(html_lang, Some("fr"));
+    // Without its own xml:lang, the stream’s language is in scope.
+    let (body, lang) = children.next().unwrap();
+    assert_eq!((body.name(), lang), ("body", Some("de")));
+    assert_eq!(children.next(), None);
+
+    let (body, body_lang) = html.children_with_lang(html_lang).next().unwrap();
+    assert_eq!(body_lang, Some("fr"));
+    let langs: Vec<_> = body
+        .children_with_lang(body_lang)
+        .map(|(p, lang)| (p.text(), lang))
+        .collect();
+    assert_eq!(
+        langs,
+        [
+            (String::from("Salut"), Some("fr")),
+            (String::from("Hi"), Some("en")),
+            // An empty xml:lang resets the language.
+            (String::from("?"), None),
+        ]
+    );
+
+    // Nothing in scope anywhere.
+    let root: Element = r#"<message xmlns="jabber:client"><body>?</body></message>"#
+        .parse()
+        .unwrap();
+    let (_, lang) = root.children_with_lang(None).next().unwrap();
+    assert_eq!(lang, None);
+}
+
+#[test]
+fn attr_parse_works() {
+    let elem: Element = r#"<stream xmlns="ns1" port="5222" size="big" />"#.parse().unwrap();
+    assert_eq!(elem.attr_parse::<u16>("port"), Some(Ok(5222)));
+    assert!(elem.attr_parse::<u8>("port").unwrap().is_err());
+    assert!(elem.attr_parse::<u64>("size").unwrap().is_err());
+    assert_eq!(
+        elem.attr_parse::<String>("size"),
+        Some(Ok(String::from("big")))
+    );
+    assert_eq!(elem.attr_parse::<u16>("missing"), None);
+}
+
 #[test]
 fn namespace_propagation_works() {
     let mut root = Element::builder("root", "root_ns").build();
@@ -464,3 +547,30 @@ fn misserialisation() {
     let data = String::from(&elem);
     assert_eq!(xml, data);
 }
+
+#[test]
+fn invalid_chars() {
+    let mut elem = Element::builder("message", "jabber:client")
+        .attr("id", "a\u{0}b")
+        .append(
+            Element::builder("body", "jabber:client")
+                .append("one\u{b}two\u{ffff}\tthree")
+                .build(),
+        )
+        .build();
+    let mut writer = Vec::new();
+    match elem.write_to(&mut writer) {
+        Err(crate::error::Error::InvalidChar('\u{0}')) => (),
+        err => panic!("No or wrong error: {:?}", err),
+    }
+    assert!(writer.is_empty());
+
+    elem.strip_invalid_chars();
+    elem.check_chars().unwrap();
+    assert_eq!(elem.attr("id"), Some("ab"));
+    assert_eq!(
+        elem.get_child("body", "jabber:client").unwrap().text(),
+        "onetwo\tthree"
+    );
+    elem.write_to(&mut writer).unwrap();
+}
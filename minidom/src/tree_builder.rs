@@ -2,14 +2,14 @@
 
 //! SAX events to DOM tree conversion
 
+use crate::element::Attributes;
 use crate::prefixes::{Prefix, Prefixes};
 use crate::{Element, Error};
 use rxml::RawEvent;
-use std::collections::BTreeMap;
 
 /// Tree-building parser state
 pub struct TreeBuilder {
-    next_tag: Option<(Prefix, String, Prefixes, BTreeMap<String, String>)>,
+    next_tag: Option<(Prefix, String, Prefixes, Attributes)>,
     /// Parsing stack
     stack: Vec<Element>,
     /// Namespace set stack by prefix
@@ -76,7 +76,8 @@ impl TreeBuilder {
     }
 
     fn process_end_tag(&mut self) -> Result<(), Error> {
-        if let Some(el) = self.pop() {
+        if let Some(mut el) = self.pop() {
+            el.shrink_to_fit();
             if self.depth() > 0 {
                 let top = self.stack.len() - 1;
                 self.stack[top].append_child(el);
@@ -105,7 +106,7 @@ impl TreeBuilder {
                     prefix.map(|prefix| prefix.as_str().to_owned()),
                     name.as_str().to_owned(),
                     Prefixes::default(),
-                    BTreeMap::new(),
+                    Attributes::default(),
                 ))
             }
 
@@ -127,7 +128,7 @@ impl TreeBuilder {
                                 );
                             }
                             (None, name) => {
-                                attrs.insert(name.as_str().to_owned(), value.as_str().to_owned());
+                                attrs.insert(name.as_str(), value.as_str().to_owned());
                             }
                         },
                     );
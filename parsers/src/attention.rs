@@ -18,8 +18,6 @@ impl MessagePayload for Attention {}
 #[cfg(test)]
 mod tests {
     use super::*;
-    #[cfg(not(feature = "disable-validation"))]
-    use crate::util::error::Error;
     use crate::Element;
     use std::convert::TryFrom;
 
@@ -41,11 +39,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = Attention::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in attention element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in attention element"
+        );
     }
 
     #[cfg(not(feature = "disable-validation"))]
@@ -55,11 +52,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = Attention::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in attention element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in attention element"
+        );
     }
 
     #[test]
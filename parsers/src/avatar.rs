@@ -6,7 +6,10 @@
 
 use crate::hashes::Sha1HexAttribute;
 use crate::pubsub::PubSubPayload;
+use crate::util::error::Error;
 use crate::util::helpers::WhitespaceAwareBase64;
+use crate::Element;
+use std::convert::TryFrom;
 
 generate_element!(
     /// Communicates information about an avatar.
@@ -54,12 +57,59 @@ generate_element!(
 
 impl PubSubPayload for Data {}
 
+impl Data {
+    /// Parses a `<data/>` element, refusing it without decoding it when its
+    /// image would be larger than `max_bytes`, so that a contact can’t make
+    /// us decode a huge avatar.
+    ///
+    /// When the `<info/>` this data was requested for is given, the image
+    /// must also have the size announced there.
+    pub fn parse_with_limit(
+        elem: Element,
+        max_bytes: usize,
+        info: Option<&Info>,
+    ) -> Result<Data, Error> {
+        check_self!(elem, "data", AVATAR_DATA);
+        // Each four base64 characters encode three bytes, minus the padding.
+        let chars = elem
+            .texts()
+            .flat_map(str::chars)
+            .filter(|ch| *ch != ' ' && *ch != '\n' && *ch != '\t')
+            .count();
+        let min_bytes = (chars / 4 * 3).saturating_sub(2);
+        if min_bytes > max_bytes {
+            return Err(Error::ParseError(
+                "Avatar data larger than the maximum size.",
+            ));
+        }
+        if let Some(info) = info {
+            if min_bytes > usize::from(info.bytes) {
+                return Err(Error::ParseError(
+                    "Avatar data size doesn’t match its metadata.",
+                ));
+            }
+        }
+        let data = Data::try_from(elem)?;
+        if data.data.len() > max_bytes {
+            return Err(Error::ParseError(
+                "Avatar data larger than the maximum size.",
+            ));
+        }
+        if let Some(info) = info {
+            if data.data.len() != usize::from(info.bytes) {
+                return Err(Error::ParseError(
+                    "Avatar data size doesn’t match its metadata.",
+                ));
+            }
+        }
+        Ok(data)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::hashes::Algo;
-    #[cfg(not(feature = "disable-validation"))]
-    use crate::util::error::Error;
     use crate::Element;
     use std::convert::TryFrom;
 
@@ -112,6 +162,61 @@ mod tests {
         assert_eq!(data.data, b"\0\0\0");
     }
 
+    #[test]
+    fn test_size_limit() {
+        let metadata: Element = "<metadata xmlns='urn:xmpp:avatar:metadata'>
+                                     <info bytes='6'
+                                           id='111f4b3c50d7b0df729d299bc6f8e9ef9066971f'
+                                           type='image/png'/>
+                                 </metadata>"
+            .parse()
+            .unwrap();
+        let metadata = Metadata::try_from(metadata).unwrap();
+        let info = Some(&metadata.infos[0]);
+
+        let elem: Element = "<data xmlns='urn:xmpp:avatar:data'>AAAA\nAAAA</data>"
+            .parse()
+            .unwrap();
+        let data = Data::parse_with_limit(elem.clone(), 6, info).unwrap();
+        assert_eq!(data.data, [0; 6]);
+
+        let error = Data::parse_with_limit(elem, 5, None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parse error: Avatar data larger than the maximum size."
+        );
+
+        // Announced as six bytes, but only four.
+        let elem: Element = "<data xmlns='urn:xmpp:avatar:data'>AAAAAA==</data>"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            Data::parse_with_limit(elem.clone(), 6, None)
+                .unwrap()
+                .data
+                .len(),
+            4
+        );
+        let error = Data::parse_with_limit(elem, 6, info).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parse error: Avatar data size doesn’t match its metadata."
+        );
+
+        // Refused before decoding, even though it isn’t valid base64.
+        let elem: Element = format!(
+            "<data xmlns='urn:xmpp:avatar:data'>{}</data>",
+            "!".repeat(4096)
+        )
+        .parse()
+        .unwrap();
+        let error = Data::parse_with_limit(elem, 1024, None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parse error: Avatar data larger than the maximum size."
+        );
+    }
+
     #[cfg(not(feature = "disable-validation"))]
     #[test]
     fn test_invalid() {
@@ -119,10 +224,6 @@ mod tests {
             .parse()
             .unwrap();
         let error = Data::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in data element.")
+        assert_eq!(error.to_string(), "unknown attribute 'id' in data element");
     }
 }
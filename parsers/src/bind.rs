@@ -22,7 +22,7 @@ pub struct BindQuery {
     ///
     /// If this is None, we request no particular resource, and a random one
     /// will be affected by the server.
-    resource: Option<String>,
+    pub resource: Option<String>,
 }
 
 impl BindQuery {
@@ -76,7 +76,14 @@ impl From<BindQuery> for Element {
 #[derive(Debug, Clone, PartialEq)]
 pub struct BindResponse {
     /// The full JID returned by the server for this client.
-    jid: FullJid,
+    pub jid: FullJid,
+}
+
+impl BindResponse {
+    /// Creates a resource binding response, for the server to send.
+    pub fn new(jid: FullJid) -> BindResponse {
+        BindResponse { jid }
+    }
 }
 
 impl IqResultPayload for BindResponse {}
@@ -180,6 +187,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_serialise() {
+        let query = BindQuery::new(Some(String::from("balcony")));
+        let elem: Element = query.clone().into();
+        assert_eq!(
+            elem,
+            "<bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><resource>balcony</resource></bind>"
+                .parse::<Element>()
+                .unwrap()
+        );
+        assert_eq!(BindQuery::try_from(elem).unwrap(), query);
+
+        let response = BindResponse::new(FullJid::new("juliet@example.com/balcony").unwrap());
+        let elem: Element = response.clone().into();
+        assert_eq!(BindResponse::try_from(elem).unwrap(), response);
+        assert_eq!(
+            Jid::from(response),
+            Jid::new("juliet@example.com/balcony").unwrap()
+        );
+    }
+
     #[cfg(not(feature = "disable-validation"))]
     #[test]
     fn test_invalid_resource() {
@@ -187,20 +215,18 @@ mod tests {
             .parse()
             .unwrap();
         let error = BindQuery::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in resource element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'attr' in resource element"
+        );
 
         let elem: Element = "<bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><resource><hello-world/>resource</resource></bind>"
             .parse()
             .unwrap();
         let error = BindQuery::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in resource element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <hello-world/> in resource element"
+        );
     }
 }
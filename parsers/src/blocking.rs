@@ -165,39 +165,35 @@ mod tests {
             .unwrap();
         let request_elem = elem.clone();
         let error = BlocklistRequest::try_from(request_elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in blocklist element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in blocklist element"
+        );
 
         let result_elem = elem.clone();
         let error = BlocklistResult::try_from(result_elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in blocklist element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in blocklist element"
+        );
 
         let elem: Element = "<block xmlns='urn:xmpp:blocking' coucou=''/>"
             .parse()
             .unwrap();
         let error = Block::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in block element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in block element"
+        );
 
         let elem: Element = "<unblock xmlns='urn:xmpp:blocking' coucou=''/>"
             .parse()
             .unwrap();
         let error = Unblock::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in unblock element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in unblock element"
+        );
     }
 
     #[cfg(not(feature = "disable-validation"))]
@@ -205,10 +201,9 @@ mod tests {
     fn test_non_empty_blocklist_request() {
         let elem: Element = "<blocklist xmlns='urn:xmpp:blocking'><item jid='coucou@coucou'/><item jid='domain'/></blocklist>".parse().unwrap();
         let error = BlocklistRequest::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in blocklist element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <item/> in blocklist element"
+        );
     }
 }
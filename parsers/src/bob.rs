@@ -173,10 +173,6 @@ mod tests {
             .parse()
             .unwrap();
         let error = Data::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in data element.");
+        assert_eq!(error.to_string(), "unknown child <coucou/> in data element");
     }
 }
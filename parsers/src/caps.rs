@@ -37,7 +37,10 @@ pub struct Caps {
     pub hash: Hash,
 }
 
-impl PresencePayload for Caps {}
+impl PresencePayload for Caps {
+    const NAME: &'static str = "c";
+    const NS: &'static str = ns::CAPS;
+}
 
 impl TryFrom<Element> for Caps {
     type Error = Error;
@@ -251,11 +254,7 @@ mod tests {
     fn test_invalid_child() {
         let elem: Element = "<c xmlns='http://jabber.org/protocol/caps'><hash xmlns='urn:xmpp:hashes:2' algo='sha-256'>K1Njy3HZBThlo4moOD5gBGhn0U0oK7/CbfLlIUDi6o4=</hash></c>".parse().unwrap();
         let error = Caps::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in caps element.");
+        assert_eq!(error.to_string(), "unknown child <hash/> in caps element");
     }
 
     #[test]
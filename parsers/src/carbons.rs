@@ -72,8 +72,8 @@ mod tests {
         assert_size!(Enable, 0);
         assert_size!(Disable, 0);
         assert_size!(Private, 0);
-        assert_size!(Received, 152);
-        assert_size!(Sent, 152);
+        assert_size!(Received, 176);
+        assert_size!(Sent, 176);
     }
 
     #[cfg(target_pointer_width = "64")]
@@ -82,8 +82,8 @@ mod tests {
         assert_size!(Enable, 0);
         assert_size!(Disable, 0);
         assert_size!(Private, 0);
-        assert_size!(Received, 288);
-        assert_size!(Sent, 288);
+        assert_size!(Received, 336);
+        assert_size!(Sent, 336);
     }
 
     #[test]
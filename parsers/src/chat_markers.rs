@@ -0,0 +1,129 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::message::MessagePayload;
+
+generate_empty_element!(
+    /// Requests that the recipient sends chat markers for this message.
+    Markable,
+    "markable",
+    CHAT_MARKERS
+);
+
+impl MessagePayload for Markable {}
+
+generate_element!(
+    /// Notes that a previous message has been received by a client.
+    Received, "received", CHAT_MARKERS,
+    attributes: [
+        /// The 'id' attribute of the received message.
+        id: Required<String> = "id",
+    ]
+);
+
+impl MessagePayload for Received {}
+
+generate_element!(
+    /// Notes that a previous message, and every message before it in the
+    /// same chat, has been displayed to the user.
+    Displayed, "displayed", CHAT_MARKERS,
+    attributes: [
+        /// The 'id' attribute of the displayed message.
+        id: Required<String> = "id",
+    ]
+);
+
+impl MessagePayload for Displayed {}
+
+generate_element!(
+    /// Notes that a previous message, and every message before it in the
+    /// same chat, has been acted upon by the user.
+    Acknowledged, "acknowledged", CHAT_MARKERS,
+    attributes: [
+        /// The 'id' attribute of the acknowledged message.
+        id: Required<String> = "id",
+    ]
+);
+
+impl MessagePayload for Acknowledged {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ns;
+    use crate::util::error::Error;
+    use crate::Element;
+    use std::convert::TryFrom;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Markable, 0);
+        assert_size!(Received, 12);
+        assert_size!(Displayed, 12);
+        assert_size!(Acknowledged, 12);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Markable, 0);
+        assert_size!(Received, 24);
+        assert_size!(Displayed, 24);
+        assert_size!(Acknowledged, 24);
+    }
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<markable xmlns='urn:xmpp:chat-markers:0'/>"
+            .parse()
+            .unwrap();
+        Markable::try_from(elem).unwrap();
+
+        let elem: Element = "<received xmlns='urn:xmpp:chat-markers:0' id='message-1'/>"
+            .parse()
+            .unwrap();
+        let received = Received::try_from(elem).unwrap();
+        assert_eq!(received.id, "message-1");
+
+        let elem: Element = "<displayed xmlns='urn:xmpp:chat-markers:0' id='message-2'/>"
+            .parse()
+            .unwrap();
+        let displayed = Displayed::try_from(elem).unwrap();
+        assert_eq!(displayed.id, "message-2");
+
+        let elem: Element = "<acknowledged xmlns='urn:xmpp:chat-markers:0' id='message-3'/>"
+            .parse()
+            .unwrap();
+        let acknowledged = Acknowledged::try_from(elem).unwrap();
+        assert_eq!(acknowledged.id, "message-3");
+    }
+
+    #[test]
+    fn test_missing_id() {
+        let elem: Element = "<displayed xmlns='urn:xmpp:chat-markers:0'/>"
+            .parse()
+            .unwrap();
+        let error = Displayed::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Required attribute 'id' missing.");
+    }
+
+    #[test]
+    fn test_serialise() {
+        let elem: Element = Markable.into();
+        assert!(elem.is("markable", ns::CHAT_MARKERS));
+        assert_eq!(elem.attrs().count(), 0);
+
+        let displayed = Displayed {
+            id: String::from("coucou"),
+        };
+        let elem: Element = displayed.into();
+        assert!(elem.is("displayed", ns::CHAT_MARKERS));
+        assert_eq!(elem.attr("id"), Some("coucou"));
+    }
+}
@@ -70,11 +70,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = ChatState::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in chatstate element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in chatstate element"
+        );
     }
 
     #[cfg(not(feature = "disable-validation"))]
@@ -84,11 +83,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = ChatState::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in chatstate element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in chatstate element"
+        );
     }
 
     #[test]
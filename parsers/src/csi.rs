@@ -62,4 +62,15 @@ mod tests {
         let elem: Element = Active.into();
         assert!(elem.is("active", ns::CSI));
     }
+
+    #[test]
+    fn round_trip() {
+        let elem: Element = "<inactive xmlns='urn:xmpp:csi:0'/>".parse().unwrap();
+        let inactive = Inactive::try_from(elem.clone()).unwrap();
+        assert_eq!(Element::from(inactive), elem);
+
+        let elem: Element = "<active xmlns='urn:xmpp:csi:0'/>".parse().unwrap();
+        let active = Active::try_from(elem.clone()).unwrap();
+        assert_eq!(Element::from(active), elem);
+    }
 }
@@ -293,7 +293,10 @@ impl TryFrom<Element> for DataForm {
                     form.fields.push(field);
                 }
             } else {
-                return Err(Error::ParseError("Unknown child in data form element."));
+                return Err(Error::UnknownChild {
+                    element: "data form",
+                    child: child.name().to_owned(),
+                });
             }
         }
         Ok(form)
@@ -323,6 +326,86 @@ impl From<DataForm> for Element {
     }
 }
 
+/// Fills a received type="form" [data form](struct.DataForm.html), to
+/// produce the type="submit" one to send back.
+#[derive(Debug, Clone)]
+pub struct DataFormBuilder {
+    form: DataForm,
+}
+
+impl DataFormBuilder {
+    /// Start filling the given form, which must be of type="form".
+    pub fn new(form: DataForm) -> Result<DataFormBuilder, Error> {
+        if form.type_ != DataFormType::Form {
+            return Err(Error::ParseError(
+                "Only a form of type 'form' can be filled.",
+            ));
+        }
+        Ok(DataFormBuilder { form })
+    }
+
+    fn set_values(mut self, var: &str, values: Vec<String>) -> Result<DataFormBuilder, Error> {
+        let field = self
+            .form
+            .fields
+            .iter_mut()
+            .find(|field| field.var == var && field.type_ != FieldType::Fixed)
+            .ok_or(Error::ParseError("No such field in the form."))?;
+        field.values = values;
+        Ok(self)
+    }
+
+    /// Set the value of a text-single, text-private or jid-single field.
+    pub fn set_text(self, var: &str, value: &str) -> Result<DataFormBuilder, Error> {
+        self.set_values(var, vec![String::from(value)])
+    }
+
+    /// Set the value of a boolean field.
+    pub fn set_bool(self, var: &str, value: bool) -> Result<DataFormBuilder, Error> {
+        self.set_values(var, vec![String::from(if value { "1" } else { "0" })])
+    }
+
+    /// Set the values of a list-multi, text-multi or jid-multi field.
+    pub fn set_list<S: AsRef<str>>(
+        self,
+        var: &str,
+        values: &[S],
+    ) -> Result<DataFormBuilder, Error> {
+        let values = values
+            .iter()
+            .map(|value| String::from(value.as_ref()))
+            .collect();
+        self.set_values(var, values)
+    }
+
+    /// Produce the type="submit" form, containing the FORM_TYPE of the
+    /// original form and the values of all of its non-fixed fields.
+    pub fn build(self) -> DataForm {
+        let fields = self
+            .form
+            .fields
+            .into_iter()
+            .filter(|field| field.type_ != FieldType::Fixed)
+            .map(|field| Field {
+                var: field.var,
+                type_: field.type_,
+                label: None,
+                required: false,
+                options: vec![],
+                values: field.values,
+                media: vec![],
+            })
+            .collect();
+        DataForm {
+            type_: DataFormType::Submit,
+            form_type: self.form.form_type,
+            title: None,
+            instructions: None,
+            fields,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -381,11 +464,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = DataForm::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in data form element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in data form element"
+        );
     }
 
     #[test]
@@ -419,4 +501,56 @@ mod tests {
             "Element option must not have more than one value child."
         );
     }
+
+    #[test]
+    fn fill_form() {
+        let elem: Element = "<x xmlns='jabber:x:data' type='form'><title>Register</title><field var='FORM_TYPE' type='hidden'><value>jabber:iq:register</value></field><field var='note' type='fixed'><value>Fill me.</value></field><field var='username' type='text-single' label='Username'><required/></field><field var='remember' type='boolean'/><field var='langs' type='list-multi'><option><value>en</value></option><option><value>fr</value></option></field></x>".parse().unwrap();
+        let form = DataForm::try_from(elem).unwrap();
+        let submit = DataFormBuilder::new(form)
+            .unwrap()
+            .set_text("username", "juliet")
+            .unwrap()
+            .set_bool("remember", true)
+            .unwrap()
+            .set_list("langs", &["en", "fr"])
+            .unwrap()
+            .build();
+        assert_eq!(submit.type_, DataFormType::Submit);
+        assert_eq!(submit.form_type.as_deref(), Some("jabber:iq:register"));
+        assert_eq!(submit.title, None);
+        assert_eq!(submit.fields.len(), 3);
+        assert_eq!(submit.fields[0].var, "username");
+        assert_eq!(submit.fields[0].values, ["juliet"]);
+        assert_eq!(submit.fields[0].label, None);
+        assert!(!submit.fields[0].required);
+        assert_eq!(submit.fields[1].values, ["1"]);
+        assert_eq!(submit.fields[2].values, ["en", "fr"]);
+        assert!(submit.fields[2].options.is_empty());
+    }
+
+    #[test]
+    fn fill_form_unknown_field() {
+        let form = DataForm::new(
+            DataFormType::Form,
+            "jabber:iq:register",
+            vec![Field::new("username", FieldType::TextSingle)],
+        );
+        let error = DataFormBuilder::new(form)
+            .unwrap()
+            .set_text("password", "secret")
+            .unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "No such field in the form.");
+
+        let form = DataForm::new(DataFormType::Result_, "jabber:iq:register", vec![]);
+        let error = DataFormBuilder::new(form).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Only a form of type 'form' can be filled.");
+    }
 }
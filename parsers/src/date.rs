@@ -5,9 +5,11 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::util::error::Error;
-use chrono::{DateTime as ChronoDateTime, FixedOffset};
+use chrono::{DateTime as ChronoDateTime, FixedOffset, Utc};
 use minidom::{IntoAttributeValue, Node};
+use std::fmt;
 use std::str::FromStr;
+use std::time::{Duration as StdDuration, SystemTime};
 
 /// Implements the DateTime profile of XEP-0082, which represents a
 /// non-recurring moment in time, with an accuracy of seconds or fraction of
@@ -52,15 +54,187 @@ impl From<DateTime> for Node {
     }
 }
 
+/// A source for the current time, given to the helpers stamping payloads
+/// with “now”, so that tests can use fixed instants.
+///
+/// Any `Fn() -> DateTime` closure is a clock.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> DateTime;
+}
+
+/// The system clock, in UTC.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime {
+        let now = ChronoDateTime::<Utc>::from(SystemTime::now());
+        DateTime(now.with_timezone(&FixedOffset::east_opt(0).unwrap()))
+    }
+}
+
+impl<F: Fn() -> DateTime> Clock for F {
+    fn now(&self) -> DateTime {
+        self()
+    }
+}
+
+/// Implements the xs:duration datatype referenced by XEP-0082, in the
+/// `PnYnMnDTnHnMnS` format.
+///
+/// As a `std::time::Duration` can’t represent calendar units, a year is
+/// counted as 365 days and a month as 30 days, and durations are always
+/// serialised using days at most.  Negative durations aren’t supported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Duration(pub StdDuration);
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Parses a sequence of number and designator pairs, in the order given by
+/// `units`, returning the total amount of seconds and nanoseconds.  Only the
+/// `S` designator accepts a fractional number.
+fn parse_duration_components(mut s: &str, units: &[(char, u64)]) -> Result<(u64, u32), Error> {
+    let mut secs = 0u64;
+    let mut nanos = 0u32;
+    let mut next_unit = 0;
+    while !s.is_empty() {
+        let index = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or(Error::ParseError("Missing designator in duration."))?;
+        let (number, rest) = s.split_at(index);
+        let designator = rest.chars().next().unwrap();
+        s = &rest[designator.len_utf8()..];
+
+        let unit = units[next_unit..]
+            .iter()
+            .position(|(c, _)| *c == designator)
+            .ok_or(Error::ParseError("Invalid designator in duration."))?;
+        let (_, factor) = units[next_unit + unit];
+        next_unit += unit + 1;
+
+        let (integer, fraction) = match number.find('.') {
+            Some(dot) if designator == 'S' => (&number[..dot], Some(&number[dot + 1..])),
+            Some(_) => {
+                return Err(Error::ParseError(
+                    "Only seconds can be fractional in duration.",
+                ))
+            }
+            None => (number, None),
+        };
+        if integer.is_empty() || fraction == Some("") {
+            return Err(Error::ParseError("Missing number in duration."));
+        }
+        let value = integer
+            .parse::<u64>()?
+            .checked_mul(factor)
+            .ok_or(Error::ParseError("Duration too long."))?;
+        secs = secs
+            .checked_add(value)
+            .ok_or(Error::ParseError("Duration too long."))?;
+        if let Some(fraction) = fraction {
+            if !fraction.chars().all(|c| c.is_ascii_digit()) {
+                return Err(Error::ParseError("Invalid number in duration."));
+            }
+            // Anything finer than a nanosecond gets truncated.
+            let digits = &fraction[..fraction.len().min(9)];
+            nanos = digits.parse::<u32>()? * 10u32.pow(9 - digits.len() as u32);
+        }
+    }
+    Ok((secs, nanos))
+}
+
+impl FromStr for Duration {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Duration, Error> {
+        let s = s
+            .strip_prefix('P')
+            .ok_or(Error::ParseError("Duration must start with 'P'."))?;
+        let (date, time) = match s.find('T') {
+            Some(index) => (&s[..index], Some(&s[index + 1..])),
+            None => (s, None),
+        };
+        if time == Some("") {
+            return Err(Error::ParseError("Missing time after 'T' in duration."));
+        }
+        if date.is_empty() && time.is_none() {
+            return Err(Error::ParseError("Empty duration."));
+        }
+        let (date_secs, _) = parse_duration_components(
+            date,
+            &[
+                ('Y', 365 * SECONDS_PER_DAY),
+                ('M', 30 * SECONDS_PER_DAY),
+                ('D', SECONDS_PER_DAY),
+            ],
+        )?;
+        let (time_secs, nanos) =
+            parse_duration_components(time.unwrap_or(""), &[('H', 3600), ('M', 60), ('S', 1)])?;
+        let secs = date_secs
+            .checked_add(time_secs)
+            .ok_or(Error::ParseError("Duration too long."))?;
+        Ok(Duration(StdDuration::new(secs, nanos)))
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0.as_secs();
+        let nanos = self.0.subsec_nanos();
+        let days = secs / SECONDS_PER_DAY;
+        let hours = secs % SECONDS_PER_DAY / 3600;
+        let minutes = secs % 3600 / 60;
+        let seconds = secs % 60;
+        write!(fmt, "P")?;
+        if days > 0 {
+            write!(fmt, "{}D", days)?;
+        }
+        if hours == 0 && minutes == 0 && seconds == 0 && nanos == 0 {
+            if days == 0 {
+                write!(fmt, "T0S")?;
+            }
+            return Ok(());
+        }
+        write!(fmt, "T")?;
+        if hours > 0 {
+            write!(fmt, "{}H", hours)?;
+        }
+        if minutes > 0 {
+            write!(fmt, "{}M", minutes)?;
+        }
+        if nanos > 0 {
+            let fraction = format!("{:09}", nanos);
+            write!(fmt, "{}.{}S", seconds, fraction.trim_end_matches('0'))?;
+        } else if seconds > 0 {
+            write!(fmt, "{}S", seconds)?;
+        }
+        Ok(())
+    }
+}
+
+impl IntoAttributeValue for Duration {
+    fn into_attribute_value(self) -> Option<String> {
+        Some(self.to_string())
+    }
+}
+
+impl From<Duration> for Node {
+    fn from(duration: Duration) -> Node {
+        Node::Text(duration.to_string())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use chrono::{Datelike, Timelike};
 
-    // DateTime’s size doesn’t depend on the architecture.
+    // DateTime’s and Duration’s sizes don’t depend on the architecture.
     #[test]
     fn test_size() {
         assert_size!(DateTime, 16);
+        assert_size!(Duration, 16);
     }
 
     #[test]
@@ -76,6 +250,18 @@ mod tests {
         assert_eq!(date.0.timezone(), FixedOffset::east_opt(0).unwrap());
     }
 
+    #[test]
+    fn test_clock() {
+        let before = ChronoDateTime::<Utc>::from(SystemTime::now());
+        let now = SystemClock.now();
+        assert!(now.0 >= before);
+        assert_eq!(now.timezone(), FixedOffset::east_opt(0).unwrap());
+
+        let fixed = DateTime::from_str("2017-05-21T20:19:55+01:00").unwrap();
+        let clock = || fixed.clone();
+        assert_eq!(clock.now(), fixed);
+    }
+
     #[test]
     fn test_invalid_date() {
         // There is no thirteenth month.
@@ -134,4 +320,63 @@ mod tests {
         let attr = date.into_attribute_value();
         assert_eq!(attr, Some(String::from("2017-05-21T20:19:55+01:00")));
     }
+
+    #[test]
+    fn test_duration() {
+        let duration: Duration = "PT30S".parse().unwrap();
+        assert_eq!(duration.0, StdDuration::from_secs(30));
+
+        let duration: Duration = "P1DT2H3M4.5S".parse().unwrap();
+        assert_eq!(duration.0, StdDuration::new(93784, 500_000_000));
+
+        let duration: Duration = "P1Y2M".parse().unwrap();
+        assert_eq!(duration.0, StdDuration::from_secs(425 * 24 * 3600));
+
+        let duration: Duration = "PT0.000000001S".parse().unwrap();
+        assert_eq!(duration.0, StdDuration::new(0, 1));
+    }
+
+    #[test]
+    fn test_invalid_duration() {
+        let invalid = [
+            ("", "Duration must start with 'P'."),
+            ("-P1D", "Duration must start with 'P'."),
+            ("P", "Empty duration."),
+            ("P1DT", "Missing time after 'T' in duration."),
+            ("P1H", "Invalid designator in duration."),
+            ("PT1M1H", "Invalid designator in duration."),
+            ("P1D1D", "Invalid designator in duration."),
+            ("P1", "Missing designator in duration."),
+            ("PTS", "Missing number in duration."),
+            ("PT1.S", "Missing number in duration."),
+            ("P1.5D", "Only seconds can be fractional in duration."),
+            ("PT1.2.3S", "Invalid number in duration."),
+        ];
+        for (string, expected) in invalid.iter() {
+            let error = Duration::from_str(string).unwrap_err();
+            let message = match error {
+                Error::ParseError(string) => string,
+                _ => panic!(),
+            };
+            assert_eq!(message, *expected);
+        }
+    }
+
+    #[test]
+    fn test_serialise_duration() {
+        for (secs, nanos, string) in [
+            (0, 0, "PT0S"),
+            (30, 0, "PT30S"),
+            (86400, 0, "P1D"),
+            (93784, 500_000_000, "P1DT2H3M4.5S"),
+            (3600, 0, "PT1H"),
+            (0, 1, "PT0.000000001S"),
+        ]
+        .iter()
+        {
+            let duration = Duration(StdDuration::new(*secs, *nanos));
+            assert_eq!(duration.into_attribute_value(), Some(String::from(*string)));
+            assert_eq!(Duration::from_str(string).unwrap(), duration);
+        }
+    }
 }
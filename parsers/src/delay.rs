@@ -4,8 +4,9 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::date::DateTime;
+use crate::date::{Clock, DateTime};
 use crate::message::MessagePayload;
+use crate::ns;
 use crate::presence::PresencePayload;
 use crate::util::helpers::PlainText;
 use jid::Jid;
@@ -26,8 +27,23 @@ generate_element!(
     )
 );
 
+impl Delay {
+    /// Creates a delay stamped with the current time according to the given
+    /// clock, noting that `from` stores this stanza from now on.
+    pub fn now<C: Clock>(from: Option<Jid>, clock: &C) -> Delay {
+        Delay {
+            from,
+            stamp: clock.now(),
+            data: None,
+        }
+    }
+}
+
 impl MessagePayload for Delay {}
-impl PresencePayload for Delay {}
+impl PresencePayload for Delay {
+    const NAME: &'static str = "delay";
+    const NS: &'static str = ns::DELAY;
+}
 
 #[cfg(test)]
 mod tests {
@@ -84,11 +100,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = Delay::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in delay element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in delay element"
+        );
     }
 
     #[test]
@@ -116,4 +131,16 @@ mod tests {
         let elem2 = delay.into();
         assert_eq!(elem, elem2);
     }
+
+    #[test]
+    fn test_now() {
+        let elem: Element =
+            "<delay xmlns='urn:xmpp:delay' from='capulet.com' stamp='2002-09-10T23:08:25+00:00'/>"
+                .parse()
+                .unwrap();
+        let clock = || DateTime::from_str("2002-09-10T23:08:25Z").unwrap();
+        let delay = Delay::now(Some(Jid::new("capulet.com").unwrap()), &clock);
+        let elem2 = delay.into();
+        assert_eq!(elem, elem2);
+    }
 }
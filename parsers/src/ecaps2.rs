@@ -27,7 +27,10 @@ generate_element!(
     ]
 );
 
-impl PresencePayload for ECaps2 {}
+impl PresencePayload for ECaps2 {
+    const NAME: &'static str = "c";
+    const NS: &'static str = ns::ECAPS2;
+}
 
 impl ECaps2 {
     /// Create an ECaps2 element from a list of hashes.
@@ -183,7 +186,6 @@ pub fn query_ecaps2(hash: Hash) -> DiscoInfoQuery {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::error::Error;
     use crate::Element;
     use std::convert::TryFrom;
 
@@ -224,11 +226,7 @@ mod tests {
     fn test_invalid_child() {
         let elem: Element = "<c xmlns='urn:xmpp:caps'><hash xmlns='urn:xmpp:hashes:2' algo='sha-256'>K1Njy3HZBThlo4moOD5gBGhn0U0oK7/CbfLlIUDi6o4=</hash><hash xmlns='urn:xmpp:hashes:1' algo='sha3-256'>+sDTQqBmX6iG/X3zjt06fjZMBBqL/723knFIyRf0sg8=</hash></c>".parse().unwrap();
         let error = ECaps2::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in c element.");
+        assert_eq!(error.to_string(), "unknown child <hash/> in c element");
     }
 
     #[test]
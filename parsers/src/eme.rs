@@ -5,9 +5,24 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::message::MessagePayload;
+use crate::ns;
+
+/// The encryption schemes listed in XEP-0380, along with their
+/// user-friendly names.
+const KNOWN_SCHEMES: [(&str, &str); 5] = [
+    (ns::OTR, "OTR"),
+    (ns::LEGACY_OPENPGP, "Legacy OpenPGP"),
+    (ns::OX, "OpenPGP for XMPP"),
+    (ns::LEGACY_OMEMO, "OMEMO"),
+    (ns::OMEMO, "OMEMO"),
+];
 
 generate_element!(
     /// Structure representing an `<encryption xmlns='urn:xmpp:eme:0'/>` element.
+    ///
+    /// A message sent with it should also carry a `<body/>` telling clients
+    /// which don’t support the scheme that the message is encrypted, as they
+    /// will display that fallback instead.
     ExplicitMessageEncryption, "encryption", EME,
     attributes: [
         /// Namespace of the encryption scheme used.
@@ -19,6 +34,60 @@ generate_element!(
     ]
 );
 
+impl ExplicitMessageEncryption {
+    /// Returns whether this encryption scheme is one of the well-known ones
+    /// listed in XEP-0380.
+    pub fn is_known(&self) -> bool {
+        KNOWN_SCHEMES
+            .iter()
+            .any(|(namespace, _)| *namespace == self.namespace)
+    }
+
+    /// Returns a user-friendly name for this encryption scheme, either the
+    /// one sent along with it or the one of a well-known scheme.
+    ///
+    /// When a message carries an `<encryption/>` element, its `<body/>` only
+    /// contains a fallback text meant for clients which don’t implement the
+    /// encryption scheme, so it shouldn’t be displayed as if it was the actual
+    /// content of the message.
+    ///
+    /// A client should instead look for this payload on every received message,
+    /// and replace the body with a placeholder such as “This message is encrypted
+    /// with OMEMO, which this client doesn’t support.” whenever it can’t decrypt
+    /// it, using this method to name the scheme:
+    ///
+    /// ```
+    /// use std::convert::TryFrom;
+    /// use xmpp_parsers::eme::ExplicitMessageEncryption;
+    /// use xmpp_parsers::message::Message;
+    /// use xmpp_parsers::{ns, Element};
+    ///
+    /// # let elem: Element = "<message xmlns='jabber:client'><body>I sent you an OMEMO encrypted message but your client doesn’t seem to support that.</body><encryption xmlns='urn:xmpp:eme:0' namespace='eu.siacs.conversations.axolotl'/></message>".parse().unwrap();
+    /// let message = Message::try_from(elem).unwrap();
+    /// let eme = message
+    ///     .payloads
+    ///     .iter()
+    ///     .find(|payload| payload.is("encryption", ns::EME))
+    ///     .and_then(|payload| ExplicitMessageEncryption::try_from(payload.clone()).ok());
+    /// if let Some(eme) = eme {
+    ///     assert!(eme.is_known());
+    ///     let placeholder = format!(
+    ///         "This message is encrypted with {}.",
+    ///         eme.scheme_name().unwrap_or(&eme.namespace)
+    ///     );
+    ///     assert_eq!(placeholder, "This message is encrypted with OMEMO.");
+    /// }
+    /// ```
+    pub fn scheme_name(&self) -> Option<&str> {
+        self.name.as_deref().or_else(|| {
+            KNOWN_SCHEMES
+                .iter()
+                .find(|(namespace, _)| *namespace == self.namespace)
+                .map(|(_, name)| *name)
+        })
+    }
+}
+
 impl MessagePayload for ExplicitMessageEncryption {}
 
 #[cfg(test)]
@@ -55,6 +124,29 @@ mod tests {
         assert_eq!(encryption.name, Some(String::from("SuperMechanism")));
     }
 
+    #[test]
+    fn test_known() {
+        let elem: Element = "<encryption xmlns='urn:xmpp:eme:0' namespace='urn:xmpp:otr:0'/>"
+            .parse()
+            .unwrap();
+        let encryption = ExplicitMessageEncryption::try_from(elem).unwrap();
+        assert!(encryption.is_known());
+        assert_eq!(encryption.scheme_name(), Some("OTR"));
+
+        let elem: Element = "<encryption xmlns='urn:xmpp:eme:0' namespace='some.unknown.mechanism' name='SuperMechanism'/>".parse().unwrap();
+        let encryption = ExplicitMessageEncryption::try_from(elem).unwrap();
+        assert!(!encryption.is_known());
+        assert_eq!(encryption.scheme_name(), Some("SuperMechanism"));
+
+        let elem: Element =
+            "<encryption xmlns='urn:xmpp:eme:0' namespace='some.unknown.mechanism'/>"
+                .parse()
+                .unwrap();
+        let encryption = ExplicitMessageEncryption::try_from(elem).unwrap();
+        assert!(!encryption.is_known());
+        assert_eq!(encryption.scheme_name(), None);
+    }
+
     #[test]
     fn test_unknown() {
         let elem: Element = "<replace xmlns='urn:xmpp:message-correct:0'/>"
@@ -74,11 +166,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = ExplicitMessageEncryption::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in encryption element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in encryption element"
+        );
     }
 
     #[test]
@@ -4,8 +4,14 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::date::{Clock, DateTime, SystemClock};
 use crate::delay::Delay;
 use crate::message::Message;
+use crate::ns;
+use crate::stanza;
+use crate::util::error::Error;
+use crate::Element;
+use std::convert::TryFrom;
 
 generate_element!(
     /// Contains a forwarded stanza, either standalone or part of another
@@ -23,23 +29,117 @@ generate_element!(
     ]
 );
 
+/// Whether this element is a stanza, in any namespace a stream can carry
+/// stanzas in.
+fn is_stanza(elem: &Element) -> bool {
+    ["message", "presence", "iq"].contains(&elem.name())
+        && stanza::NAMESPACES.contains(&&*elem.ns())
+}
+
+impl Forwarded {
+    /// Wraps any stanza in a `<forwarded/>` element, with the `<delay/>`
+    /// telling when it was originally sent.
+    ///
+    /// Unlike a `Forwarded`, which only holds messages in the default
+    /// namespace, the stanza is kept as is, e.g. in `jabber:server` for a
+    /// server re-forwarding it.
+    pub fn wrap(stanza: Element, delay: Option<Delay>) -> Result<Element, Error> {
+        if !is_stanza(&stanza) {
+            return Err(Error::ParseError("Forwarded element must be a stanza."));
+        }
+        Ok(Element::builder("forwarded", ns::FORWARD)
+            .append_all(delay.map(Element::from))
+            .append(stanza)
+            .build())
+    }
+
+    /// Returns the stanza wrapped in a `<forwarded/>` element, in its
+    /// original namespace, and its `<delay/>` if any.
+    ///
+    /// Fails unless the element contains exactly one stanza.
+    pub fn unwrap(elem: Element) -> Result<(Element, Option<Delay>), Error> {
+        check_self!(elem, "forwarded", FORWARD);
+        check_no_attributes!(elem, "forwarded");
+        let mut delay = None;
+        let mut stanza = None;
+        for child in elem.children() {
+            if child.is("delay", ns::DELAY) {
+                if delay.is_some() {
+                    return Err(Error::ParseError(
+                        "Element forwarded must not have more than one delay child.",
+                    ));
+                }
+                delay = Some(Delay::try_from(child.clone())?);
+            } else if is_stanza(child) {
+                if stanza.is_some() {
+                    return Err(Error::ParseError(
+                        "Forwarded element must contain exactly one stanza.",
+                    ));
+                }
+                stanza = Some(child.clone());
+            } else {
+                return Err(Error::UnknownChild {
+                    element: "forwarded",
+                    child: child.name().to_owned(),
+                });
+            }
+        }
+        match stanza {
+            Some(stanza) => Ok((stanza, delay)),
+            None => Err(Error::ParseError(
+                "Forwarded element must contain exactly one stanza.",
+            )),
+        }
+    }
+
+    /// When the forwarded stanza got sent, as far as we can tell.
+    ///
+    /// This is the stamp of the `<delay/>` of this element, e.g. the time at
+    /// which a MAM archive stored the stanza, unless the stanza itself carries
+    /// an earlier `<delay/>`, e.g. because it was held in offline storage
+    /// before being archived.
+    pub fn timestamp(&self) -> Option<DateTime> {
+        let inner = self.stanza.as_ref().and_then(|message| {
+            message
+                .payloads
+                .iter()
+                .find(|payload| payload.is("delay", ns::DELAY))
+                .and_then(|payload| Delay::try_from(payload.clone()).ok())
+        });
+        match (self.delay.as_ref(), inner) {
+            (Some(outer), Some(inner)) if inner.stamp.0 < outer.stamp.0 => Some(inner.stamp),
+            (Some(outer), _) => Some(outer.stamp.clone()),
+            (None, inner) => inner.map(|delay| delay.stamp),
+        }
+    }
+
+    /// Like [timestamp](#method.timestamp), falling back to the current
+    /// time, so that a page of MAM results can be sorted chronologically.
+    pub fn timestamp_or_now(&self) -> DateTime {
+        self.timestamp_or_now_with(&SystemClock)
+    }
+
+    /// Like [timestamp_or_now](#method.timestamp_or_now), reading the current
+    /// time from the given clock.
+    pub fn timestamp_or_now_with<C: Clock>(&self, clock: &C) -> DateTime {
+        self.timestamp().unwrap_or_else(|| clock.now())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::error::Error;
-    use crate::Element;
-    use std::convert::TryFrom;
 
     #[cfg(target_pointer_width = "32")]
     #[test]
     fn test_size() {
-        assert_size!(Forwarded, 152);
+        assert_size!(Forwarded, 176);
     }
 
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn test_size() {
-        assert_size!(Forwarded, 288);
+        assert_size!(Forwarded, 336);
     }
 
     #[test]
@@ -54,11 +154,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = Forwarded::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in forwarded element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in forwarded element"
+        );
     }
 
     #[test]
@@ -97,4 +196,127 @@ mod tests {
         let serialized: Element = forwarded.into();
         assert_eq!(serialized, reference);
     }
+
+    #[test]
+    fn test_wrap() {
+        let stanza: Element = "<presence xmlns='jabber:server' from='juliet@capulet.example/balcony' to='romeo@montague.example'/>"
+            .parse()
+            .unwrap();
+        let delay: Element =
+            "<delay xmlns='urn:xmpp:delay' from='capulet.example' stamp='2002-09-10T23:08:25Z'/>"
+                .parse()
+                .unwrap();
+        let delay = Delay::try_from(delay).unwrap();
+        let elem = Forwarded::wrap(stanza.clone(), Some(delay.clone())).unwrap();
+        let reference: Element = "<forwarded xmlns='urn:xmpp:forward:0'><delay xmlns='urn:xmpp:delay' from='capulet.example' stamp='2002-09-10T23:08:25+00:00'/><presence xmlns='jabber:server' from='juliet@capulet.example/balcony' to='romeo@montague.example'/></forwarded>"
+            .parse()
+            .unwrap();
+        assert_eq!(elem, reference);
+
+        let (inner, inner_delay) = Forwarded::unwrap(elem).unwrap();
+        assert_eq!(inner, stanza);
+        assert_eq!(inner.ns(), ns::JABBER_SERVER);
+        assert_eq!(inner_delay, Some(delay));
+
+        let elem: Element = "<coucou xmlns='jabber:server'/>".parse().unwrap();
+        let error = Forwarded::wrap(elem, None).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Forwarded element must be a stanza.");
+    }
+
+    #[test]
+    fn test_unwrap_invalid() {
+        for xml in [
+            "<forwarded xmlns='urn:xmpp:forward:0'/>",
+            "<forwarded xmlns='urn:xmpp:forward:0'><message xmlns='jabber:client'/><iq xmlns='jabber:client' type='get' id='a'/></forwarded>",
+        ] {
+            let elem: Element = xml.parse().unwrap();
+            let error = Forwarded::unwrap(elem).unwrap_err();
+            let message = match error {
+                Error::ParseError(string) => string,
+                _ => panic!(),
+            };
+            assert_eq!(message, "Forwarded element must contain exactly one stanza.");
+        }
+
+        let elem: Element =
+            "<forwarded xmlns='urn:xmpp:forward:0'><message xmlns='urn:example'/></forwarded>"
+                .parse()
+                .unwrap();
+        let error = Forwarded::unwrap(elem).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown child <message/> in forwarded element"
+        );
+    }
+
+    #[test]
+    fn test_timestamp() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<forwarded xmlns='urn:xmpp:forward:0'><delay xmlns='urn:xmpp:delay' from='capulet.com' stamp='2002-09-10T23:08:25Z'/><message xmlns='jabber:client'/></forwarded>"
+            .parse()
+            .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<forwarded xmlns='urn:xmpp:forward:0'><delay xmlns='urn:xmpp:delay' from='capulet.com' stamp='2002-09-10T23:08:25Z'/><message xmlns='jabber:component:accept'/></forwarded>"
+            .parse()
+            .unwrap();
+        let forwarded = Forwarded::try_from(elem).unwrap();
+        assert_eq!(
+            forwarded.timestamp(),
+            Some("2002-09-10T23:08:25Z".parse().unwrap())
+        );
+
+        // The stanza was stored offline an hour before getting archived.
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<forwarded xmlns='urn:xmpp:forward:0'><delay xmlns='urn:xmpp:delay' from='capulet.com' stamp='2002-09-10T23:08:25Z'/><message xmlns='jabber:client'><delay xmlns='urn:xmpp:delay' from='capulet.com' stamp='2002-09-10T22:08:25Z'/></message></forwarded>"
+            .parse()
+            .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<forwarded xmlns='urn:xmpp:forward:0'><delay xmlns='urn:xmpp:delay' from='capulet.com' stamp='2002-09-10T23:08:25Z'/><message xmlns='jabber:component:accept'><delay xmlns='urn:xmpp:delay' from='capulet.com' stamp='2002-09-10T22:08:25Z'/></message></forwarded>"
+            .parse()
+            .unwrap();
+        let forwarded = Forwarded::try_from(elem).unwrap();
+        assert_eq!(
+            forwarded.timestamp(),
+            Some("2002-09-10T22:08:25Z".parse().unwrap())
+        );
+
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<forwarded xmlns='urn:xmpp:forward:0'><message xmlns='jabber:client'><delay xmlns='urn:xmpp:delay' stamp='2002-09-10T22:08:25Z'/></message></forwarded>"
+            .parse()
+            .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<forwarded xmlns='urn:xmpp:forward:0'><message xmlns='jabber:component:accept'><delay xmlns='urn:xmpp:delay' stamp='2002-09-10T22:08:25Z'/></message></forwarded>"
+            .parse()
+            .unwrap();
+        let forwarded = Forwarded::try_from(elem).unwrap();
+        assert_eq!(
+            forwarded.timestamp(),
+            Some("2002-09-10T22:08:25Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_timestamp_or_now() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element =
+            "<forwarded xmlns='urn:xmpp:forward:0'><message xmlns='jabber:client'/></forwarded>"
+                .parse()
+                .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<forwarded xmlns='urn:xmpp:forward:0'><message xmlns='jabber:component:accept'/></forwarded>"
+            .parse()
+            .unwrap();
+        let forwarded = Forwarded::try_from(elem).unwrap();
+        assert_eq!(forwarded.timestamp(), None);
+        let before = chrono::DateTime::<chrono::Utc>::from(std::time::SystemTime::now());
+        let now = forwarded.timestamp_or_now();
+        assert!(now.0 >= before);
+
+        let fixed: DateTime = "2002-09-10T23:08:25+01:00".parse().unwrap();
+        assert_eq!(forwarded.timestamp_or_now_with(&|| fixed.clone()), fixed);
+    }
 }
@@ -268,10 +268,6 @@ mod tests {
             .parse()
             .unwrap();
         let error = Hash::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in hash element.");
+        assert_eq!(error.to_string(), "unknown child <coucou/> in hash element");
     }
 }
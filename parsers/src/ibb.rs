@@ -5,7 +5,39 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::iq::IqSetPayload;
+use crate::message::MessagePayload;
+use crate::util::error::Error;
 use crate::util::helpers::Base64;
+use crate::Element;
+use std::convert::TryFrom;
+
+/// The largest chunk a peer can announce in its block-size.
+pub const MAX_BLOCK_SIZE: u16 = u16::MAX;
+
+/// Length of the base64 encoding of a chunk of `block_size` bytes.
+fn encoded_len(block_size: u16) -> usize {
+    (block_size as usize).div_ceil(3) * 4
+}
+
+/// Codec for the chunks of a stream, refusing to decode more than
+/// `MAX_BLOCK_SIZE` bytes, as a relay of untrusted traffic would otherwise
+/// allocate as much as the peer sends.
+struct Chunk;
+
+impl Chunk {
+    fn decode(s: &str) -> Result<Vec<u8>, Error> {
+        if s.len() > encoded_len(MAX_BLOCK_SIZE) {
+            return Err(Error::ParseError(
+                "IBB data larger than the maximum block size.",
+            ));
+        }
+        Base64::decode(s)
+    }
+
+    fn encode(b: &[u8]) -> Option<String> {
+        Base64::encode(b)
+    }
+}
 
 generate_id!(
     /// An identifier matching a stream.
@@ -25,6 +57,24 @@ Stanza, "stanza", {
     Message => "message",
 }, Default = Iq);
 
+impl Copy for Stanza {}
+
+impl Stanza {
+    /// The value of the stanza attribute for this stanza type.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stanza::Iq => "iq",
+            Stanza::Message => "message",
+        }
+    }
+}
+
+impl std::fmt::Display for Stanza {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
+
 generate_element!(
 /// Starts an In-Band Bytestream session with the given parameters.
 Open, "open", IBB,
@@ -53,11 +103,53 @@ Data, "data", IBB,
     ],
     text: (
         /// Vector of bytes to be exchanged.
-        data: Base64<Vec<u8>>
+        data: Chunk<Vec<u8>>
     )
 );
 
+impl Data {
+    /// Parses a chunk of a stream opened with the given block-size,
+    /// refusing larger chunks before decoding them.
+    pub fn parse_with_block_size(elem: Element, block_size: u16) -> Result<Data, Error> {
+        let len: usize = elem.texts().map(str::len).sum();
+        if len > encoded_len(block_size) {
+            return Err(Error::ParseError("IBB data larger than the block size."));
+        }
+        Data::try_from(elem)
+    }
+
+    /// Decodes the chunk of a stream opened with the given block-size at
+    /// the end of `buf`, returning its seq and sid.
+    ///
+    /// Unlike parsing a `Data`, this doesn’t allocate a new vector for every
+    /// chunk, so that a stream reader can keep reusing the same buffer.
+    pub fn decode_into(
+        elem: &Element,
+        block_size: u16,
+        buf: &mut Vec<u8>,
+    ) -> Result<(u16, StreamId), Error> {
+        check_self!(elem, "data", IBB);
+        check_no_unknown_attributes!(elem, "data", ["seq", "sid"]);
+        check_no_children!(elem, "data");
+        let seq = get_attr!(elem, "seq", Required);
+        let sid = get_attr!(elem, "sid", Required);
+        let mut texts = elem.texts();
+        let len: usize = elem.texts().map(str::len).sum();
+        if len > encoded_len(block_size) {
+            return Err(Error::ParseError("IBB data larger than the block size."));
+        }
+        // The parser only splits text around comments and the like.
+        match (texts.next(), texts.next()) {
+            (None, _) => (),
+            (Some(text), None) => Base64::decode_into(text, buf)?,
+            (Some(_), Some(_)) => Base64::decode_into(&elem.text(), buf)?,
+        }
+        Ok((seq, sid))
+    }
+}
+
 impl IqSetPayload for Data {}
+impl MessagePayload for Data {}
 
 generate_element!(
 /// Close an open stream.
@@ -72,9 +164,7 @@ impl IqSetPayload for Close {}
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::error::Error;
-    use crate::Element;
-    use std::convert::TryFrom;
+    use crate::ns;
 
     #[cfg(target_pointer_width = "32")]
     #[test]
@@ -141,8 +231,8 @@ mod tests {
             .parse()
             .unwrap();
         let error = Open::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseIntError(error) => error,
+        let message = match *error.unlocated() {
+            Error::ParseIntError(ref error) => error,
             _ => panic!(),
         };
         assert_eq!(message.to_string(), "invalid digit found in string");
@@ -158,11 +248,85 @@ mod tests {
         assert_eq!(message, "Required attribute 'sid' missing.");
     }
 
+    #[test]
+    fn test_data_too_large() {
+        let elem: Element =
+            "<data xmlns='http://jabber.org/protocol/ibb' seq='0' sid='coucou'>AAAAAAAA</data>"
+                .parse()
+                .unwrap();
+        let data = Data::parse_with_block_size(elem.clone(), 6).unwrap();
+        assert_eq!(data.data, vec!(0, 0, 0, 0, 0, 0));
+        let error = Data::parse_with_block_size(elem, 3).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "IBB data larger than the block size.");
+
+        let elem = Element::builder("data", ns::IBB)
+            .attr("seq", 0)
+            .attr("sid", "coucou")
+            .append("A".repeat(encoded_len(MAX_BLOCK_SIZE) + 4))
+            .build();
+        let error = Data::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "IBB data larger than the maximum block size.");
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let elem: Element =
+            "<data xmlns='http://jabber.org/protocol/ibb' seq='1' sid='coucou'>AAAA</data>"
+                .parse()
+                .unwrap();
+        let mut buf = vec![1, 2];
+        let (seq, sid) = Data::decode_into(&elem, 3, &mut buf).unwrap();
+        assert_eq!(seq, 1);
+        assert_eq!(sid, StreamId(String::from("coucou")));
+        assert_eq!(buf, vec!(1, 2, 0, 0, 0));
+
+        let elem: Element =
+            "<data xmlns='http://jabber.org/protocol/ibb' seq='2' sid='coucou'>AAAAAAAA</data>"
+                .parse()
+                .unwrap();
+        buf.clear();
+        let error = Data::decode_into(&elem, 3, &mut buf).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "IBB data larger than the block size.");
+
+        let elem: Element =
+            "<data xmlns='http://jabber.org/protocol/ibb' seq='3' sid='coucou'>AAA!</data>"
+                .parse()
+                .unwrap();
+        buf.push(3);
+        Data::decode_into(&elem, 3, &mut buf).unwrap_err();
+        assert_eq!(buf, vec!(3));
+    }
+
+    #[test]
+    fn test_stanza() {
+        let elem: Element = "<open xmlns='http://jabber.org/protocol/ibb' block-size='128' sid='coucou' stanza='message'/>".parse().unwrap();
+        let open = Open::try_from(elem).unwrap();
+        let stanza = open.stanza;
+        assert_eq!(stanza, Stanza::Message);
+        assert_eq!(stanza.to_string(), "message");
+        assert_eq!(Stanza::Iq.as_str(), "iq");
+        for stanza in [Stanza::Iq, Stanza::Message] {
+            assert_eq!(stanza.as_str().parse::<Stanza>().unwrap(), stanza);
+        }
+    }
+
     #[test]
     fn test_invalid_stanza() {
         let elem: Element = "<open xmlns='http://jabber.org/protocol/ibb' block-size='128' sid='coucou' stanza='fdsq'/>".parse().unwrap();
         let error = Open::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ParseError(string) => string,
             _ => panic!(),
         };
@@ -82,7 +82,10 @@ impl TryFrom<Element> for Query {
             } else if child.is("x", ns::DATA_FORMS) {
                 query.form = Some(DataForm::try_from(child.clone())?);
             } else {
-                return Err(Error::ParseError("Unknown child in ibr element."));
+                return Err(Error::UnknownChild {
+                    element: "ibr",
+                    child: child.name().to_owned(),
+                });
             }
         }
         Ok(query)
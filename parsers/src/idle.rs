@@ -4,7 +4,8 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::date::DateTime;
+use crate::date::{Clock, DateTime};
+use crate::ns;
 use crate::presence::PresencePayload;
 
 generate_element!(
@@ -16,7 +17,18 @@ generate_element!(
     ]
 );
 
-impl PresencePayload for Idle {}
+impl Idle {
+    /// Creates an idle payload noting that the user stopped interacting
+    /// right now, according to the given clock.
+    pub fn now<C: Clock>(clock: &C) -> Idle {
+        Idle { since: clock.now() }
+    }
+}
+
+impl PresencePayload for Idle {
+    const NAME: &'static str = "idle";
+    const NS: &'static str = ns::IDLE;
+}
 
 #[cfg(test)]
 mod tests {
@@ -31,6 +43,14 @@ mod tests {
         assert_size!(Idle, 16);
     }
 
+    #[test]
+    fn test_now() {
+        let since = DateTime::from_str("2017-05-21T20:19:55+01:00").unwrap();
+        let idle = Idle::now(&|| since.clone());
+        let elem: Element = idle.into();
+        assert_eq!(elem.attr("since"), Some("2017-05-21T20:19:55+01:00"));
+    }
+
     #[test]
     fn test_simple() {
         let elem: Element = "<idle xmlns='urn:xmpp:idle:1' since='2017-05-21T20:19:55+01:00'/>"
@@ -45,11 +65,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Idle::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in idle element.");
+        assert_eq!(error.to_string(), "unknown child <coucou/> in idle element");
     }
 
     #[test]
@@ -70,7 +86,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Idle::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ChronoParseError(string) => string,
             _ => panic!(),
         };
@@ -81,7 +97,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Idle::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ChronoParseError(string) => string,
             _ => panic!(),
         };
@@ -92,7 +108,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Idle::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ChronoParseError(string) => string,
             _ => panic!(),
         };
@@ -103,7 +119,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Idle::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ChronoParseError(string) => string,
             _ => panic!(),
         };
@@ -114,7 +130,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Idle::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ChronoParseError(string) => string,
             _ => panic!(),
         };
@@ -125,7 +141,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Idle::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ChronoParseError(string) => string,
             _ => panic!(),
         };
@@ -6,7 +6,9 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::ns;
+use crate::stanza::to_default_ns;
 use crate::stanza_error::StanzaError;
+use crate::stanza_id::generate_id;
 use crate::util::error::Error;
 use crate::Element;
 use jid::Jid;
@@ -24,18 +26,28 @@ pub trait IqResultPayload: TryFrom<Element> + Into<Element> {}
 
 /// Represents one of the four possible iq types.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IqType {
     /// This is a request for accessing some data.
-    Get(Element),
+    Get(#[cfg_attr(feature = "serde", serde(with = "crate::util::helpers::xml_string"))] Element),
 
     /// This is a request for modifying some data.
-    Set(Element),
+    Set(#[cfg_attr(feature = "serde", serde(with = "crate::util::helpers::xml_string"))] Element),
 
     /// This is a result containing some data.
-    Result(Option<Element>),
+    Result(
+        #[cfg_attr(
+            feature = "serde",
+            serde(with = "crate::util::helpers::xml_string::option")
+        )]
+        Option<Element>,
+    ),
 
     /// A get or set request failed.
-    Error(StanzaError),
+    Error(
+        #[cfg_attr(feature = "serde", serde(with = "crate::util::helpers::xml_string"))]
+        StanzaError,
+    ),
 }
 
 impl<'a> IntoAttributeValue for &'a IqType {
@@ -54,6 +66,7 @@ impl<'a> IntoAttributeValue for &'a IqType {
 
 /// The main structure representing the `<iq/>` stanza.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Iq {
     /// The JID emitting this stanza.
     pub from: Option<Jid>,
@@ -137,15 +150,33 @@ impl Iq {
         self.id = id;
         self
     }
+
+    /// Sets the id of this stanza to a newly generated unique one, see
+    /// [generate_id](../stanza_id/fn.generate_id.html).
+    pub fn with_generated_id(mut self) -> Iq {
+        self.id = generate_id();
+        self
+    }
 }
 
 impl TryFrom<Element> for Iq {
     type Error = Error;
 
     fn try_from(root: Element) -> Result<Iq, Error> {
+        Iq::try_from(&root)
+    }
+}
+
+impl TryFrom<&Element> for Iq {
+    type Error = Error;
+
+    fn try_from(root: &Element) -> Result<Iq, Error> {
+        if let Some(root) = to_default_ns(root) {
+            return Iq::try_from(&root);
+        }
         check_self!(root, "iq", DEFAULT_NS);
-        let from = get_attr!(root, "from", Option);
-        let to = get_attr!(root, "to", Option);
+        let from = get_jid_attr!(root, "from", "iq");
+        let to = get_jid_attr!(root, "to", "iq");
         let id = get_attr!(root, "id", Required);
         let type_: String = get_attr!(root, "type", Required);
 
@@ -271,6 +302,21 @@ mod tests {
         assert_eq!(message, "Required attribute 'type' missing.");
     }
 
+    #[test]
+    fn test_borrowed() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<iq xmlns='jabber:client' type='set' id='foo'><foo xmlns='bar'/></iq>"
+            .parse()
+            .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element =
+            "<iq xmlns='jabber:component:accept' type='set' id='foo'><foo xmlns='bar'/></iq>"
+                .parse()
+                .unwrap();
+        let iq = Iq::try_from(&elem).unwrap();
+        assert_eq!(iq, Iq::try_from(elem).unwrap());
+    }
+
     #[test]
     fn test_get() {
         #[cfg(not(feature = "component"))]
@@ -445,6 +491,14 @@ mod tests {
         assert_eq!(elem, elem2);
     }
 
+    #[test]
+    fn test_generated_id() {
+        let iq1 = Iq::empty_result(Jid::new("example.org").unwrap(), "").with_generated_id();
+        let iq2 = Iq::empty_result(Jid::new("example.org").unwrap(), "").with_generated_id();
+        assert!(!iq1.id.is_empty());
+        assert_ne!(iq1.id, iq2.id);
+    }
+
     #[test]
     fn test_disco() {
         #[cfg(not(feature = "component"))]
@@ -458,4 +512,44 @@ mod tests {
         };
         assert!(disco_info.node.is_none());
     }
+
+    #[test]
+    fn test_invalid_to_jid() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<iq xmlns='jabber:client' to='@example.org' type='get' id='coucou'/>"
+            .parse()
+            .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element =
+            "<iq xmlns='jabber:component:accept' to='@example.org' type='get' id='coucou'/>"
+                .parse()
+                .unwrap();
+        let error = Iq::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Invalid 'to' JID in iq.");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let iq = Iq::from_get("disco", DiscoInfoQuery { node: None })
+            .with_to(Jid::new("example.org").unwrap());
+        let json = serde_json::to_string(&iq).unwrap();
+        let iq2: Iq = serde_json::from_str(&json).unwrap();
+        assert_eq!(iq, iq2);
+
+        let error = StanzaError::new(
+            ErrorType::Cancel,
+            DefinedCondition::ServiceUnavailable,
+            "en",
+            "coucou",
+        );
+        let iq = Iq::from_error("disco", error);
+        let json = serde_json::to_string(&iq).unwrap();
+        let iq2: Iq = serde_json::from_str(&json).unwrap();
+        assert_eq!(iq, iq2);
+    }
 }
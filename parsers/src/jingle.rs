@@ -795,7 +795,7 @@ mod tests {
 
         let elem: Element = "<jingle xmlns='urn:xmpp:jingle:1' action='session-initiate' sid='coucou'><content creator='coucou' name='coucou'/></jingle>".parse().unwrap();
         let error = Jingle::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ParseError(string) => string,
             _ => panic!(),
         };
@@ -803,7 +803,7 @@ mod tests {
 
         let elem: Element = "<jingle xmlns='urn:xmpp:jingle:1' action='session-initiate' sid='coucou'><content creator='initiator' name='coucou' senders='coucou'/></jingle>".parse().unwrap();
         let error = Jingle::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ParseError(string) => string,
             _ => panic!(),
         };
@@ -811,7 +811,7 @@ mod tests {
 
         let elem: Element = "<jingle xmlns='urn:xmpp:jingle:1' action='session-initiate' sid='coucou'><content creator='initiator' name='coucou' senders=''/></jingle>".parse().unwrap();
         let error = Jingle::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ParseError(string) => string,
             _ => panic!(),
         };
@@ -149,7 +149,7 @@ impl TryFrom<Element> for File {
                 if file.date.is_some() {
                     return Err(Error::ParseError("File must not have more than one date."));
                 }
-                file.date = Some(child.text().parse()?);
+                file.date = Some(child.text_trimmed().parse()?);
             } else if child.is("media-type", ns::JINGLE_FT) {
                 if file.media_type.is_some() {
                     return Err(Error::ParseError(
@@ -174,7 +174,7 @@ impl TryFrom<Element> for File {
                 if file.size.is_some() {
                     return Err(Error::ParseError("File must not have more than one size."));
                 }
-                file.size = Some(child.text().parse()?);
+                file.size = Some(child.text_trimmed().parse()?);
             } else if child.is("range", ns::JINGLE_FT) {
                 if file.range.is_some() {
                     return Err(Error::ParseError("File must not have more than one range."));
@@ -471,11 +471,10 @@ mod tests {
 
         let elem: Element = "<received xmlns='urn:xmpp:jingle:apps:file-transfer:5' name='coucou' creator='initiator'><coucou/></received>".parse().unwrap();
         let error = Received::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in received element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in received element"
+        );
 
         let elem: Element =
             "<received xmlns='urn:xmpp:jingle:apps:file-transfer:5' creator='initiator'/>"
@@ -490,7 +489,7 @@ mod tests {
 
         let elem: Element = "<received xmlns='urn:xmpp:jingle:apps:file-transfer:5' name='coucou' creator='coucou'/>".parse().unwrap();
         let error = Received::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ParseError(string) => string,
             _ => panic!(),
         };
@@ -502,11 +501,10 @@ mod tests {
     fn test_invalid_received() {
         let elem: Element = "<received xmlns='urn:xmpp:jingle:apps:file-transfer:5' name='coucou' creator='initiator' coucou=''/>".parse().unwrap();
         let error = Received::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in received element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in received element"
+        );
     }
 
     #[test]
@@ -568,11 +566,10 @@ mod tests {
     fn test_invalid_checksum() {
         let elem: Element = "<checksum xmlns='urn:xmpp:jingle:apps:file-transfer:5' name='coucou' creator='initiator' coucou=''><file><hash xmlns='urn:xmpp:hashes:2' algo='sha-1'>w0mcJylzCn+AfvuGdqkty2+KP48=</hash></file></checksum>".parse().unwrap();
         let error = Checksum::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in checksum element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in checksum element"
+        );
     }
 
     #[test]
@@ -611,10 +608,9 @@ mod tests {
             .parse()
             .unwrap();
         let error = Range::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in range element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in range element"
+        );
     }
 }
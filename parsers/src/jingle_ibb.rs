@@ -69,8 +69,8 @@ mod tests {
                 .parse()
                 .unwrap();
         let error = Transport::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseIntError(error) => error,
+        let message = match *error.unlocated() {
+            Error::ParseIntError(ref error) => error,
             _ => panic!(),
         };
         assert_eq!(
@@ -82,8 +82,8 @@ mod tests {
             .parse()
             .unwrap();
         let error = Transport::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseIntError(error) => error,
+        let message = match *error.unlocated() {
+            Error::ParseIntError(ref error) => error,
             _ => panic!(),
         };
         assert_eq!(message.to_string(), "invalid digit found in string");
@@ -104,7 +104,7 @@ mod tests {
     fn test_invalid_stanza() {
         let elem: Element = "<transport xmlns='urn:xmpp:jingle:transports:ibb:1' block-size='128' sid='coucou' stanza='fdsq'/>".parse().unwrap();
         let error = Transport::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ParseError(string) => string,
             _ => panic!(),
         };
@@ -59,7 +59,10 @@ impl TryFrom<Element> for JingleMI {
                 let mut description = None;
                 for child in elem.children() {
                     if child.name() != "description" {
-                        return Err(Error::ParseError("Unknown child in propose element."));
+                        return Err(Error::UnknownChild {
+                            element: "propose",
+                            child: child.name().to_owned(),
+                        });
                     }
                     if description.is_some() {
                         return Err(Error::ParseError("Too many children in propose element."));
@@ -134,10 +137,9 @@ mod tests {
                 .parse()
                 .unwrap();
         let error = JingleMI::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in propose element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in propose element"
+        );
     }
 }
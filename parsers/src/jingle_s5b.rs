@@ -225,9 +225,10 @@ impl TryFrom<Element> for Transport {
                 }
                 TransportPayload::ProxyError
             } else {
-                return Err(Error::ParseError(
-                    "Unknown child in JingleS5B transport element.",
-                ));
+                return Err(Error::UnknownChild {
+                    element: "JingleS5B transport",
+                    child: child.name().to_owned(),
+                });
             });
         }
         let payload = payload.unwrap_or(TransportPayload::None);
@@ -44,9 +44,13 @@ pub mod presence;
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub mod sasl;
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
+pub mod stanza;
+/// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub mod stanza_error;
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub mod stream;
+/// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
+pub mod stream_features;
 
 /// RFC 6121: Extensible Messaging and Presence Protocol (XMPP): Instant Messaging and Presence
 pub mod roster;
@@ -69,6 +73,9 @@ pub mod ibb;
 /// XEP-0048: Bookmarks
 pub mod bookmarks;
 
+/// XEP-0055: Jabber Search
+pub mod search;
+
 /// XEP-0059: Result Set Management
 pub mod rsm;
 
@@ -105,6 +112,9 @@ pub mod caps;
 /// XEP-0118: User Tune
 pub mod tune;
 
+/// XEP-0131: Stanza Headers and Internet Metadata
+pub mod shim;
+
 /// XEP-0157: Contact Addresses for XMPP Services
 pub mod server_info;
 
@@ -198,6 +208,9 @@ pub mod jingle_dtls_srtp;
 /// XEP-0328: JID Prep
 pub mod jid_prep;
 
+/// XEP-0333: Chat Markers
+pub mod chat_markers;
+
 /// XEP-0338: Jingle Grouping Framework
 pub mod jingle_grouping;
 
@@ -90,7 +90,6 @@ impl IqResultPayload for Fin {}
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::error::Error;
     use minidom::Element;
     use std::convert::TryFrom;
 
@@ -99,7 +98,7 @@ mod tests {
     fn test_size() {
         assert_size!(QueryId, 12);
         assert_size!(Query, 116);
-        assert_size!(Result_, 176);
+        assert_size!(Result_, 200);
         assert_size!(Complete, 1);
         assert_size!(Fin, 44);
     }
@@ -109,7 +108,7 @@ mod tests {
     fn test_size() {
         assert_size!(QueryId, 24);
         assert_size!(Query, 232);
-        assert_size!(Result_, 336);
+        assert_size!(Result_, 384);
         assert_size!(Complete, 1);
         assert_size!(Fin, 88);
     }
@@ -206,11 +205,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = Query::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in query element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in query element"
+        );
     }
 
     #[test]
@@ -68,7 +68,10 @@ impl TryFrom<Element> for Prefs {
                     never.push(jid_elem.text().parse()?);
                 }
             } else {
-                return Err(Error::ParseError("Unknown child in prefs element."));
+                return Err(Error::UnknownChild {
+                    element: "prefs",
+                    child: child.name().to_owned(),
+                });
             }
         }
         let default_ = get_attr!(elem, "default", Required);
@@ -98,8 +98,8 @@ mod tests {
             .parse()
             .unwrap();
         let error = MediaElement::try_from(elem).unwrap_err();
-        let error = match error {
-            Error::ParseIntError(error) => error,
+        let error = match *error.unlocated() {
+            Error::ParseIntError(ref error) => error,
             _ => panic!(),
         };
         assert_eq!(error.to_string(), "cannot parse integer from empty string");
@@ -108,8 +108,8 @@ mod tests {
             .parse()
             .unwrap();
         let error = MediaElement::try_from(elem).unwrap_err();
-        let error = match error {
-            Error::ParseIntError(error) => error,
+        let error = match *error.unlocated() {
+            Error::ParseIntError(ref error) => error,
             _ => panic!(),
         };
         assert_eq!(error.to_string(), "invalid digit found in string");
@@ -118,8 +118,8 @@ mod tests {
             .parse()
             .unwrap();
         let error = MediaElement::try_from(elem).unwrap_err();
-        let error = match error {
-            Error::ParseIntError(error) => error,
+        let error = match *error.unlocated() {
+            Error::ParseIntError(ref error) => error,
             _ => panic!(),
         };
         assert_eq!(error.to_string(), "cannot parse integer from empty string");
@@ -128,8 +128,8 @@ mod tests {
             .parse()
             .unwrap();
         let error = MediaElement::try_from(elem).unwrap_err();
-        let error = match error {
-            Error::ParseIntError(error) => error,
+        let error = match *error.unlocated() {
+            Error::ParseIntError(ref error) => error,
             _ => panic!(),
         };
         assert_eq!(error.to_string(), "invalid digit found in string");
@@ -141,11 +141,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = MediaElement::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in media element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in media element"
+        );
     }
 
     #[test]
@@ -155,7 +154,7 @@ mod tests {
                 .parse()
                 .unwrap();
         let error = MediaElement::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ParseError(string) => string,
             _ => panic!(),
         };
@@ -165,7 +164,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = MediaElement::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ParseError(string) => string,
             _ => panic!(),
         };
@@ -5,7 +5,9 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::ns;
+use crate::stanza::to_default_ns;
 use crate::util::error::Error;
+use crate::util::helpers::{child_lang, Text};
 use crate::Element;
 use jid::Jid;
 use std::collections::BTreeMap;
@@ -16,6 +18,7 @@ pub trait MessagePayload: TryFrom<Element> + Into<Element> {}
 
 generate_attribute!(
     /// The type of a message.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     MessageType, "type", {
         /// Standard instant messaging message.
         Chat => "chat",
@@ -40,6 +43,7 @@ type Lang = String;
 generate_elem_id!(
     /// Represents one `<body/>` element, that is the free form text content of
     /// a message.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     Body,
     "body",
     DEFAULT_NS
@@ -47,21 +51,50 @@ generate_elem_id!(
 
 generate_elem_id!(
     /// Defines the subject of a room, or of an email-like normal message.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     Subject,
     "subject",
     DEFAULT_NS
 );
 
-generate_elem_id!(
+generate_element!(
     /// A thread identifier, so that other people can specify to which message
     /// they are replying.
-    Thread,
-    "thread",
-    DEFAULT_NS
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    Thread, "thread", DEFAULT_NS,
+    attributes: [
+        /// The identifier of the thread this one got spawned from.
+        parent: Option<String> = "parent",
+    ],
+    text: (
+        /// The identifier of this thread.
+        id: Text<String>
+    )
 );
 
+impl Thread {
+    /// Creates a thread with this identifier, without a parent.
+    pub fn new<S: Into<String>>(id: S) -> Thread {
+        Thread {
+            parent: None,
+            id: id.into(),
+        }
+    }
+
+    /// Creates a thread spawned from this one, with this identifier.
+    pub fn new_child<S: Into<String>>(&self, id: S) -> Thread {
+        Thread {
+            parent: Some(self.id.clone()),
+            id: id.into(),
+        }
+    }
+}
+
 /// The main structure representing the `<message/>` stanza.
+///
+/// Two messages compare equal only if their payloads are in the same order.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Message {
     /// The JID emitting this stanza.
     pub from: Option<Jid>,
@@ -76,6 +109,10 @@ pub struct Message {
     /// The type of this message.
     pub type_: MessageType,
 
+    /// The xml:lang of this stanza, the language of the bodies and subjects
+    /// without their own.
+    pub lang: Option<String>,
+
     /// A list of bodies, sorted per language.  Use
     /// [get_best_body()](#method.get_best_body) to access them on reception.
     pub bodies: BTreeMap<Lang, Body>,
@@ -89,7 +126,12 @@ pub struct Message {
     /// to this message.
     pub thread: Option<Thread>,
 
-    /// A list of the extension payloads contained in this stanza.
+    /// A list of the extension payloads contained in this stanza, in document
+    /// order.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::util::helpers::xml_string::vec")
+    )]
     pub payloads: Vec<Element>,
 }
 
@@ -102,6 +144,7 @@ impl Message {
             to: to.into(),
             id: None,
             type_: MessageType::Chat,
+            lang: None,
             bodies: BTreeMap::new(),
             subjects: BTreeMap::new(),
             thread: None,
@@ -116,6 +159,7 @@ impl Message {
             to: to.into(),
             id: None,
             type_,
+            lang: None,
             bodies: BTreeMap::new(),
             subjects: BTreeMap::new(),
             thread: None,
@@ -166,6 +210,27 @@ impl Message {
         self
     }
 
+    /// Set the thread of this message.
+    pub fn with_thread(mut self, thread: Thread) -> Message {
+        self.thread = Some(thread);
+        self
+    }
+
+    /// Creates an empty reply to this message, of the same type and in the
+    /// same thread.
+    ///
+    /// It is addressed to the sender, or to the room itself for a groupchat
+    /// message.
+    pub fn reply(&self) -> Message {
+        let to = match (&self.type_, &self.from) {
+            (MessageType::Groupchat, Some(from)) => Some(Jid::Bare(from.to_bare())),
+            (_, from) => from.clone(),
+        };
+        let mut reply = Message::new_with_type(self.type_.clone(), to);
+        reply.thread = self.thread.clone();
+        reply
+    }
+
     fn get_best<'a, T>(
         map: &'a BTreeMap<Lang, T>,
         preferred_langs: Vec<&str>,
@@ -205,15 +270,62 @@ impl Message {
     pub fn get_best_subject(&self, preferred_langs: Vec<&str>) -> Option<(Lang, &Subject)> {
         Message::get_best::<Subject>(&self.subjects, preferred_langs)
     }
-}
 
-impl TryFrom<Element> for Message {
-    type Error = Error;
+    /// Returns whether this message changes the subject of a multi-user chat
+    /// room (XEP-0045), that is a groupchat message with a subject but no
+    /// body.
+    ///
+    /// This includes the case of an empty subject, which means the room has
+    /// no subject anymore.
+    pub fn is_subject_change(&self) -> bool {
+        self.type_ == MessageType::Groupchat && !self.subjects.is_empty() && self.bodies.is_empty()
+    }
 
-    fn try_from(root: Element) -> Result<Message, Error> {
+    /// Parses a message, storing bodies and subjects without an explicit
+    /// xml:lang under the one inherited from the stanza or, failing that,
+    /// from the stream.
+    ///
+    /// The `TryFrom<Element>` implementation is equivalent to passing an
+    /// empty `default_lang`.
+    pub fn parse_with_lang(root: Element, default_lang: &str) -> Result<Message, Error> {
+        Message::parse_impl(&root, default_lang, false).map(|(message, _)| message)
+    }
+
+    /// Parses a message like `parse_with_lang()`, but recovers from a
+    /// duplicate body or subject for the same xml:lang, or a duplicate
+    /// thread, by ignoring the offending element.
+    ///
+    /// The errors recovered from are returned alongside the message, so
+    /// that e.g. an archiving tool can log them.
+    pub fn parse_lenient(
+        root: Element,
+        default_lang: &str,
+    ) -> Result<(Message, Vec<Error>), Error> {
+        Message::parse_impl(&root, default_lang, true)
+    }
+
+    fn parse_impl(
+        root: &Element,
+        default_lang: &str,
+        lenient: bool,
+    ) -> Result<(Message, Vec<Error>), Error> {
+        if let Some(root) = to_default_ns(root) {
+            return Message::parse_impl(&root, default_lang, lenient);
+        }
         check_self!(root, "message", DEFAULT_NS);
-        let from = get_attr!(root, "from", Option);
-        let to = get_attr!(root, "to", Option);
+        let lang = get_attr!(root, "xml:lang", Option);
+        let default_lang = root.attr("xml:lang").unwrap_or(default_lang);
+        let mut warnings = vec![];
+        let mut recover = |error: Error| {
+            if lenient {
+                warnings.push(error);
+                Ok(())
+            } else {
+                Err(error)
+            }
+        };
+        let from = get_jid_attr!(root, "from", "message");
+        let to = get_jid_attr!(root, "to", "message");
         let id = get_attr!(root, "id", Option);
         let type_ = get_attr!(root, "type", Default);
         let mut bodies = BTreeMap::new();
@@ -223,74 +335,81 @@ impl TryFrom<Element> for Message {
         for elem in root.children() {
             if elem.is("body", ns::DEFAULT_NS) {
                 check_no_children!(elem, "body");
-                let lang = get_attr!(elem, "xml:lang", Default);
-                let body = Body(elem.text());
-                if bodies.insert(lang, body).is_some() {
-                    return Err(Error::ParseError(
-                        "Body element present twice for the same xml:lang.",
-                    ));
+                let lang = elem.attr("xml:lang").unwrap_or(default_lang).to_owned();
+                if bodies.contains_key(&lang) {
+                    recover(Error::DuplicateElement { name: "body" })?;
+                    continue;
                 }
+                bodies.insert(lang, Body(elem.text()));
             } else if elem.is("subject", ns::DEFAULT_NS) {
                 check_no_children!(elem, "subject");
-                let lang = get_attr!(elem, "xml:lang", Default);
-                let subject = Subject(elem.text());
-                if subjects.insert(lang, subject).is_some() {
-                    return Err(Error::ParseError(
-                        "Subject element present twice for the same xml:lang.",
-                    ));
+                let lang = elem.attr("xml:lang").unwrap_or(default_lang).to_owned();
+                if subjects.contains_key(&lang) {
+                    recover(Error::DuplicateElement { name: "subject" })?;
+                    continue;
                 }
+                subjects.insert(lang, Subject(elem.text()));
             } else if elem.is("thread", ns::DEFAULT_NS) {
                 if thread.is_some() {
-                    return Err(Error::ParseError("Thread element present twice."));
+                    recover(Error::DuplicateElement { name: "thread" })?;
+                    continue;
                 }
-                check_no_children!(elem, "thread");
-                thread = Some(Thread(elem.text()));
+                thread = Some(Thread::try_from(elem.clone())?);
             } else {
                 payloads.push(elem.clone())
             }
         }
-        Ok(Message {
+        let message = Message {
             from,
             to,
             id,
             type_,
+            lang,
             bodies,
             subjects,
             thread,
             payloads,
-        })
+        };
+        Ok((message, warnings))
+    }
+}
+
+impl TryFrom<Element> for Message {
+    type Error = Error;
+
+    fn try_from(root: Element) -> Result<Message, Error> {
+        Message::parse_with_lang(root, "")
+    }
+}
+
+impl TryFrom<&Element> for Message {
+    type Error = Error;
+
+    fn try_from(root: &Element) -> Result<Message, Error> {
+        Message::parse_impl(root, "", false).map(|(message, _)| message)
     }
 }
 
 impl From<Message> for Element {
     fn from(message: Message) -> Element {
+        let stanza_lang = message.lang.as_deref();
         Element::builder("message", ns::DEFAULT_NS)
             .attr("from", message.from)
             .attr("to", message.to)
             .attr("id", message.id)
             .attr("type", message.type_)
+            .attr("xml:lang", stanza_lang)
             .append_all(message.subjects.into_iter().map(|(lang, subject)| {
                 let mut subject = Element::from(subject);
-                subject.set_attr(
-                    "xml:lang",
-                    match lang.as_ref() {
-                        "" => None,
-                        lang => Some(lang),
-                    },
-                );
+                subject.set_attr("xml:lang", child_lang(&lang, stanza_lang));
                 subject
             }))
             .append_all(message.bodies.into_iter().map(|(lang, body)| {
                 let mut body = Element::from(body);
-                body.set_attr(
-                    "xml:lang",
-                    match lang.as_ref() {
-                        "" => None,
-                        lang => Some(lang),
-                    },
-                );
+                body.set_attr("xml:lang", child_lang(&lang, stanza_lang));
                 body
             }))
+            .append_all(message.thread)
             .append_all(message.payloads.into_iter())
             .build()
     }
@@ -307,8 +426,8 @@ mod tests {
         assert_size!(MessageType, 1);
         assert_size!(Body, 12);
         assert_size!(Subject, 12);
-        assert_size!(Thread, 12);
-        assert_size!(Message, 104);
+        assert_size!(Thread, 24);
+        assert_size!(Message, 128);
     }
 
     #[cfg(target_pointer_width = "64")]
@@ -317,8 +436,8 @@ mod tests {
         assert_size!(MessageType, 1);
         assert_size!(Body, 24);
         assert_size!(Subject, 24);
-        assert_size!(Thread, 24);
-        assert_size!(Message, 208);
+        assert_size!(Thread, 48);
+        assert_size!(Message, 256);
     }
 
     #[test]
@@ -337,6 +456,16 @@ mod tests {
         assert!(message.payloads.is_empty());
     }
 
+    #[test]
+    fn test_borrowed() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client' type='chat'><body>Hello</body><active xmlns='http://jabber.org/protocol/chatstates'/></message>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept' type='chat'><body>Hello</body><active xmlns='http://jabber.org/protocol/chatstates'/></message>".parse().unwrap();
+        let message = Message::try_from(&elem).unwrap();
+        assert_eq!(message, Message::try_from(elem).unwrap());
+    }
+
     #[test]
     fn test_serialise() {
         #[cfg(not(feature = "component"))]
@@ -403,11 +532,45 @@ mod tests {
             assert_eq!(lang, "");
             assert_eq!(subject, &Subject::from_str("Hello world!").unwrap());
         }
+        assert!(!message.is_subject_change());
 
         let elem2 = message.into();
         assert_eq!(elem1, elem2);
     }
 
+    #[test]
+    fn test_subject_change() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client' from='coven@chat.shakespeare.lit/secondwitch' type='groupchat'><subject>Fire Burn and Cauldron Bubble!</subject></message>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept' from='coven@chat.shakespeare.lit/secondwitch' type='groupchat'><subject>Fire Burn and Cauldron Bubble!</subject></message>".parse().unwrap();
+        let message = Message::try_from(elem).unwrap();
+        assert!(message.is_subject_change());
+        let (_, subject) = message.get_best_subject(vec![]).unwrap();
+        assert_eq!(subject.0, "Fire Burn and Cauldron Bubble!");
+
+        // An empty subject clears it.
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client' from='coven@chat.shakespeare.lit/secondwitch' type='groupchat'><subject/></message>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept' from='coven@chat.shakespeare.lit/secondwitch' type='groupchat'><subject/></message>".parse().unwrap();
+        let message = Message::try_from(elem).unwrap();
+        assert!(message.is_subject_change());
+        let (_, subject) = message.get_best_subject(vec![]).unwrap();
+        assert_eq!(subject.0, "");
+
+        // A message with a body isn’t one, even with a subject.
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client' from='coven@chat.shakespeare.lit/secondwitch' type='groupchat'><subject>Topic</subject><body>Hi!</body></message>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept' from='coven@chat.shakespeare.lit/secondwitch' type='groupchat'><subject>Topic</subject><body>Hi!</body></message>".parse().unwrap();
+        let message = Message::try_from(elem).unwrap();
+        assert!(!message.is_subject_change());
+
+        let message = Message::groupchat(None);
+        assert!(!message.is_subject_change());
+    }
+
     #[test]
     fn get_best_body() {
         #[cfg(not(feature = "component"))]
@@ -450,6 +613,111 @@ mod tests {
         assert_eq!(message.get_best_body(vec!("ja")), None);
     }
 
+    #[test]
+    fn test_body_default_lang() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client' to='coucou@example.org' type='chat'><subject>Greeting</subject><body xml:lang='fr'>Salut le monde !</body><body>Hello world!</body></message>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept' to='coucou@example.org' type='chat'><subject>Greeting</subject><body xml:lang='fr'>Salut le monde !</body><body>Hello world!</body></message>".parse().unwrap();
+        let message = Message::parse_with_lang(elem, "en").unwrap();
+        assert_eq!(message.bodies.len(), 2);
+        assert_eq!(
+            message.bodies["en"],
+            Body::from_str("Hello world!").unwrap()
+        );
+        assert_eq!(
+            message.bodies["fr"],
+            Body::from_str("Salut le monde !").unwrap()
+        );
+        assert_eq!(
+            message.subjects["en"],
+            Subject::from_str("Greeting").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stanza_lang() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client' xml:lang='de'><subject>Gruß</subject><body>Hallo Welt!</body><body xml:lang='fr'>Salut le monde !</body></message>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept' xml:lang='de'><subject>Gruß</subject><body>Hallo Welt!</body><body xml:lang='fr'>Salut le monde !</body></message>".parse().unwrap();
+        let message = Message::parse_with_lang(elem, "en").unwrap();
+        assert_eq!(message.lang.as_deref(), Some("de"));
+        assert_eq!(message.bodies["de"], Body::from_str("Hallo Welt!").unwrap());
+        assert_eq!(message.subjects["de"], Subject::from_str("Gruß").unwrap());
+
+        // Only the children in another language keep their own xml:lang.
+        let elem = Element::from(message.clone());
+        assert_eq!(elem.attr("xml:lang"), Some("de"));
+        let langs: Vec<_> = elem
+            .children()
+            .map(|child| child.attr("xml:lang"))
+            .collect();
+        assert_eq!(langs, [None, None, Some("fr")]);
+        assert_eq!(Message::try_from(elem).unwrap(), message);
+    }
+
+    #[test]
+    fn test_lenient() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client'><body>Hello</body><body>world!</body><thread>a</thread><thread>b</thread></message>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept'><body>Hello</body><body>world!</body><thread>a</thread><thread>b</thread></message>".parse().unwrap();
+        let error = Message::try_from(elem.clone()).unwrap_err();
+        let name = match error {
+            Error::DuplicateElement { name } => name,
+            _ => panic!(),
+        };
+        assert_eq!(name, "body");
+
+        let (message, warnings) = Message::parse_lenient(elem, "").unwrap();
+        assert_eq!(message.bodies[""], Body::from_str("Hello").unwrap());
+        assert_eq!(message.thread, Some(Thread::new("a")));
+        let warnings: Vec<_> = warnings
+            .into_iter()
+            .map(|error| match error {
+                Error::DuplicateElement { name } => name,
+                _ => panic!(),
+            })
+            .collect();
+        assert_eq!(warnings, ["body", "thread"]);
+    }
+
+    #[test]
+    fn test_thread() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client' from='juliet@capulet.example/balcony' type='chat'><body>Art thou not Romeo, and a Montague?</body><thread parent='e0ffe42b28561960c6b12b944a092794b9683a38'>7edac73ab41e45c4aafa7b2d7b749080</thread></message>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept' from='juliet@capulet.example/balcony' type='chat'><body>Art thou not Romeo, and a Montague?</body><thread parent='e0ffe42b28561960c6b12b944a092794b9683a38'>7edac73ab41e45c4aafa7b2d7b749080</thread></message>".parse().unwrap();
+        let message = Message::try_from(elem.clone()).unwrap();
+        let thread = message.thread.clone().unwrap();
+        assert_eq!(thread.id, "7edac73ab41e45c4aafa7b2d7b749080");
+        assert_eq!(
+            thread.parent.as_deref(),
+            Some("e0ffe42b28561960c6b12b944a092794b9683a38")
+        );
+        let elem2: Element = message.clone().into();
+        assert_eq!(elem, elem2);
+
+        let reply = message.reply();
+        assert_eq!(
+            reply.to,
+            Some(Jid::from_str("juliet@capulet.example/balcony").unwrap())
+        );
+        assert_eq!(reply.type_, MessageType::Chat);
+        assert_eq!(reply.thread, Some(thread.clone()));
+
+        let child = thread.new_child("e4b2b6e4c7a4e8f1");
+        assert_eq!(child.parent.as_deref(), Some(thread.id.as_str()));
+        let elem: Element = Message::new(None).with_thread(child).into();
+        let thread = elem.get_child("thread", ns::DEFAULT_NS).unwrap();
+        assert_eq!(thread.text(), "e4b2b6e4c7a4e8f1");
+        assert_eq!(
+            thread.attr("parent"),
+            Some("7edac73ab41e45c4aafa7b2d7b749080")
+        );
+    }
+
     #[test]
     fn test_attention() {
         #[cfg(not(feature = "component"))]
@@ -461,4 +729,37 @@ mod tests {
         let elem2 = message.into();
         assert_eq!(elem1, elem2);
     }
+
+    #[test]
+    fn test_invalid_from_jid() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client' from='@example.org'/>"
+            .parse()
+            .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept' from='@example.org'/>"
+            .parse()
+            .unwrap();
+        let error = Message::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Invalid 'from' JID in message.");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client' to='coucou@example.org' type='chat'><body xml:lang='fr'>Salut !</body><thread>coucou</thread><attention xmlns='urn:xmpp:attention:0'/></message>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept' to='coucou@example.org' type='chat'><body xml:lang='fr'>Salut !</body><thread>coucou</thread><attention xmlns='urn:xmpp:attention:0'/></message>".parse().unwrap();
+        let message = Message::try_from(elem).unwrap();
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.contains("\"to\":\"coucou@example.org\""));
+        assert!(json.contains("<attention xmlns='urn:xmpp:attention:0'/>"));
+        let message2: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(message, message2);
+    }
 }
@@ -52,11 +52,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = Replace::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in replace element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in replace element"
+        );
     }
 
     #[test]
@@ -65,11 +64,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = Replace::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in replace element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in replace element"
+        );
     }
 
     #[test]
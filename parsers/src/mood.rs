@@ -4,6 +4,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::ns;
+use crate::pubsub::PubSubPayload;
+use crate::util::error::Error;
+use crate::Element;
+use std::convert::TryFrom;
+
 generate_element_enum!(
     /// Enum representing all of the possible values of the XEP-0107 moods.
     MoodEnum, "mood", MOOD, {
@@ -268,15 +274,72 @@ generate_elem_id!(
     MOOD
 );
 
+/// The mood of the user, as published over PEP.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Mood {
+    /// The mood value, absent when the user stops publishing their mood.
+    pub value: Option<MoodEnum>,
+
+    /// Free-form text description of the mood.
+    pub text: Option<Text>,
+}
+
+impl PubSubPayload for Mood {}
+
+impl Mood {
+    /// Whether this is the empty `<mood/>` a user publishes to stop
+    /// publishing their mood, after which the mood previously received should
+    /// no longer be displayed.
+    pub fn is_empty(&self) -> bool {
+        self.value.is_none() && self.text.is_none()
+    }
+}
+
+impl TryFrom<Element> for Mood {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Mood, Error> {
+        check_self!(elem, "mood", MOOD);
+        check_no_attributes!(elem, "mood");
+
+        let mut mood = Mood::default();
+        for child in elem.children() {
+            if child.is("text", ns::MOOD) {
+                if mood.text.is_some() {
+                    return Err(Error::ParseError("Mood can’t have more than one text."));
+                }
+                mood.text = Some(Text::try_from(child.clone())?);
+            } else if child.has_ns(ns::MOOD) {
+                if mood.value.is_some() {
+                    return Err(Error::ParseError("Mood can’t have more than one value."));
+                }
+                mood.value = Some(MoodEnum::try_from(child.clone())?);
+            } else {
+                return Err(Error::ParseError("Unknown element in User Mood."));
+            }
+        }
+
+        Ok(mood)
+    }
+}
+
+impl From<Mood> for Element {
+    fn from(mood: Mood) -> Element {
+        Element::builder("mood", ns::MOOD)
+            .append_all(mood.value)
+            .append_all(mood.text)
+            .build()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Element;
-    use std::convert::TryFrom;
 
     #[cfg(target_pointer_width = "32")]
     #[test]
     fn test_size() {
+        assert_size!(Mood, 16);
         assert_size!(MoodEnum, 1);
         assert_size!(Text, 12);
     }
@@ -284,6 +347,7 @@ mod tests {
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn test_size() {
+        assert_size!(Mood, 32);
         assert_size!(MoodEnum, 1);
         assert_size!(Text, 24);
     }
@@ -309,4 +373,40 @@ mod tests {
         let elem3 = text.into();
         assert_eq!(elem2, elem3);
     }
+
+    #[test]
+    fn test_mood() {
+        let elem: Element =
+            "<mood xmlns='http://jabber.org/protocol/mood'><happy/><text>Yay!</text></mood>"
+                .parse()
+                .unwrap();
+        let mood = Mood::try_from(elem.clone()).unwrap();
+        assert_eq!(mood.value, Some(MoodEnum::Happy));
+        assert_eq!(mood.text, Some(Text(String::from("Yay!"))));
+        assert!(!mood.is_empty());
+        assert_eq!(Element::from(mood), elem);
+    }
+
+    #[test]
+    fn test_retraction() {
+        let elem: Element = "<mood xmlns='http://jabber.org/protocol/mood'/>"
+            .parse()
+            .unwrap();
+        let mood = Mood::try_from(elem.clone()).unwrap();
+        assert!(mood.is_empty());
+        assert_eq!(Element::from(Mood::default()), elem);
+    }
+
+    #[test]
+    fn test_two_values() {
+        let elem: Element = "<mood xmlns='http://jabber.org/protocol/mood'><happy/><sad/></mood>"
+            .parse()
+            .unwrap();
+        let error = Mood::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Mood can’t have more than one value.");
+    }
 }
@@ -0,0 +1,228 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::iq::{IqGetPayload, IqResultPayload, IqSetPayload};
+use crate::muc::user::{Affiliation, Role};
+use crate::ns;
+use crate::util::error::Error;
+use crate::Element;
+use jid::BareJid;
+use std::convert::TryFrom;
+
+/// An item of a muc#admin query.
+///
+/// A role is tied to an occupant, and thus changed by nickname, while an
+/// affiliation is tied to a user, and thus changed by bare JID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    /// The new affiliation of this user, or the one to list.
+    pub affiliation: Option<Affiliation>,
+
+    /// The bare JID of the user whose affiliation changes.
+    pub jid: Option<BareJid>,
+
+    /// The nickname of the occupant whose role changes.
+    pub nick: Option<String>,
+
+    /// The new role of this occupant, or the one to list.
+    pub role: Option<Role>,
+
+    /// Why this change got made.
+    pub reason: Option<String>,
+}
+
+impl Item {
+    /// Creates an item changing the role of the occupant with this
+    /// nickname, e.g. to `Role::None` to kick them.
+    pub fn role<S: Into<String>>(nick: S, role: Role) -> Item {
+        Item {
+            affiliation: None,
+            jid: None,
+            nick: Some(nick.into()),
+            role: Some(role),
+            reason: None,
+        }
+    }
+
+    /// Creates an item changing the affiliation of this user, e.g. to
+    /// `Affiliation::Outcast` to ban them.
+    pub fn affiliation(jid: BareJid, affiliation: Affiliation) -> Item {
+        Item {
+            affiliation: Some(affiliation),
+            jid: Some(jid),
+            nick: None,
+            role: None,
+            reason: None,
+        }
+    }
+
+    /// Sets the reason for this change.
+    pub fn with_reason<S: Into<String>>(mut self, reason: S) -> Item {
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+// The generated attributes don’t serialise their default value, which is
+// meaningful here.
+fn affiliation_to_str(affiliation: &Affiliation) -> &'static str {
+    match affiliation {
+        Affiliation::Owner => "owner",
+        Affiliation::Admin => "admin",
+        Affiliation::Member => "member",
+        Affiliation::Outcast => "outcast",
+        Affiliation::None => "none",
+    }
+}
+
+fn role_to_str(role: &Role) -> &'static str {
+    match role {
+        Role::Moderator => "moderator",
+        Role::Participant => "participant",
+        Role::Visitor => "visitor",
+        Role::None => "none",
+    }
+}
+
+impl TryFrom<Element> for Item {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Item, Error> {
+        check_self!(elem, "item", MUC_ADMIN);
+        check_no_unknown_attributes!(elem, "item", ["affiliation", "jid", "nick", "role"]);
+        let mut reason = None;
+        for child in elem.children() {
+            if child.is("reason", ns::MUC_ADMIN) {
+                if reason.is_some() {
+                    return Err(Error::ParseError("More than one reason in item element."));
+                }
+                check_no_attributes!(child, "reason");
+                check_no_children!(child, "reason");
+                reason = Some(child.text());
+            } else {
+                return Err(Error::UnknownChild {
+                    element: "item",
+                    child: child.name().to_owned(),
+                });
+            }
+        }
+        Ok(Item {
+            affiliation: get_attr!(elem, "affiliation", Option),
+            jid: get_attr!(elem, "jid", Option),
+            nick: get_attr!(elem, "nick", Option),
+            role: get_attr!(elem, "role", Option),
+            reason,
+        })
+    }
+}
+
+impl From<Item> for Element {
+    fn from(item: Item) -> Element {
+        Element::builder("item", ns::MUC_ADMIN)
+            .attr(
+                "affiliation",
+                item.affiliation.as_ref().map(affiliation_to_str),
+            )
+            .attr("jid", item.jid)
+            .attr("nick", item.nick)
+            .attr("role", item.role.as_ref().map(role_to_str))
+            .append_all(
+                item.reason
+                    .map(|reason| Element::builder("reason", ns::MUC_ADMIN).append(reason)),
+            )
+            .build()
+    }
+}
+
+generate_element!(
+    /// The muc#admin query, used by moderators to change the role of
+    /// occupants, and by admins to change or list the affiliations of users.
+    MucAdmin, "query", MUC_ADMIN,
+    children: [
+        /// The changes to make, or the users to list.
+        items: Vec<Item> = ("item", MUC_ADMIN) => Item
+    ]
+);
+
+impl MucAdmin {
+    /// Creates a query with a single item.
+    pub fn new(item: Item) -> MucAdmin {
+        MucAdmin { items: vec![item] }
+    }
+}
+
+impl IqGetPayload for MucAdmin {}
+impl IqSetPayload for MucAdmin {}
+impl IqResultPayload for MucAdmin {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Item, 44);
+        assert_size!(MucAdmin, 12);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Item, 88);
+        assert_size!(MucAdmin, 24);
+    }
+
+    #[test]
+    fn test_kick() {
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/muc#admin'>
+            <item nick='pistol' role='none'><reason>Avaunt, you cullion!</reason></item>
+        </query>"
+            .parse()
+            .unwrap();
+        let admin = MucAdmin::try_from(elem).unwrap();
+        assert_eq!(
+            admin.items,
+            [Item::role("pistol", Role::None).with_reason("Avaunt, you cullion!")]
+        );
+
+        // The none role has to be kept when serialising.
+        let elem: Element = MucAdmin::new(Item::role("pistol", Role::None)).into();
+        let item = elem.get_child("item", ns::MUC_ADMIN).unwrap();
+        assert_eq!(item.attr("role"), Some("none"));
+        assert_eq!(item.attr("nick"), Some("pistol"));
+        assert_eq!(item.attr("affiliation"), None);
+    }
+
+    #[test]
+    fn test_ban() {
+        let jid = BareJid::from_str("earlofcambridge@shakespeare.lit").unwrap();
+        let item = Item::affiliation(jid.clone(), Affiliation::Outcast).with_reason("Treason");
+        let elem: Element = MucAdmin::new(item.clone()).into();
+        let expected: Element = "<query xmlns='http://jabber.org/protocol/muc#admin'><item affiliation='outcast' jid='earlofcambridge@shakespeare.lit'><reason>Treason</reason></item></query>"
+            .parse()
+            .unwrap();
+        assert_eq!(elem, expected);
+        assert_eq!(MucAdmin::try_from(elem).unwrap().items, [item]);
+
+        let elem: Element = MucAdmin::new(Item::affiliation(jid, Affiliation::None)).into();
+        let item = elem.get_child("item", ns::MUC_ADMIN).unwrap();
+        assert_eq!(item.attr("affiliation"), Some("none"));
+    }
+
+    #[test]
+    fn test_invalid_child() {
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/muc#admin'>
+            <item nick='pistol' role='none'><coucou/></item>
+        </query>"
+            .parse()
+            .unwrap();
+        let error = MucAdmin::try_from(elem).unwrap_err();
+        assert_eq!(
+            error.unlocated().to_string(),
+            "unknown child <coucou/> in item element"
+        );
+    }
+}
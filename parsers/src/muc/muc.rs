@@ -6,6 +6,7 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::date::DateTime;
+use crate::ns;
 use crate::presence::PresencePayload;
 
 generate_element!(
@@ -70,7 +71,10 @@ generate_element!(
     ]
 );
 
-impl PresencePayload for Muc {}
+impl PresencePayload for Muc {
+    const NAME: &'static str = "x";
+    const NS: &'static str = ns::MUC;
+}
 
 impl Muc {
     /// Create a new MUC join element.
@@ -94,7 +98,6 @@ impl Muc {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::error::Error;
     use crate::Element;
     use std::convert::TryFrom;
     use std::str::FromStr;
@@ -113,11 +116,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Muc::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in x element.");
+        assert_eq!(error.to_string(), "unknown child <coucou/> in x element");
     }
 
     #[test]
@@ -140,11 +139,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Muc::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in x element.");
+        assert_eq!(error.to_string(), "unknown attribute 'coucou' in x element");
     }
 
     #[test]
@@ -0,0 +1,307 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::data_forms::{DataForm, DataFormBuilder, DataFormType, Field, FieldType};
+use crate::iq::{IqGetPayload, IqResultPayload, IqSetPayload};
+use crate::ns;
+use crate::util::error::Error;
+use std::convert::TryFrom;
+
+generate_element!(
+    /// The muc#owner query, used by room owners to retrieve and submit the
+    /// configuration form of a room.
+    MucOwner, "query", MUC_OWNER,
+    children: [
+        /// The configuration form, absent from the initial request.
+        form: Option<DataForm> = ("x", DATA_FORMS) => DataForm
+    ]
+);
+
+impl MucOwner {
+    /// Creates a query without a form, to request the configuration form.
+    pub fn new() -> MucOwner {
+        MucOwner { form: None }
+    }
+
+    /// Creates a query submitting the given form.
+    pub fn with_form(form: DataForm) -> MucOwner {
+        MucOwner { form: Some(form) }
+    }
+}
+
+impl Default for MucOwner {
+    fn default() -> MucOwner {
+        MucOwner::new()
+    }
+}
+
+impl IqGetPayload for MucOwner {}
+impl IqSetPayload for MucOwner {}
+impl IqResultPayload for MucOwner {}
+
+generate_attribute!(
+    /// Who may discover the real JIDs of the occupants of a room.
+    WhoIs, "whois", {
+        /// Only the moderators.
+        Moderators => "moderators",
+
+        /// Every occupant.
+        Anyone => "anyone",
+    }
+);
+
+/// Typed access to the well-known fields of a muc#roomconfig form, as
+/// received from the room.
+///
+/// Every field is kept, including the ones not exposed here, so that
+/// submitting the form back doesn’t reset the settings we don’t know about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoomConfig {
+    form: DataForm,
+}
+
+impl TryFrom<DataForm> for RoomConfig {
+    type Error = Error;
+
+    fn try_from(form: DataForm) -> Result<RoomConfig, Error> {
+        if form.type_ != DataFormType::Form && form.type_ != DataFormType::Submit {
+            return Err(Error::ParseError("Wrong type of form."));
+        }
+        if form.form_type.as_deref() != Some(ns::MUC_ROOMCONFIG) {
+            return Err(Error::ParseError("Wrong FORM_TYPE for form."));
+        }
+        Ok(RoomConfig { form })
+    }
+}
+
+impl RoomConfig {
+    /// The underlying form.
+    pub fn form(&self) -> &DataForm {
+        &self.form
+    }
+
+    /// Produces the type="submit" form to send back to the room, keeping
+    /// the values of every field.
+    pub fn into_submit(self) -> DataForm {
+        match DataFormBuilder::new(self.form.clone()) {
+            Ok(builder) => builder.build(),
+            // Already a submit form.
+            Err(_) => self.form,
+        }
+    }
+
+    /// The value of the given field, for single-valued fields.
+    pub fn value(&self, var: &str) -> Option<&str> {
+        self.form
+            .fields
+            .iter()
+            .find(|field| field.var == var)
+            .and_then(|field| field.values.first())
+            .map(String::as_str)
+    }
+
+    fn bool_value(&self, var: &str) -> Option<bool> {
+        match self.value(var)? {
+            "1" | "true" => Some(true),
+            "0" | "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Sets the value of the given field, adding it with the given type if
+    /// the room didn’t include it.
+    pub fn set_value(&mut self, var: &str, type_: FieldType, value: &str) {
+        match self.form.fields.iter_mut().find(|field| field.var == var) {
+            Some(field) => field.values = vec![String::from(value)],
+            None => self
+                .form
+                .fields
+                .push(Field::new(var, type_).with_value(value)),
+        }
+    }
+
+    fn set_bool(&mut self, var: &str, value: bool) {
+        self.set_value(var, FieldType::Boolean, if value { "1" } else { "0" });
+    }
+
+    /// The natural-language name of the room (muc#roomconfig_roomname).
+    pub fn name(&self) -> Option<&str> {
+        self.value("muc#roomconfig_roomname")
+    }
+
+    /// Sets the natural-language name of the room.
+    pub fn set_name(&mut self, name: &str) {
+        self.set_value("muc#roomconfig_roomname", FieldType::TextSingle, name);
+    }
+
+    /// The short description of the room (muc#roomconfig_roomdesc).
+    pub fn description(&self) -> Option<&str> {
+        self.value("muc#roomconfig_roomdesc")
+    }
+
+    /// Sets the short description of the room.
+    pub fn set_description(&mut self, description: &str) {
+        self.set_value(
+            "muc#roomconfig_roomdesc",
+            FieldType::TextSingle,
+            description,
+        );
+    }
+
+    /// Whether the room survives its last occupant leaving
+    /// (muc#roomconfig_persistentroom).
+    pub fn persistent(&self) -> Option<bool> {
+        self.bool_value("muc#roomconfig_persistentroom")
+    }
+
+    /// Sets whether the room survives its last occupant leaving.
+    pub fn set_persistent(&mut self, persistent: bool) {
+        self.set_bool("muc#roomconfig_persistentroom", persistent);
+    }
+
+    /// Whether the room is listed in the directory
+    /// (muc#roomconfig_publicroom).
+    pub fn public(&self) -> Option<bool> {
+        self.bool_value("muc#roomconfig_publicroom")
+    }
+
+    /// Sets whether the room is listed in the directory.
+    pub fn set_public(&mut self, public: bool) {
+        self.set_bool("muc#roomconfig_publicroom", public);
+    }
+
+    /// Whether only members may join the room (muc#roomconfig_membersonly).
+    pub fn members_only(&self) -> Option<bool> {
+        self.bool_value("muc#roomconfig_membersonly")
+    }
+
+    /// Sets whether only members may join the room.
+    pub fn set_members_only(&mut self, members_only: bool) {
+        self.set_bool("muc#roomconfig_membersonly", members_only);
+    }
+
+    /// Whether only occupants with voice may speak
+    /// (muc#roomconfig_moderatedroom).
+    pub fn moderated(&self) -> Option<bool> {
+        self.bool_value("muc#roomconfig_moderatedroom")
+    }
+
+    /// Sets whether only occupants with voice may speak.
+    pub fn set_moderated(&mut self, moderated: bool) {
+        self.set_bool("muc#roomconfig_moderatedroom", moderated);
+    }
+
+    /// Whether a password is required to join the room
+    /// (muc#roomconfig_passwordprotectedroom).
+    pub fn password_protected(&self) -> Option<bool> {
+        self.bool_value("muc#roomconfig_passwordprotectedroom")
+    }
+
+    /// Sets the password required to join the room, or removes the need
+    /// for one if `None`.
+    pub fn set_password(&mut self, password: Option<&str>) {
+        self.set_bool("muc#roomconfig_passwordprotectedroom", password.is_some());
+        self.set_value(
+            "muc#roomconfig_roomsecret",
+            FieldType::TextPrivate,
+            password.unwrap_or(""),
+        );
+    }
+
+    /// Who may discover the real JIDs of the occupants
+    /// (muc#roomconfig_whois).
+    pub fn whois(&self) -> Option<WhoIs> {
+        self.value("muc#roomconfig_whois")?.parse().ok()
+    }
+
+    /// Sets who may discover the real JIDs of the occupants.
+    pub fn set_whois(&mut self, whois: WhoIs) {
+        self.set_value(
+            "muc#roomconfig_whois",
+            FieldType::ListSingle,
+            &whois.to_string(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Element;
+
+    #[test]
+    fn test_request() {
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/muc#owner'/>"
+            .parse()
+            .unwrap();
+        let query = MucOwner::try_from(elem.clone()).unwrap();
+        assert_eq!(query.form, None);
+        assert_eq!(Element::from(MucOwner::new()), elem);
+    }
+
+    #[test]
+    fn test_room_config() {
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/muc#owner'>
+            <x xmlns='jabber:x:data' type='form'>
+                <title>Configuration for coven Room</title>
+                <field type='hidden' var='FORM_TYPE'>
+                    <value>http://jabber.org/protocol/muc#roomconfig</value>
+                </field>
+                <field label='Natural-Language Room Name' type='text-single' var='muc#roomconfig_roomname'>
+                    <value>A Dark Cave</value>
+                </field>
+                <field label='Make Room Persistent?' type='boolean' var='muc#roomconfig_persistentroom'>
+                    <value>0</value>
+                </field>
+                <field label='Make Room Members-Only?' type='boolean' var='muc#roomconfig_membersonly'>
+                    <value>false</value>
+                </field>
+                <field label='Who May Discover Real JIDs?' type='list-single' var='muc#roomconfig_whois'>
+                    <option label='Moderators Only'><value>moderators</value></option>
+                    <option label='Anyone'><value>anyone</value></option>
+                    <value>moderators</value>
+                </field>
+                <field type='text-single' var='x-example#custom'>
+                    <value>keep me</value>
+                </field>
+            </x>
+        </query>"
+            .parse()
+            .unwrap();
+        let query = MucOwner::try_from(elem).unwrap();
+        let mut config = RoomConfig::try_from(query.form.unwrap()).unwrap();
+        assert_eq!(config.name(), Some("A Dark Cave"));
+        assert_eq!(config.persistent(), Some(false));
+        assert_eq!(config.members_only(), Some(false));
+        assert_eq!(config.whois(), Some(WhoIs::Moderators));
+        assert_eq!(config.public(), None);
+
+        config.set_persistent(true);
+        config.set_whois(WhoIs::Anyone);
+        config.set_public(false);
+        let form = config.into_submit();
+        assert_eq!(form.type_, DataFormType::Submit);
+        assert_eq!(form.form_type.as_deref(), Some(ns::MUC_ROOMCONFIG));
+
+        let config = RoomConfig::try_from(form.clone()).unwrap();
+        assert_eq!(config.persistent(), Some(true));
+        assert_eq!(config.whois(), Some(WhoIs::Anyone));
+        assert_eq!(config.public(), Some(false));
+        assert_eq!(config.value("x-example#custom"), Some("keep me"));
+
+        let elem = Element::from(MucOwner::with_form(form));
+        MucOwner::try_from(elem).unwrap();
+    }
+
+    #[test]
+    fn test_wrong_form_type() {
+        let form = DataForm::new(DataFormType::Form, ns::SERVER_INFO, vec![]);
+        let error = RoomConfig::try_from(form).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Wrong FORM_TYPE for form.");
+    }
+}
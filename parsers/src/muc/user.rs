@@ -291,10 +291,47 @@ impl MucUser {
         self.items = items;
         self
     }
+
+    /// Whether this contains the given status code.
+    pub fn has_status(&self, status: Status) -> bool {
+        self.status.contains(&status)
+    }
+
+    /// Whether this presence is about ourselves (status 110).
+    pub fn is_self_presence(&self) -> bool {
+        self.has_status(Status::SelfPresence)
+    }
+
+    /// Whether the service assigned or modified our nick on join (status 210).
+    pub fn nick_assigned(&self) -> bool {
+        self.has_status(Status::AssignedNick)
+    }
+
+    /// Whether this occupant has been kicked from the room (status 307).
+    pub fn was_kicked(&self) -> bool {
+        self.has_status(Status::Kicked)
+    }
+
+    /// Whether this occupant has been banned from the room (status 301).
+    pub fn was_banned(&self) -> bool {
+        self.has_status(Status::Banned)
+    }
+
+    /// The new nick of this occupant, if this unavailable presence is sent
+    /// because of a nick change (status 303).
+    pub fn nick_changed_to(&self) -> Option<&str> {
+        if !self.has_status(Status::NewNick) {
+            return None;
+        }
+        self.items.iter().find_map(|item| item.nick.as_deref())
+    }
 }
 
 impl MessagePayload for MucUser {}
-impl PresencePayload for MucUser {}
+impl PresencePayload for MucUser {
+    const NAME: &'static str = "x";
+    const NS: &'static str = ns::MUC_USER;
+}
 
 #[cfg(test)]
 mod tests {
@@ -329,6 +366,62 @@ mod tests {
         assert_eq!(muc_user.items[0].role, Role::Moderator);
     }
 
+    #[test]
+    fn test_status_helpers_on_join() {
+        let elem: Element = "<x xmlns='http://jabber.org/protocol/muc#user'>
+                <item affiliation='member' role='participant'/>
+                <status code='100'/>
+                <status code='110'/>
+                <status code='210'/>
+            </x>"
+            .parse()
+            .unwrap();
+        let muc_user = MucUser::try_from(elem).unwrap();
+        assert!(muc_user.has_status(Status::NonAnonymousRoom));
+        assert!(muc_user.is_self_presence());
+        assert!(muc_user.nick_assigned());
+        assert!(!muc_user.was_kicked());
+        assert!(!muc_user.was_banned());
+        assert_eq!(muc_user.nick_changed_to(), None);
+    }
+
+    #[test]
+    fn test_status_helpers_on_leave() {
+        let elem: Element = "<x xmlns='http://jabber.org/protocol/muc#user'>
+                <item affiliation='member' nick='oldhag' role='participant'/>
+                <status code='303'/>
+                <status code='110'/>
+            </x>"
+            .parse()
+            .unwrap();
+        let muc_user = MucUser::try_from(elem).unwrap();
+        assert!(muc_user.is_self_presence());
+        assert_eq!(muc_user.nick_changed_to(), Some("oldhag"));
+
+        let elem: Element = "<x xmlns='http://jabber.org/protocol/muc#user'>
+                <item affiliation='none' role='none'/>
+                <status code='307'/>
+            </x>"
+            .parse()
+            .unwrap();
+        let muc_user = MucUser::try_from(elem).unwrap();
+        assert!(muc_user.was_kicked());
+        assert!(!muc_user.was_banned());
+        assert!(!muc_user.is_self_presence());
+
+        let elem: Element = "<x xmlns='http://jabber.org/protocol/muc#user'>
+                <item affiliation='outcast' role='none'/>
+                <status code='301'/>
+                <status code='110'/>
+            </x>"
+            .parse()
+            .unwrap();
+        let muc_user = MucUser::try_from(elem).unwrap();
+        assert!(muc_user.was_banned());
+        assert!(!muc_user.was_kicked());
+        assert!(muc_user.is_self_presence());
+    }
+
     #[test]
     fn test_invalid_child() {
         let elem: Element = "<x xmlns='http://jabber.org/protocol/muc#user'>
@@ -337,11 +430,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = MucUser::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in x element.");
+        assert_eq!(error.to_string(), "unknown child <coucou/> in x element");
     }
 
     #[test]
@@ -364,11 +453,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = MucUser::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in x element.");
+        assert_eq!(error.to_string(), "unknown attribute 'coucou' in x element");
     }
 
     #[test]
@@ -401,11 +486,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Status::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in status element.");
+        assert_eq!(error.to_string(), "unknown child <foo/> in status element");
     }
 
     #[test]
@@ -524,11 +605,10 @@ mod tests {
             .parse()
             .unwrap();
         let continue_ = Continue::try_from(elem).unwrap_err();
-        let message = match continue_ {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in continue element.".to_owned());
+        assert_eq!(
+            continue_.to_string(),
+            "unknown child <foobar/> in continue element"
+        );
     }
 
     #[test]
@@ -551,11 +631,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = Reason::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in reason element.".to_owned());
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'foo' in reason element"
+        );
     }
 
     #[cfg(not(feature = "disable-validation"))]
@@ -567,11 +646,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = Reason::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in reason element.".to_owned());
+        assert_eq!(
+            error.to_string(),
+            "unknown child <foobar/> in reason element"
+        );
     }
 
     #[cfg(not(feature = "disable-validation"))]
@@ -582,11 +660,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Item::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in item element.".to_owned());
+        assert_eq!(error.to_string(), "unknown attribute 'foo' in item element");
     }
 
     #[test]
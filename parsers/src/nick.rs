@@ -14,8 +14,6 @@ generate_elem_id!(
 #[cfg(test)]
 mod tests {
     use super::*;
-    #[cfg(not(feature = "disable-validation"))]
-    use crate::util::error::Error;
     use crate::Element;
     use std::convert::TryFrom;
 
@@ -56,11 +54,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Nick::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in nick element.");
+        assert_eq!(error.to_string(), "unknown child <coucou/> in nick element");
     }
 
     #[cfg(not(feature = "disable-validation"))]
@@ -70,10 +64,9 @@ mod tests {
             .parse()
             .unwrap();
         let error = Nick::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in nick element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in nick element"
+        );
     }
 }
@@ -8,8 +8,12 @@
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub const JABBER_CLIENT: &str = "jabber:client";
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
+pub const JABBER_SERVER: &str = "jabber:server";
+/// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub const XMPP_STANZAS: &str = "urn:ietf:params:xml:ns:xmpp-stanzas";
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
+pub const XMPP_STREAMS: &str = "urn:ietf:params:xml:ns:xmpp-streams";
+/// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub const STREAM: &str = "http://etherx.jabber.org/streams";
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub const TLS: &str = "urn:ietf:params:xml:ns:xmpp-tls";
@@ -18,6 +22,9 @@ pub const SASL: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub const BIND: &str = "urn:ietf:params:xml:ns:xmpp-bind";
 
+/// RFC 3921: Extensible Messaging and Presence Protocol (XMPP): Instant Messaging and Presence
+pub const SESSION: &str = "urn:ietf:params:xml:ns:xmpp-session";
+
 /// RFC 6121: Extensible Messaging and Presence Protocol (XMPP): Instant Messaging and Presence
 pub const ROSTER: &str = "jabber:iq:roster";
 
@@ -27,6 +34,9 @@ pub const WEBSOCKET: &str = "urn:ietf:params:xml:ns:xmpp-framing";
 /// XEP-0004: Data Forms
 pub const DATA_FORMS: &str = "jabber:x:data";
 
+/// XEP-0027: Current Jabber OpenPGP Usage
+pub const LEGACY_OPENPGP: &str = "jabber:x:encrypted";
+
 /// XEP-0030: Service Discovery
 pub const DISCO_INFO: &str = "http://jabber.org/protocol/disco#info";
 /// XEP-0030: Service Discovery
@@ -36,6 +46,12 @@ pub const DISCO_ITEMS: &str = "http://jabber.org/protocol/disco#items";
 pub const MUC: &str = "http://jabber.org/protocol/muc";
 /// XEP-0045: Multi-User Chat
 pub const MUC_USER: &str = "http://jabber.org/protocol/muc#user";
+/// XEP-0045: Multi-User Chat
+pub const MUC_OWNER: &str = "http://jabber.org/protocol/muc#owner";
+/// XEP-0045: Multi-User Chat
+pub const MUC_ADMIN: &str = "http://jabber.org/protocol/muc#admin";
+/// XEP-0045: Multi-User Chat
+pub const MUC_ROOMCONFIG: &str = "http://jabber.org/protocol/muc#roomconfig";
 
 /// XEP-0047: In-Band Bytestreams
 pub const IBB: &str = "http://jabber.org/protocol/ibb";
@@ -43,6 +59,9 @@ pub const IBB: &str = "http://jabber.org/protocol/ibb";
 /// XEP-0048: Bookmarks
 pub const BOOKMARKS: &str = "storage:bookmarks";
 
+/// XEP-0055: Jabber Search
+pub const SEARCH: &str = "jabber:iq:search";
+
 /// XEP-0059: Result Set Management
 pub const RSM: &str = "http://jabber.org/protocol/rsm";
 
@@ -64,6 +83,8 @@ pub const XHTML: &str = "http://www.w3.org/1999/xhtml";
 
 /// XEP-0077: In-Band Registration
 pub const REGISTER: &str = "jabber:iq:register";
+/// XEP-0077: In-Band Registration
+pub const REGISTER_FEATURE: &str = "http://jabber.org/features/iq-register";
 
 /// XEP-0084: User Avatar
 pub const AVATAR_DATA: &str = "urn:xmpp:avatar:data";
@@ -83,7 +104,7 @@ pub const MOOD: &str = "http://jabber.org/protocol/mood";
 pub const COMPONENT_ACCEPT: &str = "jabber:component:accept";
 
 /// XEP-0114: Jabber Component Protocol
-pub const COMPONENT: &str = "jabber:component:accept";
+pub const COMPONENT: &str = COMPONENT_ACCEPT;
 
 /// XEP-0115: Entity Capabilities
 pub const CAPS: &str = "http://jabber.org/protocol/caps";
@@ -91,6 +112,9 @@ pub const CAPS: &str = "http://jabber.org/protocol/caps";
 /// XEP-0118: User Tune
 pub const TUNE: &str = "http://jabber.org/protocol/tune";
 
+/// XEP-0131: Stanza Headers and Internet Metadata
+pub const SHIM: &str = "http://jabber.org/protocol/shim";
+
 /// XEP-0157: Contact Addresses for XMPP Services
 pub const SERVER_INFO: &str = "http://jabber.org/network/serverinfo";
 
@@ -207,6 +231,9 @@ pub const JINGLE_DTLS: &str = "urn:xmpp:jingle:apps:dtls:0";
 /// XEP-0328: JID Prep
 pub const JID_PREP: &str = "urn:xmpp:jidprep:0";
 
+/// XEP-0333: Chat Markers
+pub const CHAT_MARKERS: &str = "urn:xmpp:chat-markers:0";
+
 /// XEP-0338: Jingle Grouping Framework
 pub const JINGLE_GROUPING: &str = "urn:xmpp:jingle:apps:grouping:0";
 
@@ -225,6 +252,9 @@ pub const SID: &str = "urn:xmpp:sid:0";
 /// XEP-0363: HTTP File Upload
 pub const HTTP_UPLOAD: &str = "urn:xmpp:http:upload:0";
 
+/// XEP-0364: Current Off-the-Record Messaging Usage
+pub const OTR: &str = "urn:xmpp:otr:0";
+
 /// XEP-0369: Mediated Information eXchange (MIX)
 pub const MIX_CORE: &str = "urn:xmpp:mix:core:1";
 /// XEP-0369: Mediated Information eXchange (MIX)
@@ -256,6 +286,8 @@ pub const LEGACY_OMEMO: &str = "eu.siacs.conversations.axolotl";
 pub const LEGACY_OMEMO_DEVICELIST: &str = "eu.siacs.conversations.axolotl.devicelist";
 /// XEP-0384: OMEMO Encryption (experimental version 0.3.0)
 pub const LEGACY_OMEMO_BUNDLES: &str = "eu.siacs.conversations.axolotl.bundles";
+/// XEP-0384: OMEMO Encryption
+pub const OMEMO: &str = "urn:xmpp:omemo:1";
 
 /// XEP-0390: Entity Capabilities 2.0
 pub const ECAPS2: &str = "urn:xmpp:caps";
@@ -284,3 +316,150 @@ pub const DEFAULT_NS: &str = JABBER_CLIENT;
 /// "jabber:component:accept" when the component feature is enabled.
 #[cfg(feature = "component")]
 pub const DEFAULT_NS: &str = COMPONENT_ACCEPT;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Every namespace defined above, aliases excepted; naming a constant
+    /// which doesn’t exist fails to compile.
+    const ALL: &[&str] = &[
+        JABBER_CLIENT,
+        JABBER_SERVER,
+        XMPP_STANZAS,
+        XMPP_STREAMS,
+        STREAM,
+        TLS,
+        SASL,
+        BIND,
+        SESSION,
+        ROSTER,
+        WEBSOCKET,
+        DATA_FORMS,
+        LEGACY_OPENPGP,
+        DISCO_INFO,
+        DISCO_ITEMS,
+        MUC,
+        MUC_USER,
+        MUC_OWNER,
+        MUC_ADMIN,
+        MUC_ROOMCONFIG,
+        IBB,
+        BOOKMARKS,
+        SEARCH,
+        RSM,
+        PUBSUB,
+        PUBSUB_ERRORS,
+        PUBSUB_EVENT,
+        PUBSUB_OWNER,
+        PUBSUB_CONFIGURE,
+        XHTML_IM,
+        XHTML,
+        REGISTER,
+        REGISTER_FEATURE,
+        AVATAR_DATA,
+        AVATAR_METADATA,
+        CHATSTATES,
+        VERSION,
+        MOOD,
+        COMPONENT_ACCEPT,
+        CAPS,
+        TUNE,
+        SHIM,
+        SERVER_INFO,
+        JINGLE,
+        JINGLE_RTP,
+        JINGLE_RTP_AUDIO,
+        JINGLE_RTP_VIDEO,
+        NICK,
+        JINGLE_ICE_UDP,
+        JINGLE_RAW_UDP,
+        RECEIPTS,
+        BLOCKING,
+        BLOCKING_ERRORS,
+        SM,
+        PING,
+        TIME,
+        DELAY,
+        EXT_DISCO,
+        MEDIA_ELEMENT,
+        ATTENTION,
+        BOB,
+        JINGLE_FT,
+        JINGLE_FT_ERROR,
+        SASL_CERT,
+        JINGLE_S5B,
+        JINGLE_IBB,
+        MICROBLOG,
+        CARBONS,
+        JINGLE_RTCP_FB,
+        JINGLE_RTP_HDREXT,
+        FORWARD,
+        HASHES,
+        HASH_ALGO_SHA_256,
+        HASH_ALGO_SHA_512,
+        HASH_ALGO_SHA3_256,
+        HASH_ALGO_SHA3_512,
+        HASH_ALGO_BLAKE2B_256,
+        HASH_ALGO_BLAKE2B_512,
+        RTT,
+        MESSAGE_CORRECT,
+        MAM,
+        IDLE,
+        JINGLE_DTLS,
+        JID_PREP,
+        CHAT_MARKERS,
+        JINGLE_GROUPING,
+        JINGLE_SSMA,
+        CSI,
+        JINGLE_MESSAGE,
+        SID,
+        HTTP_UPLOAD,
+        OTR,
+        MIX_CORE,
+        MIX_CORE_SEARCHABLE,
+        MIX_CORE_CREATE_CHANNEL,
+        MIX_NODES_PRESENCE,
+        MIX_NODES_PARTICIPANTS,
+        MIX_NODES_MESSAGES,
+        MIX_NODES_CONFIG,
+        MIX_NODES_INFO,
+        OX,
+        OX_PUBKEYS,
+        EME,
+        LEGACY_OMEMO,
+        LEGACY_OMEMO_DEVICELIST,
+        LEGACY_OMEMO_BUNDLES,
+        OMEMO,
+        ECAPS2,
+        ECAPS2_OPTIMIZE,
+        BOOKMARKS2,
+        BOOKMARKS2_COMPAT,
+        BOOKMARKS2_COMPAT_PEP,
+        OID,
+        REACTIONS,
+    ];
+
+    #[test]
+    fn test_well_formed() {
+        for ns in ALL {
+            assert!(!ns.is_empty());
+            assert!(!ns.contains(char::is_whitespace), "{:?}", ns);
+        }
+    }
+
+    #[test]
+    fn test_unique() {
+        let mut seen = HashSet::new();
+        for ns in ALL {
+            assert!(seen.insert(ns), "{} is defined twice", ns);
+        }
+    }
+
+    #[test]
+    fn test_aliases() {
+        assert!(ALL.contains(&COMPONENT));
+        assert!(ALL.contains(&DEFAULT_NS));
+    }
+}
@@ -5,6 +5,7 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::message::MessagePayload;
+use crate::ns;
 use crate::presence::PresencePayload;
 
 generate_element!(
@@ -21,7 +22,10 @@ generate_element!(
 );
 
 impl MessagePayload for OccupantId {}
-impl PresencePayload for OccupantId {}
+impl PresencePayload for OccupantId {
+    const NAME: &'static str = "occupant-id";
+    const NS: &'static str = ns::OID;
+}
 
 #[cfg(test)]
 mod tests {
@@ -57,11 +61,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = OccupantId::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in occupant-id element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in occupant-id element"
+        );
     }
 
     #[test]
@@ -20,8 +20,6 @@ impl IqGetPayload for Ping {}
 #[cfg(test)]
 mod tests {
     use super::*;
-    #[cfg(not(feature = "disable-validation"))]
-    use crate::util::error::Error;
     use crate::Element;
     use std::convert::TryFrom;
 
@@ -50,11 +48,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Ping::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in ping element.");
+        assert_eq!(error.to_string(), "unknown child <coucou/> in ping element");
     }
 
     #[cfg(not(feature = "disable-validation"))]
@@ -62,10 +56,9 @@ mod tests {
     fn test_invalid_attribute() {
         let elem: Element = "<ping xmlns='urn:xmpp:ping' coucou=''/>".parse().unwrap();
         let error = Ping::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in ping element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in ping element"
+        );
     }
 }
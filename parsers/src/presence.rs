@@ -6,18 +6,29 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::ns;
+use crate::stanza::{is_valid_lang, to_default_ns};
 use crate::util::error::Error;
+use crate::util::helpers::child_lang;
 use jid::Jid;
 use minidom::{Element, IntoAttributeValue};
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::fmt;
+use std::num::IntErrorKind;
 use std::str::FromStr;
 
 /// Should be implemented on every known payload of a `<presence/>`.
-pub trait PresencePayload: TryFrom<Element> + Into<Element> {}
+pub trait PresencePayload: TryFrom<Element> + Into<Element> {
+    /// The name of the element of this payload.
+    const NAME: &'static str;
+
+    /// The namespace of the element of this payload.
+    const NS: &'static str;
+}
 
 /// Specifies the availability of an entity or resource.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Show {
     /// The entity or resource is temporarily away.
     Away,
@@ -48,15 +59,22 @@ impl FromStr for Show {
     }
 }
 
+impl Show {
+    /// The value of this show on the wire.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Show::Away => "away",
+            Show::Chat => "chat",
+            Show::Dnd => "dnd",
+            Show::Xa => "xa",
+        }
+    }
+}
+
 impl From<Show> for Element {
     fn from(show: Show) -> Element {
         Element::builder("show", ns::DEFAULT_NS)
-            .append(match show {
-                Show::Away => "away",
-                Show::Chat => "chat",
-                Show::Dnd => "dnd",
-                Show::Xa => "xa",
-            })
+            .append(show.as_str())
             .build()
     }
 }
@@ -64,10 +82,55 @@ impl From<Show> for Element {
 type Lang = String;
 type Status = String;
 
-type Priority = i8;
+/// The priority of a resource, from -128 to 127, used by the server to pick
+/// which resources receive the messages sent to the bare JID.
+///
+/// A negative priority means this resource never receives messages which
+/// haven’t been directed to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Priority(pub i8);
+
+impl FromStr for Priority {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Priority, Error> {
+        match i8::from_str(s) {
+            Ok(priority) => Ok(Priority(priority)),
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    IntErrorKind::PosOverflow | IntErrorKind::NegOverflow
+                ) =>
+            {
+                Err(Error::ParseError("Priority out of range -128..127."))
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl From<i8> for Priority {
+    fn from(priority: i8) -> Priority {
+        Priority(priority)
+    }
+}
+
+impl From<Priority> for i8 {
+    fn from(priority: Priority) -> i8 {
+        priority.0
+    }
+}
 
 ///
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     /// This value is not an acceptable 'type' attribute, it is only used
     /// internally to signal the absence of 'type'.
@@ -128,27 +191,35 @@ impl FromStr for Type {
     }
 }
 
+impl Type {
+    /// The value of the 'type' attribute for this type, or `None` when the
+    /// attribute is absent.
+    pub fn as_str(&self) -> Option<&'static str> {
+        match self {
+            Type::None => None,
+
+            Type::Error => Some("error"),
+            Type::Probe => Some("probe"),
+            Type::Subscribe => Some("subscribe"),
+            Type::Subscribed => Some("subscribed"),
+            Type::Unavailable => Some("unavailable"),
+            Type::Unsubscribe => Some("unsubscribe"),
+            Type::Unsubscribed => Some("unsubscribed"),
+        }
+    }
+}
+
 impl IntoAttributeValue for Type {
     fn into_attribute_value(self) -> Option<String> {
-        Some(
-            match self {
-                Type::None => return None,
-
-                Type::Error => "error",
-                Type::Probe => "probe",
-                Type::Subscribe => "subscribe",
-                Type::Subscribed => "subscribed",
-                Type::Unavailable => "unavailable",
-                Type::Unsubscribe => "unsubscribe",
-                Type::Unsubscribed => "unsubscribed",
-            }
-            .to_owned(),
-        )
+        self.as_str().map(String::from)
     }
 }
 
 /// The main structure representing the `<presence/>` stanza.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Two presences compare equal only if their payloads are in the same order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Presence {
     /// The sender of this presence.
     pub from: Option<Jid>,
@@ -162,6 +233,10 @@ pub struct Presence {
     /// The type of this presence stanza.
     pub type_: Type,
 
+    /// The xml:lang of this stanza, the language of the statuses without
+    /// their own.
+    pub lang: Option<String>,
+
     /// The availability of the sender of this presence.
     pub show: Option<Show>,
 
@@ -172,7 +247,11 @@ pub struct Presence {
     /// that haven’t been directed to it.
     pub priority: Priority,
 
-    /// A list of payloads contained in this presence.
+    /// A list of payloads contained in this presence, in document order.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::util::helpers::xml_string::vec")
+    )]
     pub payloads: Vec<Element>,
 }
 
@@ -184,9 +263,10 @@ impl Presence {
             to: None,
             id: None,
             type_,
+            lang: None,
             show: None,
             statuses: BTreeMap::new(),
-            priority: 0i8,
+            priority: Priority(0),
             payloads: vec![],
         }
     }
@@ -226,6 +306,31 @@ impl Presence {
         Self::new(Type::Unsubscribe)
     }
 
+    /// Builds a presence of type Unsubscribed
+    pub fn unsubscribed() -> Presence {
+        Self::new(Type::Unsubscribed)
+    }
+
+    /// Builds a presence of type Subscribe, addressed to `to`.
+    pub fn subscribe_to<J: Into<Jid>>(to: J) -> Presence {
+        Self::subscribe().with_to(to)
+    }
+
+    /// Builds a presence of type Subscribed, addressed to `to`.
+    pub fn subscribed_to<J: Into<Jid>>(to: J) -> Presence {
+        Self::subscribed().with_to(to)
+    }
+
+    /// Builds a presence of type Unsubscribe, addressed to `to`.
+    pub fn unsubscribe_to<J: Into<Jid>>(to: J) -> Presence {
+        Self::unsubscribe().with_to(to)
+    }
+
+    /// Builds a presence of type Unsubscribed, addressed to `to`.
+    pub fn unsubscribed_to<J: Into<Jid>>(to: J) -> Presence {
+        Self::unsubscribed().with_to(to)
+    }
+
     /// Set the emitter of this presence, this should only be useful for
     /// servers and components, as clients can only send presences from their
     /// own resource (which is implicit).
@@ -255,7 +360,7 @@ impl Presence {
 
     /// Set the priority of this presence.
     pub fn with_priority(mut self, priority: i8) -> Presence {
-        self.priority = priority;
+        self.priority = Priority(priority);
         self
     }
 
@@ -271,105 +376,237 @@ impl Presence {
         self
     }
 
-    /// Set the availability information of this presence.
-    pub fn set_status<L, S>(&mut self, lang: L, status: S)
+    /// Set the availability information of this presence, in the given
+    /// language, or in the default one if `lang` is empty.
+    ///
+    /// The language tag is trimmed and lowercased, and rejected if it
+    /// isn’t made of alphanumeric subtags separated by hyphens.
+    pub fn set_status<L, S>(&mut self, lang: L, status: S) -> Result<(), Error>
     where
         L: Into<Lang>,
         S: Into<Status>,
     {
-        self.statuses.insert(lang.into(), status.into());
+        let lang = normalize_lang(&lang.into())?;
+        self.statuses.insert(lang, status.into());
+        Ok(())
     }
 
     /// Add a payload to this presence.
     pub fn add_payload<P: PresencePayload>(&mut self, payload: P) {
         self.payloads.push(payload.into());
     }
+
+    /// Add a copy of a payload to this presence, keeping the typed value
+    /// around, e.g. to compare it with the next one we send.
+    ///
+    /// This clones the payload before serialising it; when it isn’t needed
+    /// afterwards, prefer [add_payload](#method.add_payload) which avoids
+    /// that copy.
+    pub fn add_payload_ref<P: PresencePayload + Clone>(&mut self, payload: &P) {
+        self.payloads.push(payload.clone().into());
+    }
+
+    /// Returns the first payload of this presence with the given name and
+    /// namespace, if any.
+    pub fn first_payload(&self, name: &str, ns: &str) -> Option<&Element> {
+        self.payloads.iter().find(|payload| payload.is(name, ns))
+    }
+
+    /// Parses the first payload of this presence matching the element of
+    /// `P`, if any.
+    pub fn extract_payload<P>(&self) -> Option<Result<P, Error>>
+    where
+        P: PresencePayload + TryFrom<Element, Error = Error>,
+    {
+        self.first_payload(P::NAME, P::NS)
+            .map(|payload| P::try_from(payload.clone()))
+    }
 }
 
-impl TryFrom<Element> for Presence {
-    type Error = Error;
+/// Trims and lowercases a language tag, checking that it looks like one.
+fn normalize_lang(lang: &str) -> Result<Lang, Error> {
+    let lang = lang.trim().to_ascii_lowercase();
+    if lang.is_empty() {
+        return Ok(lang);
+    }
+    if !is_valid_lang(&lang) {
+        return Err(Error::ParseError("Invalid language tag for status."));
+    }
+    Ok(lang)
+}
 
-    fn try_from(root: Element) -> Result<Presence, Error> {
+impl Presence {
+    /// Parses a presence, storing statuses without an explicit xml:lang
+    /// under the one inherited from the stanza or, failing that, from the
+    /// stream.
+    ///
+    /// The `TryFrom<Element>` implementation is equivalent to passing an
+    /// empty `default_lang`.
+    pub fn parse_with_lang(root: Element, default_lang: &str) -> Result<Presence, Error> {
+        Presence::parse_impl(&root, default_lang, false).map(|(presence, _)| presence)
+    }
+
+    /// Parses a presence like `parse_with_lang()`, but recovers from an
+    /// invalid or duplicate show or priority, and from a duplicate status
+    /// for the same xml:lang, by ignoring the offending element.
+    ///
+    /// The errors recovered from are returned alongside the presence, so
+    /// that e.g. an archiving tool can log them.
+    pub fn parse_lenient(
+        root: Element,
+        default_lang: &str,
+    ) -> Result<(Presence, Vec<Error>), Error> {
+        Presence::parse_impl(&root, default_lang, true)
+    }
+
+    fn parse_impl(
+        root: &Element,
+        default_lang: &str,
+        lenient: bool,
+    ) -> Result<(Presence, Vec<Error>), Error> {
+        if let Some(root) = to_default_ns(root) {
+            return Presence::parse_impl(&root, default_lang, lenient);
+        }
         check_self!(root, "presence", DEFAULT_NS);
-        let mut show = None;
+        let default_lang = root.attr("xml:lang").unwrap_or(default_lang);
+        let mut warnings = vec![];
+        let mut recover = |error: Error| {
+            if lenient {
+                warnings.push(error);
+                Ok(())
+            } else {
+                Err(error)
+            }
+        };
+        let mut show: Option<Show> = None;
         let mut priority = None;
         let mut presence = Presence {
-            from: get_attr!(root, "from", Option),
-            to: get_attr!(root, "to", Option),
+            from: get_jid_attr!(root, "from", "presence"),
+            to: get_jid_attr!(root, "to", "presence"),
             id: get_attr!(root, "id", Option),
             type_: get_attr!(root, "type", Default),
+            lang: get_attr!(root, "xml:lang", Option),
             show: None,
             statuses: BTreeMap::new(),
-            priority: 0i8,
+            priority: Priority(0),
             payloads: vec![],
         };
         for elem in root.children() {
             if elem.is("show", ns::DEFAULT_NS) {
-                if show.is_some() {
-                    return Err(Error::ParseError(
-                        "More than one show element in a presence.",
-                    ));
+                if let Some(ref first) = show {
+                    recover(Error::DuplicateValue {
+                        name: "show",
+                        first: first.as_str().to_owned(),
+                        second: elem.text(),
+                    })?;
+                    continue;
                 }
                 check_no_attributes!(elem, "show");
                 check_no_children!(elem, "show");
-                show = Some(Show::from_str(elem.text().as_ref())?);
+                match Show::from_str(elem.text().as_ref()) {
+                    Ok(value) => show = Some(value),
+                    Err(error) => recover(error)?,
+                }
             } else if elem.is("status", ns::DEFAULT_NS) {
                 check_no_unknown_attributes!(elem, "status", ["xml:lang"]);
                 check_no_children!(elem, "status");
-                let lang = get_attr!(elem, "xml:lang", Default);
-                if presence.statuses.insert(lang, elem.text()).is_some() {
-                    return Err(Error::ParseError(
-                        "Status element present twice for the same xml:lang.",
-                    ));
+                let lang = elem.attr("xml:lang").unwrap_or(default_lang).to_owned();
+                if presence.statuses.contains_key(&lang) {
+                    recover(Error::DuplicateElement { name: "status" })?;
+                    continue;
                 }
+                presence.statuses.insert(lang, elem.text());
             } else if elem.is("priority", ns::DEFAULT_NS) {
                 if priority.is_some() {
-                    return Err(Error::ParseError(
-                        "More than one priority element in a presence.",
-                    ));
+                    recover(Error::DuplicateElement { name: "priority" })?;
+                    continue;
                 }
                 check_no_attributes!(elem, "priority");
                 check_no_children!(elem, "priority");
-                priority = Some(Priority::from_str(elem.text().as_ref())?);
+                match Priority::from_str(&elem.text_trimmed()) {
+                    Ok(value) => priority = Some(value),
+                    Err(error) => recover(error)?,
+                }
             } else {
                 presence.payloads.push(elem.clone());
             }
         }
+        #[cfg(not(feature = "disable-validation"))]
+        {
+            let subscription = matches!(
+                presence.type_,
+                Type::Subscribe | Type::Subscribed | Type::Unsubscribe | Type::Unsubscribed
+            );
+            if subscription && show.is_some() {
+                recover(Error::ParseError(
+                    "Subscription presence must not have a show element.",
+                ))?;
+                show = None;
+            }
+            if subscription && priority.is_some() {
+                recover(Error::ParseError(
+                    "Subscription presence must not have a priority element.",
+                ))?;
+                priority = None;
+            }
+            if presence.type_ == Type::None
+                && presence
+                    .payloads
+                    .iter()
+                    .any(|payload| payload.is("error", ns::DEFAULT_NS))
+            {
+                recover(Error::ParseError(
+                    "Available presence must not have an error element.",
+                ))?;
+                presence
+                    .payloads
+                    .retain(|payload| !payload.is("error", ns::DEFAULT_NS));
+            }
+        }
         presence.show = show;
         if let Some(priority) = priority {
             presence.priority = priority;
         }
-        Ok(presence)
+        Ok((presence, warnings))
+    }
+}
+
+impl TryFrom<Element> for Presence {
+    type Error = Error;
+
+    fn try_from(root: Element) -> Result<Presence, Error> {
+        Presence::parse_with_lang(root, "")
+    }
+}
+
+impl TryFrom<&Element> for Presence {
+    type Error = Error;
+
+    fn try_from(root: &Element) -> Result<Presence, Error> {
+        Presence::parse_impl(root, "", false).map(|(presence, _)| presence)
     }
 }
 
 impl From<Presence> for Element {
     fn from(presence: Presence) -> Element {
+        let stanza_lang = presence.lang.as_deref();
         Element::builder("presence", ns::DEFAULT_NS)
             .attr("from", presence.from)
             .attr("to", presence.to)
             .attr("id", presence.id)
             .attr("type", presence.type_)
+            .attr("xml:lang", stanza_lang)
             .append_all(presence.show.into_iter())
             .append_all(presence.statuses.into_iter().map(|(lang, status)| {
                 Element::builder("status", ns::DEFAULT_NS)
-                    .attr(
-                        "xml:lang",
-                        match lang.as_ref() {
-                            "" => None,
-                            lang => Some(lang),
-                        },
-                    )
+                    .attr("xml:lang", child_lang(&lang, stanza_lang))
                     .append(status)
             }))
-            .append_all(if presence.priority == 0 {
-                None
-            } else {
-                Some(
-                    Element::builder("priority", ns::DEFAULT_NS)
-                        .append(format!("{}", presence.priority)),
-                )
-            })
+            .append_if(
+                presence.priority != Priority(0),
+                Element::builder("priority", ns::DEFAULT_NS)
+                    .append(format!("{}", presence.priority)),
+            )
             .append_all(presence.payloads.into_iter())
             .build()
     }
@@ -385,7 +622,7 @@ mod tests {
     fn test_size() {
         assert_size!(Show, 1);
         assert_size!(Type, 1);
-        assert_size!(Presence, 80);
+        assert_size!(Presence, 92);
     }
 
     #[cfg(target_pointer_width = "64")]
@@ -393,7 +630,7 @@ mod tests {
     fn test_size() {
         assert_size!(Show, 1);
         assert_size!(Type, 1);
-        assert_size!(Presence, 160);
+        assert_size!(Presence, 184);
     }
 
     #[test]
@@ -412,6 +649,16 @@ mod tests {
         assert!(presence.payloads.is_empty());
     }
 
+    #[test]
+    fn test_borrowed() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<presence xmlns='jabber:client' type='unavailable'><status>Bye</status><x xmlns='http://jabber.org/protocol/muc'/></presence>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<presence xmlns='jabber:component:accept' type='unavailable'><status>Bye</status><x xmlns='http://jabber.org/protocol/muc'/></presence>".parse().unwrap();
+        let presence = Presence::try_from(&elem).unwrap();
+        assert_eq!(presence, Presence::try_from(elem).unwrap());
+    }
+
     #[test]
     fn test_serialise() {
         #[cfg(not(feature = "component"))]
@@ -427,6 +674,108 @@ mod tests {
         assert_eq!(elem, elem2);
     }
 
+    #[test]
+    fn test_subscription() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element =
+            "<presence xmlns='jabber:client' to='juliet@example.com' type='unsubscribed'/>"
+                .parse()
+                .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<presence xmlns='jabber:component:accept' to='juliet@example.com' type='unsubscribed'/>"
+            .parse()
+            .unwrap();
+        let presence = Presence::unsubscribed().with_to(Jid::new("juliet@example.com").unwrap());
+        let elem2 = presence.into();
+        assert_eq!(elem, elem2);
+
+        assert_eq!(Presence::subscribe().type_, Type::Subscribe);
+        assert_eq!(Presence::subscribed().type_, Type::Subscribed);
+        assert_eq!(Presence::unsubscribe().type_, Type::Unsubscribe);
+    }
+
+    #[test]
+    fn test_directed_subscription() {
+        let juliet = Jid::new("juliet@example.com").unwrap();
+        for (presence, type_) in [
+            (Presence::subscribe_to(juliet.clone()), Type::Subscribe),
+            (Presence::subscribed_to(juliet.clone()), Type::Subscribed),
+            (Presence::unsubscribe_to(juliet.clone()), Type::Unsubscribe),
+            (
+                Presence::unsubscribed_to(juliet.clone()),
+                Type::Unsubscribed,
+            ),
+        ] {
+            assert_eq!(presence.type_, type_);
+            assert_eq!(presence.to, Some(juliet.clone()));
+            assert_eq!(presence.from, None);
+        }
+
+        let presence = Presence::subscribe_to(juliet.clone());
+        let elem: Element = presence.into();
+        assert_eq!(elem.attr("to"), Some("juliet@example.com"));
+        assert_eq!(elem.attr("type"), Some("subscribe"));
+    }
+
+    #[test]
+    fn test_eq() {
+        let presence1 = Presence::available().with_payloads(vec![
+            Element::builder("test", "urn:test:1").build(),
+            Element::builder("test", "urn:test:2").build(),
+        ]);
+        let presence2 = presence1.clone();
+        assert_eq!(presence1, presence2);
+
+        // The order of payloads is significant.
+        let mut presence3 = presence1.clone();
+        presence3.payloads.reverse();
+        assert_ne!(presence1, presence3);
+    }
+
+    #[test]
+    fn test_extract_payload() {
+        use crate::idle::Idle;
+        use crate::muc::Muc;
+
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<presence xmlns='jabber:client'><x xmlns='http://jabber.org/protocol/muc'/><idle xmlns='urn:xmpp:idle:1' since='2017-05-21T20:19:55+01:00'/></presence>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<presence xmlns='jabber:component:accept'><x xmlns='http://jabber.org/protocol/muc'/><idle xmlns='urn:xmpp:idle:1' since='2017-05-21T20:19:55+01:00'/></presence>".parse().unwrap();
+        let presence = Presence::try_from(elem).unwrap();
+        let idle = presence.extract_payload::<Idle>().unwrap().unwrap();
+        assert_eq!(idle.since.format("%Y"), "2017");
+        assert!(presence.extract_payload::<Muc>().unwrap().is_ok());
+        assert!(presence.extract_payload::<crate::caps::Caps>().is_none());
+    }
+
+    #[test]
+    fn test_first_payload() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<presence xmlns='jabber:client'><x xmlns='vcard-temp:x:update'/><c xmlns='http://jabber.org/protocol/caps' node='a'/><c xmlns='http://jabber.org/protocol/caps' node='b'/></presence>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<presence xmlns='jabber:component:accept'><x xmlns='vcard-temp:x:update'/><c xmlns='http://jabber.org/protocol/caps' node='a'/><c xmlns='http://jabber.org/protocol/caps' node='b'/></presence>".parse().unwrap();
+        let presence = Presence::try_from(elem).unwrap();
+        let caps = presence.first_payload("c", ns::CAPS).unwrap();
+        assert_eq!(caps.attr("node"), Some("a"));
+        assert!(presence.first_payload("x", "vcard-temp:x:update").is_some());
+        assert!(presence.first_payload("x", ns::MUC).is_none());
+    }
+
+    #[test]
+    fn test_add_payload_ref() {
+        let elem: Element =
+            "<delay xmlns='urn:xmpp:delay' from='capulet.com' stamp='2002-09-10T23:08:25Z'/>"
+                .parse()
+                .unwrap();
+        let delay = crate::delay::Delay::try_from(elem).unwrap();
+        let mut presence = Presence::available();
+        presence.add_payload_ref(&delay);
+        presence.add_payload_ref(&delay);
+        let elem = Element::from(delay.clone());
+        assert_eq!(presence.payloads, vec![elem.clone(), elem]);
+        assert_eq!(delay.from, Some(Jid::new("capulet.com").unwrap()));
+    }
+
     #[test]
     fn test_show() {
         #[cfg(not(feature = "component"))]
@@ -443,6 +792,30 @@ mod tests {
         assert_eq!(presence.show, Some(Show::Chat));
     }
 
+    #[test]
+    fn test_show_type_values() {
+        for show in [Show::Away, Show::Chat, Show::Dnd, Show::Xa] {
+            assert_eq!(Show::from_str(show.as_str()).unwrap(), show);
+            let elem = Element::from(show.clone());
+            assert_eq!(elem.text(), show.as_str());
+        }
+        assert_eq!(Type::None.as_str(), None);
+        assert_eq!(Type::None.into_attribute_value(), None);
+        for type_ in [
+            Type::Error,
+            Type::Probe,
+            Type::Subscribe,
+            Type::Subscribed,
+            Type::Unavailable,
+            Type::Unsubscribe,
+            Type::Unsubscribed,
+        ] {
+            let value = type_.as_str().unwrap();
+            assert_eq!(Type::from_str(value).unwrap(), type_);
+            assert_eq!(type_.into_attribute_value().as_deref(), Some(value));
+        }
+    }
+
     #[test]
     fn test_empty_show_value() {
         #[cfg(not(feature = "component"))]
@@ -539,6 +912,102 @@ mod tests {
         assert_eq!(presence.statuses["fr"], "Là!");
     }
 
+    #[test]
+    fn test_status_default_lang() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<presence xmlns='jabber:client'><status>Here!</status><status xml:lang='fr'>Là!</status></presence>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<presence xmlns='jabber:component:accept'><status>Here!</status><status xml:lang='fr'>Là!</status></presence>".parse().unwrap();
+        let presence = Presence::parse_with_lang(elem, "en").unwrap();
+        assert_eq!(presence.statuses.len(), 2);
+        assert_eq!(presence.statuses["en"], "Here!");
+        assert_eq!(presence.statuses["fr"], "Là!");
+
+        // The stanza’s own xml:lang takes precedence over the stream’s.
+        #[cfg(not(feature = "component"))]
+        let elem: Element =
+            "<presence xmlns='jabber:client' xml:lang='de'><status>Hier!</status></presence>"
+                .parse()
+                .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<presence xmlns='jabber:component:accept' xml:lang='de'><status>Hier!</status></presence>".parse().unwrap();
+        let presence = Presence::parse_with_lang(elem, "en").unwrap();
+        assert_eq!(presence.statuses.len(), 1);
+        assert_eq!(presence.statuses["de"], "Hier!");
+    }
+
+    #[test]
+    fn test_stanza_lang() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<presence xmlns='jabber:client' xml:lang='de'><status>Hier!</status><status xml:lang='fr'>Là!</status><status xml:lang=''>Here!</status></presence>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<presence xmlns='jabber:component:accept' xml:lang='de'><status>Hier!</status><status xml:lang='fr'>Là!</status><status xml:lang=''>Here!</status></presence>".parse().unwrap();
+        let presence = Presence::try_from(elem).unwrap();
+        assert_eq!(presence.lang.as_deref(), Some("de"));
+        assert_eq!(presence.statuses["de"], "Hier!");
+        assert_eq!(presence.statuses["fr"], "Là!");
+        assert_eq!(presence.statuses[""], "Here!");
+
+        let elem = Element::from(presence.clone());
+        assert_eq!(elem.attr("xml:lang"), Some("de"));
+        let langs: Vec<_> = elem
+            .children()
+            .map(|child| child.attr("xml:lang"))
+            .collect();
+        assert_eq!(langs, [Some(""), None, Some("fr")]);
+        assert_eq!(Presence::try_from(elem).unwrap(), presence);
+    }
+
+    #[test]
+    fn test_lenient() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<presence xmlns='jabber:client'><show>coucou</show><status>Here!</status><status>Là!</status><priority>1</priority><priority>2</priority></presence>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<presence xmlns='jabber:component:accept'><show>coucou</show><status>Here!</status><status>Là!</status><priority>1</priority><priority>2</priority></presence>".parse().unwrap();
+        let (presence, warnings) = Presence::parse_lenient(elem, "").unwrap();
+        assert_eq!(presence.show, None);
+        assert_eq!(presence.statuses.len(), 1);
+        assert_eq!(presence.statuses[""], "Here!");
+        assert_eq!(presence.priority, Priority(1));
+        let warnings: Vec<_> = warnings
+            .into_iter()
+            .map(|error| match error {
+                Error::ParseError(string) => string,
+                Error::DuplicateElement { name } => name,
+                _ => panic!(),
+            })
+            .collect();
+        assert_eq!(warnings, ["Invalid value for show.", "status", "priority"]);
+    }
+
+    #[test]
+    fn test_invalid_multiple_shows() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element =
+            "<presence xmlns='jabber:client'><show>away</show><show>dnd</show></presence>"
+                .parse()
+                .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<presence xmlns='jabber:component:accept'><show>away</show><show>dnd</show></presence>".parse().unwrap();
+        let error = Presence::try_from(elem).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "duplicate <show/> element: \"away\" then \"dnd\""
+        );
+        match error {
+            Error::DuplicateValue {
+                name,
+                first,
+                second,
+            } => {
+                assert_eq!(name, "show");
+                assert_eq!(first, "away");
+                assert_eq!(second, "dnd");
+            }
+            _ => panic!(),
+        }
+    }
+
     #[test]
     fn test_invalid_multiple_statuses() {
         #[cfg(not(feature = "component"))]
@@ -546,14 +1015,11 @@ mod tests {
         #[cfg(feature = "component")]
         let elem: Element = "<presence xmlns='jabber:component:accept'><status xml:lang='fr'>Here!</status><status xml:lang='fr'>Là!</status></presence>".parse().unwrap();
         let error = Presence::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
+        let name = match error {
+            Error::DuplicateElement { name } => name,
             _ => panic!(),
         };
-        assert_eq!(
-            message,
-            "Status element present twice for the same xml:lang."
-        );
+        assert_eq!(name, "status");
     }
 
     #[test]
@@ -569,7 +1035,23 @@ mod tests {
                 .unwrap();
         let presence = Presence::try_from(elem).unwrap();
         assert_eq!(presence.payloads.len(), 0);
-        assert_eq!(presence.priority, -1i8);
+        assert_eq!(presence.priority, Priority(-1));
+    }
+
+    #[test]
+    fn test_priority_with_whitespace() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element =
+            "<presence xmlns='jabber:client'><priority>\n  -1 </priority></presence>"
+                .parse()
+                .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element =
+            "<presence xmlns='jabber:component:accept'><priority>\n  -1 </priority></presence>"
+                .parse()
+                .unwrap();
+        let presence = Presence::try_from(elem).unwrap();
+        assert_eq!(presence.priority, Priority(-1));
     }
 
     #[test]
@@ -584,10 +1066,64 @@ mod tests {
                 .parse()
                 .unwrap();
         let error = Presence::try_from(elem).unwrap_err();
-        match error {
-            Error::ParseIntError(_) => (),
+        let message = match error {
+            Error::ParseError(string) => string,
             _ => panic!(),
         };
+        assert_eq!(message, "Priority out of range -128..127.");
+
+        assert_eq!(Priority::from_str("-128").unwrap(), Priority(-128));
+        assert_eq!(Priority::from_str("127").unwrap(), Priority(127));
+        match Priority::from_str("-129").unwrap_err() {
+            Error::ParseError("Priority out of range -128..127.") => (),
+            _ => panic!(),
+        }
+        match Priority::from_str("high").unwrap_err() {
+            Error::ParseIntError(_) => (),
+            _ => panic!(),
+        }
+    }
+
+    #[cfg(not(feature = "disable-validation"))]
+    #[test]
+    fn test_invalid_type_combinations() {
+        let invalid = [
+            (
+                "<presence xmlns='{}' type='subscribe'><show>away</show></presence>",
+                "Subscription presence must not have a show element.",
+            ),
+            (
+                "<presence xmlns='{}' type='unsubscribed'><priority>5</priority></presence>",
+                "Subscription presence must not have a priority element.",
+            ),
+            (
+                "<presence xmlns='{}'><error xmlns='{}' type='cancel'/></presence>",
+                "Available presence must not have an error element.",
+            ),
+        ];
+        for (xml, expected) in invalid.iter() {
+            let elem: Element = xml.replace("{}", ns::DEFAULT_NS).parse().unwrap();
+            let error = Presence::try_from(elem.clone()).unwrap_err();
+            let message = match error {
+                Error::ParseError(string) => string,
+                _ => panic!(),
+            };
+            assert_eq!(message, *expected);
+
+            let (presence, warnings) = Presence::parse_lenient(elem, "").unwrap();
+            assert_eq!(presence.show, None);
+            assert_eq!(presence.priority, Priority(0));
+            assert!(presence.payloads.is_empty());
+            assert_eq!(warnings.len(), 1);
+        }
+
+        let elem: Element = format!(
+            "<presence xmlns='{}' type='unavailable'><show>away</show><priority>5</priority></presence>",
+            ns::DEFAULT_NS
+        )
+        .parse()
+        .unwrap();
+        Presence::try_from(elem).unwrap();
     }
 
     #[test]
@@ -619,11 +1155,10 @@ mod tests {
                 .parse()
                 .unwrap();
         let error = Presence::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in status element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in status element"
+        );
     }
 
     #[cfg(not(feature = "disable-validation"))]
@@ -639,11 +1174,10 @@ mod tests {
                 .parse()
                 .unwrap();
         let error = Presence::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in status element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in status element"
+        );
     }
 
     #[test]
@@ -656,6 +1190,27 @@ mod tests {
         assert!(elem.children().next().unwrap().is("status", ns::DEFAULT_NS));
     }
 
+    #[test]
+    fn test_set_status() {
+        let mut presence = Presence::new(Type::None);
+        presence.set_status(" FR ", "Là!").unwrap();
+        presence.set_status("en-GB", "Here!").unwrap();
+        presence.set_status("", "Hi!").unwrap();
+        assert_eq!(presence.statuses.len(), 3);
+        assert_eq!(presence.statuses["fr"], "Là!");
+        assert_eq!(presence.statuses["en-gb"], "Here!");
+        assert_eq!(presence.statuses[""], "Hi!");
+
+        for lang in ["fr_FR", "en--gb", "1fr", "fr-abcdefghi"].iter() {
+            let error = presence.set_status(*lang, "Oops").unwrap_err();
+            let message = match error {
+                Error::ParseError(string) => string,
+                _ => panic!(),
+            };
+            assert_eq!(message, "Invalid language tag for status.");
+        }
+    }
+
     #[test]
     fn test_serialise_priority() {
         let presence = Presence::new(Type::None).with_priority(42);
@@ -690,4 +1245,22 @@ mod tests {
         let elem: Element = presence.into();
         assert_eq!(elem.attr("to"), Some("test@localhost/coucou"));
     }
+
+    #[test]
+    fn test_invalid_to_jid() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<presence xmlns='jabber:client' to='@example.org'/>"
+            .parse()
+            .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<presence xmlns='jabber:component:accept' to='@example.org'/>"
+            .parse()
+            .unwrap();
+        let error = Presence::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Invalid 'to' JID in presence.");
+    }
 }
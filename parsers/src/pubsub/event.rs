@@ -167,7 +167,10 @@ impl TryFrom<Element> for PubSubEvent {
                         let uri = get_attr!(item, "uri", Required);
                         redirect = Some(uri);
                     } else {
-                        return Err(Error::ParseError("Unknown child in delete element."));
+                        return Err(Error::UnknownChild {
+                            element: "delete",
+                            child: item.name().to_owned(),
+                        });
                     }
                 }
                 payload = Some(PubSubEvent::Delete { node, redirect });
@@ -186,7 +189,10 @@ impl TryFrom<Element> for PubSubEvent {
                     subscription: get_attr!(child, "subscription", Option),
                 });
             } else {
-                return Err(Error::ParseError("Unknown child in event element."));
+                return Err(Error::UnknownChild {
+                    element: "event",
+                    child: child.name().to_owned(),
+                });
             }
         }
         payload.ok_or(Error::ParseError("No payload in event element."))
@@ -361,11 +367,10 @@ mod tests {
                 .parse()
                 .unwrap();
         let error = PubSubEvent::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in event element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in event element"
+        );
     }
 
     #[cfg(not(feature = "disable-validation"))]
@@ -375,11 +380,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = PubSubEvent::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in event element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in event element"
+        );
     }
 
     #[test]
@@ -161,7 +161,10 @@ impl TryFrom<Element> for PubSubOwner {
                 let configure = Configure::try_from(child.clone())?;
                 payload = Some(PubSubOwner::Configure(configure));
             } else {
-                return Err(Error::ParseError("Unknown child in pubsub element."));
+                return Err(Error::UnknownChild {
+                    element: "pubsub",
+                    child: child.name().to_owned(),
+                });
             }
         }
         payload.ok_or(Error::ParseError("No payload in pubsub element."))
@@ -197,9 +197,10 @@ impl TryFrom<Element> for SubscribeOptions {
                 }
                 required = true;
             } else {
-                return Err(Error::ParseError(
-                    "Unknown child in subscribe-options element.",
-                ));
+                return Err(Error::UnknownChild {
+                    element: "subscribe-options",
+                    child: child.name().to_owned(),
+                });
             }
         }
         Ok(SubscribeOptions { required })
@@ -492,7 +493,10 @@ impl TryFrom<Element> for PubSub {
                 let unsubscribe = Unsubscribe::try_from(child.clone())?;
                 payload = Some(PubSub::Unsubscribe(unsubscribe));
             } else {
-                return Err(Error::ParseError("Unknown child in pubsub element."));
+                return Err(Error::UnknownChild {
+                    element: "pubsub",
+                    child: child.name().to_owned(),
+                });
             }
         }
         payload.ok_or(Error::ParseError("No payload in pubsub element."))
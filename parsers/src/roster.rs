@@ -270,21 +270,47 @@ mod tests {
             .parse()
             .unwrap();
         let error = Roster::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in query element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in query element"
+        );
 
         let elem: Element = "<query xmlns='jabber:iq:roster' coucou=''/>"
             .parse()
             .unwrap();
         let error = Roster::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
+        assert_eq!(
+            error.to_string(),
+            "unknown attribute 'coucou' in query element"
+        );
+    }
+
+    #[test]
+    fn test_invalid_item_location() {
+        let elem: Element = "<query xmlns='jabber:iq:roster'>
+            <item jid='a@example.org'/>
+            <item jid='b@example.org'/>
+            <item jid='@example.org'/>
+        </query>"
+            .parse()
+            .unwrap();
+        let error = Roster::try_from(elem).unwrap_err();
+        match error {
+            Error::Located {
+                ref path,
+                ref error,
+            } => {
+                assert_eq!(path, "item[3]/@jid");
+                match **error {
+                    Error::JidParseError(_) => (),
+                    _ => panic!(),
+                }
+            }
             _ => panic!(),
-        };
-        assert_eq!(message, "Unknown attribute in query element.");
+        }
+        assert!(error
+            .to_string()
+            .starts_with("item[3]/@jid: JID parsing error"));
     }
 
     #[test]
@@ -293,7 +319,7 @@ mod tests {
             .parse()
             .unwrap();
         let error = Roster::try_from(elem).unwrap_err();
-        let message = match error {
+        let message = match *error.unlocated() {
             Error::ParseError(string) => string,
             _ => panic!(),
         };
@@ -314,10 +340,9 @@ mod tests {
                 .parse()
                 .unwrap();
         let error = Roster::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in item element.");
+        assert_eq!(
+            error.unlocated().to_string(),
+            "unknown child <coucou/> in item element"
+        );
     }
 }
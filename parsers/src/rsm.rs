@@ -44,7 +44,7 @@ impl TryFrom<Element> for SetQuery {
                 if set.max.is_some() {
                     return Err(Error::ParseError("Set can’t have more than one max."));
                 }
-                set.max = Some(child.text().parse()?);
+                set.max = Some(child.text_trimmed().parse()?);
             } else if child.is("after", ns::RSM) {
                 if set.after.is_some() {
                     return Err(Error::ParseError("Set can’t have more than one after."));
@@ -59,9 +59,12 @@ impl TryFrom<Element> for SetQuery {
                 if set.index.is_some() {
                     return Err(Error::ParseError("Set can’t have more than one index."));
                 }
-                set.index = Some(child.text().parse()?);
+                set.index = Some(child.text_trimmed().parse()?);
             } else {
-                return Err(Error::ParseError("Unknown child in set element."));
+                return Err(Error::UnknownChild {
+                    element: "set",
+                    child: child.name().to_owned(),
+                });
             }
         }
         Ok(set)
@@ -135,9 +138,12 @@ impl TryFrom<Element> for SetResult {
                 if set.count.is_some() {
                     return Err(Error::ParseError("Set can’t have more than one count."));
                 }
-                set.count = Some(child.text().parse()?);
+                set.count = Some(child.text_trimmed().parse()?);
             } else {
-                return Err(Error::ParseError("Unknown child in set element."));
+                return Err(Error::UnknownChild {
+                    element: "set",
+                    child: child.name().to_owned(),
+                });
             }
         }
         Ok(set)
@@ -206,6 +212,23 @@ mod tests {
         assert_eq!(set.count, None);
     }
 
+    #[test]
+    fn test_whitespace_padded_integers() {
+        let elem: Element = "<set xmlns='http://jabber.org/protocol/rsm'><max> 10\n</max><index>\n  2\n</index></set>"
+            .parse()
+            .unwrap();
+        let set = SetQuery::try_from(elem).unwrap();
+        assert_eq!(set.max, Some(10));
+        assert_eq!(set.index, Some(2));
+
+        let elem: Element =
+            "<set xmlns='http://jabber.org/protocol/rsm'><count> 800 </count></set>"
+                .parse()
+                .unwrap();
+        let set = SetResult::try_from(elem).unwrap();
+        assert_eq!(set.count, Some(800));
+    }
+
     #[test]
     fn test_unknown() {
         let elem: Element = "<replace xmlns='urn:xmpp:message-correct:0'/>"
@@ -235,21 +258,13 @@ mod tests {
             .parse()
             .unwrap();
         let error = SetQuery::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in set element.");
+        assert_eq!(error.to_string(), "unknown child <coucou/> in set element");
 
         let elem: Element = "<set xmlns='http://jabber.org/protocol/rsm'><coucou/></set>"
             .parse()
             .unwrap();
         let error = SetResult::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in set element.");
+        assert_eq!(error.to_string(), "unknown child <coucou/> in set element");
     }
 
     #[test]
@@ -217,7 +217,10 @@ impl TryFrom<Element> for Rtt {
         let mut actions = Vec::new();
         for child in elem.children() {
             if child.ns() != ns::RTT {
-                return Err(Error::ParseError("Unknown child in rtt element."));
+                return Err(Error::UnknownChild {
+                    element: "rtt",
+                    child: child.name().to_owned(),
+                });
             }
             actions.push(Action::try_from(child.clone())?);
         }
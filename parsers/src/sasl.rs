@@ -40,6 +40,12 @@ generate_attribute!(
         /// Creates a temporary JID on login, which will be destroyed on
         /// disconnect.
         Anonymous => "ANONYMOUS",
+
+        /// Uses an OAuth 2.0 bearer token instead of a password, in a single
+        /// step.
+        ///
+        /// See <https://www.rfc-editor.org/rfc/rfc7628>
+        OAuthBearer => "OAUTHBEARER",
     }
 );
 
@@ -280,6 +286,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_defined_conditions() {
+        let conditions = [
+            ("aborted", DefinedCondition::Aborted),
+            ("account-disabled", DefinedCondition::AccountDisabled),
+            ("credentials-expired", DefinedCondition::CredentialsExpired),
+            ("encryption-required", DefinedCondition::EncryptionRequired),
+            ("incorrect-encoding", DefinedCondition::IncorrectEncoding),
+            ("invalid-authzid", DefinedCondition::InvalidAuthzid),
+            ("invalid-mechanism", DefinedCondition::InvalidMechanism),
+            ("malformed-request", DefinedCondition::MalformedRequest),
+            ("mechanism-too-weak", DefinedCondition::MechanismTooWeak),
+            ("not-authorized", DefinedCondition::NotAuthorized),
+            (
+                "temporary-auth-failure",
+                DefinedCondition::TemporaryAuthFailure,
+            ),
+        ];
+        for (name, condition) in conditions.iter() {
+            let elem: Element = format!(
+                "<failure xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><{}/></failure>",
+                name
+            )
+            .parse()
+            .unwrap();
+            let failure = Failure::try_from(elem).unwrap();
+            assert_eq!(failure.defined_condition, *condition);
+
+            let elem = Element::from(failure);
+            assert!(elem.get_child(name, ns::SASL).is_some());
+        }
+    }
+
     /// Some servers apparently use a non-namespaced 'lang' attribute, which is invalid as not part
     /// of the schema.  This tests whether we can parse it when disabling validation.
     #[cfg(feature = "disable-validation")]
@@ -0,0 +1,153 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::data_forms::DataForm;
+use crate::iq::{IqGetPayload, IqResultPayload, IqSetPayload};
+use crate::Jid;
+
+generate_element!(
+    /// Asks a directory for its search fields when empty, carries them or a
+    /// search request otherwise, and the matching items in a result.
+    Query, "query", SEARCH,
+    children: [
+        /// Human-readable instructions on how to search.
+        instructions: Option<String> = ("instructions", SEARCH) => String,
+
+        /// Legacy search field for the first name, empty when only advertised.
+        first: Option<String> = ("first", SEARCH) => String,
+
+        /// Legacy search field for the last name, empty when only advertised.
+        last: Option<String> = ("last", SEARCH) => String,
+
+        /// Legacy search field for the nickname, empty when only advertised.
+        nick: Option<String> = ("nick", SEARCH) => String,
+
+        /// Legacy search field for the email address, empty when only
+        /// advertised.
+        email: Option<String> = ("email", SEARCH) => String,
+
+        /// An extensible search form, replacing the legacy fields.
+        form: Option<DataForm> = ("x", DATA_FORMS) => DataForm,
+
+        /// The entities matching a legacy search.
+        items: Vec<Item> = ("item", SEARCH) => Item
+    ]
+);
+
+impl IqGetPayload for Query {}
+impl IqSetPayload for Query {}
+impl IqResultPayload for Query {}
+
+generate_element!(
+    /// An entity matching a legacy search.
+    Item, "item", SEARCH,
+    attributes: [
+        /// The JID of this entity.
+        jid: Required<Jid> = "jid",
+    ],
+    children: [
+        /// Its first name.
+        first: Option<String> = ("first", SEARCH) => String,
+
+        /// Its last name.
+        last: Option<String> = ("last", SEARCH) => String,
+
+        /// Its nickname.
+        nick: Option<String> = ("nick", SEARCH) => String,
+
+        /// Its email address.
+        email: Option<String> = ("email", SEARCH) => String
+    ]
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_forms::DataFormType;
+    use crate::Element;
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Query, 124);
+        assert_size!(Item, 68);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Query, 248);
+        assert_size!(Item, 136);
+    }
+
+    #[test]
+    fn test_fields() {
+        let elem: Element = "<query xmlns='jabber:iq:search'><instructions>Fill in one or more fields to search for any matching Jabber users.</instructions><first/><last/><nick/><email/></query>"
+            .parse()
+            .unwrap();
+        let query = Query::try_from(elem.clone()).unwrap();
+        assert_eq!(
+            query.instructions.as_deref(),
+            Some("Fill in one or more fields to search for any matching Jabber users.")
+        );
+        assert_eq!(query.first.as_deref(), Some(""));
+        assert_eq!(query.email.as_deref(), Some(""));
+        assert!(query.form.is_none());
+        assert!(query.items.is_empty());
+        let elem2 = query.into();
+        assert_eq!(elem, elem2);
+    }
+
+    #[test]
+    fn test_legacy_result() {
+        let elem: Element = "<query xmlns='jabber:iq:search'><item jid='juliet@capulet.com'><first>Juliet</first><last>Capulet</last><nick>JuliC</nick><email>juliet@shakespeare.lit</email></item><item jid='tybalt@shakespeare.lit'><first>Tybalt</first><last>Capulet</last><nick>ty</nick></item></query>"
+            .parse()
+            .unwrap();
+        let query = Query::try_from(elem.clone()).unwrap();
+        assert_eq!(query.items.len(), 2);
+        assert_eq!(
+            query.items[0].jid,
+            Jid::from_str("juliet@capulet.com").unwrap()
+        );
+        assert_eq!(query.items[0].nick.as_deref(), Some("JuliC"));
+        assert_eq!(query.items[1].email, None);
+        let elem2 = query.into();
+        assert_eq!(elem, elem2);
+    }
+
+    #[test]
+    fn test_form() {
+        let elem: Element = "<query xmlns='jabber:iq:search'><instructions>Use the enclosed form to search.</instructions><x xmlns='jabber:x:data' type='form'><field type='hidden' var='FORM_TYPE'><value>jabber:iq:search</value></field><field type='text-single' label='Given Name' var='first'/></x></query>"
+            .parse()
+            .unwrap();
+        let query = Query::try_from(elem).unwrap();
+        let form = query.form.unwrap();
+        assert_eq!(form.type_, DataFormType::Form);
+        assert_eq!(form.form_type.as_deref(), Some("jabber:iq:search"));
+        assert_eq!(form.fields[0].var, "first");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<query xmlns='jabber:iq:search'><coucou/></query>"
+            .parse()
+            .unwrap();
+        let error = Query::try_from(elem).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in query element"
+        );
+
+        let elem: Element = "<query xmlns='jabber:iq:search'><item/></query>"
+            .parse()
+            .unwrap();
+        let error = Query::try_from(elem).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "item[1]: parse error: Required attribute 'jid' missing."
+        );
+    }
+}
@@ -0,0 +1,131 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::message::MessagePayload;
+use crate::ns;
+use crate::presence::PresencePayload;
+use crate::util::helpers::Text;
+
+generate_element!(
+    /// A list of headers, in the order they were sent; a name can appear
+    /// more than once.
+    Headers, "headers", SHIM,
+    children: [
+        /// The headers.
+        headers: Vec<Header> = ("header", SHIM) => Header
+    ]
+);
+
+impl MessagePayload for Headers {}
+impl PresencePayload for Headers {
+    const NAME: &'static str = "headers";
+    const NS: &'static str = ns::SHIM;
+}
+
+impl Headers {
+    /// The values of every header with this name, in order.
+    pub fn get(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|header| header.name == name)
+            .map(|header| header.value.as_str())
+            .collect()
+    }
+}
+
+generate_element!(
+    /// A single header, e.g. `SubID` or `Urgency`.
+    Header, "header", SHIM,
+    attributes: [
+        /// The name of this header.
+        name: Required<String> = "name",
+    ],
+    text: (
+        /// The value of this header.
+        value: Text<String>
+    )
+);
+
+impl Header {
+    /// Creates a header with this name and value.
+    pub fn new<N: Into<String>, V: Into<String>>(name: N, value: V) -> Header {
+        Header {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Element;
+    use std::convert::TryFrom;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Headers, 12);
+        assert_size!(Header, 24);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Headers, 24);
+        assert_size!(Header, 48);
+    }
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<headers xmlns='http://jabber.org/protocol/shim'><header name='In-Reply-To'>123456789@capulet.com</header><header name='Keywords'>shakespeare</header><header name='Keywords'>juliet</header></headers>"
+            .parse()
+            .unwrap();
+        let headers = Headers::try_from(elem.clone()).unwrap();
+        assert_eq!(headers.headers.len(), 3);
+        assert_eq!(headers.headers[1], Header::new("Keywords", "shakespeare"));
+        assert_eq!(headers.get("Keywords"), ["shakespeare", "juliet"]);
+        assert_eq!(headers.get("In-Reply-To"), ["123456789@capulet.com"]);
+        assert!(headers.get("Urgency").is_empty());
+        let elem2 = headers.into();
+        assert_eq!(elem, elem2);
+    }
+
+    #[test]
+    fn test_serialise() {
+        let elem: Element = "<headers xmlns='http://jabber.org/protocol/shim'><header name='SubID'>ba49252aaa4f5d320c24d3766f0bdcade78c78d3</header></headers>"
+            .parse()
+            .unwrap();
+        let headers = Headers {
+            headers: vec![Header::new(
+                "SubID",
+                "ba49252aaa4f5d320c24d3766f0bdcade78c78d3",
+            )],
+        };
+        let elem2: Element = headers.into();
+        assert_eq!(elem, elem2);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element =
+            "<headers xmlns='http://jabber.org/protocol/shim'><header>1</header></headers>"
+                .parse()
+                .unwrap();
+        let error = Headers::try_from(elem).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "header[1]: parse error: Required attribute 'name' missing."
+        );
+
+        let elem: Element = "<headers xmlns='http://jabber.org/protocol/shim'><coucou/></headers>"
+            .parse()
+            .unwrap();
+        let error = Headers::try_from(elem).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in headers element"
+        );
+    }
+}
@@ -0,0 +1,308 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::iq::Iq;
+use crate::message::Message;
+use crate::ns;
+use crate::presence::Presence;
+use crate::util::error::Error;
+use crate::Element;
+use jid::Jid;
+use minidom::Node;
+use std::convert::TryFrom;
+
+/// The namespaces a stream can carry stanzas in: `jabber:client`,
+/// `jabber:server` and `jabber:component:accept`.
+///
+/// Message, presence and iq parse stanzas in any of them, whichever one
+/// the component feature makes the default.
+pub const NAMESPACES: [&str; 3] = [ns::JABBER_CLIENT, ns::JABBER_SERVER, ns::COMPONENT_ACCEPT];
+
+/// Copies `elem` into the `to` namespace, along with its descendants in the
+/// `from` one; payloads in other namespaces are kept as is.
+fn rename_ns(elem: &Element, from: &str, to: &str) -> Element {
+    let mut builder = Element::builder(elem.name(), to);
+    for (name, value) in elem.attrs() {
+        builder = builder.attr(name, value);
+    }
+    for node in elem.nodes() {
+        builder = builder.append(match node {
+            Node::Element(child) if child.ns() == from => Node::Element(rename_ns(child, from, to)),
+            node => node.clone(),
+        });
+    }
+    builder.build()
+}
+
+/// The stanza moved to the default namespace, if it is in another one of
+/// `NAMESPACES`, so that it can be parsed.
+pub(crate) fn to_default_ns(stanza: &Element) -> Option<Element> {
+    let from = stanza.ns();
+    if from != ns::DEFAULT_NS && NAMESPACES.contains(&&*from) {
+        Some(rename_ns(stanza, &from, ns::DEFAULT_NS))
+    } else {
+        None
+    }
+}
+
+/// Moves a stanza serialised in any of the `NAMESPACES`, e.g. from a
+/// `Message`, to the one of `NAMESPACES` used by the stream it gets sent
+/// on, such as `jabber:server` for a server-to-server stream.
+pub fn into_namespace(stanza: Element, ns: &str) -> Element {
+    let from = stanza.ns();
+    if from == ns || !NAMESPACES.contains(&&*from) {
+        stanza
+    } else {
+        rename_ns(&stanza, &from, ns)
+    }
+}
+
+/// Whether `lang` looks like a BCP 47 language tag, as used in xml:lang:
+/// subtags of one to eight letters or digits separated by hyphens, the
+/// first one being letters only.
+pub fn is_valid_lang(lang: &str) -> bool {
+    let mut subtags = lang.split('-');
+    let primary = subtags.next().unwrap_or("");
+    (1..=8).contains(&primary.len())
+        && primary.bytes().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.bytes().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Any of the three stanzas, for code routing them without caring about
+/// their kind first.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stanza {
+    /// A `<message/>` stanza.
+    Message(Message),
+
+    /// A `<presence/>` stanza.
+    Presence(Presence),
+
+    /// An `<iq/>` stanza.
+    Iq(Iq),
+}
+
+impl Stanza {
+    /// The JID emitting this stanza.
+    pub fn from(&self) -> Option<&Jid> {
+        match self {
+            Stanza::Message(message) => message.from.as_ref(),
+            Stanza::Presence(presence) => presence.from.as_ref(),
+            Stanza::Iq(iq) => iq.from.as_ref(),
+        }
+    }
+
+    /// The recipient of this stanza.
+    pub fn to(&self) -> Option<&Jid> {
+        match self {
+            Stanza::Message(message) => message.to.as_ref(),
+            Stanza::Presence(presence) => presence.to.as_ref(),
+            Stanza::Iq(iq) => iq.to.as_ref(),
+        }
+    }
+
+    /// The @id attribute of this stanza, always present on an iq.
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Stanza::Message(message) => message.id.as_deref(),
+            Stanza::Presence(presence) => presence.id.as_deref(),
+            Stanza::Iq(iq) => Some(&iq.id),
+        }
+    }
+}
+
+impl TryFrom<Element> for Stanza {
+    type Error = Error;
+
+    fn try_from(root: Element) -> Result<Stanza, Error> {
+        Ok(match root.name() {
+            "message" => Stanza::Message(Message::try_from(root)?),
+            "presence" => Stanza::Presence(Presence::try_from(root)?),
+            "iq" => Stanza::Iq(Iq::try_from(root)?),
+            _ => return Err(Error::ParseError("This is not a stanza element.")),
+        })
+    }
+}
+
+impl From<Stanza> for Element {
+    fn from(stanza: Stanza) -> Element {
+        match stanza {
+            Stanza::Message(message) => message.into(),
+            Stanza::Presence(presence) => presence.into(),
+            Stanza::Iq(iq) => iq.into(),
+        }
+    }
+}
+
+impl From<Message> for Stanza {
+    fn from(message: Message) -> Stanza {
+        Stanza::Message(message)
+    }
+}
+
+impl From<Presence> for Stanza {
+    fn from(presence: Presence) -> Stanza {
+        Stanza::Presence(presence)
+    }
+}
+
+impl From<Iq> for Stanza {
+    fn from(iq: Iq) -> Stanza {
+        Stanza::Iq(iq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iq::IqType;
+    use crate::presence::Type as PresenceType;
+    use crate::roster::Roster;
+    use std::str::FromStr;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Stanza, 148);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Stanza, 296);
+    }
+
+    #[test]
+    fn test_valid_lang() {
+        assert!(is_valid_lang("en"));
+        assert!(is_valid_lang("zh-Hant-TW"));
+        assert!(is_valid_lang("de-1996"));
+        assert!(!is_valid_lang(""));
+        assert!(!is_valid_lang("en-"));
+        assert!(!is_valid_lang("1en"));
+        assert!(!is_valid_lang("en_GB"));
+        assert!(!is_valid_lang("en-abcdefghi"));
+    }
+
+    #[test]
+    fn test_dispatch() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element =
+            "<message xmlns='jabber:client' from='juliet@example.com/balcony' id='a'/>"
+                .parse()
+                .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element =
+            "<message xmlns='jabber:component:accept' from='juliet@example.com/balcony' id='a'/>"
+                .parse()
+                .unwrap();
+        let stanza = Stanza::try_from(elem.clone()).unwrap();
+        assert!(matches!(stanza, Stanza::Message(_)));
+        assert_eq!(
+            stanza.from(),
+            Some(&Jid::from_str("juliet@example.com/balcony").unwrap())
+        );
+        assert_eq!(stanza.to(), None);
+        assert_eq!(stanza.id(), Some("a"));
+        assert_eq!(Element::from(stanza), elem);
+
+        let stanza = Stanza::Presence(
+            Presence::new(PresenceType::Unavailable)
+                .with_to(Jid::from_str("romeo@example.net").unwrap()),
+        );
+        let stanza = Stanza::try_from(Element::from(stanza)).unwrap();
+        assert!(matches!(stanza, Stanza::Presence(_)));
+        assert_eq!(stanza.from(), None);
+        assert_eq!(
+            stanza.to(),
+            Some(&Jid::from_str("romeo@example.net").unwrap())
+        );
+        assert_eq!(stanza.id(), None);
+
+        let stanza = Stanza::Iq(Iq::from_result("b", None::<Roster>));
+        let stanza = Stanza::try_from(Element::from(stanza)).unwrap();
+        match stanza {
+            Stanza::Iq(Iq {
+                payload: IqType::Result(None),
+                ..
+            }) => (),
+            _ => panic!(),
+        }
+        assert_eq!(stanza.id(), Some("b"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'/>"
+            .parse()
+            .unwrap();
+        let error = Stanza::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "This is not a stanza element.");
+
+        // The right name in the wrong namespace.
+        let elem: Element = "<message xmlns='urn:example'/>".parse().unwrap();
+        let error = Stanza::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "This is not a message element.");
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let elem: Element = "<message xmlns='jabber:server' from='juliet@capulet.example/balcony' to='romeo@montague.example' type='chat'><body>Art thou not Romeo?</body><thread>e0ffe42b28561960c6b12b944a092794b9683a38</thread><active xmlns='http://jabber.org/protocol/chatstates'/></message>"
+            .parse()
+            .unwrap();
+        let message = match Stanza::try_from(elem.clone()).unwrap() {
+            Stanza::Message(message) => message,
+            stanza => panic!("{:?}", stanza),
+        };
+        assert_eq!(message.bodies[""].0, "Art thou not Romeo?");
+        assert_eq!(
+            message.thread.as_ref().unwrap().id,
+            "e0ffe42b28561960c6b12b944a092794b9683a38"
+        );
+        assert_eq!(message.payloads.len(), 1);
+        assert_eq!(
+            message.payloads[0].ns(),
+            "http://jabber.org/protocol/chatstates"
+        );
+        let elem2 = into_namespace(message.into(), ns::JABBER_SERVER);
+        assert_eq!(elem, elem2);
+
+        let elem: Element = "<iq xmlns='jabber:server' type='error' id='a'><error type='cancel'><item-not-found xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/></error></iq>"
+            .parse()
+            .unwrap();
+        let iq = Iq::try_from(elem.clone()).unwrap();
+        assert!(matches!(iq.payload, IqType::Error(_)));
+        assert_eq!(into_namespace(iq.into(), ns::JABBER_SERVER), elem);
+
+        for namespace in NAMESPACES {
+            let elem: Element = format!(
+                "<presence xmlns='{}'><show>dnd</show></presence>",
+                namespace
+            )
+            .parse()
+            .unwrap();
+            let presence = Presence::try_from(elem.clone()).unwrap();
+            assert_eq!(presence.show, Some(crate::presence::Show::Dnd));
+            let elem2 = Element::from(presence);
+            assert!(elem2.is("presence", ns::DEFAULT_NS));
+            assert!(elem2.has_child("show", ns::DEFAULT_NS));
+            assert_eq!(into_namespace(elem2, namespace), elem);
+        }
+
+        // Payloads aren’t moved, only stanzas.
+        let elem: Element = "<ping xmlns='urn:xmpp:ping'/>".parse().unwrap();
+        assert_eq!(into_namespace(elem.clone(), ns::JABBER_SERVER), elem);
+    }
+}
@@ -10,8 +10,11 @@ use crate::presence::PresencePayload;
 use crate::util::error::Error;
 use crate::Element;
 use jid::Jid;
+use minidom::IntoAttributeValue;
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
 
 generate_attribute!(
     /// The type of the error.
@@ -213,7 +216,10 @@ pub struct StanzaError {
 }
 
 impl MessagePayload for StanzaError {}
-impl PresencePayload for StanzaError {}
+impl PresencePayload for StanzaError {
+    const NAME: &'static str = "error";
+    const NS: &'static str = ns::DEFAULT_NS;
+}
 
 impl StanzaError {
     /// Create a new `<error/>` with the according content.
@@ -241,6 +247,22 @@ impl StanzaError {
     }
 }
 
+/// Displays the condition and type of this error, along with its text in
+/// the default language, or any other one.
+impl fmt::Display for StanzaError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let condition = Element::from(self.defined_condition.clone());
+        let type_ = self.type_.clone().into_attribute_value().unwrap();
+        write!(fmt, "{} ({})", condition.name(), type_)?;
+        if let Some(text) = self.texts.get("").or_else(|| self.texts.values().next()) {
+            write!(fmt, ": {}", text)?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for StanzaError {}
+
 impl TryFrom<Element> for StanzaError {
     type Error = Error;
 
@@ -261,11 +283,9 @@ impl TryFrom<Element> for StanzaError {
             if child.is("text", ns::XMPP_STANZAS) {
                 check_no_children!(child, "text");
                 check_no_unknown_attributes!(child, "text", ["xml:lang"]);
-                let lang = get_attr!(elem, "xml:lang", Default);
+                let lang = get_attr!(child, "xml:lang", Default);
                 if stanza_error.texts.insert(lang, child.text()).is_some() {
-                    return Err(Error::ParseError(
-                        "Text element present twice for the same xml:lang.",
-                    ));
+                    return Err(Error::DuplicateElement { name: "text" });
                 }
             } else if child.has_ns(ns::XMPP_STANZAS) {
                 if defined_condition.is_some() {
@@ -343,6 +363,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_text_lang() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<error xmlns='jabber:client' type='cancel'><undefined-condition xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/><text xmlns='urn:ietf:params:xml:ns:xmpp-stanzas' xml:lang='en'>Oops</text><text xmlns='urn:ietf:params:xml:ns:xmpp-stanzas' xml:lang='fr'>Zut</text></error>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<error xmlns='jabber:component:accept' type='cancel'><undefined-condition xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/><text xmlns='urn:ietf:params:xml:ns:xmpp-stanzas' xml:lang='en'>Oops</text><text xmlns='urn:ietf:params:xml:ns:xmpp-stanzas' xml:lang='fr'>Zut</text></error>".parse().unwrap();
+        let error = StanzaError::try_from(elem).unwrap();
+        assert_eq!(error.texts[&String::from("en")], "Oops");
+        assert_eq!(error.texts[&String::from("fr")], "Zut");
+    }
+
     #[test]
     fn test_invalid_type() {
         #[cfg(not(feature = "component"))]
@@ -389,4 +420,33 @@ mod tests {
         };
         assert_eq!(message, "Error must have a defined-condition.");
     }
+
+    #[test]
+    fn test_display() {
+        let error = StanzaError::new(
+            ErrorType::Cancel,
+            DefinedCondition::ItemNotFound,
+            "en",
+            "No such room",
+        );
+        assert_eq!(error.to_string(), "item-not-found (cancel): No such room");
+
+        let elem: Element = "<iq xmlns='jabber:client' type='error' id='a'><error type='modify' by='@'><bad-request xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/></error></iq>"
+            .parse()
+            .unwrap();
+        let error = crate::iq::Iq::try_from(elem).unwrap_err();
+        let mut causes = vec![error.to_string()];
+        let mut source = error.source();
+        while let Some(error) = source {
+            causes.push(error.to_string());
+            source = error.source();
+        }
+        assert_eq!(
+            causes,
+            [
+                "JID parsing error: nodepart empty despite the presence of a @",
+                "nodepart empty despite the presence of a @"
+            ]
+        );
+    }
 }
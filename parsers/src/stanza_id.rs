@@ -4,8 +4,30 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::message::MessagePayload;
-use jid::Jid;
+use crate::message::{Message, MessagePayload, MessageType};
+use crate::ns;
+use jid::{BareJid, Jid};
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static ID_PREFIX: u64 = RandomState::new().build_hasher().finish();
+}
+
+/// Generates an id unique within this process, to be used as the `id`
+/// attribute of a stanza or in an [OriginId](struct.OriginId.html).
+///
+/// It is made of a random prefix followed by an incrementing counter, so
+/// ids generated by different processes are unlikely to collide either.
+pub fn generate_id() -> String {
+    let prefix = ID_PREFIX.with(|prefix| *prefix);
+    let count = ID_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}-{}", prefix, count)
+}
 
 generate_element!(
     /// Gives the identifier a service has stamped on this stanza, often in
@@ -34,13 +56,100 @@ generate_element!(
 
 impl MessagePayload for OriginId {}
 
+/// A key identifying a message across the copies of it received live,
+/// through [Carbons](../carbons/index.html) or from [an
+/// archive](../mam/index.html), to deduplicate them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MessageKey {
+    /// The origin-id set by the sender.
+    OriginId {
+        /// The sender, as a bare JID except in a MUC where it is the
+        /// occupant JID.
+        from: Jid,
+
+        /// The origin-id.
+        id: String,
+    },
+
+    /// The stanza-id stamped by our archive, which is our account for
+    /// one-to-one messages or the room for groupchat ones.
+    StanzaId {
+        /// The archive which stamped this id.
+        by: BareJid,
+
+        /// The stanza-id.
+        id: String,
+    },
+
+    /// The id attribute of the message, the least reliable of all since
+    /// it may be missing or reused by careless clients.
+    Id {
+        /// The sender, as for `OriginId`.
+        from: Jid,
+
+        /// The id attribute.
+        id: String,
+    },
+}
+
+impl MessageKey {
+    /// Picks the best key available on this message, received on the
+    /// account `account`: its origin-id, then the stanza-id stamped by
+    /// our archive, then its id attribute.
+    ///
+    /// Carbons and MAM results have to be unwrapped first, so that this
+    /// gets the forwarded message.
+    pub fn from_message(message: &Message, account: &BareJid) -> Option<MessageKey> {
+        let groupchat = message.type_ == MessageType::Groupchat;
+        let from = match message.from {
+            Some(ref from) if groupchat => from.clone(),
+            Some(ref from) => Jid::Bare(from.to_bare()),
+            None => Jid::Bare(account.clone()),
+        };
+        let archive = if groupchat {
+            from.to_bare()
+        } else {
+            account.clone()
+        };
+
+        let mut stanza_id = None;
+        for payload in message.payloads.iter() {
+            if payload.is("origin-id", ns::SID) {
+                if let Ok(origin_id) = OriginId::try_from(payload.clone()) {
+                    return Some(MessageKey::OriginId {
+                        from,
+                        id: origin_id.id,
+                    });
+                }
+            } else if stanza_id.is_none() && payload.is("stanza-id", ns::SID) {
+                // Any other entity on the way could have stamped its own
+                // stanza-id, only the one of our archive is of any use.
+                match StanzaId::try_from(payload.clone()) {
+                    Ok(StanzaId {
+                        id,
+                        by: Jid::Bare(by),
+                    }) if by == archive => stanza_id = Some(id),
+                    _ => (),
+                }
+            }
+        }
+
+        match (stanza_id, &message.id) {
+            (Some(id), _) => Some(MessageKey::StanzaId { by: archive, id }),
+            (None, Some(id)) => Some(MessageKey::Id {
+                from,
+                id: id.clone(),
+            }),
+            (None, None) => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::util::error::Error;
     use crate::Element;
-    use jid::BareJid;
-    use std::convert::TryFrom;
 
     #[cfg(target_pointer_width = "32")]
     #[test]
@@ -56,6 +165,67 @@ mod tests {
         assert_size!(OriginId, 24);
     }
 
+    #[test]
+    fn test_message_key() {
+        let account = BareJid::new("juliet@capulet.example").unwrap();
+        #[cfg(not(feature = "component"))]
+        let xmlns = "jabber:client";
+        #[cfg(feature = "component")]
+        let xmlns = "jabber:component:accept";
+
+        let elem: Element = format!("<message xmlns='{}' from='romeo@montague.example/orchard' id='a'><origin-id xmlns='urn:xmpp:sid:0' id='b'/><stanza-id xmlns='urn:xmpp:sid:0' id='c' by='juliet@capulet.example'/></message>", xmlns).parse().unwrap();
+        let message = Message::try_from(elem).unwrap();
+        assert_eq!(
+            MessageKey::from_message(&message, &account),
+            Some(MessageKey::OriginId {
+                from: Jid::new("romeo@montague.example").unwrap(),
+                id: String::from("b"),
+            })
+        );
+
+        let elem: Element = format!("<message xmlns='{}' from='romeo@montague.example/orchard' id='a'><stanza-id xmlns='urn:xmpp:sid:0' id='d' by='montague.example'/><stanza-id xmlns='urn:xmpp:sid:0' id='c' by='juliet@capulet.example'/></message>", xmlns).parse().unwrap();
+        let message = Message::try_from(elem).unwrap();
+        assert_eq!(
+            MessageKey::from_message(&message, &account),
+            Some(MessageKey::StanzaId {
+                by: account.clone(),
+                id: String::from("c"),
+            })
+        );
+
+        let elem: Element = format!("<message xmlns='{}' from='romeo@montague.example/orchard' id='a'><stanza-id xmlns='urn:xmpp:sid:0' id='d' by='montague.example'/></message>", xmlns).parse().unwrap();
+        let message = Message::try_from(elem).unwrap();
+        assert_eq!(
+            MessageKey::from_message(&message, &account),
+            Some(MessageKey::Id {
+                from: Jid::new("romeo@montague.example").unwrap(),
+                id: String::from("a"),
+            })
+        );
+
+        let elem: Element = format!("<message xmlns='{}' from='coven@chat.shakespeare.example/thirdwitch' type='groupchat'><stanza-id xmlns='urn:xmpp:sid:0' id='e' by='coven@chat.shakespeare.example'/></message>", xmlns).parse().unwrap();
+        let message = Message::try_from(elem).unwrap();
+        assert_eq!(
+            MessageKey::from_message(&message, &account),
+            Some(MessageKey::StanzaId {
+                by: BareJid::new("coven@chat.shakespeare.example").unwrap(),
+                id: String::from("e"),
+            })
+        );
+
+        let elem: Element = format!("<message xmlns='{}'/>", xmlns).parse().unwrap();
+        let message = Message::try_from(elem).unwrap();
+        assert_eq!(MessageKey::from_message(&message, &account), None);
+    }
+
+    #[test]
+    fn test_generate_id() {
+        let id1 = generate_id();
+        let id2 = generate_id();
+        assert_ne!(id1, id2);
+        assert!(!id1.is_empty());
+    }
+
     #[test]
     fn test_simple() {
         let elem: Element = "<stanza-id xmlns='urn:xmpp:sid:0' id='coucou' by='coucou@coucou'/>"
@@ -78,11 +248,10 @@ mod tests {
             .parse()
             .unwrap();
         let error = StanzaId::try_from(elem).unwrap_err();
-        let message = match error {
-            Error::ParseError(string) => string,
-            _ => panic!(),
-        };
-        assert_eq!(message, "Unknown child in stanza-id element.");
+        assert_eq!(
+            error.to_string(),
+            "unknown child <coucou/> in stanza-id element"
+        );
     }
 
     #[test]
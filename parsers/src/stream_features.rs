@@ -0,0 +1,176 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::csi;
+use crate::ns;
+use crate::sm::StreamManagement;
+use crate::util::error::Error;
+use crate::Element;
+use std::convert::TryFrom;
+
+/// The `<stream:features/>` nonza, advertising what the server supports at
+/// the current stage of the stream.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StreamFeatures {
+    /// Whether the server offers to upgrade the stream to TLS.
+    pub starttls: bool,
+
+    /// The SASL mechanisms the server offers, in its order of preference.
+    pub sasl_mechanisms: Vec<String>,
+
+    /// Whether the server offers resource binding.
+    pub bind: bool,
+
+    /// Whether the server supports Stream Management (XEP-0198).
+    pub sm: bool,
+
+    /// Whether the server supports Client State Indication (XEP-0352).
+    pub csi: bool,
+
+    /// Whether the server supports In-Band Registration (XEP-0077).
+    pub register: bool,
+
+    /// The features this struct doesn’t know about.
+    pub others: Vec<Element>,
+}
+
+impl TryFrom<Element> for StreamFeatures {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<StreamFeatures, Error> {
+        check_self!(elem, "features", STREAM);
+
+        let mut features = StreamFeatures::default();
+        for child in elem.children() {
+            if child.is("starttls", ns::TLS) {
+                features.starttls = true;
+            } else if child.is("mechanisms", ns::SASL) {
+                features.sasl_mechanisms = child
+                    .children()
+                    .filter(|mechanism| mechanism.is("mechanism", ns::SASL))
+                    .map(|mechanism| mechanism.text_trimmed())
+                    .collect();
+            } else if child.is("bind", ns::BIND) {
+                features.bind = true;
+            } else if child.is("sm", ns::SM) {
+                features.sm = true;
+            } else if child.is("csi", ns::CSI) {
+                features.csi = true;
+            } else if child.is("register", ns::REGISTER_FEATURE) {
+                features.register = true;
+            } else {
+                features.others.push(child.clone());
+            }
+        }
+        Ok(features)
+    }
+}
+
+impl From<StreamFeatures> for Element {
+    fn from(features: StreamFeatures) -> Element {
+        Element::builder("features", ns::STREAM)
+            .append_if(
+                features.starttls,
+                Element::builder("starttls", ns::TLS).build(),
+            )
+            .append_if(
+                !features.sasl_mechanisms.is_empty(),
+                Element::builder("mechanisms", ns::SASL).append_all(
+                    features
+                        .sasl_mechanisms
+                        .into_iter()
+                        .map(|mechanism| Element::builder("mechanism", ns::SASL).append(mechanism)),
+                ),
+            )
+            .append_if(features.bind, Element::builder("bind", ns::BIND).build())
+            .append_if(features.sm, Element::from(StreamManagement))
+            .append_if(features.csi, Element::from(csi::Feature))
+            .append_if(
+                features.register,
+                Element::builder("register", ns::REGISTER_FEATURE).build(),
+            )
+            .append_all(features.others)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(StreamFeatures, 32);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(StreamFeatures, 56);
+    }
+
+    #[test]
+    fn test_before_tls() {
+        let elem: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'>
+            <starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'><required/></starttls>
+            <mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>
+                <mechanism>SCRAM-SHA-1-PLUS</mechanism>
+                <mechanism>SCRAM-SHA-1</mechanism>
+            </mechanisms>
+            <register xmlns='http://jabber.org/features/iq-register'/>
+        </stream:features>"
+            .parse()
+            .unwrap();
+        let features = StreamFeatures::try_from(elem).unwrap();
+        assert!(features.starttls);
+        assert_eq!(
+            features.sasl_mechanisms,
+            vec![
+                String::from("SCRAM-SHA-1-PLUS"),
+                String::from("SCRAM-SHA-1")
+            ]
+        );
+        assert!(features.register);
+        assert!(!features.bind);
+        assert!(!features.sm);
+        assert!(!features.csi);
+        assert!(features.others.is_empty());
+    }
+
+    #[test]
+    fn test_after_auth() {
+        let elem: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'>
+            <bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/>
+            <sm xmlns='urn:xmpp:sm:3'/>
+            <csi xmlns='urn:xmpp:csi:0'/>
+            <ver xmlns='urn:xmpp:features:rosterver'/>
+        </stream:features>"
+            .parse()
+            .unwrap();
+        let features = StreamFeatures::try_from(elem).unwrap();
+        assert!(!features.starttls);
+        assert!(features.sasl_mechanisms.is_empty());
+        assert!(features.bind);
+        assert!(features.sm);
+        assert!(features.csi);
+        assert!(!features.register);
+        assert_eq!(features.others.len(), 1);
+        assert!(features.others[0].is("ver", "urn:xmpp:features:rosterver"));
+
+        let elem = Element::from(features.clone());
+        assert_eq!(StreamFeatures::try_from(elem).unwrap(), features);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<features xmlns='jabber:client'/>".parse().unwrap();
+        let error = StreamFeatures::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "This is not a features element.");
+    }
+}
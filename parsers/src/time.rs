@@ -4,7 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::date::DateTime;
+use crate::date::{Clock, DateTime};
 use crate::iq::{IqGetPayload, IqResultPayload};
 use crate::ns;
 use crate::util::error::Error;
@@ -26,6 +26,14 @@ impl IqGetPayload for TimeQuery {}
 #[derive(Debug, Clone)]
 pub struct TimeResult(pub DateTime);
 
+impl TimeResult {
+    /// Creates a result containing the current time according to the given
+    /// clock, in its timezone.
+    pub fn now<C: Clock>(clock: &C) -> TimeResult {
+        TimeResult(clock.now())
+    }
+}
+
 impl IqResultPayload for TimeResult {}
 
 impl TryFrom<Element> for TimeResult {
@@ -62,7 +70,10 @@ impl TryFrom<Element> for TimeResult {
                 }
                 utc = Some(date_time);
             } else {
-                return Err(Error::ParseError("Unknown child in time element."));
+                return Err(Error::UnknownChild {
+                    element: "time",
+                    child: child.name().to_owned(),
+                });
             }
         }
 
@@ -116,4 +127,15 @@ mod tests {
         let elem2 = Element::from(time);
         assert_eq!(elem1, elem2);
     }
+
+    #[test]
+    fn test_now() {
+        let clock = || DateTime::from_str("2006-12-19T12:58:35-05:00").unwrap();
+        let elem: Element = TimeResult::now(&clock).into();
+        let expected: Element =
+            "<time xmlns='urn:xmpp:time'><tzo>-05:00</tzo><utc>2006-12-19T17:58:35Z</utc></time>"
+                .parse()
+                .unwrap();
+        assert_eq!(elem, expected);
+    }
 }
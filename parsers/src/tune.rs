@@ -104,6 +104,19 @@ impl Tune {
             uri: None,
         }
     }
+
+    /// Whether this is the empty `<tune/>` a user publishes when they stop
+    /// listening to music, after which the tune previously received should no
+    /// longer be displayed.
+    pub fn is_empty(&self) -> bool {
+        self.artist.is_none()
+            && self.length.is_none()
+            && self.rating.is_none()
+            && self.source.is_none()
+            && self.title.is_none()
+            && self.track.is_none()
+            && self.uri.is_none()
+    }
 }
 
 impl TryFrom<Element> for Tune {
@@ -218,6 +231,7 @@ mod tests {
         assert!(tune.title.is_none());
         assert!(tune.track.is_none());
         assert!(tune.uri.is_none());
+        assert!(tune.is_empty());
 
         let elem3 = tune.into();
         assert_eq!(elem2, elem3);
@@ -229,6 +243,7 @@ mod tests {
             .parse()
             .unwrap();
         let tune = Tune::try_from(elem).unwrap();
+        assert!(!tune.is_empty());
         assert_eq!(tune.artist, Some(Artist::from_str("Yes").unwrap()));
         assert_eq!(tune.length, Some(Length(686)));
         assert_eq!(tune.rating, Some(Rating(8)));
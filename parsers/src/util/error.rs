@@ -38,10 +38,85 @@ pub enum Error {
     /// Generated when text which should be a
     /// [DateTime](../date/struct.DateTime.html) fails to parse.
     ChronoParseError(chrono::ParseError),
+
+    /// Generated when an element carries an attribute its specification
+    /// doesn’t allow.
+    UnknownAttribute {
+        /// The name of the element, e.g. `item`.
+        element: &'static str,
+
+        /// The name of the unknown attribute.
+        attribute: String,
+    },
+
+    /// Generated when an element contains a child its specification doesn’t
+    /// allow.
+    UnknownChild {
+        /// The name of the parent element, e.g. `item`.
+        element: &'static str,
+
+        /// The local name of the unknown child.
+        child: String,
+    },
+
+    /// Generated when a child element which may only appear once in its
+    /// parent (or once per xml:lang) is present more than once.
+    DuplicateElement {
+        /// The local name of the duplicated element.
+        name: &'static str,
+    },
+
+    /// Like `DuplicateElement`, for an element whose values are worth
+    /// reporting, e.g. the two `<show/>` of a presence.
+    DuplicateValue {
+        /// The local name of the duplicated element.
+        name: &'static str,
+
+        /// The value of the first one, which got kept.
+        first: String,
+
+        /// The text of the next one, which may not even be valid.
+        second: String,
+    },
+
+    /// Wraps an error which happened in a child element or in an attribute,
+    /// e.g. `item[3]/@jid` for the jid attribute of the third item child.
+    Located {
+        /// The path to the faulty element or attribute, relative to the
+        /// element being parsed.
+        path: String,
+
+        /// The error which happened there.
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Prefixes the location of this error with the given path step.
+    pub(crate) fn within<S: fmt::Display>(self, step: S) -> Error {
+        match self {
+            Error::Located { path, error } => Error::Located {
+                path: format!("{}/{}", step, path),
+                error,
+            },
+            error => Error::Located {
+                path: step.to_string(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Returns the actual error, stripped from its location if any.
+    pub fn unlocated(&self) -> &Error {
+        match self {
+            Error::Located { error, .. } => error,
+            error => error,
+        }
+    }
 }
 
 impl StdError for Error {
-    fn cause(&self) -> Option<&dyn StdError> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match self {
             Error::ParseError(_) => None,
             Error::Base64Error(e) => Some(e),
@@ -50,6 +125,11 @@ impl StdError for Error {
             Error::ParseAddrError(e) => Some(e),
             Error::JidParseError(e) => Some(e),
             Error::ChronoParseError(e) => Some(e),
+            Error::UnknownAttribute { .. } => None,
+            Error::UnknownChild { .. } => None,
+            Error::DuplicateElement { .. } => None,
+            Error::DuplicateValue { .. } => None,
+            Error::Located { error, .. } => Some(&**error),
         }
     }
 }
@@ -64,6 +144,27 @@ impl fmt::Display for Error {
             Error::ParseAddrError(e) => write!(fmt, "IP address parsing error: {}", e),
             Error::JidParseError(e) => write!(fmt, "JID parsing error: {}", e),
             Error::ChronoParseError(e) => write!(fmt, "time parsing error: {}", e),
+            Error::UnknownAttribute { element, attribute } => {
+                write!(
+                    fmt,
+                    "unknown attribute '{}' in {} element",
+                    attribute, element
+                )
+            }
+            Error::UnknownChild { element, child } => {
+                write!(fmt, "unknown child <{}/> in {} element", child, element)
+            }
+            Error::DuplicateElement { name } => write!(fmt, "duplicate <{}/> element", name),
+            Error::DuplicateValue {
+                name,
+                first,
+                second,
+            } => write!(
+                fmt,
+                "duplicate <{}/> element: {:?} then {:?}",
+                name, first, second
+            ),
+            Error::Located { path, error } => write!(fmt, "{}: {}", path, error),
         }
     }
 }
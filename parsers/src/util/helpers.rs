@@ -9,6 +9,17 @@ use base64::{engine::general_purpose::STANDARD as Base64Engine, Engine};
 use jid::Jid;
 use std::str::FromStr;
 
+/// The xml:lang to put on a child keyed under `lang`, so that it parses back
+/// the same under a stanza whose own xml:lang is `stanza_lang`.
+pub(crate) fn child_lang<'a>(lang: &'a str, stanza_lang: Option<&str>) -> Option<&'a str> {
+    match stanza_lang {
+        Some(stanza_lang) if stanza_lang == lang => None,
+        Some(_) => Some(lang),
+        None if lang.is_empty() => None,
+        None => Some(lang),
+    }
+}
+
 /// Codec for text content.
 pub struct Text;
 
@@ -65,6 +76,15 @@ impl Base64 {
     pub fn encode(b: &[u8]) -> Option<String> {
         Some(Base64Engine.encode(b))
     }
+
+    /// Decodes `s` at the end of `buf`, leaving it untouched on error.
+    pub fn decode_into(s: &str, buf: &mut Vec<u8>) -> Result<(), Error> {
+        let len = buf.len();
+        Base64Engine.decode_vec(s, buf).map_err(|error| {
+            buf.truncate(len);
+            error.into()
+        })
+    }
 }
 
 /// Codec wrapping base64 encode/decode, while ignoring whitespace characters.
@@ -121,3 +141,100 @@ impl JidCodec {
         Some(jid.to_string())
     }
 }
+
+/// Serde helpers, to be used with `#[serde(with = "...")]` on anything which
+/// can be converted from and into an `Element`, so that it gets stored as its
+/// XML serialisation and parsed back.
+#[cfg(feature = "serde")]
+pub(crate) mod xml_string {
+    use crate::Element;
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::convert::TryFrom;
+    use std::fmt::Display;
+
+    fn to_string<T: Clone + Into<Element>>(value: &T) -> String {
+        String::from(&value.clone().into())
+    }
+
+    fn from_string<'de, D, T>(string: &str) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: TryFrom<Element>,
+        T::Error: Display,
+    {
+        let elem: Element = string.parse().map_err(D::Error::custom)?;
+        T::try_from(elem).map_err(D::Error::custom)
+    }
+
+    pub fn serialize<S, T>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Clone + Into<Element>,
+    {
+        to_string(value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: TryFrom<Element>,
+        T::Error: Display,
+    {
+        let string = String::deserialize(deserializer)?;
+        from_string::<D, T>(&string)
+    }
+
+    /// The same, for an `Option` of something convertible into an `Element`.
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+            T: Clone + Into<Element>,
+        {
+            value.as_ref().map(to_string).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+        where
+            D: Deserializer<'de>,
+            T: TryFrom<Element>,
+            T::Error: Display,
+        {
+            Option::<String>::deserialize(deserializer)?
+                .map(|string| from_string::<D, T>(&string))
+                .transpose()
+        }
+    }
+
+    /// The same, for a `Vec` of something convertible into an `Element`.
+    pub mod vec {
+        use super::*;
+
+        pub fn serialize<S, T>(values: &[T], serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+            T: Clone + Into<Element>,
+        {
+            values
+                .iter()
+                .map(to_string)
+                .collect::<Vec<_>>()
+                .serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+        where
+            D: Deserializer<'de>,
+            T: TryFrom<Element>,
+            T::Error: Display,
+        {
+            Vec::<String>::deserialize(deserializer)?
+                .iter()
+                .map(|string| from_string::<D, T>(string))
+                .collect()
+        }
+    }
+}
@@ -60,6 +60,26 @@ macro_rules! get_attr {
     };
 }
 
+macro_rules! get_jid_attr {
+    ($elem:ident, $attr:tt, $stanza:tt) => {
+        get_attr!(
+            $elem,
+            $attr,
+            Option,
+            value,
+            value.parse().map_err(|_| {
+                crate::util::error::Error::ParseError(concat!(
+                    "Invalid '",
+                    $attr,
+                    "' JID in ",
+                    $stanza,
+                    "."
+                ))
+            })?
+        )
+    };
+}
+
 macro_rules! generate_attribute {
     ($(#[$meta:meta])* $elem:ident, $name:tt, {$($(#[$a_meta:meta])* $a:ident => $b:tt),+$(,)?}) => (
         $(#[$meta])*
@@ -318,12 +338,11 @@ macro_rules! check_ns_only {
 macro_rules! check_no_children {
     ($elem:ident, $name:tt) => {
         #[cfg(not(feature = "disable-validation"))]
-        for _ in $elem.children() {
-            return Err(crate::util::error::Error::ParseError(concat!(
-                "Unknown child in ",
-                $name,
-                " element."
-            )));
+        for _child in $elem.children() {
+            return Err(crate::util::error::Error::UnknownChild {
+                element: $name,
+                child: _child.name().to_owned(),
+            });
         }
     };
 }
@@ -331,12 +350,11 @@ macro_rules! check_no_children {
 macro_rules! check_no_attributes {
     ($elem:ident, $name:tt) => {
         #[cfg(not(feature = "disable-validation"))]
-        for _ in $elem.attrs() {
-            return Err(crate::util::error::Error::ParseError(concat!(
-                "Unknown attribute in ",
-                $name,
-                " element."
-            )));
+        for (_attr, _) in $elem.attrs() {
+            return Err(crate::util::error::Error::UnknownAttribute {
+                element: $name,
+                attribute: _attr.to_owned(),
+            });
         }
     };
 }
@@ -350,7 +368,10 @@ macro_rules! check_no_unknown_attributes {
                     continue;
                 }
             )*
-            return Err(crate::util::error::Error::ParseError(concat!("Unknown attribute in ", $name, " element.")));
+            return Err(crate::util::error::Error::UnknownAttribute {
+                element: $name,
+                attribute: _attr.to_owned(),
+            });
         }
     );
 }
@@ -485,20 +506,24 @@ macro_rules! start_parse_elem {
 }
 
 macro_rules! do_parse {
-    ($elem:ident, Element) => {
+    ($elem:ident, Element, $path:expr) => {
         $elem.clone()
     };
-    ($elem:ident, String) => {
+    ($elem:ident, String, $path:expr) => {
         $elem.text()
     };
-    ($elem:ident, $constructor:ident) => {
-        $constructor::try_from($elem.clone())?
+    ($elem:ident, $constructor:ident, $path:expr) => {
+        $constructor::try_from($elem.clone()).map_err(|error| error.within($path))?
     };
 }
 
 macro_rules! do_parse_elem {
     ($temp:ident: Vec = $constructor:ident => $elem:ident, $name:tt, $parent_name:tt) => {
-        $temp.push(do_parse!($elem, $constructor));
+        $temp.push(do_parse!(
+            $elem,
+            $constructor,
+            format!("{}[{}]", $name, $temp.len() + 1)
+        ));
     };
     ($temp:ident: Option = $constructor:ident => $elem:ident, $name:tt, $parent_name:tt) => {
         if $temp.is_some() {
@@ -510,7 +535,7 @@ macro_rules! do_parse_elem {
                 " child."
             )));
         }
-        $temp = Some(do_parse!($elem, $constructor));
+        $temp = Some(do_parse!($elem, $constructor, $name));
     };
     ($temp:ident: Required = $constructor:ident => $elem:ident, $name:tt, $parent_name:tt) => {
         if $temp.is_some() {
@@ -522,7 +547,7 @@ macro_rules! do_parse_elem {
                 " child."
             )));
         }
-        $temp = Some(do_parse!($elem, $constructor));
+        $temp = Some(do_parse!($elem, $constructor, $name));
     };
     ($temp:ident: Present = $constructor:ident => $elem:ident, $name:tt, $parent_name:tt) => {
         if $temp {
@@ -561,14 +586,21 @@ macro_rules! finish_parse_elem {
 
 macro_rules! generate_serialiser {
     ($builder:ident, $parent:ident, $elem:ident, Required, String, ($name:tt, $ns:ident)) => {
-        $builder.append(
-            crate::Element::builder($name, crate::ns::$ns)
-                .append(::minidom::Node::Text($parent.$elem)),
-        )
+        $builder.append(crate::Element::builder($name, crate::ns::$ns).append_all(
+            if $parent.$elem.is_empty() {
+                None
+            } else {
+                Some(::minidom::Node::Text($parent.$elem))
+            },
+        ))
     };
     ($builder:ident, $parent:ident, $elem:ident, Option, String, ($name:tt, $ns:ident)) => {
         $builder.append_all($parent.$elem.map(|elem| {
-            crate::Element::builder($name, crate::ns::$ns).append(::minidom::Node::Text(elem))
+            crate::Element::builder($name, crate::ns::$ns).append_all(if elem.is_empty() {
+                None
+            } else {
+                Some(::minidom::Node::Text(elem))
+            })
         }))
     };
     ($builder:ident, $parent:ident, $elem:ident, Option, $constructor:ident, ($name:tt, *)) => {
@@ -658,11 +690,22 @@ macro_rules! generate_element {
                         continue;
                     }
                     )*
-                    return Err(crate::util::error::Error::ParseError(concat!("Unknown child in ", $name, " element.")));
+                    return Err(crate::util::error::Error::UnknownChild {
+                        element: $name,
+                        child: _child.name().to_owned(),
+                    });
                 }
                 Ok($elem {
                     $(
-                        $attr: get_attr!(elem, $attr_name, $attr_action),
+                        $attr: get_attr!(
+                            elem,
+                            $attr_name,
+                            $attr_action,
+                            value,
+                            value
+                                .parse()
+                                .map_err(|error| crate::util::error::Error::from(error).within(concat!("@", $attr_name)))?
+                        ),
                     )*
                     $(
                         $child_ident: finish_parse_elem!($child_ident: $coucou = $child_name, $name),
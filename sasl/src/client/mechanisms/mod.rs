@@ -1,12 +1,14 @@
 //! Provides a few SASL mechanisms.
 
 mod anonymous;
+mod oauthbearer;
 mod plain;
 
 #[cfg(feature = "scram")]
 mod scram;
 
 pub use self::anonymous::Anonymous;
+pub use self::oauthbearer::OAuthBearer;
 pub use self::plain::Plain;
 
 #[cfg(feature = "scram")]
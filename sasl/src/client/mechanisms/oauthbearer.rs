@@ -0,0 +1,119 @@
+//! Provides the SASL "OAUTHBEARER" mechanism, as defined in RFC 7628.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::client::{Mechanism, MechanismError};
+use crate::common::{escape_username, Credentials, Identity, Secret};
+
+/// A struct for the SASL OAUTHBEARER mechanism.
+pub struct OAuthBearer {
+    authzid: Option<String>,
+    token: String,
+}
+
+impl OAuthBearer {
+    /// Constructs a new struct for authenticating using the SASL OAUTHBEARER mechanism.
+    ///
+    /// It is recommended that instead you use a `Credentials` struct and turn it into the
+    /// requested mechanism using `from_credentials`.
+    pub fn new<T: Into<String>>(authzid: Option<String>, token: T) -> OAuthBearer {
+        OAuthBearer {
+            authzid,
+            token: token.into(),
+        }
+    }
+}
+
+impl Mechanism for OAuthBearer {
+    fn name(&self) -> &str {
+        "OAUTHBEARER"
+    }
+
+    fn from_credentials(credentials: Credentials) -> Result<OAuthBearer, MechanismError> {
+        if let Secret::Token(token) = credentials.secret {
+            let authzid = match credentials.identity {
+                Identity::Username(username) => Some(username),
+                Identity::None => None,
+            };
+            Ok(OAuthBearer::new(authzid, token))
+        } else {
+            Err(MechanismError::OAuthBearerRequiresToken)
+        }
+    }
+
+    fn initial(&mut self) -> Vec<u8> {
+        let mut auth = Vec::new();
+        auth.extend(b"n,");
+        if let Some(ref authzid) = self.authzid {
+            auth.extend(b"a=");
+            auth.extend(escape_username(authzid).bytes());
+        }
+        auth.extend(b",\x01auth=Bearer ");
+        auth.extend(self.token.bytes());
+        auth.extend(b"\x01\x01");
+        auth
+    }
+
+    fn response(&mut self, _challenge: &[u8]) -> Result<Vec<u8>, MechanismError> {
+        // The only challenge the server can send is an error status, to which the client must
+        // reply with a single %x01 so that the server can send its <failure/>.
+        Ok(vec![0x01])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::mechanisms::OAuthBearer;
+    use crate::client::{Mechanism, MechanismError};
+    use crate::common::Credentials;
+
+    #[test]
+    fn oauthbearer_works() {
+        // Source: RFC 7628, without the optional host and port.
+        let creds = Credentials::default()
+            .with_username("user@example.com")
+            .with_token("vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==");
+        let mut mechanism = OAuthBearer::from_credentials(creds).unwrap();
+        assert_eq!(
+            mechanism.initial(),
+            &b"n,a=user@example.com,\x01auth=Bearer vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==\x01\x01"[..]
+        );
+        let error = br#"{"status":"invalid_token","scope":"example_scope"}"#;
+        assert_eq!(mechanism.response(&error[..]).unwrap(), &b"\x01"[..]);
+    }
+
+    #[test]
+    fn oauthbearer_without_authzid() {
+        let creds = Credentials::default().with_token("token");
+        let mut mechanism = OAuthBearer::from_credentials(creds).unwrap();
+        assert_eq!(
+            mechanism.initial(),
+            &b"n,,\x01auth=Bearer token\x01\x01"[..]
+        );
+    }
+
+    #[test]
+    fn oauthbearer_escapes_authzid() {
+        let creds = Credentials::default()
+            .with_username("a,b=c")
+            .with_token("token");
+        let mut mechanism = OAuthBearer::from_credentials(creds).unwrap();
+        assert_eq!(
+            mechanism.initial(),
+            &b"n,a=a=2Cb=3Dc,\x01auth=Bearer token\x01\x01"[..]
+        );
+    }
+
+    #[test]
+    fn oauthbearer_requires_token() {
+        let creds = Credentials::default()
+            .with_username("user")
+            .with_password("pencil");
+        assert_eq!(
+            OAuthBearer::from_credentials(creds).err(),
+            Some(MechanismError::OAuthBearerRequiresToken)
+        );
+    }
+}
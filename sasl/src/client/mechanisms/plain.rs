@@ -1,5 +1,8 @@
 //! Provides the SASL "PLAIN" mechanism.
 
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use crate::client::{Mechanism, MechanismError};
 use crate::common::{Credentials, Identity, Password, Secret};
 
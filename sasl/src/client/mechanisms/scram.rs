@@ -3,12 +3,16 @@
 use base64::{engine::general_purpose::STANDARD as Base64, Engine};
 
 use crate::client::{Mechanism, MechanismError};
-use crate::common::scram::{generate_nonce, ScramProvider};
+use crate::common::escape_username;
+use crate::common::scram::{generate_nonce, saslprep, ScramProvider};
 use crate::common::{parse_frame, xor, ChannelBinding, Credentials, Identity, Password, Secret};
 
 use crate::error::Error;
 
-use std::marker::PhantomData;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
 
 enum ScramState {
     Init,
@@ -19,6 +23,18 @@ enum ScramState {
     GotServerData {
         server_signature: Vec<u8>,
     },
+    /// The server signature came in a last challenge, before an empty success.
+    Verified,
+}
+
+/// Checks the server-final-message against the signature we computed.
+fn verify_server_final(data: &[u8], server_signature: &[u8]) -> Result<(), MechanismError> {
+    let frame = parse_frame(data).map_err(|_| MechanismError::CannotDecodeSuccessResponse)?;
+    match frame.get("v").and_then(|v| Base64.decode(v).ok()) {
+        Some(sig) if sig == server_signature => Ok(()),
+        Some(_) => Err(MechanismError::InvalidSignatureInSuccessResponse),
+        None => Err(MechanismError::NoSignatureInSuccessResponse),
+    }
 }
 
 /// A struct for the SASL SCRAM-* and SCRAM-*-PLUS mechanisms.
@@ -38,14 +54,23 @@ impl<S: ScramProvider> Scram<S> {
     ///
     /// It is recommended that instead you use a `Credentials` struct and turn it into the
     /// requested mechanism using `from_credentials`.
+    ///
+    /// The username gets prepared with SASLprep here, failing with `Error::DeriveError` when that
+    /// isn’t possible.
     pub fn new<N: Into<String>, P: Into<Password>>(
         username: N,
         password: P,
         channel_binding: ChannelBinding,
     ) -> Result<Scram<S>, Error> {
+        let name = if channel_binding.is_bound() {
+            format!("SCRAM-{}-PLUS", S::name())
+        } else {
+            format!("SCRAM-{}", S::name())
+        };
+        let username = saslprep(&username.into())?.into_owned();
         Ok(Scram {
-            name: format!("SCRAM-{}", S::name()),
-            username: username.into(),
+            name,
+            username,
             password: password.into(),
             client_nonce: generate_nonce()?,
             state: ScramState::Init,
@@ -83,8 +108,11 @@ impl<S: ScramProvider> Mechanism for Scram<S> {
     fn from_credentials(credentials: Credentials) -> Result<Scram<S>, MechanismError> {
         if let Secret::Password(password) = credentials.secret {
             if let Identity::Username(username) = credentials.identity {
-                Scram::new(username, password, credentials.channel_binding)
-                    .map_err(|_| MechanismError::CannotGenerateNonce)
+                Scram::new(username, password, credentials.channel_binding).map_err(|err| match err
+                {
+                    Error::DeriveError(err) => MechanismError::DeriveError(err),
+                    _ => MechanismError::CannotGenerateNonce,
+                })
             } else {
                 Err(MechanismError::ScramRequiresUsername)
             }
@@ -98,7 +126,7 @@ impl<S: ScramProvider> Mechanism for Scram<S> {
         gs2_header.extend(self.channel_binding.header());
         let mut bare = Vec::new();
         bare.extend(b"n=");
-        bare.extend(self.username.bytes());
+        bare.extend(escape_username(&self.username).bytes());
         bare.extend(b",r=");
         bare.extend(self.client_nonce.bytes());
         let mut data = Vec::new();
@@ -127,7 +155,6 @@ impl<S: ScramProvider> Mechanism for Scram<S> {
                 let server_nonce = server_nonce.ok_or_else(|| MechanismError::NoServerNonce)?;
                 let salt = salt.ok_or_else(|| MechanismError::NoServerSalt)?;
                 let iterations = iterations.ok_or_else(|| MechanismError::NoServerIterations)?;
-                // TODO: SASLprep
                 let mut client_final_message_bare = Vec::new();
                 client_final_message_bare.extend(b"c=");
                 let mut cb_data: Vec<u8> = Vec::new();
@@ -158,6 +185,15 @@ impl<S: ScramProvider> Mechanism for Scram<S> {
                 };
                 ret = client_final_message;
             }
+            // Some servers send the server-final-message as a challenge, to which we answer
+            // with an empty response.
+            ScramState::GotServerData {
+                ref server_signature,
+            } => {
+                verify_server_final(challenge, server_signature)?;
+                next_state = ScramState::Verified;
+                ret = Vec::new();
+            }
             _ => {
                 return Err(MechanismError::InvalidState);
             }
@@ -167,21 +203,11 @@ impl<S: ScramProvider> Mechanism for Scram<S> {
     }
 
     fn success(&mut self, data: &[u8]) -> Result<(), MechanismError> {
-        let frame = parse_frame(data).map_err(|_| MechanismError::CannotDecodeSuccessResponse)?;
         match self.state {
             ScramState::GotServerData {
                 ref server_signature,
-            } => {
-                if let Some(sig) = frame.get("v").and_then(|v| Base64.decode(&v).ok()) {
-                    if sig == *server_signature {
-                        Ok(())
-                    } else {
-                        Err(MechanismError::InvalidSignatureInSuccessResponse)
-                    }
-                } else {
-                    Err(MechanismError::NoSignatureInSuccessResponse)
-                }
-            }
+            } => verify_server_final(data, server_signature),
+            ScramState::Verified if data.is_empty() => Ok(()),
             _ => Err(MechanismError::InvalidState),
         }
     }
@@ -189,9 +215,27 @@ impl<S: ScramProvider> Mechanism for Scram<S> {
 
 #[cfg(test)]
 mod tests {
+    use alloc::borrow::ToOwned;
+    use alloc::string::String;
+    use alloc::vec;
+
     use crate::client::mechanisms::Scram;
-    use crate::client::Mechanism;
+    use crate::client::{Mechanism, MechanismError};
     use crate::common::scram::{Sha1, Sha256};
+    use crate::common::ChannelBinding;
+
+    #[test]
+    fn scram_plus_with_tls_exporter() {
+        let binding = ChannelBinding::TlsExporter(vec![0x42; 32]);
+        let mut mechanism = Scram::<Sha256>::new("user", "pencil", binding).unwrap();
+        assert_eq!(mechanism.name(), "SCRAM-SHA-256-PLUS");
+        let init = mechanism.initial();
+        assert!(init.starts_with(b"p=tls-exporter,,n=user,r="));
+
+        let mechanism =
+            Scram::<Sha256>::new("user", "pencil", ChannelBinding::Unsupported).unwrap();
+        assert_eq!(mechanism.name(), "SCRAM-SHA-256");
+    }
 
     #[test]
     fn scram_sha1_works() {
@@ -243,4 +287,66 @@ mod tests {
         ); // again, depends on ordering…
         mechanism.success(&server_final[..]).unwrap();
     }
+
+    #[test]
+    fn scram_server_final_in_challenge() {
+        // The RFC 7677 exchange, with the server-final-message sent as a challenge.
+        let server_init = b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+        let server_final = b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+        let mut mechanism =
+            Scram::<Sha256>::new_with_nonce("user", "pencil", "rOprNGfwEbeRWgbNEkqO".to_owned());
+        mechanism.initial();
+        mechanism.response(&server_init[..]).unwrap();
+        let resp = mechanism.response(&server_final[..]).unwrap();
+        assert!(resp.is_empty());
+        mechanism.success(b"").unwrap();
+    }
+
+    #[test]
+    fn scram_wrong_server_signature() {
+        let server_init = b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+        let server_final = b"v=AAAATRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+        let mut mechanism =
+            Scram::<Sha256>::new_with_nonce("user", "pencil", "rOprNGfwEbeRWgbNEkqO".to_owned());
+        mechanism.initial();
+        mechanism.response(&server_init[..]).unwrap();
+        assert_eq!(
+            mechanism.success(&server_final[..]),
+            Err(MechanismError::InvalidSignatureInSuccessResponse)
+        );
+
+        // An empty success doesn’t skip the verification.
+        let mut mechanism =
+            Scram::<Sha256>::new_with_nonce("user", "pencil", "rOprNGfwEbeRWgbNEkqO".to_owned());
+        mechanism.initial();
+        mechanism.response(&server_init[..]).unwrap();
+        assert!(mechanism.success(b"").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn scram_special_characters() {
+        // The RFC 7677 exchange, with a username needing escaping and a password needing
+        // SASLprep.
+        let username = "us,er=";
+        let password = "pen\u{00AD}cil";
+        let client_nonce = "rOprNGfwEbeRWgbNEkqO";
+        let client_init = b"n,,n=us=2Cer=3D,r=rOprNGfwEbeRWgbNEkqO";
+        let server_init = b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+        let client_final = b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=WW6C8S0DPLpjc61Y7MWnGCcd1ijUnLGv3yqAyhwcxIk=";
+        let server_final = b"v=/h9sNjTyso3lv46QA8Fih6dXeyqFPvmpD0GnadEMiqs=";
+        let mut mechanism =
+            Scram::<Sha256>::new_with_nonce(username, password, client_nonce.to_owned());
+        let init = mechanism.initial();
+        assert_eq!(
+            String::from_utf8(init).unwrap(),
+            String::from_utf8(client_init[..].to_owned()).unwrap()
+        );
+        let resp = mechanism.response(&server_init[..]).unwrap();
+        assert_eq!(
+            String::from_utf8(resp).unwrap(),
+            String::from_utf8(client_final[..].to_owned()).unwrap()
+        );
+        mechanism.success(&server_final[..]).unwrap();
+    }
 }
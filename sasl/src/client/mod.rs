@@ -1,4 +1,5 @@
-use std::fmt;
+use alloc::vec::Vec;
+use core::fmt;
 
 use crate::common::Credentials;
 
@@ -14,6 +15,8 @@ pub enum MechanismError {
     PlainRequiresUsername,
     PlainRequiresPlaintextPassword,
 
+    OAuthBearerRequiresToken,
+
     CannotGenerateNonce,
     ScramRequiresUsername,
     ScramRequiresPassword,
@@ -60,6 +63,8 @@ impl fmt::Display for MechanismError {
                 MechanismError::PlainRequiresPlaintextPassword =>
                     "PLAIN requires a plaintext password",
 
+                MechanismError::OAuthBearerRequiresToken => "OAUTHBEARER requires a token",
+
                 MechanismError::CannotGenerateNonce => "can't generate nonce",
                 MechanismError::ScramRequiresUsername => "SCRAM requires a username",
                 MechanismError::ScramRequiresPassword => "SCRAM requires a password",
@@ -84,6 +89,7 @@ impl fmt::Display for MechanismError {
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for MechanismError {}
 
 /// A trait which defines SASL mechanisms.
@@ -1,12 +1,20 @@
-use std::collections::HashMap;
-
-use std::convert::From;
-
-use std::string::FromUtf8Error;
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
+use alloc::string::{FromUtf8Error, String};
+use alloc::vec::Vec;
 
 #[cfg(feature = "scram")]
 pub mod scram;
 
+#[cfg(feature = "scram")]
+use crate::common::scram::{DeriveError, ScramProvider};
+
+/// Escapes the `=` and `,` of a username, as the SCRAM `n=` attribute and the GS2 `a=`
+/// authzid require (RFC 5802 section 5.1).
+pub(crate) fn escape_username(username: &str) -> String {
+    username.replace('=', "=3D").replace(',', "=2C")
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Identity {
     None,
@@ -59,6 +67,12 @@ impl Credentials {
         self
     }
 
+    /// Creates a new Credentials with the specified OAuth 2.0 bearer token.
+    pub fn with_token<T: Into<String>>(mut self, token: T) -> Credentials {
+        self.secret = Secret::Token(token.into());
+        self
+    }
+
     /// Creates a new Credentials with the specified chanel binding.
     pub fn with_channel_binding(mut self, channel_binding: ChannelBinding) -> Credentials {
         self.channel_binding = channel_binding;
@@ -73,6 +87,8 @@ pub enum Secret {
     None,
     /// Password required.
     Password(Password),
+    /// OAuth 2.0 bearer token required.
+    Token(String),
 }
 
 impl Secret {
@@ -109,6 +125,29 @@ pub enum Password {
     },
 }
 
+impl Password {
+    /// Derives a `Password::Pbkdf2` from a plaintext password, using the PBKDF2 function of the
+    /// given `ScramProvider`.
+    ///
+    /// This lets a client do the expensive derivation only once and store its result, for
+    /// instance to reuse it across reconnections, as long as the server keeps sending the same
+    /// salt and iteration count.
+    #[cfg(feature = "scram")]
+    pub fn derive_pbkdf2<P: ScramProvider>(
+        plain: &str,
+        salt: &[u8],
+        iterations: u32,
+    ) -> Result<Password, DeriveError> {
+        let data = P::derive(&Password::Plain(plain.to_owned()), salt, iterations)?;
+        Ok(Password::Pbkdf2 {
+            method: P::name().to_owned(),
+            salt: salt.to_vec(),
+            iterations,
+            data,
+        })
+    }
+}
+
 impl From<String> for Password {
     fn from(s: String) -> Password {
         Password::Plain(s)
@@ -144,9 +183,9 @@ pub fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
 }
 
 #[doc(hidden)]
-pub fn parse_frame(frame: &[u8]) -> Result<HashMap<String, String>, FromUtf8Error> {
+pub fn parse_frame(frame: &[u8]) -> Result<BTreeMap<String, String>, FromUtf8Error> {
     let inner = String::from_utf8(frame.to_owned())?;
-    let mut ret = HashMap::new();
+    let mut ret = BTreeMap::new();
     for s in inner.split(',') {
         let mut tmp = s.splitn(2, '=');
         let key = tmp.next();
@@ -170,6 +209,8 @@ pub enum ChannelBinding {
     Unsupported,
     /// p=tls-unique channel binding data.
     TlsUnique(Vec<u8>),
+    /// p=tls-exporter channel binding data (RFC 9266), for TLS 1.3.
+    TlsExporter(Vec<u8>),
 }
 
 impl ChannelBinding {
@@ -179,6 +220,7 @@ impl ChannelBinding {
             ChannelBinding::None => b"n,,",
             ChannelBinding::Unsupported => b"y,,",
             ChannelBinding::TlsUnique(_) => b"p=tls-unique,,",
+            ChannelBinding::TlsExporter(_) => b"p=tls-exporter,,",
         }
     }
 
@@ -188,6 +230,7 @@ impl ChannelBinding {
             ChannelBinding::None => &[],
             ChannelBinding::Unsupported => &[],
             ChannelBinding::TlsUnique(ref data) => data,
+            ChannelBinding::TlsExporter(ref data) => data,
         }
     }
 
@@ -197,6 +240,16 @@ impl ChannelBinding {
             ChannelBinding::None => false,
             ChannelBinding::Unsupported => false,
             ChannelBinding::TlsUnique(_) => mechanism == "tls-unique",
+            ChannelBinding::TlsExporter(_) => mechanism == "tls-exporter",
+        }
+    }
+
+    /// Checks whether this carries channel binding data, and so must be used with a *-PLUS
+    /// mechanism.
+    pub fn is_bound(&self) -> bool {
+        match *self {
+            ChannelBinding::None | ChannelBinding::Unsupported => false,
+            ChannelBinding::TlsUnique(_) | ChannelBinding::TlsExporter(_) => true,
         }
     }
 }
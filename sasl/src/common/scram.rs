@@ -1,3 +1,7 @@
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
 use getrandom::{getrandom, Error as RngError};
 use hmac::{digest::InvalidLength, Hmac, Mac};
 use pbkdf2::pbkdf2;
@@ -17,16 +21,54 @@ pub fn generate_nonce() -> Result<String, RngError> {
     Ok(Base64.encode(&data))
 }
 
+/// Prepares a username or password with SASLprep (RFC 4013), as RFC 5802 requires.
+///
+/// A string SASLprep rejects is kept as is rather than refused, so that it still works with
+/// servers which don’t normalize either.
+///
+/// Without the `std` feature, SASLprep isn’t available: an ASCII string, which it would leave
+/// alone, is kept as is, and anything else fails with `DeriveError::SaslprepUnavailable`
+/// instead of silently deriving the wrong key.
+pub(crate) fn saslprep(s: &str) -> Result<Cow<'_, str>, DeriveError> {
+    #[cfg(feature = "std")]
+    return Ok(stringprep::saslprep(s).unwrap_or(Cow::Borrowed(s)));
+    #[cfg(not(feature = "std"))]
+    if s.is_ascii() {
+        Ok(Cow::Borrowed(s))
+    } else {
+        Err(DeriveError::SaslprepUnavailable)
+    }
+}
+
+/// Reverses [`escape_username`], returning `None` for a `=` not followed by `3D` or `2C`.
+pub(crate) fn unescape_username(escaped: &str) -> Option<String> {
+    let mut parts = escaped.split('=');
+    let mut username = String::from(parts.next()?);
+    for part in parts {
+        if let Some(rest) = part.strip_prefix("3D") {
+            username.push('=');
+            username.push_str(rest);
+        } else if let Some(rest) = part.strip_prefix("2C") {
+            username.push(',');
+            username.push_str(rest);
+        } else {
+            return None;
+        }
+    }
+    Some(username)
+}
+
 #[derive(Debug, PartialEq)]
 pub enum DeriveError {
     IncompatibleHashingMethod(String, String),
     IncorrectSalt,
     InvalidLength,
     IncompatibleIterationCount(u32, u32),
+    SaslprepUnavailable,
 }
 
-impl std::fmt::Display for DeriveError {
-    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl core::fmt::Display for DeriveError {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
         match self {
             DeriveError::IncompatibleHashingMethod(one, two) => {
                 write!(fmt, "incompatible hashing method, {} is not {}", one, two)
@@ -36,10 +78,17 @@ impl std::fmt::Display for DeriveError {
             DeriveError::IncompatibleIterationCount(one, two) => {
                 write!(fmt, "incompatible iteration count, {} is not {}", one, two)
             }
+            DeriveError::SaslprepUnavailable => {
+                write!(
+                    fmt,
+                    "non-ASCII string, which needs SASLprep from the std feature"
+                )
+            }
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for DeriveError {}
 
 impl From<hmac::digest::InvalidLength> for DeriveError {
@@ -97,6 +146,7 @@ impl ScramProvider for Sha1 {
         match *password {
             Password::Plain(ref plain) => {
                 let mut result = vec![0; 20];
+                let plain = saslprep(plain)?;
                 pbkdf2::<Hmac<Sha1_hash>>(plain.as_bytes(), salt, iterations, &mut result)?;
                 Ok(result)
             }
@@ -111,9 +161,9 @@ impl ScramProvider for Sha1 {
                         method.to_string(),
                         Self::name().to_string(),
                     ))
-                } else if my_salt == &salt {
+                } else if my_salt != salt {
                     Err(DeriveError::IncorrectSalt)
-                } else if my_iterations == iterations {
+                } else if my_iterations != iterations {
                     Err(DeriveError::IncompatibleIterationCount(
                         my_iterations,
                         iterations,
@@ -157,6 +207,7 @@ impl ScramProvider for Sha256 {
         match *password {
             Password::Plain(ref plain) => {
                 let mut result = vec![0; 32];
+                let plain = saslprep(plain)?;
                 pbkdf2::<Hmac<Sha256_hash>>(plain.as_bytes(), salt, iterations, &mut result)?;
                 Ok(result)
             }
@@ -171,9 +222,9 @@ impl ScramProvider for Sha256 {
                         method.to_string(),
                         Self::name().to_string(),
                     ))
-                } else if my_salt == &salt {
+                } else if my_salt != salt {
                     Err(DeriveError::IncorrectSalt)
-                } else if my_iterations == iterations {
+                } else if my_iterations != iterations {
                     Err(DeriveError::IncompatibleIterationCount(
                         my_iterations,
                         iterations,
@@ -185,3 +236,95 @@ impl ScramProvider for Sha256 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::{saslprep, unescape_username, DeriveError, ScramProvider, Sha1, Sha256};
+    use crate::common::escape_username;
+    use crate::common::Password;
+
+    const SALT: [u8; 8] = [35, 71, 92, 105, 212, 219, 114, 93];
+
+    #[test]
+    fn derive_pbkdf2_matches_plain() {
+        let cached = Password::derive_pbkdf2::<Sha1>("pencil", &SALT, 4096).unwrap();
+        let plain = Password::Plain(String::from("pencil"));
+        assert_eq!(
+            Sha1::derive(&cached, &SALT, 4096).unwrap(),
+            Sha1::derive(&plain, &SALT, 4096).unwrap()
+        );
+
+        let cached = Password::derive_pbkdf2::<Sha256>("pencil", &SALT, 4096).unwrap();
+        assert_eq!(
+            Sha256::derive(&cached, &SALT, 4096).unwrap(),
+            Sha256::derive(&plain, &SALT, 4096).unwrap()
+        );
+    }
+
+    #[test]
+    fn derive_pbkdf2_mismatch() {
+        let cached = Password::derive_pbkdf2::<Sha1>("pencil", &SALT, 4096).unwrap();
+        assert_eq!(
+            Sha256::derive(&cached, &SALT, 4096),
+            Err(DeriveError::IncompatibleHashingMethod(
+                String::from("SHA-1"),
+                String::from("SHA-256")
+            ))
+        );
+        assert_eq!(
+            Sha1::derive(&cached, b"other salt", 4096),
+            Err(DeriveError::IncorrectSalt)
+        );
+        assert_eq!(
+            Sha1::derive(&cached, &SALT, 8192),
+            Err(DeriveError::IncompatibleIterationCount(4096, 8192))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn saslprep_vectors() {
+        // Source: RFC 4013 section 3
+        assert_eq!(saslprep("I\u{00AD}X").unwrap(), "IX");
+        assert_eq!(saslprep("user").unwrap(), "user");
+        assert_eq!(saslprep("USER").unwrap(), "USER");
+        assert_eq!(saslprep("\u{00AA}").unwrap(), "a");
+        assert_eq!(saslprep("\u{2168}").unwrap(), "IX");
+        // Prohibited, kept as is.
+        assert_eq!(saslprep("\u{0007}").unwrap(), "\u{0007}");
+    }
+
+    #[test]
+    #[cfg(not(feature = "std"))]
+    fn saslprep_without_std() {
+        assert_eq!(saslprep("user").unwrap(), "user");
+        assert_eq!(
+            saslprep("I\u{00AD}X"),
+            Err(DeriveError::SaslprepUnavailable)
+        );
+    }
+
+    #[test]
+    fn username_escaping() {
+        assert_eq!(escape_username("user"), "user");
+        assert_eq!(escape_username("a,b=c"), "a=2Cb=3Dc");
+        assert_eq!(escape_username("=,"), "=3D=2C");
+        assert_eq!(unescape_username("a=2Cb=3Dc").unwrap(), "a,b=c");
+        assert_eq!(unescape_username("=3D=2C").unwrap(), "=,");
+        assert_eq!(unescape_username("a=b"), None);
+        assert_eq!(unescape_username("a="), None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn derive_normalizes_password() {
+        let plain = Password::Plain(String::from("pencil"));
+        let soft_hyphen = Password::Plain(String::from("pen\u{00AD}cil"));
+        assert_eq!(
+            Sha256::derive(&plain, &SALT, 4096).unwrap(),
+            Sha256::derive(&soft_hyphen, &SALT, 4096).unwrap()
+        );
+    }
+}
@@ -1,12 +1,19 @@
+use alloc::string::String;
 #[cfg(feature = "scram")]
 use getrandom::Error as RngError;
 
+#[cfg(feature = "scram")]
+use crate::common::scram::DeriveError;
+
 /// A wrapper enum for things that could go wrong in this crate.
 #[derive(Debug)]
 pub enum Error {
     #[cfg(feature = "scram")]
     /// An error while initializing the Rng.
     RngError(RngError),
+    #[cfg(feature = "scram")]
+    /// A username or password which can’t be prepared for SCRAM.
+    DeriveError(DeriveError),
     /// An error in a SASL mechanism.
     SaslError(String),
 }
@@ -17,3 +24,10 @@ impl From<RngError> for Error {
         Error::RngError(err)
     }
 }
+
+#[cfg(feature = "scram")]
+impl From<DeriveError> for Error {
+    fn from(err: DeriveError) -> Error {
+        Error::DeriveError(err)
+    }
+}
@@ -1,4 +1,5 @@
 //#![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
 //! This crate provides a framework for SASL authentication and a few authentication mechanisms.
 //!
@@ -181,6 +182,21 @@
 //! ```toml,ignore
 //! sasl = "*"
 //! ```
+//!
+//! # `no_std`
+//!
+//! Disabling the default `std` feature makes this crate `no_std`, it still needs `alloc`
+//! though. Everything keeps working, with these differences:
+//!
+//! - the error types don’t implement `std::error::Error`, only `Display`;
+//! - SCRAM can’t run the username and password through SASLprep, so it refuses non-ASCII ones
+//!   with `DeriveError::SaslprepUnavailable` rather than deriving a key the server won’t match.
+//!
+//! The `scram` and `anonymous` features generate their nonces with `getrandom`, which supports
+//! some bare-metal targets on its own and lets firmware register its own source of randomness
+//! for the others, with its `custom` feature.
+
+extern crate alloc;
 
 mod error;
 
@@ -1,3 +1,6 @@
+use alloc::format;
+use alloc::vec::Vec;
+
 use crate::common::Identity;
 use crate::server::{Mechanism, MechanismError, Response};
 
@@ -1,3 +1,6 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use crate::common::Identity;
 use crate::secret;
 use crate::server::{Mechanism, MechanismError, Response, Validator};
@@ -1,8 +1,11 @@
-use std::marker::PhantomData;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
 
 use base64::{engine::general_purpose::STANDARD as Base64, Engine};
 
-use crate::common::scram::{generate_nonce, ScramProvider};
+use crate::common::scram::{generate_nonce, unescape_username, ScramProvider};
 use crate::common::{parse_frame, xor, ChannelBinding, Identity};
 use crate::secret;
 use crate::secret::Pbkdf2Secret;
@@ -89,9 +92,9 @@ where
                 // TODO: process gs2 header properly, not this ugly stuff
                 match self.channel_binding {
                     ChannelBinding::None | ChannelBinding::Unsupported => {
-                        // Not supported.
-                        if gs2_header[0] != 0x79 {
-                            // ord("y")
+                        // Not supported, the client must not require it.
+                        if gs2_header[0] != 0x79 && gs2_header[0] != 0x6e {
+                            // ord("y"), ord("n")
                             return Err(MechanismError::ChannelBindingNotSupported);
                         }
                     }
@@ -100,16 +103,24 @@ where
                         if gs2_header[0] == 0x79 {
                             // ord("y")
                             return Err(MechanismError::ChannelBindingIsSupported);
-                        } else if !other.supports("tls-unique") {
+                        } else {
                             // TODO: grab the data
-                            return Err(MechanismError::ChannelBindingMechanismIncorrect);
+                            let cb_name = gs2_header
+                                .strip_prefix(b"p=")
+                                .and_then(|rest| rest.split(|&b| b == b',').next())
+                                .and_then(|name| core::str::from_utf8(name).ok());
+                            if !cb_name.is_some_and(|name| other.supports(name)) {
+                                return Err(MechanismError::ChannelBindingMechanismIncorrect);
+                            }
                         }
                     }
                 }
                 let frame =
                     parse_frame(&rest).map_err(|_| MechanismError::CannotDecodeInitialMessage)?;
                 let username = frame.get("n").ok_or_else(|| MechanismError::NoUsername)?;
-                let identity = Identity::Username(username.to_owned());
+                let username =
+                    unescape_username(username).ok_or(MechanismError::ErrorDecodingUsername)?;
+                let identity = Identity::Username(username);
                 let client_nonce = frame.get("r").ok_or_else(|| MechanismError::NoNonce)?;
                 let mut server_nonce = String::new();
                 server_nonce += client_nonce;
@@ -1,6 +1,7 @@
 use crate::common::Identity;
 use crate::secret::Secret;
-use std::fmt;
+use alloc::vec::Vec;
+use core::fmt;
 
 #[cfg(feature = "scram")]
 use crate::common::scram::DeriveError;
@@ -167,11 +168,15 @@ impl fmt::Display for MechanismError {
     }
 }
 
+#[cfg(feature = "std")]
 impl Error for ProviderError {}
 
+#[cfg(feature = "std")]
 impl Error for ValidatorError {}
 
+#[cfg(feature = "std")]
 use std::error::Error;
+#[cfg(feature = "std")]
 impl Error for MechanismError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
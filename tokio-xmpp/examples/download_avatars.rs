@@ -194,7 +194,7 @@ fn get_disco_caps(disco: &DiscoInfoResult, node: &str) -> Caps {
 // Construct a <presence/>
 fn make_presence(caps: Caps) -> Presence {
     let mut presence = Presence::new(PresenceType::None).with_priority(-1);
-    presence.set_status("en", "Downloading avatars.");
+    presence.set_status("en", "Downloading avatars.").unwrap();
     presence.add_payload(caps);
     presence
 }
@@ -5,9 +5,10 @@ use std::process::exit;
 use std::str::FromStr;
 use tokio;
 use tokio_xmpp::AsyncClient as Client;
+use xmpp_parsers::eme::ExplicitMessageEncryption;
 use xmpp_parsers::message::{Body, Message, MessageType};
 use xmpp_parsers::presence::{Presence, Show as PresenceShow, Type as PresenceType};
-use xmpp_parsers::{BareJid, Element, Jid};
+use xmpp_parsers::{ns, BareJid, Element, Jid};
 
 #[tokio::main]
 async fn main() {
@@ -46,6 +47,15 @@ async fn main() {
                 .into_stanza()
                 .and_then(|stanza| Message::try_from(stanza).ok())
             {
+                if let Some(eme) = get_encryption(&message) {
+                    // The body of an encrypted message is only a fallback text, we don’t want to
+                    // echo it back as if it was what our contact wrote.
+                    println!(
+                        "Ignoring a message encrypted with {}.",
+                        eme.scheme_name().unwrap_or(&eme.namespace)
+                    );
+                    continue;
+                }
                 match (message.from, message.bodies.get("")) {
                     (Some(ref from), Some(ref body)) if body.0 == "die" => {
                         println!("Secret die command triggered by {}", from);
@@ -69,6 +79,15 @@ async fn main() {
     }
 }
 
+// Find the XEP-0380 <encryption/> payload of a <message/>, if any
+fn get_encryption(message: &Message) -> Option<ExplicitMessageEncryption> {
+    message
+        .payloads
+        .iter()
+        .find(|payload| payload.is("encryption", ns::EME))
+        .and_then(|payload| ExplicitMessageEncryption::try_from(payload.clone()).ok())
+}
+
 // Construct a <presence/>
 fn make_presence() -> Element {
     let mut presence = Presence::new(PresenceType::None);
@@ -1,24 +1,42 @@
-use futures::{sink::SinkExt, task::Poll, Future, Sink, Stream};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::{sink::SinkExt, stream::StreamExt, task::Poll, Future, Sink, Stream};
+use log::warn;
 use sasl::common::{ChannelBinding, Credentials};
+use std::collections::{HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
 use std::mem::replace;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::Context;
-use tokio::net::TcpStream;
+use std::time::Duration;
 use tokio::task::JoinHandle;
-#[cfg(feature = "tls-native")]
-use tokio_native_tls::TlsStream;
-#[cfg(feature = "tls-rust")]
-use tokio_rustls::client::TlsStream;
-use xmpp_parsers::{ns, Element, Jid};
+use xmpp_parsers::{
+    chatstates::ChatState,
+    csi,
+    iq::{Iq, IqType},
+    message::{Message, MessageType},
+    ns,
+    presence::Presence,
+    receipts::{Received, Request as ReceiptRequest},
+    roster::{Roster, Subscription},
+    sm::{A, R},
+    stanza::is_valid_lang,
+    stanza_id::{generate_id, OriginId},
+    stream_features::StreamFeatures,
+    BareJid, Element, Jid,
+};
 
 use super::auth::auth;
 use super::bind::bind;
+use super::sm::{self, StreamManagement};
 use crate::event::Event;
-use crate::happy_eyeballs::{connect_to_host, connect_with_srv};
-use crate::starttls::starttls;
+use crate::happy_eyeballs::{connect_to_host, connect_with_srv, set_tcp_options};
+use crate::resolver::{Resolver, SystemResolver};
+use crate::starttls::{channel_binding, starttls};
 use crate::xmpp_codec::Packet;
 use crate::xmpp_stream::{self, add_stanza_id};
-use crate::{Error, ProtocolError};
+use crate::{AsyncReadAndWrite, ConfigError, Error, ProtocolError};
 
 /// XMPP client connection and state
 ///
@@ -30,11 +48,33 @@ pub struct Client {
     config: Config,
     state: ClientState,
     reconnect: bool,
-    // TODO: tls_required=true
+    sm: Option<StreamManagement>,
+    queue: VecDeque<Packet>,
+    /// Stanzas received while enabling Stream Management, not reported yet
+    received: VecDeque<Element>,
+    last_presence: Option<Element>,
+    /// Contacts subscribed to our presence according to the roster, the
+    /// only ones `Config::auto_receipts` answers
+    receipt_contacts: HashSet<BareJid>,
+    /// Stanzas the previous session left unacknowledged, to report when
+    /// the new one has no Stream Management to do it
+    stale_dropped: u32,
+    /// Defined condition of the `<stream:error/>` received, to report once
+    /// the server closes the stream
+    stream_error: Option<String>,
+    /// Error sending an automatic receipt, to report on the next poll once
+    /// the stanza it answers has been
+    receipt_error: Option<Error>,
+    /// Stanzas received while waiting for `close()`, reported on the next
+    /// polls
+    received_while_closing: VecDeque<Element>,
 }
 
 /// XMPP server connection configuration
+///
+/// Non-exhaustive, as which variants exist depends on the enabled features.
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum ServerConfig {
     /// Use SRV record to find server host
     UseSrv,
@@ -46,6 +86,10 @@ pub enum ServerConfig {
         /// Server port
         port: u16,
     },
+    /// Connect over in-memory transports, to the `FakeServer`s accepted
+    /// from a `test_util::DuplexListener`
+    #[cfg(any(test, feature = "test-util"))]
+    InMemory(crate::test_util::DuplexConnector),
 }
 
 /// XMMPP client configuration
@@ -55,17 +99,383 @@ pub struct Config {
     pub jid: Jid,
     /// password of the account
     pub password: String,
+    /// OAuth 2.0 bearer token to authenticate with OAUTHBEARER (RFC 7628)
+    /// instead of the password, when the server offers it
+    pub token: Option<String>,
     /// server configuration for the account
     pub server: ServerConfig,
+    /// maximum number of stanzas to hold while not connected, to be sent
+    /// once online again; `0` disables queueing, and so does disabling
+    /// reconnection once disconnected
+    pub queue_capacity: usize,
+    /// refuse to authenticate if the connection couldn’t be upgraded to
+    /// TLS; only disable this for testing against a local server
+    pub tls_required: bool,
+    /// bind a resource assigned by the server when the one in `jid` is
+    /// already in use, e.g. by a session which hasn’t timed out yet
+    pub bind_retry_on_conflict: bool,
+    /// language advertised in the `xml:lang` of our stream header, which
+    /// the server may use to localise its error texts
+    pub lang: Option<String>,
+    /// disable Nagle’s algorithm on the TCP connection, so that small
+    /// stanzas get sent without delay
+    pub tcp_nodelay: bool,
+    /// idle time after which TCP keepalive probes get sent; `None` leaves
+    /// the system default, usually without probes
+    pub tcp_keepalive: Option<Duration>,
+    /// answer the messages requesting a delivery receipt (XEP-0184) with
+    /// one, except in groupchats and for errors, receipts and our own
+    /// messages; only contacts subscribed to our presence get one, as
+    /// learnt from the roster results and pushes received, so that others
+    /// can't tell we are online
+    pub auto_receipts: bool,
+    /// enable Stream Management (XEP-0198) when the server supports it, to
+    /// get the stanzas we send acknowledged
+    pub stream_management: bool,
+    /// maximum number of sent stanzas to keep until the server
+    /// acknowledges them (XEP-0198); past it the oldest ones get dropped,
+    /// and the session can’t be resumed anymore
+    pub sm_max_unacked: usize,
+    /// time to wait after sending a stanza before asking the server to
+    /// acknowledge it, unless half of `sm_max_unacked` got sent meanwhile
+    pub sm_ack_delay: Duration,
+    /// DNS resolver used to find the server
+    pub resolver: Arc<dyn Resolver>,
+    /// function called with every stanza sent or received once online,
+    /// e.g. to log them while debugging
+    pub on_stanza: Option<StanzaHook>,
+    /// send the last available presence we broadcast again once online on
+    /// a new session, as the server forgot it along with the previous one
+    pub rebroadcast_presence: bool,
+    /// time to wait for the answer to an iq sent with
+    /// `ClientSender::send_iq`, past which it fails with `Error::Timeout`
+    pub iq_timeout: Duration,
+}
+
+/// Whether a stanza given to a `StanzaHook` is being sent or was received
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StanzaDirection {
+    /// Sent by us, about to be serialized
+    Sent,
+    /// Received from the server, once parsed
+    Received,
+}
+
+/// A function called with every stanza sent or received, see
+/// `Config::on_stanza`
+///
+/// The stanza is only borrowed, so that nothing gets cloned for it.
+#[derive(Clone)]
+pub struct StanzaHook(Arc<StanzaHookFn>);
+
+type StanzaHookFn = dyn Fn(StanzaDirection, &Element) + Send + Sync;
+
+impl StanzaHook {
+    /// Wrap the given function
+    pub fn new<F: Fn(StanzaDirection, &Element) + Send + Sync + 'static>(hook: F) -> Self {
+        StanzaHook(Arc::new(hook))
+    }
+
+    fn call(&self, direction: StanzaDirection, stanza: &Element) {
+        (self.0)(direction, stanza)
+    }
+}
+
+impl fmt::Debug for StanzaHook {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "StanzaHook")
+    }
 }
 
-type XMPPStream = xmpp_stream::XMPPStream<TlsStream<TcpStream>>;
+/// Builds a `Config`, refusing invalid combinations of options
+///
+/// Options left unset default to what `Client::new()` uses.
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder {
+    config: Config,
+    resource: Option<String>,
+}
+
+impl ConfigBuilder {
+    /// Start building the configuration of the given account
+    pub fn new<J: Into<Jid>, P: Into<String>>(jid: J, password: P) -> Self {
+        ConfigBuilder {
+            config: Config {
+                jid: jid.into(),
+                password: password.into(),
+                token: None,
+                server: ServerConfig::UseSrv,
+                queue_capacity: 0,
+                tls_required: true,
+                bind_retry_on_conflict: true,
+                lang: None,
+                tcp_nodelay: false,
+                tcp_keepalive: None,
+                auto_receipts: false,
+                stream_management: true,
+                sm_max_unacked: 256,
+                sm_ack_delay: Duration::from_secs(5),
+                resolver: Arc::new(SystemResolver),
+                on_stanza: None,
+                rebroadcast_presence: false,
+                iq_timeout: Duration::from_secs(60),
+            },
+            resource: None,
+        }
+    }
+
+    /// Authenticate with the given OAuth 2.0 bearer token instead of the
+    /// password
+    pub fn set_token(mut self, token: &str) -> Self {
+        self.config.token = Some(String::from(token));
+        self
+    }
+
+    /// Set how to find the server, by SRV lookup by default
+    pub fn set_server(mut self, server: ServerConfig) -> Self {
+        self.config.server = server;
+        self
+    }
+
+    /// Request the given resource, replacing the one of the JID if any
+    pub fn set_resource(mut self, resource: &str) -> Self {
+        self.resource = Some(String::from(resource));
+        self
+    }
+
+    /// Set the maximum number of stanzas to hold while not connected
+    pub fn set_queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.config.queue_capacity = queue_capacity;
+        self
+    }
+
+    /// Set whether to refuse to authenticate without TLS
+    pub fn set_tls_required(mut self, tls_required: bool) -> Self {
+        self.config.tls_required = tls_required;
+        self
+    }
+
+    /// Set whether to let the server assign a resource when ours is in use
+    pub fn set_bind_retry_on_conflict(mut self, bind_retry_on_conflict: bool) -> Self {
+        self.config.bind_retry_on_conflict = bind_retry_on_conflict;
+        self
+    }
+
+    /// Set the language advertised in our stream header
+    pub fn set_lang(mut self, lang: &str) -> Self {
+        self.config.lang = Some(String::from(lang));
+        self
+    }
+
+    /// Set whether to disable Nagle’s algorithm on the TCP connection
+    pub fn set_tcp_nodelay(mut self, tcp_nodelay: bool) -> Self {
+        self.config.tcp_nodelay = tcp_nodelay;
+        self
+    }
+
+    /// Enable TCP keepalive probes after the given idle time
+    pub fn set_tcp_keepalive(mut self, idle: Duration) -> Self {
+        self.config.tcp_keepalive = Some(idle);
+        self
+    }
+
+    /// Set whether to answer delivery receipt requests automatically
+    pub fn set_auto_receipts(mut self, auto_receipts: bool) -> Self {
+        self.config.auto_receipts = auto_receipts;
+        self
+    }
+
+    /// Set whether to enable Stream Management when the server supports it
+    pub fn set_stream_management(mut self, stream_management: bool) -> Self {
+        self.config.stream_management = stream_management;
+        self
+    }
+
+    /// Set the maximum number of sent stanzas to keep until acknowledged
+    pub fn set_sm_max_unacked(mut self, sm_max_unacked: usize) -> Self {
+        self.config.sm_max_unacked = sm_max_unacked;
+        self
+    }
+
+    /// Set the time to wait before asking the server to acknowledge the
+    /// stanzas sent
+    pub fn set_sm_ack_delay(mut self, sm_ack_delay: Duration) -> Self {
+        self.config.sm_ack_delay = sm_ack_delay;
+        self
+    }
+
+    /// Set the DNS resolver used to find the server
+    pub fn set_resolver(mut self, resolver: Arc<dyn Resolver>) -> Self {
+        self.config.resolver = resolver;
+        self
+    }
+
+    /// Set a function to call with every stanza sent or received
+    pub fn set_on_stanza(mut self, on_stanza: StanzaHook) -> Self {
+        self.config.on_stanza = Some(on_stanza);
+        self
+    }
+
+    /// Set whether to broadcast our last available presence again after
+    /// reconnecting
+    pub fn set_rebroadcast_presence(mut self, rebroadcast_presence: bool) -> Self {
+        self.config.rebroadcast_presence = rebroadcast_presence;
+        self
+    }
+
+    /// Set the time to wait for the answer to an iq
+    pub fn set_iq_timeout(mut self, iq_timeout: Duration) -> Self {
+        self.config.iq_timeout = iq_timeout;
+        self
+    }
+
+    /// Check the options and return the resulting `Config`
+    pub fn build(self) -> Result<Config, ConfigError> {
+        let mut config = self.config;
+        if config.jid.node_str().is_none() {
+            return Err(ConfigError::NoNode);
+        }
+        if let Some(resource) = self.resource {
+            let full = config
+                .jid
+                .to_bare()
+                .with_resource_str(&resource)
+                .map_err(ConfigError::InvalidResource)?;
+            config.jid = Jid::Full(full);
+        }
+        if let ServerConfig::Manual { ref host, port } = config.server {
+            if host.is_empty() {
+                return Err(ConfigError::EmptyHost);
+            }
+            if port == 0 {
+                return Err(ConfigError::InvalidPort);
+            }
+        }
+        if let Some(ref lang) = config.lang {
+            if !is_valid_lang(lang) {
+                return Err(ConfigError::InvalidLang(lang.clone()));
+            }
+        }
+        if config.sm_max_unacked == 0 {
+            return Err(ConfigError::NoUnackedStanzas);
+        }
+        Ok(config)
+    }
+}
+
+/// The defined condition of a `<stream:error/>`, e.g. `conflict`
+fn stream_error_condition(error: &Element) -> String {
+    error
+        .children()
+        .find(|child| child.ns() == ns::XMPP_STREAMS && child.name() != "text")
+        .map_or_else(
+            || String::from("undefined-condition"),
+            |condition| condition.name().to_owned(),
+        )
+}
+
+/// Updates `contacts`, the bare JIDs subscribed to our presence, if
+/// `stanza` is a roster result or push from our own account `jid`
+fn track_roster(contacts: &mut HashSet<BareJid>, stanza: &Element, jid: &Jid) {
+    if !stanza.is("iq", ns::JABBER_CLIENT) || !stanza.has_child("query", ns::ROSTER) {
+        return;
+    }
+    let iq = match Iq::try_from(stanza.clone()) {
+        Ok(iq) => iq,
+        Err(_) => return,
+    };
+    // Anyone else could forge them.
+    if iq.from.is_some_and(|from| from.to_bare() != jid.to_bare()) {
+        return;
+    }
+    let (payload, whole) = match iq.payload {
+        IqType::Result(Some(payload)) => (payload, true),
+        IqType::Set(payload) => (payload, false),
+        _ => return,
+    };
+    let roster = match Roster::try_from(payload) {
+        Ok(roster) => roster,
+        Err(_) => return,
+    };
+    if whole {
+        contacts.clear();
+    }
+    for item in roster.items {
+        if matches!(item.subscription, Subscription::From | Subscription::Both) {
+            contacts.insert(item.jid);
+        } else {
+            contacts.remove(&item.jid);
+        }
+    }
+}
+
+/// The delivery receipt (XEP-0184) to send for `stanza`, if it is a
+/// message asking for one from one of `contacts`, the bare JIDs subscribed
+/// to our presence, which isn’t from our own account `jid`, e.g. a carbon
+///
+/// Groupchat messages, errors and receipts themselves never get one, which
+/// also prevents two clients from acknowledging each other’s receipts
+/// forever.
+fn receipt_for(stanza: &Element, jid: &Jid, contacts: &HashSet<BareJid>) -> Option<Element> {
+    if !stanza.is("message", ns::JABBER_CLIENT)
+        || !stanza.has_child("request", ns::RECEIPTS)
+        || stanza.has_child("received", ns::RECEIPTS)
+    {
+        return None;
+    }
+    let message = Message::try_from(stanza).ok()?;
+    if matches!(message.type_, MessageType::Groupchat | MessageType::Error) {
+        return None;
+    }
+    // Answer the exact resource which asked, as its bare JID could route
+    // the receipt elsewhere.
+    let from = message.from?;
+    if from.to_bare() == jid.to_bare() || !contacts.contains(&from.to_bare()) {
+        return None;
+    }
+    let receipt = Message::new_with_type(MessageType::Normal, from)
+        .with_payload(Received { id: message.id? });
+    Some(add_stanza_id(receipt.into(), ns::JABBER_CLIENT))
+}
+
+/// Additional payloads for `Client::send_message()`
+#[derive(Clone, Debug, Default)]
+pub struct MessageOptions {
+    request_receipt: bool,
+    active: bool,
+}
+
+impl MessageOptions {
+    /// No additional payload
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request a delivery receipt (XEP-0184), which will reference the id
+    /// returned by `Client::send_message()`
+    pub fn with_receipt_request(mut self) -> Self {
+        self.request_receipt = true;
+        self
+    }
+
+    /// Notify the recipient that we are actively participating in the
+    /// chat (XEP-0085)
+    pub fn with_active_chat_state(mut self) -> Self {
+        self.active = true;
+        self
+    }
+}
+
+type XMPPStream = xmpp_stream::XMPPStream<Box<dyn AsyncReadAndWrite>>;
+
+type Session = (XMPPStream, Option<StreamManagement>, Vec<Element>);
 
 enum ClientState {
     Invalid,
     Disconnected,
-    Connecting(JoinHandle<Result<XMPPStream, Error>>),
-    Connected(XMPPStream),
+    /// The connection task, and the progress events it sends
+    Connecting(JoinHandle<Result<Session, Error>>, UnboundedReceiver<Event>),
+    Connected(Box<XMPPStream>),
 }
 
 impl Client {
@@ -74,25 +484,25 @@ impl Client {
     /// Start polling the returned instance so that it will connect
     /// and yield events.
     pub fn new<J: Into<Jid>, P: Into<String>>(jid: J, password: P) -> Self {
-        let config = Config {
-            jid: jid.into(),
-            password: password.into(),
-            server: ServerConfig::UseSrv,
-        };
-        Self::new_with_config(config)
+        Self::new_with_config(ConfigBuilder::new(jid, password).config)
     }
 
     /// Start a new client given that the JID is already parsed.
     pub fn new_with_config(config: Config) -> Self {
-        let connect = tokio::spawn(Self::connect(
-            config.server.clone(),
-            config.jid.clone(),
-            config.password.clone(),
-        ));
+        let state = Self::start_connecting(config.clone());
         let client = Client {
             config,
-            state: ClientState::Connecting(connect),
+            state,
             reconnect: false,
+            sm: None,
+            queue: VecDeque::new(),
+            received: VecDeque::new(),
+            last_presence: None,
+            receipt_contacts: HashSet::new(),
+            stale_dropped: 0,
+            stream_error: None,
+            receipt_error: None,
+            received_while_closing: VecDeque::new(),
         };
         client
     }
@@ -104,50 +514,128 @@ impl Client {
         self
     }
 
-    async fn connect(
-        server: ServerConfig,
-        jid: Jid,
-        password: String,
-    ) -> Result<XMPPStream, Error> {
+    fn start_connecting(config: Config) -> ClientState {
+        let (progress, events) = unbounded();
+        let connect = tokio::spawn(Self::connect(config, progress));
+        ClientState::Connecting(connect, events)
+    }
+
+    // Never record the whole config, it holds the password.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(jid = %config.jid, host, port),
+            err(Display)
+        )
+    )]
+    async fn connect(config: Config, progress: UnboundedSender<Event>) -> Result<Session, Error> {
+        // The client may have been dropped already, in which case nobody cares.
+        let _ = progress.unbounded_send(Event::Connecting);
+        let Config {
+            jid,
+            password,
+            token,
+            server,
+            tls_required,
+            bind_retry_on_conflict,
+            lang,
+            tcp_nodelay,
+            tcp_keepalive,
+            stream_management,
+            sm_ack_delay,
+            resolver,
+            ..
+        } = config;
+        let lang = lang.as_deref();
         let username = jid.node_str().unwrap();
-        let password = password;
 
         // TCP connection
-        let tcp_stream = match server {
+        let tcp_stream: Box<dyn AsyncReadAndWrite> = match server {
             ServerConfig::UseSrv => {
-                connect_with_srv(jid.domain_str(), "_xmpp-client._tcp", 5222).await?
+                trace_record!(host = jid.domain_str());
+                let tcp_stream =
+                    connect_with_srv(&*resolver, jid.domain_str(), "_xmpp-client._tcp", 5222)
+                        .await?;
+                // Before TLS wraps it
+                set_tcp_options(&tcp_stream, tcp_nodelay, tcp_keepalive)?;
+                Box::new(tcp_stream)
             }
-            ServerConfig::Manual { host, port } => connect_to_host(host.as_str(), port).await?,
+            ServerConfig::Manual { host, port } => {
+                trace_record!(host = host.as_str(), port = port);
+                let tcp_stream = connect_to_host(&*resolver, host.as_str(), port).await?;
+                set_tcp_options(&tcp_stream, tcp_nodelay, tcp_keepalive)?;
+                Box::new(tcp_stream)
+            }
+            #[cfg(any(test, feature = "test-util"))]
+            ServerConfig::InMemory(connector) => Box::new(connector.connect()?),
         };
+        trace_debug!("connected");
 
         // Unencryped XMPPStream
-        let xmpp_stream =
-            xmpp_stream::XMPPStream::start(tcp_stream, jid.clone(), ns::JABBER_CLIENT.to_owned())
-                .await?;
+        let xmpp_stream = xmpp_stream::XMPPStream::start_with_lang(
+            tcp_stream,
+            jid.clone(),
+            ns::JABBER_CLIENT.to_owned(),
+            lang,
+        )
+        .await?;
+
+        // Remember what was offered in cleartext, to detect stripped mechanisms
+        let plaintext_mechs: HashSet<String> = match xmpp_stream.stream_features.sasl_mechanisms() {
+            Ok(mechs) => mechs.collect(),
+            Err(_) => HashSet::new(),
+        };
 
-        let xmpp_stream = if xmpp_stream.stream_features.can_starttls() {
+        let (xmpp_stream, channel_binding) = if xmpp_stream.stream_features.can_starttls() {
             // TlsStream
             let tls_stream = starttls(xmpp_stream).await?;
+            let channel_binding = channel_binding(&tls_stream);
             // Encrypted XMPPStream
-            xmpp_stream::XMPPStream::start(tls_stream, jid.clone(), ns::JABBER_CLIENT.to_owned())
-                .await?
-        } else {
+            let xmpp_stream = xmpp_stream::XMPPStream::start_with_lang(
+                Box::new(tls_stream) as Box<dyn AsyncReadAndWrite>,
+                jid.clone(),
+                ns::JABBER_CLIENT.to_owned(),
+                lang,
+            )
+            .await?;
+            (xmpp_stream, channel_binding)
+        } else if tls_required {
             return Err(Error::Protocol(ProtocolError::NoTls));
+        } else {
+            warn!("Server doesn’t support STARTTLS, authenticating in cleartext!");
+            (xmpp_stream, ChannelBinding::None)
         };
 
-        let creds = Credentials::default()
-            .with_username(username)
-            .with_password(password)
-            .with_channel_binding(ChannelBinding::None);
+        let creds = Credentials::default().with_username(username);
+        let creds = match token {
+            Some(token) => creds.with_token(token),
+            None => creds.with_password(password),
+        };
+        let creds = creds.with_channel_binding(channel_binding);
         // Authenticated (unspecified) stream
-        let stream = auth(xmpp_stream, creds).await?;
+        let _ = progress.unbounded_send(Event::Authenticating);
+        let stream = auth(xmpp_stream, creds, &plaintext_mechs).await?;
         // Authenticated XMPPStream
-        let xmpp_stream =
-            xmpp_stream::XMPPStream::start(stream, jid, ns::JABBER_CLIENT.to_owned()).await?;
+        let xmpp_stream = xmpp_stream::XMPPStream::start_with_lang(
+            stream,
+            jid,
+            ns::JABBER_CLIENT.to_owned(),
+            lang,
+        )
+        .await?;
 
         // XMPPStream bound to user session
-        let xmpp_stream = bind(xmpp_stream).await?;
-        Ok(xmpp_stream)
+        let _ = progress.unbounded_send(Event::Binding);
+        let xmpp_stream = bind(xmpp_stream, bind_retry_on_conflict).await?;
+
+        // Acknowledgements of the stanzas we send, if the server supports them
+        if stream_management {
+            sm::enable(xmpp_stream, sm_ack_delay).await
+        } else {
+            Ok((xmpp_stream, None, Vec::new()))
+        }
     }
 
     /// Get the client's bound JID (the one reported by the XMPP
@@ -159,12 +647,165 @@ impl Client {
         }
     }
 
+    /// Time to wait for the answer to an iq, see `Config::iq_timeout`
+    pub(crate) fn iq_timeout(&self) -> Duration {
+        self.config.iq_timeout
+    }
+
+    /// Get the default language announced by the server in its stream
+    /// header, to be passed to e.g. `Message::parse_with_lang()`.
+    pub fn stream_lang(&self) -> Option<&str> {
+        match self.state {
+            ClientState::Connected(ref stream) => stream.lang.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Get the features advertised by the server on the current session,
+    /// e.g. to check whether it supports Client State Indication.
+    pub fn stream_features(&self) -> Option<StreamFeatures> {
+        match self.state {
+            ClientState::Connected(ref stream) => Some(stream.stream_features.parse()),
+            _ => None,
+        }
+    }
+
+    /// Get the number of stanzas sent on the current session, if the
+    /// server supports Stream Management (XEP-0198).
+    ///
+    /// Once an `Event::StanzaAcked` with an `h` greater or equal to this
+    /// number is received, the server has handled every stanza sent so far.
+    pub fn sent_stanzas(&self) -> Option<u32> {
+        match self.state {
+            ClientState::Connected(_) => self.sm.as_ref().map(|sm| sm.outbound),
+            _ => None,
+        }
+    }
+
+    /// Get the stanzas sent on the last session which the server didn’t
+    /// acknowledge yet, the oldest first, if it supports Stream Management
+    /// (XEP-0198).
+    ///
+    /// They are kept after a disconnection, until the next session starts,
+    /// unless more than `Config::sm_max_unacked` of them got sent, see
+    /// `Event::UnackedDropped`. As sessions aren’t resumed, they are then
+    /// dropped instead of being sent again, and reported the same way.
+    pub fn unacked_stanzas(&self) -> Option<impl Iterator<Item = &Element>> {
+        self.sm.as_ref().map(|sm| sm.unacked.iter())
+    }
+
+    /// Get the number of stanzas waiting to be sent once connected.
+    pub fn queued_stanzas(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Send the stanzas queued while not connected, in order.
+    fn send_queue(mut self: Pin<&mut Self>, cx: &mut Context) -> Result<(), Error> {
+        if self.queue.is_empty() {
+            return Ok(());
+        }
+        while let Some(packet) = self.queue.pop_front() {
+            self.as_mut().start_send(packet)?;
+        }
+        // If flushing doesn’t complete now, it will along with the next
+        // stanza sent.
+        match self.poll_flush(cx) {
+            Poll::Ready(Err(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
     /// Send stanza
     pub async fn send_stanza(&mut self, stanza: Element) -> Result<(), Error> {
         self.send(Packet::Stanza(add_stanza_id(stanza, ns::JABBER_CLIENT)))
             .await
     }
 
+    /// Send a copy of `presence` to each of `targets`, e.g. to join several
+    /// rooms at once or to share our presence with an entity we aren't
+    /// subscribed to
+    ///
+    /// Every copy gets its own id, whatever the id of `presence`. Sending
+    /// stops at the first error, which is returned.
+    pub async fn send_directed_presence(
+        &mut self,
+        targets: Vec<Jid>,
+        presence: Presence,
+    ) -> Result<(), Error> {
+        for target in targets {
+            let mut presence = presence.clone();
+            presence.to = Some(target);
+            presence.id = None;
+            self.send_stanza(presence.into()).await?;
+        }
+        Ok(())
+    }
+
+    /// Send a nonza, a stream-level element which isn't a stanza, such as
+    /// `<r/>` (XEP-0198) or `<active/>` (XEP-0352)
+    ///
+    /// Unlike `send_stanza()`, the element is sent as is, without an id
+    /// being added. It isn't counted by stream management either, and
+    /// isn't queued while disconnected since it only makes sense on the
+    /// current stream: `Error::InvalidState` is returned instead.
+    pub async fn send_nonza(&mut self, nonza: Element) -> Result<(), Error> {
+        if !matches!(self.state, ClientState::Connected(_)) {
+            return Err(Error::InvalidState);
+        }
+        self.send(Packet::Stanza(nonza)).await
+    }
+
+    /// Send a text message
+    ///
+    /// The message gets a new id, which is also used as its origin-id
+    /// (XEP-0359), and is returned so that it can be correlated with a
+    /// later delivery receipt or error.
+    pub async fn send_message(
+        &mut self,
+        to: Jid,
+        type_: MessageType,
+        body: &str,
+        options: MessageOptions,
+    ) -> Result<String, Error> {
+        let id = generate_id();
+        let mut message = Message::new_with_type(type_, to)
+            .with_body(String::new(), body.to_owned())
+            .with_payload(OriginId { id: id.clone() });
+        message.id = Some(id.clone());
+        if options.request_receipt {
+            message = message.with_payload(ReceiptRequest);
+        }
+        if options.active {
+            message = message.with_payload(ChatState::Active);
+        }
+        self.send(Packet::Stanza(message.into())).await?;
+        Ok(id)
+    }
+
+    /// Tell the server whether the user is actively using the client,
+    /// using Client State Indication (XEP-0352), so that it can e.g. hold
+    /// back unimportant stanzas while inactive
+    ///
+    /// Returns `false` without sending anything if the server doesn't
+    /// support it. The server considers every new session active, so this
+    /// has to be called again after reconnecting.
+    pub async fn set_active(&mut self, active: bool) -> Result<bool, Error> {
+        match self.state {
+            ClientState::Connected(ref stream) if !stream.stream_features.can_csi() => {
+                return Ok(false)
+            }
+            ClientState::Connected(_) => (),
+            _ => return Err(Error::InvalidState),
+        }
+        let nonza = if active {
+            Element::from(csi::Active)
+        } else {
+            Element::from(csi::Inactive)
+        };
+        self.send_nonza(nonza).await?;
+        Ok(true)
+    }
+
     /// End connection by sending `</stream:stream>`
     ///
     /// You may expect the server to respond with the same. This
@@ -174,64 +815,141 @@ impl Client {
     pub async fn send_end(&mut self) -> Result<(), Error> {
         self.send(Packet::StreamEnd).await
     }
-}
 
-/// Incoming XMPP events
-///
-/// In an `async fn` you may want to use this with `use
-/// futures::stream::StreamExt;`
-impl Stream for Client {
-    type Item = Event;
-
-    /// Low-level read on the XMPP stream, allowing the underlying
-    /// machinery to:
+    /// End connection by sending `</stream:stream>`, then wait up to
+    /// `timeout` for the server to close its side before dropping the
+    /// connection.
     ///
-    /// * connect,
-    /// * starttls,
-    /// * authenticate,
-    /// * bind a session, and finally
-    /// * receive stanzas
+    /// Fails with `Error::Timeout("close")` if the server didn't close the
+    /// stream in time. Stanzas received in the meantime are kept, to be
+    /// returned as `Event::Stanza` by the next polls, and other events are
+    /// discarded.
     ///
-    /// ...for your client
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+    /// Make sure to disable reconnect first, or polling the client again
+    /// will reconnect.
+    pub async fn close(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.send_end().await?;
+        let mut stanzas = Vec::new();
+        let closed = crate::error::timeout("close", timeout, async {
+            while let Some(event) = self.next().await {
+                match event {
+                    Event::Stanza(stanza) => stanzas.push(stanza),
+                    Event::Disconnected(Error::StreamClosed(_) | Error::ConnectionReset) => break,
+                    Event::Disconnected(e) => return Err(e),
+                    _ => (),
+                }
+            }
+            Ok(())
+        })
+        .await;
+        self.received_while_closing.extend(stanzas);
+        self.state = ClientState::Disconnected;
+        closed
+    }
+
+    /// The state machine behind `poll_next()`
+    fn poll_event(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Event>> {
+        if let Some(stanza) = self.received_while_closing.pop_front() {
+            return Poll::Ready(Some(Event::Stanza(stanza)));
+        }
         let state = replace(&mut self.state, ClientState::Invalid);
 
         match state {
             ClientState::Invalid => panic!("Invalid client state"),
             ClientState::Disconnected if self.reconnect => {
                 // TODO: add timeout
-                let connect = tokio::spawn(Self::connect(
-                    self.config.server.clone(),
-                    self.config.jid.clone(),
-                    self.config.password.clone(),
-                ));
-                self.state = ClientState::Connecting(connect);
-                self.poll_next(cx)
+                self.state = Self::start_connecting(self.config.clone());
+                self.poll_event(cx)
             }
             ClientState::Disconnected => Poll::Ready(None),
-            ClientState::Connecting(mut connect) => match Pin::new(&mut connect).poll(cx) {
-                Poll::Ready(Ok(Ok(stream))) => {
-                    let bound_jid = stream.jid.clone();
-                    self.state = ClientState::Connected(stream);
-                    Poll::Ready(Some(Event::Online {
-                        bound_jid,
-                        resumed: false,
-                    }))
+            ClientState::Connecting(mut connect, mut progress) => {
+                // Report the progress made before the outcome
+                if let Poll::Ready(Some(event)) = Pin::new(&mut progress).poll_next(cx) {
+                    self.state = ClientState::Connecting(connect, progress);
+                    return Poll::Ready(Some(event));
                 }
-                Poll::Ready(Ok(Err(e))) => {
-                    self.state = ClientState::Disconnected;
-                    return Poll::Ready(Some(Event::Disconnected(e.into())));
+                match Pin::new(&mut connect).poll(cx) {
+                    Poll::Ready(Ok(Ok((stream, sm, received)))) => {
+                        let bound_jid = stream.jid.clone();
+                        self.state = ClientState::Connected(Box::new(stream));
+                        self.received = received.into();
+                        self.stream_error = None;
+                        // A new session, not a resumed one: nothing left
+                        // unacknowledged by the previous one is replayed.
+                        match (replace(&mut self.sm, sm), &mut self.sm) {
+                            (Some(stale), Some(sm)) => sm.replace_stale(stale),
+                            (Some(stale), None) => self.stale_dropped = stale.lost(),
+                            _ => (),
+                        }
+                        // The server forgot our presence too, so broadcast
+                        // it again unless a newer one is queued.
+                        if let Some(mut presence) = self.last_presence.clone() {
+                            if !self.queue.iter().any(is_broadcast_presence) {
+                                presence.set_attr("id", generate_id());
+                                self.queue.push_front(Packet::Stanza(presence));
+                            }
+                        }
+                        if let Err(e) = self.as_mut().send_queue(cx) {
+                            self.state = ClientState::Disconnected;
+                            return Poll::Ready(Some(Event::Disconnected(e)));
+                        }
+                        Poll::Ready(Some(Event::Online {
+                            bound_jid,
+                            resumed: false,
+                        }))
+                    }
+                    Poll::Ready(Ok(Err(e))) => {
+                        self.state = ClientState::Disconnected;
+                        return Poll::Ready(Some(Event::Disconnected(e.into())));
+                    }
+                    Poll::Ready(Err(e)) => {
+                        self.state = ClientState::Disconnected;
+                        panic!("connect task: {}", e);
+                    }
+                    Poll::Pending => {
+                        self.state = ClientState::Connecting(connect, progress);
+                        Poll::Pending
+                    }
                 }
-                Poll::Ready(Err(e)) => {
+            }
+            ClientState::Connected(mut stream) => {
+                if let Some(e) = self.receipt_error.take() {
                     self.state = ClientState::Disconnected;
-                    panic!("connect task: {}", e);
+                    return Poll::Ready(Some(Event::Disconnected(e)));
                 }
-                Poll::Pending => {
-                    self.state = ClientState::Connecting(connect);
-                    Poll::Pending
+                if self.stale_dropped > 0 {
+                    let count = replace(&mut self.stale_dropped, 0);
+                    warn!(
+                        "Dropped {} unacknowledged stanzas of the previous session",
+                        count
+                    );
+                    self.state = ClientState::Connected(stream);
+                    return Poll::Ready(Some(Event::UnackedDropped { count }));
                 }
-            },
-            ClientState::Connected(mut stream) => {
+                if let Some(count) = self.sm.as_mut().and_then(StreamManagement::take_dropped) {
+                    self.state = ClientState::Connected(stream);
+                    return Poll::Ready(Some(Event::UnackedDropped { count }));
+                }
+
+                if let Some(stanza) = self.received.pop_front() {
+                    self.state = ClientState::Connected(stream);
+                    return Poll::Ready(Some(self.receive_stanza(stanza, cx)));
+                }
+
+                // Ask the server to acknowledge the stanzas sent a while ago
+                if let Some(Poll::Ready(())) = self.sm.as_mut().map(|sm| sm.poll_request(cx)) {
+                    let sent = Pin::new(&mut stream).start_send(Packet::Stanza(R.into()));
+                    if let Err(e) = sent {
+                        self.state = ClientState::Disconnected;
+                        return Poll::Ready(Some(Event::Disconnected(e)));
+                    }
+                    // If flushing doesn’t complete now, it will along with the next stanza sent.
+                    if let Poll::Ready(Err(e)) = Pin::new(&mut stream).poll_flush(cx) {
+                        self.state = ClientState::Disconnected;
+                        return Poll::Ready(Some(Event::Disconnected(e)));
+                    }
+                }
+
                 // Poll sink
                 match Pin::new(&mut stream).poll_ready(cx) {
                     Poll::Pending => (),
@@ -254,14 +972,69 @@ impl Stream for Client {
                 loop {
                     match Pin::new(&mut stream).poll_next(cx) {
                         Poll::Ready(None) => {
-                            // EOF
+                            // EOF, which may follow a stream error without
+                            // the stream getting closed
                             self.state = ClientState::Disconnected;
-                            return Poll::Ready(Some(Event::Disconnected(Error::Disconnected)));
+                            let error = match self.stream_error.take() {
+                                Some(condition) => Error::StreamClosed(Some(condition)),
+                                None => Error::ConnectionReset,
+                            };
+                            return Poll::Ready(Some(Event::Disconnected(error)));
+                        }
+                        Poll::Ready(Some(Ok(Packet::Stanza(stanza))))
+                            if self.sm.is_some() && stanza.is("a", ns::SM) =>
+                        {
+                            // Acknowledgement of the stanzas we sent
+                            match A::try_from(stanza) {
+                                Ok(A { h }) => {
+                                    if let Some(ref mut sm) = self.sm {
+                                        sm.ack(h);
+                                    }
+                                    self.state = ClientState::Connected(stream);
+                                    return Poll::Ready(Some(Event::StanzaAcked { h }));
+                                }
+                                Err(e) => {
+                                    self.state = ClientState::Disconnected;
+                                    return Poll::Ready(Some(Event::Disconnected(
+                                        ProtocolError::Parsers(e).into(),
+                                    )));
+                                }
+                            }
+                        }
+                        Poll::Ready(Some(Ok(Packet::Stanza(stanza))))
+                            if self.sm.is_some() && stanza.is("r", ns::SM) =>
+                        {
+                            // The server wants to know how many stanzas we received
+                            let h = self.sm.as_ref().map_or(0, |sm| sm.inbound);
+                            let answer = Packet::Stanza(A::new(h).into());
+                            let sent = Pin::new(&mut stream).start_send(answer);
+                            if let Err(e) = sent {
+                                self.state = ClientState::Disconnected;
+                                return Poll::Ready(Some(Event::Disconnected(e)));
+                            }
+                            // If flushing doesn’t complete now, it will along with the next
+                            // stanza sent.
+                            if let Poll::Ready(Err(e)) = Pin::new(&mut stream).poll_flush(cx) {
+                                self.state = ClientState::Disconnected;
+                                return Poll::Ready(Some(Event::Disconnected(e)));
+                            }
+                        }
+                        Poll::Ready(Some(Ok(Packet::Stanza(stanza))))
+                            if stanza.is("error", ns::STREAM) =>
+                        {
+                            // The server is about to close the stream, tell
+                            // why once it does
+                            self.stream_error = Some(stream_error_condition(&stanza));
                         }
                         Poll::Ready(Some(Ok(Packet::Stanza(stanza)))) => {
                             // Receive stanza
+                            if let Some(ref mut sm) = self.sm {
+                                if sm::is_stanza(&stanza) {
+                                    sm.inbound = sm.inbound.wrapping_add(1);
+                                }
+                            }
                             self.state = ClientState::Connected(stream);
-                            return Poll::Ready(Some(Event::Stanza(stanza)));
+                            return Poll::Ready(Some(self.receive_stanza(stanza, cx)));
                         }
                         Poll::Ready(Some(Ok(Packet::Text(_)))) => {
                             // Ignore text between stanzas
@@ -276,7 +1049,8 @@ impl Stream for Client {
                         Poll::Ready(Some(Ok(Packet::StreamEnd))) => {
                             // End of stream: </stream:stream>
                             self.state = ClientState::Disconnected;
-                            return Poll::Ready(Some(Event::Disconnected(Error::Disconnected)));
+                            let error = Error::StreamClosed(self.stream_error.take());
+                            return Poll::Ready(Some(Event::Disconnected(error)));
                         }
                         Poll::Pending => {
                             // Try again later
@@ -292,6 +1066,95 @@ impl Stream for Client {
             }
         }
     }
+
+    /// Report a stanza received while connected, answering it with a
+    /// receipt if it requests one
+    fn receive_stanza(mut self: Pin<&mut Self>, stanza: Element, cx: &mut Context) -> Event {
+        if let Some(ref hook) = self.config.on_stanza {
+            hook.call(StanzaDirection::Received, &stanza);
+        }
+        let this = &mut *self;
+        let receipt = match this.state {
+            ClientState::Connected(ref stream) if this.config.auto_receipts => {
+                track_roster(&mut this.receipt_contacts, &stanza, &stream.jid);
+                receipt_for(&stanza, &stream.jid, &this.receipt_contacts)
+            }
+            _ => None,
+        };
+        if let Some(receipt) = receipt {
+            // Through our Sink, so that stream management counts it.
+            match self.as_mut().poll_ready(cx) {
+                Poll::Ready(Ok(())) => (),
+                Poll::Ready(Err(e)) => {
+                    // The stanza still got received, report it first.
+                    self.receipt_error = Some(e);
+                    return Event::Stanza(stanza);
+                }
+                Poll::Pending => {
+                    // Receipts are only a courtesy, don’t hold the stanza
+                    // back for one.
+                    warn!("Not ready to send a receipt, skipping it");
+                    return Event::Stanza(stanza);
+                }
+            }
+            if let Err(e) = self.as_mut().start_send(Packet::Stanza(receipt)) {
+                self.receipt_error = Some(e);
+                return Event::Stanza(stanza);
+            }
+            // If flushing doesn’t complete now, it will along with the next
+            // stanza sent.
+            if let Poll::Ready(Err(e)) = self.as_mut().poll_flush(cx) {
+                self.receipt_error = Some(e);
+            }
+        }
+        Event::Stanza(stanza)
+    }
+}
+
+/// Whether this packet is a presence broadcast to our contacts, rather than
+/// directed to someone.
+fn is_broadcast_presence(packet: &Packet) -> bool {
+    match packet {
+        Packet::Stanza(stanza) => {
+            stanza.is("presence", ns::JABBER_CLIENT) && stanza.attr("to").is_none()
+        }
+        _ => false,
+    }
+}
+
+/// Incoming XMPP events
+///
+/// In an `async fn` you may want to use this with `use
+/// futures::stream::StreamExt;`
+impl Stream for Client {
+    type Item = Event;
+
+    /// Low-level read on the XMPP stream, allowing the underlying
+    /// machinery to:
+    ///
+    /// * connect,
+    /// * starttls,
+    /// * authenticate,
+    /// * bind a session, and finally
+    /// * receive stanzas
+    ///
+    /// ...for your client
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let event = self.poll_event(cx);
+        #[cfg(feature = "tracing")]
+        match event {
+            Poll::Ready(Some(Event::Online {
+                ref bound_jid,
+                resumed,
+            })) => tracing::debug!(bound_jid = %bound_jid, resumed, "online"),
+            Poll::Ready(Some(Event::Disconnected(ref e))) => {
+                tracing::debug!(error = %e, "disconnected")
+            }
+            Poll::Ready(None) => tracing::debug!("stopped"),
+            _ => (),
+        }
+        event
+    }
 }
 
 /// Outgoing XMPP packets
@@ -301,28 +1164,80 @@ impl Sink<Packet> for Client {
     type Error = Error;
 
     fn start_send(mut self: Pin<&mut Self>, item: Packet) -> Result<(), Self::Error> {
-        match self.state {
-            ClientState::Connected(ref mut stream) => {
-                Pin::new(stream).start_send(item).map_err(|e| e.into())
+        let this = &mut *self;
+        if let (ClientState::Connected(_), Some(hook), Packet::Stanza(stanza)) =
+            (&this.state, &this.config.on_stanza, &item)
+        {
+            hook.call(StanzaDirection::Sent, stanza);
+        }
+        let presence = match item {
+            Packet::Stanza(ref stanza)
+                if this.config.rebroadcast_presence && is_broadcast_presence(&item) =>
+            {
+                Some(stanza.clone())
             }
+            _ => None,
+        };
+        let result = match this.state {
+            ClientState::Connected(ref mut stream) => match (&mut this.sm, item) {
+                (Some(sm), Packet::Stanza(stanza)) if sm::is_stanza(&stanza) => {
+                    // Count this stanza, and ask the server to acknowledge it when due
+                    let request = sm.sent(stanza.clone(), this.config.sm_max_unacked);
+                    Pin::new(&mut *stream).start_send(Packet::Stanza(stanza))?;
+                    if request {
+                        Pin::new(stream).start_send(Packet::Stanza(R.into()))
+                    } else {
+                        Ok(())
+                    }
+                }
+                (_, item) => Pin::new(stream).start_send(item).map_err(|e| e.into()),
+            },
+            // Not coming back online, so nothing would ever send it
+            ClientState::Disconnected if !this.reconnect => Err(Error::InvalidState),
+            _ if this.config.queue_capacity > 0 => match item {
+                Packet::Stanza(_) if this.queue.len() < this.config.queue_capacity => {
+                    // Keep it for when we are online again
+                    this.queue.push_back(item);
+                    Ok(())
+                }
+                Packet::Stanza(_) => Err(Error::QueueFull),
+                _ => Err(Error::InvalidState),
+            },
             _ => Err(Error::InvalidState),
+        };
+        if let (Ok(()), Some(presence)) = (&result, presence) {
+            // An unavailable presence ends the previous one.
+            this.last_presence = presence.attr("type").is_none().then_some(presence);
         }
+        result
     }
 
     fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        match self.state {
+        let this = &mut *self;
+        match this.state {
             ClientState::Connected(ref mut stream) => {
                 Pin::new(stream).poll_ready(cx).map_err(|e| e.into())
             }
+            ClientState::Disconnected if !this.reconnect => Poll::Ready(Err(Error::InvalidState)),
+            _ if this.config.queue_capacity > 0 => {
+                if this.queue.len() < this.config.queue_capacity {
+                    Poll::Ready(Ok(()))
+                } else {
+                    Poll::Ready(Err(Error::QueueFull))
+                }
+            }
             _ => Poll::Pending,
         }
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        match self.state {
+        let this = &mut *self;
+        match this.state {
             ClientState::Connected(ref mut stream) => {
                 Pin::new(stream).poll_flush(cx).map_err(|e| e.into())
             }
+            // Queued stanzas will get flushed once online
+            _ if this.config.queue_capacity > 0 => Poll::Ready(Ok(())),
             _ => Poll::Pending,
         }
     }
@@ -336,3 +1251,734 @@ impl Sink<Packet> for Client {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use xmpp_parsers::FullJid;
+
+    use crate::test_util::{
+        client_and_listener, online_client_and_server, wait_online, FakeServer, LocalResolver,
+        PASSWORD,
+    };
+    use crate::AuthError;
+
+    #[test]
+    fn test_config_builder() {
+        let config = ConfigBuilder::new(Jid::new("test@example.org").unwrap(), "password")
+            .set_resource("laptop")
+            .set_server(ServerConfig::Manual {
+                host: String::from("xmpp.example.org"),
+                port: 5223,
+            })
+            .set_lang("en-GB")
+            .build()
+            .unwrap();
+        assert_eq!(config.jid, Jid::new("test@example.org/laptop").unwrap());
+        assert!(config.tls_required);
+        assert_eq!(config.lang.as_deref(), Some("en-GB"));
+        assert_eq!(config.token, None);
+
+        let builder = ConfigBuilder::new(Jid::new("test@example.org").unwrap(), "password");
+        assert!(matches!(
+            ConfigBuilder::new(Jid::new("example.org").unwrap(), "password").build(),
+            Err(ConfigError::NoNode)
+        ));
+        assert!(matches!(
+            builder.clone().set_resource("").build(),
+            Err(ConfigError::InvalidResource(_))
+        ));
+        assert!(matches!(
+            builder
+                .clone()
+                .set_server(ServerConfig::Manual {
+                    host: String::new(),
+                    port: 5222,
+                })
+                .build(),
+            Err(ConfigError::EmptyHost)
+        ));
+        assert!(matches!(
+            builder
+                .clone()
+                .set_server(ServerConfig::Manual {
+                    host: String::from("localhost"),
+                    port: 0,
+                })
+                .build(),
+            Err(ConfigError::InvalidPort)
+        ));
+        assert!(matches!(
+            builder.clone().set_sm_max_unacked(0).build(),
+            Err(ConfigError::NoUnackedStanzas)
+        ));
+        assert!(matches!(
+            builder.set_lang("en GB").build(),
+            Err(ConfigError::InvalidLang(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stanza_hook() {
+        use std::sync::Mutex;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hook = {
+            let seen = seen.clone();
+            StanzaHook::new(move |direction, stanza| {
+                seen.lock()
+                    .unwrap()
+                    .push((direction, stanza.name().to_owned()))
+            })
+        };
+        let (mut client, mut server) =
+            online_client_and_server(|builder| builder.set_on_stanza(hook)).await;
+        server
+            .send_xml("<message xmlns='jabber:client'><body>Hi!</body></message>")
+            .await
+            .unwrap();
+
+        while let Some(event) = client.next().await {
+            if event.is_stanza("message") {
+                break;
+            }
+        }
+        client
+            .send_stanza(Element::builder("presence", ns::JABBER_CLIENT).build())
+            .await
+            .unwrap();
+        assert!(server
+            .recv()
+            .await
+            .unwrap()
+            .is("presence", ns::JABBER_CLIENT));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                (StanzaDirection::Received, String::from("message")),
+                (StanzaDirection::Sent, String::from("presence")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_nonza() {
+        let (mut client, mut listener) =
+            client_and_listener(|builder| builder.set_queue_capacity(10));
+
+        // Not queued, unlike stanzas.
+        assert!(matches!(
+            client.send_nonza(csi::Active.into()).await,
+            Err(Error::InvalidState)
+        ));
+
+        let mut server = listener.accept().await;
+        let server = tokio::spawn(async move {
+            let jid = FullJid::new("juliet@capulet.example/orchard").unwrap();
+            assert!(server.login(&jid, PASSWORD).await.unwrap());
+            let nonza = server.recv().await.unwrap();
+            assert!(nonza.is("inactive", ns::CSI));
+            assert_eq!(nonza.attr("id"), None);
+            let stanza = server.recv().await.unwrap();
+            assert!(stanza.is("presence", ns::JABBER_CLIENT));
+            assert!(stanza.attr("id").is_some());
+        });
+
+        wait_online(&mut client).await;
+        client.send_nonza(csi::Inactive.into()).await.unwrap();
+        client
+            .send_stanza(Element::builder("presence", ns::JABBER_CLIENT).build())
+            .await
+            .unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_directed_presence() {
+        use xmpp_parsers::presence::Type as PresenceType;
+
+        let (mut client, mut server) = online_client_and_server(|builder| builder).await;
+        let mut presence = Presence::available();
+        presence.id = Some(String::from("base"));
+        client
+            .send_directed_presence(
+                vec![
+                    Jid::new("coven@chat.shakespeare.lit/thirdwitch").unwrap(),
+                    Jid::new("romeo@montague.example").unwrap(),
+                ],
+                presence,
+            )
+            .await
+            .unwrap();
+
+        let mut ids = vec![];
+        for to in [
+            "coven@chat.shakespeare.lit/thirdwitch",
+            "romeo@montague.example",
+        ] {
+            let presence = Presence::try_from(server.recv().await.unwrap()).unwrap();
+            assert_eq!(presence.to, Some(Jid::new(to).unwrap()));
+            assert_eq!(presence.type_, PresenceType::None);
+            let id = presence.id.unwrap();
+            assert_ne!(id, "base");
+            ids.push(id);
+        }
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn test_receipt_for() {
+        let jid = Jid::new("juliet@capulet.example/balcony").unwrap();
+        let mut contacts = HashSet::new();
+        contacts.insert(BareJid::new("romeo@montague.example").unwrap());
+        let message = |xml: &str| -> Element {
+            format!("<message xmlns='jabber:client' from='romeo@montague.example/orchard' id='a1'{}</message>", xml)
+                .parse()
+                .unwrap()
+        };
+
+        let receipt = receipt_for(
+            &message(" type='chat'><body>Hi</body><request xmlns='urn:xmpp:receipts'/>"),
+            &jid,
+            &contacts,
+        )
+        .unwrap();
+        let receipt = Message::try_from(receipt).unwrap();
+        assert_eq!(
+            receipt.to,
+            Some(Jid::new("romeo@montague.example/orchard").unwrap())
+        );
+        assert!(receipt.id.is_some());
+        let received = Received::try_from(receipt.payloads[0].clone()).unwrap();
+        assert_eq!(received.id, "a1");
+
+        // No request.
+        assert!(receipt_for(&message("><body>Hi</body>"), &jid, &contacts).is_none());
+        for xml in [
+            " type='groupchat'><request xmlns='urn:xmpp:receipts'/>",
+            " type='error'><request xmlns='urn:xmpp:receipts'/>",
+            // A receipt asking for a receipt.
+            "><received xmlns='urn:xmpp:receipts' id='b2'/><request xmlns='urn:xmpp:receipts'/>",
+        ] {
+            assert!(receipt_for(&message(xml), &jid, &contacts).is_none());
+        }
+
+        // From another resource of ours, e.g. a carbon.
+        let own: Element = "<message xmlns='jabber:client' from='juliet@capulet.example/tomb' id='c3'><request xmlns='urn:xmpp:receipts'/></message>".parse().unwrap();
+        assert!(receipt_for(&own, &jid, &contacts).is_none());
+        // Nothing to reference.
+        let no_id: Element = "<message xmlns='jabber:client' from='romeo@montague.example'><request xmlns='urn:xmpp:receipts'/></message>".parse().unwrap();
+        assert!(receipt_for(&no_id, &jid, &contacts).is_none());
+        // Not subscribed to our presence.
+        let stranger: Element = "<message xmlns='jabber:client' from='tybalt@capulet.example/street' id='d4'><request xmlns='urn:xmpp:receipts'/></message>".parse().unwrap();
+        assert!(receipt_for(&stranger, &jid, &contacts).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_auto_receipts() {
+        let (mut client, mut server) =
+            online_client_and_server(|builder| builder.set_auto_receipts(true)).await;
+        // Not in the roster yet.
+        server
+            .send_xml("<message xmlns='jabber:client' from='romeo@montague.example/orchard' to='juliet@capulet.example/orchard' id='a0' type='chat'><body>Hello?</body><request xmlns='urn:xmpp:receipts'/></message>")
+            .await
+            .unwrap();
+        server
+            .send_xml("<iq xmlns='jabber:client' type='set' id='push1'><query xmlns='jabber:iq:roster'><item jid='romeo@montague.example' subscription='from'/></query></iq>")
+            .await
+            .unwrap();
+        server
+            .send_xml("<message xmlns='jabber:client' from='romeo@montague.example/orchard' to='juliet@capulet.example/orchard' id='a1' type='chat'><body>Hi</body><request xmlns='urn:xmpp:receipts'/></message>")
+            .await
+            .unwrap();
+
+        while let Some(event) = client.next().await {
+            if let Event::Stanza(stanza) = event {
+                if stanza.attr("id") == Some("a1") {
+                    break;
+                }
+            }
+        }
+        let receipt = Message::try_from(server.recv().await.unwrap()).unwrap();
+        assert_eq!(
+            receipt.to,
+            Some(Jid::new("romeo@montague.example/orchard").unwrap())
+        );
+        let received = Received::try_from(receipt.payloads[0].clone()).unwrap();
+        assert_eq!(received.id, "a1");
+    }
+
+    #[tokio::test]
+    async fn test_auto_receipt_error() {
+        let (mut client, mut server) =
+            online_client_and_server(|builder| builder.set_auto_receipts(true)).await;
+        server
+            .send_xml("<iq xmlns='jabber:client' type='set' id='push1'><query xmlns='jabber:iq:roster'><item jid='romeo@montague.example' subscription='from'/></query></iq>")
+            .await
+            .unwrap();
+        server
+            .send_xml("<message xmlns='jabber:client' from='romeo@montague.example/orchard' to='juliet@capulet.example/orchard' id='a1' type='chat'><body>Hi</body><request xmlns='urn:xmpp:receipts'/></message>")
+            .await
+            .unwrap();
+        // Dropped without reading, so the receipt can’t be sent.
+        drop(server);
+        client.set_reconnect(false);
+
+        assert!(client.next().await.unwrap().is_stanza("iq"));
+        let event = client.next().await.unwrap();
+        assert_eq!(event.as_stanza().unwrap().attr("id"), Some("a1"));
+        assert!(matches!(
+            client.next().await,
+            Some(Event::Disconnected(Error::Io(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_close_keeps_stanzas() {
+        let (mut client, mut server) = online_client_and_server(|builder| builder).await;
+        let server = tokio::spawn(async move {
+            // Our </stream:stream>
+            assert!(matches!(server.recv().await, Err(Error::Disconnected)));
+            server
+                .send_xml("<message xmlns='jabber:client' from='romeo@montague.example/orchard' id='late'><body>Wait!</body></message>")
+                .await
+                .unwrap();
+            server.close().await.unwrap();
+        });
+
+        client.set_reconnect(false);
+        client.close(Duration::from_secs(5)).await.unwrap();
+        server.await.unwrap();
+
+        let event = client.next().await.unwrap();
+        assert_eq!(event.as_stanza().unwrap().attr("id"), Some("late"));
+        assert!(client.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_close_timeout() {
+        // The server never closes its side.
+        let (mut client, _server) = online_client_and_server(|builder| builder).await;
+        client.set_reconnect(false);
+        let error = client.close(Duration::from_millis(50)).await.unwrap_err();
+        assert!(matches!(error, Error::Timeout("close")));
+        assert_eq!(error.to_string(), "close timed out");
+    }
+
+    #[tokio::test]
+    async fn test_stream_error() {
+        let (mut client, mut server) = online_client_and_server(|builder| builder).await;
+        client.set_reconnect(false);
+        server
+            .send_xml(&format!(
+                "<error xmlns='{}'><conflict xmlns='{}'/><text xmlns='{}'>Replaced by new connection</text></error>",
+                ns::STREAM,
+                ns::XMPP_STREAMS,
+                ns::XMPP_STREAMS
+            ))
+            .await
+            .unwrap();
+        server.close().await.unwrap();
+
+        let error = loop {
+            match client.next().await {
+                Some(Event::Disconnected(e)) => break e,
+                Some(Event::Stanza(stanza)) => panic!("unexpected stanza {:?}", stanza),
+                Some(_) => (),
+                None => panic!("client ended without a Disconnected event"),
+            }
+        };
+        assert!(
+            matches!(error, Error::StreamClosed(Some(ref condition)) if condition == "conflict")
+        );
+        assert_eq!(error.to_string(), "stream closed: conflict");
+    }
+
+    #[tokio::test]
+    async fn test_stream_closed_vs_connection_reset() {
+        for graceful in [true, false] {
+            let (mut client, mut server) = online_client_and_server(|builder| builder).await;
+            client.set_reconnect(false);
+            if graceful {
+                server.close().await.unwrap();
+            }
+            // Dropping the server closes the transport.
+            drop(server);
+
+            let error = loop {
+                match client.next().await {
+                    Some(Event::Disconnected(e)) => break e,
+                    Some(_) => (),
+                    None => panic!("client ended without a Disconnected event"),
+                }
+            };
+            if graceful {
+                assert!(matches!(error, Error::StreamClosed(None)));
+            } else {
+                assert!(matches!(error, Error::ConnectionReset));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_without_reconnect() {
+        let (mut client, mut server) =
+            online_client_and_server(|builder| builder.set_queue_capacity(10)).await;
+        client.set_reconnect(false);
+        server.close().await.unwrap();
+
+        loop {
+            match client.next().await {
+                Some(Event::Disconnected(_)) => break,
+                Some(_) => (),
+                None => panic!("client ended without a Disconnected event"),
+            }
+        }
+        // Refused instead of queued forever.
+        assert!(matches!(
+            client
+                .send_stanza(Element::builder("presence", ns::JABBER_CLIENT).build())
+                .await,
+            Err(Error::InvalidState)
+        ));
+        assert!(client.queue.is_empty());
+    }
+
+    /// Logs in as juliet, on a server supporting Stream Management
+    async fn login_offering_sm(server: &mut FakeServer<tokio::io::DuplexStream>, jid: &FullJid) {
+        server
+            .open_stream(&format!(
+                "<mechanisms xmlns='{}'><mechanism>SCRAM-SHA-1</mechanism></mechanisms>",
+                ns::SASL
+            ))
+            .await
+            .unwrap();
+        assert!(server.authenticate("juliet", PASSWORD).await.unwrap());
+        server
+            .open_stream(&format!(
+                "<bind xmlns='{}'/><sm xmlns='{}'/>",
+                ns::BIND,
+                ns::SM
+            ))
+            .await
+            .unwrap();
+        server.bind(jid).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_new_session_drops_unacked() {
+        async fn login_with_sm(server: &mut FakeServer<tokio::io::DuplexStream>, jid: &FullJid) {
+            login_offering_sm(server, jid).await;
+            assert!(server.recv().await.unwrap().is("enable", ns::SM));
+            server
+                .send_xml(&format!("<enabled xmlns='{}'/>", ns::SM))
+                .await
+                .unwrap();
+        }
+
+        let (mut client, mut listener) = client_and_listener(|builder| builder);
+        client.set_reconnect(true);
+        let server = tokio::spawn(async move {
+            let mut server = listener.accept().await;
+            login_with_sm(
+                &mut server,
+                &FullJid::new("juliet@capulet.example/orchard").unwrap(),
+            )
+            .await;
+            let message = server.recv().await.unwrap();
+            assert_eq!(message.attr("id"), Some("first"));
+            // Reset the connection without acknowledging it.
+            drop(server);
+
+            // The session can't be resumed, the resource got taken over.
+            let mut server = listener.accept().await;
+            login_with_sm(
+                &mut server,
+                &FullJid::new("juliet@capulet.example/balcony").unwrap(),
+            )
+            .await;
+            let message = server.recv().await.unwrap();
+            assert_eq!(message.attr("id"), Some("second"));
+            server.close().await.unwrap();
+        });
+
+        let message = |id: &str| {
+            let mut message = Message::new(Some(Jid::new("romeo@montague.example").unwrap()));
+            message.id = Some(String::from(id));
+            Element::from(message)
+        };
+        let (bound_jid, _) = wait_online(&mut client).await;
+        assert_eq!(
+            bound_jid,
+            Jid::new("juliet@capulet.example/orchard").unwrap()
+        );
+        client.send_stanza(message("first")).await.unwrap();
+        loop {
+            match client.next().await {
+                Some(Event::Disconnected(Error::ConnectionReset)) => break,
+                Some(_) => (),
+                None => panic!(),
+            }
+        }
+        assert_eq!(client.unacked_stanzas().unwrap().count(), 1);
+
+        let (bound_jid, resumed) = wait_online(&mut client).await;
+        assert_eq!(
+            bound_jid,
+            Jid::new("juliet@capulet.example/balcony").unwrap()
+        );
+        assert!(!resumed);
+        assert!(matches!(
+            client.next().await,
+            Some(Event::UnackedDropped { count: 1 })
+        ));
+        assert_eq!(client.unacked_stanzas().unwrap().count(), 0);
+        assert_eq!(client.sent_stanzas(), Some(0));
+        client.set_reconnect(false);
+        client.send_stanza(message("second")).await.unwrap();
+        assert!(matches!(
+            client.next().await,
+            Some(Event::Disconnected(Error::StreamClosed(None)))
+        ));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_new_session_without_sm_drops_unacked() {
+        let (mut client, mut listener) = client_and_listener(|builder| builder);
+        client.set_reconnect(true);
+        let server = tokio::spawn(async move {
+            let jid = FullJid::new("juliet@capulet.example/orchard").unwrap();
+            let mut server = listener.accept().await;
+            login_offering_sm(&mut server, &jid).await;
+            assert!(server.recv().await.unwrap().is("enable", ns::SM));
+            server
+                .send_xml(&format!("<enabled xmlns='{}'/>", ns::SM))
+                .await
+                .unwrap();
+            let message = server.recv().await.unwrap();
+            assert_eq!(message.attr("id"), Some("first"));
+            // Reset the connection without acknowledging it.
+            drop(server);
+
+            // This time the server doesn't offer Stream Management.
+            let mut server = listener.accept().await;
+            assert!(server.login(&jid, PASSWORD).await.unwrap());
+            server.close().await.unwrap();
+        });
+
+        wait_online(&mut client).await;
+        let mut message = Message::new(Some(Jid::new("romeo@montague.example").unwrap()));
+        message.id = Some(String::from("first"));
+        client.send_stanza(message.into()).await.unwrap();
+        loop {
+            match client.next().await {
+                Some(Event::Disconnected(Error::ConnectionReset)) => break,
+                Some(_) => (),
+                None => panic!(),
+            }
+        }
+
+        let (_, resumed) = wait_online(&mut client).await;
+        assert!(!resumed);
+        assert!(matches!(
+            client.next().await,
+            Some(Event::UnackedDropped { count: 1 })
+        ));
+        assert!(client.unacked_stanzas().is_none());
+        client.set_reconnect(false);
+        assert!(matches!(
+            client.next().await,
+            Some(Event::Disconnected(Error::StreamClosed(None)))
+        ));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sm_ack_requests() {
+        let (mut client, mut listener) = client_and_listener(|builder| {
+            builder
+                .set_sm_max_unacked(4)
+                .set_sm_ack_delay(Duration::from_secs(3600))
+        });
+        let server = tokio::spawn(async move {
+            let mut server = listener.accept().await;
+            let jid = FullJid::new("juliet@capulet.example/balcony").unwrap();
+            login_offering_sm(&mut server, &jid).await;
+            assert!(server.recv().await.unwrap().is("enable", ns::SM));
+            // Sent before Stream Management got enabled.
+            server
+                .send_xml("<message xmlns='jabber:client' id='early'/>")
+                .await
+                .unwrap();
+            server
+                .send_xml(&format!("<enabled xmlns='{}'/>", ns::SM))
+                .await
+                .unwrap();
+            // A single request, once half of the capacity got sent.
+            assert_eq!(server.recv().await.unwrap().attr("id"), Some("first"));
+            assert_eq!(server.recv().await.unwrap().attr("id"), Some("second"));
+            assert!(server.recv().await.unwrap().is("r", ns::SM));
+            server
+                .send_xml(&format!("<a xmlns='{}' h='2'/>", ns::SM))
+                .await
+                .unwrap();
+            server.close().await.unwrap();
+        });
+
+        wait_online(&mut client).await;
+        match client.next().await {
+            Some(Event::Stanza(stanza)) => assert_eq!(stanza.attr("id"), Some("early")),
+            _ => panic!(),
+        }
+        for id in ["first", "second"] {
+            let mut message = Message::new(Some(Jid::new("romeo@montague.example").unwrap()));
+            message.id = Some(String::from(id));
+            client.send_stanza(message.into()).await.unwrap();
+        }
+        assert!(matches!(
+            client.next().await,
+            Some(Event::StanzaAcked { h: 2 })
+        ));
+        assert_eq!(client.unacked_stanzas().unwrap().count(), 0);
+        assert!(matches!(
+            client.next().await,
+            Some(Event::Disconnected(Error::StreamClosed(None)))
+        ));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sm_disabled() {
+        let (mut client, mut listener) =
+            client_and_listener(|builder| builder.set_stream_management(false));
+        let server = tokio::spawn(async move {
+            let mut server = listener.accept().await;
+            let jid = FullJid::new("juliet@capulet.example/balcony").unwrap();
+            login_offering_sm(&mut server, &jid).await;
+            // No <enable/> first.
+            assert!(server
+                .recv()
+                .await
+                .unwrap()
+                .is("message", ns::JABBER_CLIENT));
+            server.close().await.unwrap();
+        });
+
+        wait_online(&mut client).await;
+        assert_eq!(client.sent_stanzas(), None);
+        let message = Message::new(Some(Jid::new("romeo@montague.example").unwrap()));
+        client.send_stanza(message.into()).await.unwrap();
+        assert!(matches!(
+            client.next().await,
+            Some(Event::Disconnected(Error::StreamClosed(None)))
+        ));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast_presence() {
+        use xmpp_parsers::presence::Show;
+
+        let (mut client, mut listener) =
+            client_and_listener(|builder| builder.set_rebroadcast_presence(true));
+        client.set_reconnect(true);
+        let server = tokio::spawn(async move {
+            let jid = FullJid::new("juliet@capulet.example/orchard").unwrap();
+            let mut server = listener.accept().await;
+            assert!(server.login(&jid, PASSWORD).await.unwrap());
+            let presence = Presence::try_from(server.recv().await.unwrap()).unwrap();
+            assert_eq!(presence.show, Some(Show::Away));
+            let id = presence.id.unwrap();
+            let presence = Presence::try_from(server.recv().await.unwrap()).unwrap();
+            assert!(presence.to.is_some());
+            drop(server);
+
+            let mut server = listener.accept().await;
+            assert!(server.login(&jid, PASSWORD).await.unwrap());
+            // The broadcast one, not the directed one.
+            let presence = Presence::try_from(server.recv().await.unwrap()).unwrap();
+            assert_eq!(presence.to, None);
+            assert_eq!(presence.show, Some(Show::Away));
+            assert_ne!(presence.id.unwrap(), id);
+            server.close().await.unwrap();
+        });
+
+        wait_online(&mut client).await;
+        client
+            .send_stanza(Presence::available().with_show(Show::Away).into())
+            .await
+            .unwrap();
+        client
+            .send_directed_presence(
+                vec![Jid::new("coven@chat.shakespeare.lit/thirdwitch").unwrap()],
+                Presence::available(),
+            )
+            .await
+            .unwrap();
+        loop {
+            match client.next().await {
+                Some(Event::Disconnected(Error::ConnectionReset)) => break,
+                Some(_) => (),
+                None => panic!(),
+            }
+        }
+        let (_, resumed) = wait_online(&mut client).await;
+        assert!(!resumed);
+        client.set_reconnect(false);
+        assert!(matches!(
+            client.next().await,
+            Some(Event::Disconnected(Error::StreamClosed(None)))
+        ));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_progress_events() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            socket
+                .write_all(b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' id='abc' version='1.0'>\
+                    <stream:features><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><mechanism>SCRAM-SHA-1</mechanism></mechanisms></stream:features>\
+                    <failure xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><not-authorized/></failure>")
+                .await
+                .unwrap();
+            let mut buf = vec![];
+            let _ = socket.read_to_end(&mut buf).await;
+        });
+
+        let mut client = Client::new_with_config(Config {
+            jid: Jid::new("test@example.org").unwrap(),
+            password: String::from("password"),
+            token: None,
+            server: ServerConfig::Manual {
+                host: String::from("localhost"),
+                port,
+            },
+            queue_capacity: 0,
+            tls_required: false,
+            bind_retry_on_conflict: true,
+            lang: None,
+            tcp_nodelay: false,
+            tcp_keepalive: None,
+            auto_receipts: false,
+            stream_management: true,
+            sm_max_unacked: 256,
+            sm_ack_delay: Duration::from_secs(5),
+            resolver: Arc::new(LocalResolver),
+            on_stanza: None,
+            rebroadcast_presence: false,
+            iq_timeout: Duration::from_secs(60),
+        });
+        assert!(matches!(client.next().await, Some(Event::Connecting)));
+        assert!(matches!(client.next().await, Some(Event::Authenticating)));
+        assert!(matches!(
+            client.next().await,
+            Some(Event::Disconnected(Error::Auth(AuthError::Fail(_))))
+        ));
+    }
+}
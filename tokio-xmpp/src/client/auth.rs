@@ -1,8 +1,8 @@
 use futures::stream::StreamExt;
-use sasl::client::mechanisms::Scram;
-use sasl::client::Mechanism;
+use sasl::client::mechanisms::{OAuthBearer, Scram};
+use sasl::client::{Mechanism, MechanismError as SaslMechanismError};
 use sasl::common::scram::{Sha1, Sha256};
-use sasl::common::Credentials;
+use sasl::common::{ChannelBinding, Credentials};
 use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::str::FromStr;
@@ -13,66 +13,357 @@ use crate::xmpp_codec::Packet;
 use crate::xmpp_stream::XMPPStream;
 use crate::{AuthError, Error, ProtocolError};
 
+type MechanismResult = Result<Box<dyn Mechanism + Send + Sync>, SaslMechanismError>;
+/// A mechanism this client implements, by name, and how to build it from the credentials
+type LocalMechanism<'a> = (&'static str, Box<dyn Fn() -> MechanismResult + Send + 'a>);
+
+/// Checks that none of the -PLUS mechanisms offered before STARTTLS
+/// disappeared once the stream got encrypted, which would mean someone
+/// stripped them to prevent us from using channel binding.
+fn check_downgrade(
+    plaintext_mechs: &HashSet<String>,
+    remote_mechs: &HashSet<String>,
+) -> Result<(), ProtocolError> {
+    if plaintext_mechs
+        .iter()
+        .any(|mech| mech.ends_with("-PLUS") && !remote_mechs.contains(mech))
+    {
+        return Err(ProtocolError::SaslDowngrade);
+    }
+    Ok(())
+}
+
+/// Authenticates on the stream, `plaintext_mechs` being the mechanisms
+/// the server advertised before STARTTLS, if any.
+// Skipping every argument, as the credentials hold the password.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(mechanism), err(Display))
+)]
 pub async fn auth<S: AsyncRead + AsyncWrite + Unpin>(
     mut stream: XMPPStream<S>,
     creds: Credentials,
+    plaintext_mechs: &HashSet<String>,
 ) -> Result<S, Error> {
-    let local_mechs: Vec<Box<dyn Fn() -> Box<dyn Mechanism + Send + Sync> + Send>> = vec![
-        Box::new(|| Box::new(Scram::<Sha256>::from_credentials(creds.clone()).unwrap())),
-        Box::new(|| Box::new(Scram::<Sha1>::from_credentials(creds.clone()).unwrap())),
+    // When we have channel binding data, prefer the -PLUS mechanisms, and otherwise send the
+    // gs2 `y` flag, telling the server we would have used it had it been offered. A server
+    // offering -PLUS mechanisms then fails the exchange, so stripping them from its features is
+    // detected.
+    let unbound_creds = if creds.channel_binding.is_bound() {
+        creds
+            .clone()
+            .with_channel_binding(ChannelBinding::Unsupported)
+    } else {
+        creds.clone()
+    };
+    // In order of preference
+    let local_mechs: Vec<LocalMechanism> = vec![
+        (
+            "OAUTHBEARER",
+            Box::new(|| Ok(Box::new(OAuthBearer::from_credentials(creds.clone())?))),
+        ),
+        (
+            "SCRAM-SHA-256-PLUS",
+            Box::new(|| Ok(Box::new(Scram::<Sha256>::from_credentials(creds.clone())?))),
+        ),
+        (
+            "SCRAM-SHA-1-PLUS",
+            Box::new(|| Ok(Box::new(Scram::<Sha1>::from_credentials(creds.clone())?))),
+        ),
+        (
+            "SCRAM-SHA-256",
+            Box::new(|| {
+                Ok(Box::new(Scram::<Sha256>::from_credentials(
+                    unbound_creds.clone(),
+                )?))
+            }),
+        ),
+        (
+            "SCRAM-SHA-1",
+            Box::new(|| {
+                Ok(Box::new(Scram::<Sha1>::from_credentials(
+                    unbound_creds.clone(),
+                )?))
+            }),
+        ),
     ];
 
-    let remote_mechs: HashSet<String> = stream.stream_features.sasl_mechanisms()?.collect();
+    let offered_mechs: Vec<String> = stream.stream_features.sasl_mechanisms()?.collect();
+    let remote_mechs: HashSet<String> = offered_mechs.iter().cloned().collect();
+    check_downgrade(plaintext_mechs, &remote_mechs)?;
 
-    for local_mech in local_mechs {
-        let mut mechanism = local_mech();
-        if remote_mechs.contains(mechanism.name()) {
-            let initial = mechanism.initial();
-            let mechanism_name =
-                XMPPMechanism::from_str(mechanism.name()).map_err(ProtocolError::Parsers)?;
+    // Only the mechanisms which can be used with the credentials we were given count, e.g. not
+    // the -PLUS ones without channel binding data, nor OAUTHBEARER without a token.
+    let mechanism = local_mechs
+        .into_iter()
+        .filter(|(name, _)| remote_mechs.contains(*name))
+        .find_map(|(name, local_mech)| match local_mech() {
+            Ok(mechanism) if mechanism.name() == name => Some(mechanism),
+            _ => None,
+        });
+    let Some(mut mechanism) = mechanism else {
+        return Err(ProtocolError::NoSupportedMechanism(offered_mechs).into());
+    };
 
-            stream
-                .send_stanza(Auth {
-                    mechanism: mechanism_name,
-                    data: initial,
-                })
-                .await?;
-
-            loop {
-                match stream.next().await {
-                    Some(Ok(Packet::Stanza(stanza))) => {
-                        if let Ok(challenge) = Challenge::try_from(stanza.clone()) {
-                            let response = mechanism
-                                .response(&challenge.data)
-                                .map_err(|e| AuthError::Sasl(e))?;
-
-                            // Send response and loop
-                            stream.send_stanza(Response { data: response }).await?;
-                        } else if let Ok(_) = Success::try_from(stanza.clone()) {
-                            return Ok(stream.into_inner());
-                        } else if let Ok(failure) = Failure::try_from(stanza.clone()) {
-                            return Err(Error::Auth(AuthError::Fail(failure.defined_condition)));
-                        // TODO: This code was needed for compatibility with some broken server,
-                        // but it’s been forgotten which.  It is currently commented out so that we
-                        // can find it and fix the server software instead.
-                        /*
-                        } else if stanza.name() == "failure" {
-                            // Workaround for https://gitlab.com/xmpp-rs/xmpp-parsers/merge_requests/1
-                            return Err(Error::Auth(AuthError::Sasl("failure".to_string())));
-                        */
-                        } else {
-                            // ignore and loop
-                        }
-                    }
-                    Some(Ok(_)) => {
-                        // ignore and loop
-                    }
-                    Some(Err(e)) => return Err(e),
-                    None => return Err(Error::Disconnected),
+    trace_record!(mechanism = mechanism.name());
+    let initial = mechanism.initial();
+    let mechanism_name =
+        XMPPMechanism::from_str(mechanism.name()).map_err(ProtocolError::Parsers)?;
+
+    stream
+        .send_stanza(Auth {
+            mechanism: mechanism_name,
+            data: initial,
+        })
+        .await?;
+
+    loop {
+        match stream.next().await {
+            Some(Ok(Packet::Stanza(stanza))) => {
+                if let Ok(challenge) = Challenge::try_from(stanza.clone()) {
+                    let response = mechanism
+                        .response(&challenge.data)
+                        .map_err(|e| AuthError::Sasl(e))?;
+
+                    // Send response and loop
+                    stream.send_stanza(Response { data: response }).await?;
+                } else if let Ok(success) = Success::try_from(stanza.clone()) {
+                    // For SCRAM, this verifies the server signature, which covers the
+                    // channel binding flag we sent, so the server can’t have seen a
+                    // different binding state than ours.
+                    mechanism.success(&success.data).map_err(AuthError::Sasl)?;
+                    trace_debug!("authenticated");
+                    return Ok(stream.into_inner());
+                } else if let Ok(failure) = Failure::try_from(stanza.clone()) {
+                    return Err(Error::Auth(AuthError::Fail(failure.defined_condition)));
+                // TODO: This code was needed for compatibility with some broken server,
+                // but it’s been forgotten which.  It is currently commented out so that we
+                // can find it and fix the server software instead.
+                /*
+                } else if stanza.name() == "failure" {
+                    // Workaround for https://gitlab.com/xmpp-rs/xmpp-parsers/merge_requests/1
+                    return Err(Error::Auth(AuthError::Sasl("failure".to_string())));
+                */
+                } else {
+                    // ignore and loop
                 }
             }
+            Some(Ok(_)) => {
+                // ignore and loop
+            }
+            Some(Err(e)) => return Err(e),
+            None => return Err(Error::Disconnected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::codec::Framed;
+    use xmpp_parsers::{ns, sasl::DefinedCondition, Element, Jid};
+
+    use crate::xmpp_codec::XMPPCodec;
+
+    fn mechs(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| String::from(*name)).collect()
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let plaintext = mechs(&["SCRAM-SHA-256-PLUS", "SCRAM-SHA-256"]);
+        let encrypted = mechs(&["SCRAM-SHA-256"]);
+        match check_downgrade(&plaintext, &encrypted) {
+            Err(ProtocolError::SaslDowngrade) => (),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_no_downgrade() {
+        let encrypted = mechs(&["SCRAM-SHA-256-PLUS", "SCRAM-SHA-256", "PLAIN"]);
+        check_downgrade(&HashSet::new(), &encrypted).unwrap();
+        check_downgrade(&mechs(&["SCRAM-SHA-256-PLUS"]), &encrypted).unwrap();
+        // Only the -PLUS mechanisms matter.
+        check_downgrade(&mechs(&["DIGEST-MD5"]), &encrypted).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failure() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let features: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><mechanism>SCRAM-SHA-1</mechanism></mechanisms></stream:features>".parse().unwrap();
+        let stream = XMPPStream::new(
+            Jid::new("test@example.org").unwrap(),
+            Framed::new(client, XMPPCodec::new()),
+            ns::JABBER_CLIENT.to_owned(),
+            "stream-id".to_owned(),
+            features,
+        );
+
+        server
+            .write_all(b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\
+                <failure xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><account-disabled/></failure>")
+            .await
+            .unwrap();
+        let creds = Credentials::default()
+            .with_username("test")
+            .with_password("password");
+        match auth(stream, creds, &HashSet::new()).await {
+            Err(Error::Auth(AuthError::Fail(DefinedCondition::AccountDisabled))) => (),
+            _ => panic!(),
         }
     }
 
-    Err(AuthError::NoMechanism.into())
+    #[tokio::test]
+    async fn test_wrong_server_signature() {
+        use crate::test_util::duplex;
+        use futures::sink::SinkExt;
+        use std::collections::HashMap;
+
+        let (client, mut server) = duplex();
+        let server = tokio::spawn(async move {
+            server
+                .open_stream("<mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><mechanism>SCRAM-SHA-1</mechanism></mechanisms>")
+                .await
+                .unwrap();
+            let auth = Auth::try_from(server.recv().await.unwrap()).unwrap();
+            let initial = String::from_utf8(auth.data).unwrap();
+            let nonce = initial.split(",r=").nth(1).unwrap();
+            server
+                .send(Challenge {
+                    data: format!("r={}fake,s=QSXCR+Q6sek8bf92,i=4096", nonce).into_bytes(),
+                })
+                .await
+                .unwrap();
+            Response::try_from(server.recv().await.unwrap()).unwrap();
+            // Not the signature the client computed.
+            server
+                .send(Success {
+                    data: b"v=rmF9pqV8S7suAoZWja4dJRkFsKQ=".to_vec(),
+                })
+                .await
+                .unwrap();
+        });
+
+        let mut framed = Framed::new(client, XMPPCodec::new());
+        let mut attrs = HashMap::new();
+        attrs.insert("xmlns".to_owned(), ns::JABBER_CLIENT.to_owned());
+        attrs.insert("xmlns:stream".to_owned(), ns::STREAM.to_owned());
+        framed.send(Packet::StreamStart(attrs)).await.unwrap();
+        let features: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><mechanism>SCRAM-SHA-1</mechanism></mechanisms></stream:features>".parse().unwrap();
+        let stream = XMPPStream::new(
+            Jid::new("test@example.org").unwrap(),
+            framed,
+            ns::JABBER_CLIENT.to_owned(),
+            "stream-id".to_owned(),
+            features,
+        );
+        let creds = Credentials::default()
+            .with_username("test")
+            .with_password("password");
+        match auth(stream, creds, &HashSet::new()).await {
+            Err(Error::Auth(AuthError::Sasl(
+                SaslMechanismError::InvalidSignatureInSuccessResponse,
+            ))) => (),
+            _ => panic!(),
+        }
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_plus_stripped() {
+        use crate::test_util::duplex;
+        use futures::sink::SinkExt;
+        use std::collections::HashMap;
+
+        let (client, mut server) = duplex();
+        let server = tokio::spawn(async move {
+            server
+                .open_stream("<mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><mechanism>SCRAM-SHA-1</mechanism></mechanisms>")
+                .await
+                .unwrap();
+            let auth = Auth::try_from(server.recv().await.unwrap()).unwrap();
+            assert_eq!(auth.mechanism, XMPPMechanism::ScramSha1);
+            // We could have bound the channel, had SCRAM-SHA-1-PLUS been offered.
+            assert!(auth.data.starts_with(b"y,,n=test,r="));
+            server
+                .send(Failure {
+                    defined_condition: DefinedCondition::NotAuthorized,
+                    texts: Default::default(),
+                })
+                .await
+                .unwrap();
+        });
+
+        let mut framed = Framed::new(client, XMPPCodec::new());
+        let mut attrs = HashMap::new();
+        attrs.insert("xmlns".to_owned(), ns::JABBER_CLIENT.to_owned());
+        attrs.insert("xmlns:stream".to_owned(), ns::STREAM.to_owned());
+        framed.send(Packet::StreamStart(attrs)).await.unwrap();
+        let features: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><mechanism>SCRAM-SHA-1</mechanism></mechanisms></stream:features>".parse().unwrap();
+        let stream = XMPPStream::new(
+            Jid::new("test@example.org").unwrap(),
+            framed,
+            ns::JABBER_CLIENT.to_owned(),
+            "stream-id".to_owned(),
+            features,
+        );
+        let creds = Credentials::default()
+            .with_username("test")
+            .with_password("password")
+            .with_channel_binding(ChannelBinding::TlsExporter(vec![0; 32]));
+        match auth(stream, creds, &HashSet::new()).await {
+            Err(Error::Auth(AuthError::Fail(DefinedCondition::NotAuthorized))) => (),
+            _ => panic!(),
+        }
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_no_supported_mechanism() {
+        let (client, _server) = tokio::io::duplex(4096);
+        let features: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><mechanism>DIGEST-MD5</mechanism><mechanism>X-OAUTH2</mechanism></mechanisms></stream:features>".parse().unwrap();
+        let stream = XMPPStream::new(
+            Jid::new("test@example.org").unwrap(),
+            Framed::new(client, XMPPCodec::new()),
+            ns::JABBER_CLIENT.to_owned(),
+            "stream-id".to_owned(),
+            features,
+        );
+
+        let creds = Credentials::default()
+            .with_username("test")
+            .with_password("password");
+        match auth(stream, creds, &HashSet::new()).await {
+            Err(Error::Protocol(ProtocolError::NoSupportedMechanism(offered))) => {
+                assert_eq!(offered, ["DIGEST-MD5", "X-OAUTH2"])
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_usable_mechanism() {
+        // Implemented, but neither can be built from a password without channel binding data.
+        let (client, _server) = tokio::io::duplex(4096);
+        let features: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><mechanism>SCRAM-SHA-256-PLUS</mechanism><mechanism>OAUTHBEARER</mechanism></mechanisms></stream:features>".parse().unwrap();
+        let stream = XMPPStream::new(
+            Jid::new("test@example.org").unwrap(),
+            Framed::new(client, XMPPCodec::new()),
+            ns::JABBER_CLIENT.to_owned(),
+            "stream-id".to_owned(),
+            features,
+        );
+
+        let creds = Credentials::default()
+            .with_username("test")
+            .with_password("password");
+        match auth(stream, creds, &HashSet::new()).await {
+            Err(Error::Protocol(ProtocolError::NoSupportedMechanism(offered))) => {
+                assert_eq!(offered, ["SCRAM-SHA-256-PLUS", "OAUTHBEARER"])
+            }
+            _ => panic!(),
+        }
+    }
 }
@@ -1,49 +1,215 @@
 use futures::stream::StreamExt;
+use log::warn;
 use std::convert::TryFrom;
 use std::marker::Unpin;
 use tokio::io::{AsyncRead, AsyncWrite};
 use xmpp_parsers::bind::{BindQuery, BindResponse};
 use xmpp_parsers::iq::{Iq, IqType};
+use xmpp_parsers::stanza_error::DefinedCondition;
+use xmpp_parsers::{ns, Element};
 
 use crate::xmpp_codec::Packet;
 use crate::xmpp_stream::XMPPStream;
 use crate::{Error, ProtocolError};
 
 const BIND_REQ_ID: &str = "resource-bind";
+const SESSION_REQ_ID: &str = "session";
 
+/// The stream features this client knows how to handle once authenticated.
+fn is_supported(feature: &Element) -> bool {
+    feature.is("bind", ns::BIND) || feature.is("session", ns::SESSION)
+}
+
+/// Binds a resource, asking the server to assign one instead if
+/// `retry_on_conflict` is set and the requested resource is already in use.
+///
+/// A session is then established if the server requires it (RFC 3921).
+/// Other features the server marks as required can't be satisfied, and
+/// only get a warning.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(bound_jid), err(Display))
+)]
 pub async fn bind<S: AsyncRead + AsyncWrite + Unpin>(
     mut stream: XMPPStream<S>,
+    mut retry_on_conflict: bool,
 ) -> Result<XMPPStream<S>, Error> {
-    if stream.stream_features.can_bind() {
-        let resource = stream
-            .jid
-            .resource_str()
-            .and_then(|resource| Some(resource.to_owned()));
-        let iq = Iq::from_set(BIND_REQ_ID, BindQuery::new(resource));
-        stream.send_stanza(iq).await?;
-
-        loop {
-            match stream.next().await {
-                Some(Ok(Packet::Stanza(stanza))) => match Iq::try_from(stanza) {
-                    Ok(iq) if iq.id == BIND_REQ_ID => match iq.payload {
-                        IqType::Result(payload) => {
-                            payload
-                                .and_then(|payload| BindResponse::try_from(payload).ok())
-                                .map(|bind| stream.jid = bind.into());
-                            return Ok(stream);
-                        }
-                        _ => return Err(ProtocolError::InvalidBindResponse.into()),
-                    },
-                    _ => {}
-                },
-                Some(Ok(_)) => {}
-                Some(Err(e)) => return Err(e),
-                None => return Err(Error::Disconnected),
-            }
+    for feature in stream.stream_features.required() {
+        if !is_supported(feature) {
+            warn!(
+                "Server requires the unsupported {{{}}}{} stream feature, ignoring it",
+                feature.ns(),
+                feature.name()
+            );
         }
-    } else {
+    }
+
+    if !stream.stream_features.can_bind() {
         // No resource binding available,
         // return the (probably // usable) stream immediately
+        warn!("Server doesn’t offer resource binding");
         return Ok(stream);
     }
+
+    let resource = stream
+        .jid
+        .resource_str()
+        .and_then(|resource| Some(resource.to_owned()));
+    retry_on_conflict &= resource.is_some();
+    let iq = Iq::from_set(BIND_REQ_ID, BindQuery::new(resource));
+    stream.send_stanza(iq).await?;
+
+    loop {
+        match stream.next().await {
+            Some(Ok(Packet::Stanza(stanza))) => match Iq::try_from(stanza) {
+                Ok(iq) if iq.id == BIND_REQ_ID => match iq.payload {
+                    IqType::Result(payload) => {
+                        payload
+                            .and_then(|payload| BindResponse::try_from(payload).ok())
+                            .map(|bind| stream.jid = bind.into());
+                        break;
+                    }
+                    IqType::Error(error)
+                        if retry_on_conflict
+                            && error.defined_condition == DefinedCondition::Conflict =>
+                    {
+                        // Probably our previous session, which hasn’t timed out yet.
+                        warn!("Resource already in use, asking the server for another one");
+                        retry_on_conflict = false;
+                        let iq = Iq::from_set(BIND_REQ_ID, BindQuery::new(None));
+                        stream.send_stanza(iq).await?;
+                    }
+                    _ => return Err(ProtocolError::InvalidBindResponse.into()),
+                },
+                _ => {}
+            },
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e),
+            None => return Err(Error::Disconnected),
+        }
+    }
+
+    trace_record!(bound_jid = %stream.jid);
+    if stream.stream_features.session_required() {
+        establish_session(&mut stream).await?;
+    }
+    Ok(stream)
+}
+
+/// Establishes a session, as obsoleted by RFC 6121 but still required by
+/// some servers.
+async fn establish_session<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut XMPPStream<S>,
+) -> Result<(), Error> {
+    let iq = Iq {
+        from: None,
+        to: None,
+        id: SESSION_REQ_ID.to_owned(),
+        payload: IqType::Set(Element::builder("session", ns::SESSION).build()),
+    };
+    stream.send_stanza(iq).await?;
+
+    loop {
+        match stream.next().await {
+            Some(Ok(Packet::Stanza(stanza))) => match Iq::try_from(stanza) {
+                Ok(iq) if iq.id == SESSION_REQ_ID => match iq.payload {
+                    IqType::Result(_) => return Ok(()),
+                    _ => return Err(ProtocolError::InvalidSessionResponse.into()),
+                },
+                _ => {}
+            },
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e),
+            None => return Err(Error::Disconnected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_util::codec::Framed;
+    use xmpp_parsers::Jid;
+
+    use crate::xmpp_codec::XMPPCodec;
+
+    #[tokio::test]
+    async fn test_bind_conflict() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let features: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/></stream:features>".parse().unwrap();
+        let stream = XMPPStream::new(
+            Jid::new("test@example.org/laptop").unwrap(),
+            Framed::new(client, XMPPCodec::new()),
+            ns::JABBER_CLIENT.to_owned(),
+            "stream-id".to_owned(),
+            features,
+        );
+
+        server
+            .write_all(b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\
+                <iq type='error' id='resource-bind'><error type='cancel'><conflict xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/></error></iq>\
+                <iq type='result' id='resource-bind'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><jid>test@example.org/abcdef</jid></bind></iq>")
+            .await
+            .unwrap();
+        let stream = bind(stream, true).await.unwrap();
+        assert_eq!(stream.jid, Jid::new("test@example.org/abcdef").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_session_required() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let features: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/><session xmlns='urn:ietf:params:xml:ns:xmpp-session'/></stream:features>".parse().unwrap();
+        let stream = XMPPStream::new(
+            Jid::new("test@example.org/laptop").unwrap(),
+            Framed::new(client, XMPPCodec::new()),
+            ns::JABBER_CLIENT.to_owned(),
+            "stream-id".to_owned(),
+            features,
+        );
+        assert!(stream.stream_features.session_required());
+
+        server
+            .write_all(b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\
+                <iq type='result' id='resource-bind'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><jid>test@example.org/laptop</jid></bind></iq>\
+                <iq type='result' id='session'/>")
+            .await
+            .unwrap();
+        let stream = bind(stream, true).await.unwrap();
+        drop(stream);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).await.unwrap();
+        assert!(sent.contains("urn:ietf:params:xml:ns:xmpp-session"));
+    }
+
+    #[tokio::test]
+    async fn test_session_optional() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let features: Element = "<stream:features xmlns:stream='http://etherx.jabber.org/streams'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/><session xmlns='urn:ietf:params:xml:ns:xmpp-session'><optional/></session><register xmlns='http://jabber.org/features/iq-register'><required/></register></stream:features>".parse().unwrap();
+        let stream = XMPPStream::new(
+            Jid::new("test@example.org/laptop").unwrap(),
+            Framed::new(client, XMPPCodec::new()),
+            ns::JABBER_CLIENT.to_owned(),
+            "stream-id".to_owned(),
+            features,
+        );
+        assert!(!stream.stream_features.session_required());
+        let required: Vec<_> = stream
+            .stream_features
+            .required()
+            .map(|feature| feature.name().to_owned())
+            .collect();
+        assert_eq!(required, ["register"]);
+
+        server
+            .write_all(b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\
+                <iq type='result' id='resource-bind'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><jid>test@example.org/laptop</jid></bind></iq>")
+            .await
+            .unwrap();
+        let stream = bind(stream, true).await.unwrap();
+        drop(stream);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).await.unwrap();
+        assert!(!sent.contains("urn:ietf:params:xml:ns:xmpp-session"));
+    }
 }
@@ -1,5 +1,7 @@
 mod auth;
 mod bind;
+mod sm;
 
 pub mod async_client;
 pub mod simple_client;
+pub mod split;
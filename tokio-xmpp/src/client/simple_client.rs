@@ -1,6 +1,7 @@
 use futures::{sink::SinkExt, Sink, Stream};
 use idna;
-use sasl::common::{ChannelBinding, Credentials};
+use sasl::common::Credentials;
+use std::collections::HashSet;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::task::{Context, Poll};
@@ -10,12 +11,13 @@ use tokio_native_tls::TlsStream;
 #[cfg(feature = "tls-rust")]
 use tokio_rustls::client::TlsStream;
 use tokio_stream::StreamExt;
-use xmpp_parsers::{ns, Element, Jid};
+use xmpp_parsers::{ns, stream_features::StreamFeatures, Element, Jid};
 
 use super::auth::auth;
 use super::bind::bind;
 use crate::happy_eyeballs::connect_with_srv;
-use crate::starttls::starttls;
+use crate::resolver::SystemResolver;
+use crate::starttls::{channel_binding, starttls};
 use crate::xmpp_codec::Packet;
 use crate::xmpp_stream::{self, add_stanza_id};
 use crate::{Error, ProtocolError};
@@ -55,19 +57,32 @@ impl Client {
         let domain = idna::domain_to_ascii(&jid.clone().domain_str()).map_err(|_| Error::Idna)?;
 
         // TCP connection
-        let tcp_stream = connect_with_srv(&domain, "_xmpp-client._tcp", 5222).await?;
+        let tcp_stream =
+            connect_with_srv(&SystemResolver, &domain, "_xmpp-client._tcp", 5222).await?;
 
         // Unencryped XMPPStream
         let xmpp_stream =
             xmpp_stream::XMPPStream::start(tcp_stream, jid.clone(), ns::JABBER_CLIENT.to_owned())
                 .await?;
 
-        let xmpp_stream = if xmpp_stream.stream_features.can_starttls() {
+        // Remember what was offered in cleartext, to detect stripped mechanisms
+        let plaintext_mechs: HashSet<String> = match xmpp_stream.stream_features.sasl_mechanisms() {
+            Ok(mechs) => mechs.collect(),
+            Err(_) => HashSet::new(),
+        };
+
+        let (xmpp_stream, channel_binding) = if xmpp_stream.stream_features.can_starttls() {
             // TlsStream
             let tls_stream = starttls(xmpp_stream).await?;
+            let channel_binding = channel_binding(&tls_stream);
             // Encrypted XMPPStream
-            xmpp_stream::XMPPStream::start(tls_stream, jid.clone(), ns::JABBER_CLIENT.to_owned())
-                .await?
+            let xmpp_stream = xmpp_stream::XMPPStream::start(
+                tls_stream,
+                jid.clone(),
+                ns::JABBER_CLIENT.to_owned(),
+            )
+            .await?;
+            (xmpp_stream, channel_binding)
         } else {
             return Err(Error::Protocol(ProtocolError::NoTls));
         };
@@ -75,15 +90,15 @@ impl Client {
         let creds = Credentials::default()
             .with_username(username)
             .with_password(password)
-            .with_channel_binding(ChannelBinding::None);
+            .with_channel_binding(channel_binding);
         // Authenticated (unspecified) stream
-        let stream = auth(xmpp_stream, creds).await?;
+        let stream = auth(xmpp_stream, creds, &plaintext_mechs).await?;
         // Authenticated XMPPStream
         let xmpp_stream =
             xmpp_stream::XMPPStream::start(stream, jid, ns::JABBER_CLIENT.to_owned()).await?;
 
         // XMPPStream bound to user session
-        let xmpp_stream = bind(xmpp_stream).await?;
+        let xmpp_stream = bind(xmpp_stream, true).await?;
         Ok(xmpp_stream)
     }
 
@@ -93,6 +108,11 @@ impl Client {
         &self.stream.jid
     }
 
+    /// Get the features advertised by the server on the current session.
+    pub fn stream_features(&self) -> StreamFeatures {
+        self.stream.stream_features.parse()
+    }
+
     /// Send stanza
     pub async fn send_stanza<E>(&mut self, stanza: E) -> Result<(), Error>
     where
@@ -105,6 +125,17 @@ impl Client {
         .await
     }
 
+    /// Send a nonza, a stream-level element which isn't a stanza
+    ///
+    /// Unlike `send_stanza()`, the element is sent as is, without an id
+    /// being added.
+    pub async fn send_nonza<E>(&mut self, nonza: E) -> Result<(), Error>
+    where
+        E: Into<Element>,
+    {
+        self.send(Packet::Stanza(nonza.into())).await
+    }
+
     /// End connection by sending `</stream:stream>`
     ///
     /// You may expect the server to respond with the same. This
@@ -0,0 +1,274 @@
+use futures::stream::StreamExt;
+use futures::{ready, Future};
+use log::warn;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::marker::Unpin;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{sleep, Sleep};
+use xmpp_parsers::sm::{Enable, Enabled, Failed};
+use xmpp_parsers::{ns, Element};
+
+use crate::xmpp_codec::Packet;
+use crate::xmpp_stream::XMPPStream;
+use crate::{Error, ProtocolError};
+
+/// XEP-0198 counters of a stream on which Stream Management got enabled
+pub struct StreamManagement {
+    /// Number of stanzas sent to the server
+    pub outbound: u32,
+    /// Number of stanzas received from the server
+    pub inbound: u32,
+    /// Stanzas sent but not acknowledged yet, the oldest first
+    pub unacked: VecDeque<Element>,
+    /// Whether `unacked` still holds every stanza the server didn’t
+    /// acknowledge, so that they could all be sent again
+    pub resumable: bool,
+    /// Number of stanzas dropped from `unacked` and not reported yet
+    dropped: u32,
+    /// Whether we sent an `<r/>` which the server didn’t answer yet
+    ack_requested: bool,
+    /// Time to wait after sending a stanza before requesting an
+    /// acknowledgement of it
+    ack_delay: Duration,
+    /// Expires once an acknowledgement of the stanzas sent should be
+    /// requested
+    ack_timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl StreamManagement {
+    fn new(ack_delay: Duration) -> Self {
+        StreamManagement {
+            outbound: 0,
+            inbound: 0,
+            unacked: VecDeque::new(),
+            resumable: true,
+            dropped: 0,
+            ack_requested: false,
+            ack_delay,
+            ack_timer: None,
+        }
+    }
+
+    /// Counts a stanza just sent and keeps it until acknowledged, returning
+    /// whether to request an acknowledgement right away: once half of
+    /// `capacity` stanzas wait for one, so that none gets dropped before the
+    /// server answers, or otherwise `ack_delay` after the first of them, see
+    /// `poll_request()`
+    pub fn sent(&mut self, stanza: Element, capacity: usize) -> bool {
+        self.outbound = self.outbound.wrapping_add(1);
+        self.push(stanza, capacity);
+        if self.ack_requested {
+            return false;
+        }
+        if self.unacked.len() >= (capacity / 2).max(1) {
+            self.ack_timer = None;
+            self.ack_requested = true;
+            return true;
+        }
+        self.start_timer();
+        false
+    }
+
+    /// Ready once an acknowledgement of the stanzas sent a while ago should
+    /// be requested
+    pub fn poll_request(&mut self, cx: &mut Context) -> Poll<()> {
+        match self.ack_timer {
+            Some(ref mut timer) => ready!(timer.as_mut().poll(cx)),
+            None => return Poll::Pending,
+        }
+        self.ack_timer = None;
+        if self.ack_requested || self.unacked.is_empty() {
+            return Poll::Pending;
+        }
+        self.ack_requested = true;
+        Poll::Ready(())
+    }
+
+    fn start_timer(&mut self) {
+        if self.ack_timer.is_none() {
+            self.ack_timer = Some(Box::pin(sleep(self.ack_delay)));
+        }
+    }
+
+    /// Keeps a stanza just sent until it gets acknowledged, dropping the
+    /// oldest ones past `capacity`
+    pub fn push(&mut self, stanza: Element, capacity: usize) {
+        self.unacked.push_back(stanza);
+        while self.unacked.len() > capacity {
+            self.unacked.pop_front();
+            self.dropped += 1;
+            self.resumable = false;
+        }
+    }
+
+    /// Forgets the stanzas the server acknowledged with `<a h=…/>`
+    pub fn ack(&mut self, h: u32) {
+        let oldest = self.outbound.wrapping_sub(self.unacked.len() as u32);
+        let acked = h.wrapping_sub(oldest) as usize;
+        // Otherwise this acknowledges stanzas already dropped, or more
+        // than we sent.
+        if acked <= self.unacked.len() {
+            self.unacked.drain(..acked);
+        }
+        self.ack_requested = false;
+        // Those sent after our request still need acknowledging.
+        if !self.unacked.is_empty() {
+            self.start_timer();
+        }
+    }
+
+    /// Takes over from the state of a previous session, which got
+    /// replaced instead of resumed: the stanzas it left unacknowledged
+    /// won’t be sent again, and get reported as dropped
+    pub fn replace_stale(&mut self, stale: StreamManagement) {
+        self.dropped += stale.lost();
+    }
+
+    /// Number of stanzas this session lost for good: those dropped and
+    /// not reported yet, and those never acknowledged
+    pub fn lost(&self) -> u32 {
+        self.dropped + self.unacked.len() as u32
+    }
+
+    /// Returns how many stanzas got dropped since the last call, if any
+    pub fn take_dropped(&mut self) -> Option<u32> {
+        if self.dropped == 0 {
+            return None;
+        }
+        warn!(
+            "Dropped {} unacknowledged stanzas, they can’t be sent again",
+            self.dropped
+        );
+        Some(std::mem::take(&mut self.dropped))
+    }
+}
+
+/// Whether this element gets counted by Stream Management
+pub fn is_stanza(element: &Element) -> bool {
+    element.is("iq", ns::JABBER_CLIENT)
+        || element.is("message", ns::JABBER_CLIENT)
+        || element.is("presence", ns::JABBER_CLIENT)
+}
+
+/// Enables Stream Management if the server supports it, also returning
+/// the stanzas received in the meantime, which it doesn’t count
+pub async fn enable<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: XMPPStream<S>,
+    ack_delay: Duration,
+) -> Result<(XMPPStream<S>, Option<StreamManagement>, Vec<Element>), Error> {
+    if !stream.stream_features.can_sm() {
+        return Ok((stream, None, Vec::new()));
+    }
+
+    // Resumption isn’t implemented, we only want acknowledgements.
+    stream.send_stanza(Enable::new()).await?;
+
+    let mut received = Vec::new();
+    loop {
+        match stream.next().await {
+            Some(Ok(Packet::Stanza(stanza))) => {
+                if stanza.is("enabled", ns::SM) {
+                    Enabled::try_from(stanza).map_err(ProtocolError::Parsers)?;
+                    let sm = StreamManagement::new(ack_delay);
+                    return Ok((stream, Some(sm), received));
+                } else if stanza.is("failed", ns::SM) {
+                    Failed::try_from(stanza).map_err(ProtocolError::Parsers)?;
+                    return Ok((stream, None, received));
+                } else if is_stanza(&stanza) {
+                    received.push(stanza);
+                }
+            }
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e),
+            None => return Err(Error::Disconnected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str) -> Element {
+        Element::builder("message", ns::JABBER_CLIENT)
+            .attr("id", id)
+            .build()
+    }
+
+    fn send(sm: &mut StreamManagement, id: &str, capacity: usize) {
+        sm.outbound = sm.outbound.wrapping_add(1);
+        sm.push(message(id), capacity);
+    }
+
+    fn unacked(sm: &StreamManagement) -> Vec<&str> {
+        sm.unacked
+            .iter()
+            .map(|stanza| stanza.attr("id").unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_ack() {
+        let mut sm = StreamManagement::new(Duration::from_secs(5));
+        for id in ["a", "b", "c"] {
+            send(&mut sm, id, 10);
+        }
+        sm.ack(2);
+        assert_eq!(unacked(&sm), ["c"]);
+        // Acknowledging the same stanzas again changes nothing.
+        sm.ack(2);
+        assert_eq!(unacked(&sm), ["c"]);
+        // Nor does acknowledging more than we sent.
+        sm.ack(5);
+        assert_eq!(unacked(&sm), ["c"]);
+        sm.ack(3);
+        assert!(sm.unacked.is_empty());
+        assert!(sm.resumable);
+        assert_eq!(sm.take_dropped(), None);
+    }
+
+    #[tokio::test]
+    async fn test_request() {
+        let mut sm = StreamManagement::new(Duration::from_millis(10));
+        assert!(!sm.sent(message("a"), 4));
+        // Half of the capacity is waiting for an acknowledgement.
+        assert!(sm.sent(message("b"), 4));
+        // Already requested.
+        assert!(!sm.sent(message("c"), 4));
+        futures::future::poll_fn(|cx| match sm.poll_request(cx) {
+            Poll::Ready(()) => panic!("requested twice"),
+            Poll::Pending => Poll::Ready(()),
+        })
+        .await;
+        sm.ack(2);
+        assert_eq!(unacked(&sm), ["c"]);
+        // The stanza sent after the request gets its own, after the delay.
+        futures::future::poll_fn(|cx| sm.poll_request(cx)).await;
+        sm.ack(3);
+        assert!(sm.unacked.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_overflow() {
+        let mut sm = StreamManagement::new(Duration::from_secs(5));
+        // Across the wrap-around of the counter.
+        sm.outbound = u32::MAX - 1;
+        for id in ["a", "b", "c", "d"] {
+            send(&mut sm, id, 2);
+        }
+        assert_eq!(unacked(&sm), ["c", "d"]);
+        assert!(!sm.resumable);
+        assert_eq!(sm.take_dropped(), Some(2));
+        assert_eq!(sm.take_dropped(), None);
+
+        // The dropped stanzas getting acknowledged.
+        sm.ack(u32::MAX);
+        assert_eq!(unacked(&sm), ["c", "d"]);
+        sm.ack(1);
+        assert_eq!(unacked(&sm), ["d"]);
+    }
+}
@@ -0,0 +1,388 @@
+//! Sending and receiving halves of an `AsyncClient`, driven by a
+//! background task
+
+use futures::channel::{mpsc, oneshot};
+use futures::{sink::SinkExt, stream::StreamExt, task::Poll, Sink, Stream};
+use std::convert::TryFrom;
+use std::pin::Pin;
+use std::task::Context;
+use tokio::time::{sleep_until, Instant};
+use xmpp_parsers::{
+    iq::{Iq, IqType},
+    ns,
+    presence::Presence,
+    stanza_id::generate_id,
+    Element, Jid,
+};
+
+use super::async_client::Client;
+use crate::event::Event;
+use crate::xmpp_codec::Packet;
+use crate::xmpp_stream::add_stanza_id;
+use crate::Error;
+
+enum Command {
+    Send(Packet, oneshot::Sender<Result<(), Error>>),
+    Iq(Iq, oneshot::Sender<Result<Iq, Error>>),
+}
+
+/// An iq waiting for its answer
+struct PendingIq {
+    id: String,
+    to: Option<Jid>,
+    deadline: Instant,
+    result: oneshot::Sender<Result<Iq, Error>>,
+}
+
+/// Sending half of a split `AsyncClient`
+///
+/// Stanzas are handed over to the task driving the connection, so that
+/// they can be sent from another task than the one receiving events. It
+/// can be cloned to send from as many tasks as needed.
+#[derive(Clone)]
+pub struct ClientSender {
+    commands: mpsc::Sender<Command>,
+}
+
+/// Receiving half of a split `AsyncClient`
+///
+/// This is a `Stream` of the events of the client. The task driving the
+/// connection stops once this is dropped.
+pub struct ClientReceiver {
+    events: mpsc::Receiver<Event>,
+}
+
+impl Client {
+    /// Split the client into a sending and a receiving half, moving it to
+    /// a background task which drives the connection.
+    ///
+    /// At most `capacity` events are buffered for the receiver, past which
+    /// the task waits for them to be received before reading more from the
+    /// server, and as many packets for the client to send.
+    pub fn split(self, capacity: usize) -> (ClientSender, ClientReceiver) {
+        let (commands_tx, commands_rx) = mpsc::channel(capacity);
+        let (events_tx, events_rx) = mpsc::channel(capacity);
+        tokio::spawn(run(self, commands_rx, events_tx));
+        (
+            ClientSender {
+                commands: commands_tx,
+            },
+            ClientReceiver { events: events_rx },
+        )
+    }
+}
+
+/// Hand a packet to the client, without waiting for it to be connected,
+/// as it only makes progress while its events are polled.
+async fn send(client: &mut Client, packet: Packet) -> Result<(), Error> {
+    if client.bound_jid().is_some() {
+        client.send(packet).await
+    } else {
+        // Queued, or refused, until we are online again
+        Pin::new(client).start_send(packet)
+    }
+}
+
+/// Whether an iq from `from` can answer one we sent to `to`.
+fn answers(to: Option<&Jid>, from: Option<&Jid>, own: Option<&Jid>) -> bool {
+    match (to, from, own) {
+        (to, from, _) if to == from => true,
+        // Our server answers on behalf of our account.
+        (None, Some(from), Some(own)) => {
+            from.to_bare() == own.to_bare()
+                || (from.node_str().is_none() && from.domain_str() == own.domain_str())
+        }
+        _ => false,
+    }
+}
+
+async fn run(
+    mut client: Client,
+    mut commands: mpsc::Receiver<Command>,
+    mut events: mpsc::Sender<Event>,
+) {
+    let mut senders_alive = true;
+    let mut pending: Vec<PendingIq> = Vec::new();
+    loop {
+        // Disabled while nothing is pending, but still evaluated.
+        let next_deadline = pending
+            .iter()
+            .map(|iq| iq.deadline)
+            .min()
+            .unwrap_or_else(Instant::now);
+        tokio::select! {
+            command = commands.next(), if senders_alive => match command {
+                Some(Command::Send(packet, result)) => {
+                    let _ = result.send(send(&mut client, packet).await);
+                }
+                Some(Command::Iq(iq, result)) => {
+                    let (id, to) = (iq.id.clone(), iq.to.clone());
+                    let deadline = Instant::now() + client.iq_timeout();
+                    match send(&mut client, Packet::Stanza(iq.into())).await {
+                        Ok(()) => pending.push(PendingIq {
+                            id,
+                            to,
+                            deadline,
+                            result,
+                        }),
+                        Err(e) => {
+                            let _ = result.send(Err(e));
+                        }
+                    }
+                }
+                None => senders_alive = false,
+            },
+            _ = sleep_until(next_deadline), if !pending.is_empty() => {
+                let now = Instant::now();
+                // A late answer then gets received as an event.
+                while let Some(index) = pending.iter().position(|iq| iq.deadline <= now) {
+                    let _ = pending.remove(index).result.send(Err(Error::Timeout("iq")));
+                }
+            }
+            event = client.next() => match event {
+                Some(Event::Stanza(stanza)) if stanza.is("iq", ns::JABBER_CLIENT) => {
+                    let iq = match Iq::try_from(stanza.clone()) {
+                        Ok(iq) if matches!(iq.payload, IqType::Result(_) | IqType::Error(_)) => iq,
+                        _ => {
+                            if events.send(Event::Stanza(stanza)).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    let own = client.bound_jid();
+                    match pending.iter().position(|pending| {
+                        pending.id == iq.id
+                            && answers(pending.to.as_ref(), iq.from.as_ref(), own)
+                    }) {
+                        Some(index) => {
+                            let _ = pending.remove(index).result.send(Ok(iq));
+                        }
+                        None => {
+                            if events.send(Event::Stanza(stanza)).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+                Some(event) => {
+                    if let Event::Disconnected(_) = event {
+                        // Their answers won't come on a new session.
+                        for iq in pending.drain(..) {
+                            let _ = iq.result.send(Err(Error::Disconnected));
+                        }
+                    }
+                    if events.send(event).await.is_err() {
+                        // The receiver is gone.
+                        break;
+                    }
+                }
+                None => break,
+            },
+        }
+    }
+}
+
+impl ClientSender {
+    /// Send a packet, returning once the client sent it, or queued it
+    /// while not connected.
+    ///
+    /// Fails with `Error::Disconnected` if the client isn't running
+    /// anymore.
+    pub async fn send(&mut self, packet: Packet) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        self.commands
+            .send(Command::Send(packet, tx))
+            .await
+            .map_err(|_| Error::Disconnected)?;
+        rx.await.map_err(|_| Error::Disconnected)?
+    }
+
+    /// Send stanza
+    pub async fn send_stanza(&mut self, stanza: Element) -> Result<(), Error> {
+        self.send(Packet::Stanza(add_stanza_id(stanza, ns::JABBER_CLIENT)))
+            .await
+    }
+
+    /// Send presence
+    ///
+    /// With `Config::rebroadcast_presence`, the last available presence
+    /// broadcast this way is sent again whenever a new session starts.
+    pub async fn send_presence(&mut self, presence: Presence) -> Result<(), Error> {
+        self.send_stanza(presence.into()).await
+    }
+
+    /// Send an iq, returning its answer, be it a result or an error.
+    ///
+    /// Its id gets replaced with a unique one, so that answers to
+    /// different iqs can't be mixed up. The answer isn't received as an
+    /// event. Fails with `Error::Disconnected` if the client got
+    /// disconnected before it came, or with `Error::Timeout` if it didn't
+    /// come within `Config::iq_timeout`.
+    pub async fn send_iq(&mut self, mut iq: Iq) -> Result<Iq, Error> {
+        iq.id = generate_id();
+        let (tx, rx) = oneshot::channel();
+        self.commands
+            .send(Command::Iq(iq, tx))
+            .await
+            .map_err(|_| Error::Disconnected)?;
+        rx.await.map_err(|_| Error::Disconnected)?
+    }
+
+    /// End connection by sending `</stream:stream>`
+    ///
+    /// Events keep being received until the server closes its side.
+    pub async fn send_end(&mut self) -> Result<(), Error> {
+        self.send(Packet::StreamEnd).await
+    }
+}
+
+impl Stream for ClientReceiver {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.events).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use xmpp_parsers::{message::Message, ping::Ping, FullJid};
+
+    use crate::test_util::online_client_and_server;
+
+    #[tokio::test]
+    async fn test_split() {
+        let (mut client, mut server) = online_client_and_server(|builder| builder).await;
+        client.set_reconnect(false);
+        let (mut sender, mut receiver) = client.split(4);
+        let server = tokio::spawn(async move {
+            let stanza = server.recv().await.unwrap();
+            let message = Message::try_from(stanza).unwrap();
+            assert!(message.id.is_some());
+            let reply = Message::new(message.from).with_body(
+                String::new(),
+                String::from("Deny thy father and refuse thy name."),
+            );
+            server.send(reply).await.unwrap();
+            server.close().await.unwrap();
+        });
+
+        // Sent from another task than the one receiving events.
+        tokio::spawn(async move {
+            let message = Message::new(Jid::new("romeo@montague.example").unwrap())
+                .with_body(String::new(), String::from("Wherefore art thou Romeo?"));
+            sender.send_stanza(message.into()).await.unwrap();
+        })
+        .await
+        .unwrap();
+
+        match receiver.next().await {
+            Some(Event::Stanza(stanza)) => {
+                let message = Message::try_from(stanza).unwrap();
+                assert_eq!(message.bodies[""].0, "Deny thy father and refuse thy name.");
+            }
+            event => panic!("{:?}", event),
+        }
+        assert!(matches!(
+            receiver.next().await,
+            Some(Event::Disconnected(Error::StreamClosed(None)))
+        ));
+        assert!(receiver.next().await.is_none());
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_iq() {
+        let (mut client, mut server) = online_client_and_server(|builder| builder).await;
+        client.set_reconnect(false);
+        let (sender, mut receiver) = client.split(4);
+        let jid = FullJid::new("juliet@capulet.example/orchard").unwrap();
+        let server = tokio::spawn(async move {
+            let presence = server.recv().await.unwrap();
+            assert!(presence.is("presence", ns::JABBER_CLIENT));
+            let iq = Iq::try_from(server.recv().await.unwrap()).unwrap();
+            assert!(matches!(iq.payload, IqType::Get(_)));
+            assert_ne!(iq.id, "ping");
+            // An answer from someone else, with the same id.
+            let forged = Iq::empty_result(Jid::Full(jid.clone()), iq.id.clone())
+                .with_from(Jid::new("mallory@evil.example").unwrap());
+            server.send(forged).await.unwrap();
+            let answer = Iq::empty_result(Jid::Full(jid), iq.id)
+                .with_from(Jid::new("capulet.example").unwrap());
+            server.send(answer).await.unwrap();
+            server.close().await.unwrap();
+        });
+
+        let mut presence_sender = sender.clone();
+        tokio::spawn(async move {
+            presence_sender
+                .send_presence(Presence::available())
+                .await
+                .unwrap();
+        })
+        .await
+        .unwrap();
+        let mut iq_sender = sender;
+        let ping = tokio::spawn(async move { iq_sender.send_iq(Iq::from_get("ping", Ping)).await });
+
+        // Only the forged answer is received as an event.
+        match receiver.next().await {
+            Some(Event::Stanza(stanza)) => {
+                let iq = Iq::try_from(stanza).unwrap();
+                assert_eq!(iq.from, Some(Jid::new("mallory@evil.example").unwrap()));
+            }
+            event => panic!("{:?}", event),
+        }
+        let answer = ping.await.unwrap().unwrap();
+        assert_eq!(answer.from, Some(Jid::new("capulet.example").unwrap()));
+        assert!(matches!(answer.payload, IqType::Result(None)));
+        assert!(matches!(
+            receiver.next().await,
+            Some(Event::Disconnected(Error::StreamClosed(None)))
+        ));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_iq_timeout() {
+        let (mut client, mut server) =
+            online_client_and_server(|builder| builder.set_iq_timeout(Duration::from_millis(100)))
+                .await;
+        client.set_reconnect(false);
+        let (mut sender, mut receiver) = client.split(4);
+        let jid = FullJid::new("juliet@capulet.example/orchard").unwrap();
+        let (timed_out_tx, timed_out_rx) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            let iq = Iq::try_from(server.recv().await.unwrap()).unwrap();
+            timed_out_rx.await.unwrap();
+            let answer = Iq::empty_result(Jid::Full(jid), iq.id)
+                .with_from(Jid::new("capulet.example").unwrap());
+            server.send(answer).await.unwrap();
+            server.close().await.unwrap();
+        });
+
+        let error = sender
+            .send_iq(Iq::from_get("ping", Ping))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Timeout("iq")));
+        timed_out_tx.send(()).unwrap();
+
+        // Not pending anymore, so the late answer is received as an event.
+        match receiver.next().await {
+            Some(Event::Stanza(stanza)) => {
+                let iq = Iq::try_from(stanza).unwrap();
+                assert!(matches!(iq.payload, IqType::Result(None)));
+            }
+            event => panic!("{:?}", event),
+        }
+        assert!(matches!(
+            receiver.next().await,
+            Some(Event::Disconnected(Error::StreamClosed(None)))
+        ));
+        server.await.unwrap();
+    }
+}
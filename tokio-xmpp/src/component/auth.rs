@@ -21,9 +21,7 @@ pub async fn auth<S: AsyncRead + AsyncWrite + Unpin>(
             {
                 return Ok(());
             }
-            Some(Ok(Packet::Stanza(ref stanza)))
-                if stanza.is("error", "http://etherx.jabber.org/streams") =>
-            {
+            Some(Ok(Packet::Stanza(ref stanza))) if stanza.is("error", ns::STREAM) => {
                 return Err(AuthError::ComponentFail.into());
             }
             Some(_) => {}
@@ -9,6 +9,7 @@ use tokio::net::TcpStream;
 use xmpp_parsers::{ns, Element, Jid};
 
 use super::happy_eyeballs::connect_to_host;
+use super::resolver::SystemResolver;
 use super::xmpp_codec::Packet;
 use super::xmpp_stream;
 use super::Error;
@@ -44,7 +45,7 @@ impl Component {
         port: u16,
     ) -> Result<XMPPStream, Error> {
         let password = password;
-        let tcp_stream = connect_to_host(server, port).await?;
+        let tcp_stream = connect_to_host(&SystemResolver, server, port).await?;
         let mut xmpp_stream =
             xmpp_stream::XMPPStream::start(tcp_stream, jid, ns::COMPONENT_ACCEPT.to_owned())
                 .await?;
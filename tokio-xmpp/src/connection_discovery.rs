@@ -0,0 +1,308 @@
+//! Discovery of the alternative connection methods of a server, e.g. its
+//! WebSocket endpoint, from its host-meta document (XEP-0156)
+
+use log::debug;
+#[cfg(feature = "host-meta-json")]
+use serde_json::Value;
+use std::io::ErrorKind;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use xmpp_parsers::Element;
+
+use crate::error::timeout;
+use crate::happy_eyeballs::connect_to_host;
+use crate::resolver::Resolver;
+use crate::starttls::tls_connect;
+use crate::{Error, ProtocolError};
+
+/// Link relation of WebSocket endpoints (RFC 7395)
+pub const WEBSOCKET: &str = "urn:xmpp:alt-connections:websocket";
+/// Link relation of BOSH endpoints (XEP-0206)
+pub const XBOSH: &str = "urn:xmpp:alt-connections:xbosh";
+/// Link relation of HTTP polling endpoints (XEP-0025)
+pub const HTTPPOLL: &str = "urn:xmpp:alt-connections:httppoll";
+
+const XRD: &str = "http://docs.oasis-open.org/ns/xri/xrd-1.0";
+
+/// Largest HTTP response accepted, headers included
+const MAX_RESPONSE_SIZE: u64 = 64 * 1024;
+
+/// Time given to each HTTP request to complete
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The endpoints a server advertises, in the order of its document
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AltConnections {
+    /// WebSocket URLs
+    pub websocket: Vec<String>,
+    /// BOSH URLs
+    pub bosh: Vec<String>,
+    /// HTTP polling URLs
+    pub httppoll: Vec<String>,
+}
+
+impl AltConnections {
+    fn add(&mut self, rel: &str, href: &str) {
+        let urls = match rel {
+            WEBSOCKET => &mut self.websocket,
+            XBOSH => &mut self.bosh,
+            HTTPPOLL => &mut self.httppoll,
+            _ => return,
+        };
+        urls.push(href.to_owned());
+    }
+
+    /// Parses the XML form of host-meta, an XRD document
+    pub fn from_xrd(document: &str) -> Result<Self, Error> {
+        let root: Element = document.parse()?;
+        if !root.is("XRD", XRD) {
+            return Err(ProtocolError::InvalidHostMeta.into());
+        }
+        let mut connections = AltConnections::default();
+        for link in root.children().filter(|child| child.is("Link", XRD)) {
+            if let (Some(rel), Some(href)) = (link.attr("rel"), link.attr("href")) {
+                connections.add(rel, href);
+            }
+        }
+        Ok(connections)
+    }
+
+    /// Parses the JSON form of host-meta, a JRD document
+    #[cfg(feature = "host-meta-json")]
+    pub fn from_jrd(document: &str) -> Result<Self, Error> {
+        let root: Value =
+            serde_json::from_str(document).map_err(|_| ProtocolError::InvalidHostMeta)?;
+        let links = match root.get("links") {
+            Some(Value::Array(links)) => links.as_slice(),
+            None if root.is_object() => &[],
+            _ => return Err(ProtocolError::InvalidHostMeta.into()),
+        };
+        let mut connections = AltConnections::default();
+        for link in links {
+            let rel = link.get("rel").and_then(Value::as_str);
+            let href = link.get("href").and_then(Value::as_str);
+            if let (Some(rel), Some(href)) = (rel, href) {
+                connections.add(rel, href);
+            }
+        }
+        Ok(connections)
+    }
+}
+
+/// Fetches `https://<domain>/.well-known/host-meta.json`, with the
+/// host-meta-json feature, or otherwise or if missing its XML form, and
+/// returns the alternative connection methods it lists
+///
+/// A server without any host-meta document, or without any HTTPS listener,
+/// has none. Redirections aren’t followed.
+pub async fn discover(resolver: &dyn Resolver, domain: &str) -> Result<AltConnections, Error> {
+    #[cfg(feature = "host-meta-json")]
+    if let Some(document) = fetch(resolver, domain, "/.well-known/host-meta.json").await? {
+        return AltConnections::from_jrd(&document);
+    }
+    match fetch(resolver, domain, "/.well-known/host-meta").await? {
+        Some(document) => AltConnections::from_xrd(&document),
+        None => Ok(AltConnections::default()),
+    }
+}
+
+async fn fetch(resolver: &dyn Resolver, domain: &str, path: &str) -> Result<Option<String>, Error> {
+    let stream = match connect_to_host(resolver, domain, 443).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            debug!("No HTTPS listener for {}: {}", domain, e);
+            return Ok(None);
+        }
+    };
+    let stream = tls_connect(domain, stream).await?;
+    timeout(
+        "host-meta request",
+        REQUEST_TIMEOUT,
+        get(stream, domain, path),
+    )
+    .await
+}
+
+/// Performs an HTTP/1.0 GET, so that the response body can’t be chunked,
+/// and returns it if the request succeeded
+///
+/// Responses larger than `MAX_RESPONSE_SIZE` are refused as invalid.
+async fn get<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    host: &str,
+    path: &str,
+) -> Result<Option<String>, Error> {
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    let mut response = Vec::new();
+    match (&mut stream)
+        .take(MAX_RESPONSE_SIZE + 1)
+        .read_to_end(&mut response)
+        .await
+    {
+        Ok(_) => (),
+        // Plenty of servers don’t bother closing TLS before TCP.
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof && !response.is_empty() => (),
+        Err(e) => return Err(e.into()),
+    }
+    if response.len() as u64 > MAX_RESPONSE_SIZE {
+        return Err(ProtocolError::InvalidHostMeta.into());
+    }
+    let response = std::str::from_utf8(&response)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or(ProtocolError::InvalidHostMeta)?;
+    let status: u16 = head
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or(ProtocolError::InvalidHostMeta)?;
+    if !(200..300).contains(&status) {
+        debug!("GET https://{}{}: HTTP status {}", host, path, status);
+        return Ok(None);
+    }
+    Ok(Some(body.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_xrd() {
+        let connections = AltConnections::from_xrd(
+            "<XRD xmlns='http://docs.oasis-open.org/ns/xri/xrd-1.0'>
+  <Link rel='urn:xmpp:alt-connections:xbosh' href='https://web.example.com:5280/bosh'/>
+  <Link rel='urn:xmpp:alt-connections:websocket' href='wss://web.example.com:443/ws'/>
+  <Link rel='lrdd' href='https://example.com/lrdd?uri={uri}'/>
+</XRD>",
+        )
+        .unwrap();
+        assert_eq!(connections.websocket, ["wss://web.example.com:443/ws"]);
+        assert_eq!(connections.bosh, ["https://web.example.com:5280/bosh"]);
+        assert!(connections.httppoll.is_empty());
+
+        let error = AltConnections::from_xrd("<html xmlns='http://www.w3.org/1999/xhtml'/>");
+        assert!(matches!(
+            error,
+            Err(Error::Protocol(ProtocolError::InvalidHostMeta))
+        ));
+    }
+
+    #[cfg(feature = "host-meta-json")]
+    #[test]
+    fn test_from_jrd() {
+        let connections = AltConnections::from_jrd(
+            r#"{
+  "links": [
+    {
+      "rel": "urn:xmpp:alt-connections:xbosh",
+      "href": "https://web.example.com:5280/bosh"
+    },
+    {
+      "rel": "urn:xmpp:alt-connections:websocket",
+      "href": "wss://web.example.com:443/ws"
+    },
+    {
+      "rel": "urn:xmpp:alt-connections:httppoll",
+      "href": "https://web.example.com:5280/http-poll"
+    }
+  ]
+}"#,
+        )
+        .unwrap();
+        assert_eq!(connections.websocket, ["wss://web.example.com:443/ws"]);
+        assert_eq!(connections.bosh, ["https://web.example.com:5280/bosh"]);
+        assert_eq!(
+            connections.httppoll,
+            ["https://web.example.com:5280/http-poll"]
+        );
+
+        assert_eq!(
+            AltConnections::from_jrd("{}").unwrap(),
+            AltConnections::default()
+        );
+        for invalid in ["[]", "{\"links\": 42}", "<XRD/>"] {
+            assert!(matches!(
+                AltConnections::from_jrd(invalid),
+                Err(Error::Protocol(ProtocolError::InvalidHostMeta))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        for (response, body) in [
+            (
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{}",
+                Some("{}"),
+            ),
+            ("HTTP/1.1 404 Not Found\r\n\r\nNot Found", None),
+        ] {
+            let (client, mut server) = tokio::io::duplex(1024);
+            let server = tokio::spawn(async move {
+                let mut request = vec![0; 1024];
+                let len = server.read(&mut request).await.unwrap();
+                let request = String::from_utf8(request[..len].to_vec()).unwrap();
+                assert!(request.starts_with("GET /.well-known/host-meta.json HTTP/1.0\r\n"));
+                assert!(request.contains("\r\nHost: example.com\r\n"));
+                server.write_all(response.as_bytes()).await.unwrap();
+            });
+            let document = get(client, "example.com", "/.well-known/host-meta.json")
+                .await
+                .unwrap();
+            assert_eq!(document.as_deref(), body);
+            server.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_too_large() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let mut request = vec![0; 1024];
+            assert!(server.read(&mut request).await.unwrap() > 0);
+            server.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+            // Never ending.
+            let chunk = [b' '; 1024];
+            while server.write_all(&chunk).await.is_ok() {}
+        });
+        assert!(matches!(
+            get(client, "example.com", "/.well-known/host-meta").await,
+            Err(Error::Protocol(ProtocolError::InvalidHostMeta))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_discover_unreachable() {
+        use crate::resolver::SrvRecord;
+        use futures::future::BoxFuture;
+        use std::net::IpAddr;
+
+        #[derive(Debug)]
+        struct NoAddress;
+
+        impl Resolver for NoAddress {
+            fn lookup_srv<'a>(
+                &'a self,
+                _: &'a str,
+            ) -> BoxFuture<'a, Result<Vec<SrvRecord>, Error>> {
+                Box::pin(async { Ok(vec![]) })
+            }
+
+            fn lookup_ip<'a>(&'a self, _: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>, Error>> {
+                Box::pin(async { Ok(vec![]) })
+            }
+        }
+
+        assert_eq!(
+            discover(&NoAddress, "example.com").await.unwrap(),
+            AltConnections::default()
+        );
+    }
+}
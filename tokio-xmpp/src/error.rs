@@ -6,6 +6,7 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::io::Error as IoError;
 use std::str::Utf8Error;
+use std::time::Duration;
 #[cfg(feature = "tls-rust")]
 use tokio_rustls::rustls::client::InvalidDnsNameError;
 #[cfg(feature = "tls-rust")]
@@ -14,6 +15,7 @@ use trust_dns_proto::error::ProtoError;
 use trust_dns_resolver::error::ResolveError;
 
 use xmpp_parsers::sasl::DefinedCondition as SaslDefinedCondition;
+use xmpp_parsers::stanza_error::StanzaError;
 use xmpp_parsers::{Error as ParsersError, JidParseError};
 
 /// Top-level error type
@@ -32,6 +34,8 @@ pub enum Error {
     Protocol(ProtocolError),
     /// Authentication error
     Auth(AuthError),
+    /// HTTP File Upload error
+    Upload(UploadError),
     /// TLS error
     Tls(TlsError),
     #[cfg(feature = "tls-rust")]
@@ -39,12 +43,21 @@ pub enum Error {
     DnsNameError(InvalidDnsNameError),
     /// Connection closed
     Disconnected,
+    /// The server closed the stream with `</stream:stream>`, after a
+    /// `<stream:error/>` with this defined condition if any, e.g. `conflict`
+    StreamClosed(Option<String>),
+    /// The connection ended without the stream being closed first
+    ConnectionReset,
     /// Shoud never happen
     InvalidState,
+    /// Too many stanzas queued while not connected
+    QueueFull,
     /// Fmt error
     Fmt(fmt::Error),
     /// Utf8 error
     Utf8(Utf8Error),
+    /// The named operation didn't complete in time
+    Timeout(&'static str),
 }
 
 impl fmt::Display for Error {
@@ -56,18 +69,61 @@ impl fmt::Display for Error {
             Error::JidParse(e) => write!(fmt, "jid parse error: {}", e),
             Error::Protocol(e) => write!(fmt, "protocol error: {}", e),
             Error::Auth(e) => write!(fmt, "authentication error: {}", e),
+            Error::Upload(e) => write!(fmt, "upload error: {}", e),
             Error::Tls(e) => write!(fmt, "TLS error: {}", e),
             #[cfg(feature = "tls-rust")]
             Error::DnsNameError(e) => write!(fmt, "DNS name error: {}", e),
             Error::Disconnected => write!(fmt, "disconnected"),
+            Error::StreamClosed(None) => write!(fmt, "stream closed"),
+            Error::StreamClosed(Some(condition)) => write!(fmt, "stream closed: {}", condition),
+            Error::ConnectionReset => write!(fmt, "connection reset"),
             Error::InvalidState => write!(fmt, "invalid state"),
+            Error::QueueFull => write!(fmt, "outgoing queue full"),
             Error::Fmt(e) => write!(fmt, "Fmt error: {}", e),
             Error::Utf8(e) => write!(fmt, "Utf8 error: {}", e),
+            Error::Timeout(operation) => write!(fmt, "{} timed out", operation),
         }
     }
 }
 
-impl StdError for Error {}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Connection(e) => Some(e),
+            Error::JidParse(e) => Some(e),
+            Error::Protocol(e) => Some(e),
+            Error::Auth(e) => Some(e),
+            Error::Upload(e) => Some(e),
+            Error::Tls(e) => Some(e),
+            #[cfg(feature = "tls-rust")]
+            Error::DnsNameError(e) => Some(e),
+            Error::Fmt(e) => Some(e),
+            Error::Utf8(e) => Some(e),
+            Error::Idna
+            | Error::Disconnected
+            | Error::StreamClosed(_)
+            | Error::ConnectionReset
+            | Error::InvalidState
+            | Error::QueueFull
+            | Error::Timeout(_) => None,
+        }
+    }
+}
+
+/// Runs `future` for up to `duration`, failing with `Error::Timeout(operation)` past it.
+pub(crate) async fn timeout<F, T>(
+    operation: &'static str,
+    duration: Duration,
+    future: F,
+) -> Result<T, Error>
+where
+    F: std::future::Future<Output = Result<T, Error>>,
+{
+    tokio::time::timeout(duration, future)
+        .await
+        .unwrap_or(Err(Error::Timeout(operation)))
+}
 
 impl From<IoError> for Error {
     fn from(e: IoError) -> Self {
@@ -99,6 +155,12 @@ impl From<AuthError> for Error {
     }
 }
 
+impl From<UploadError> for Error {
+    fn from(e: UploadError) -> Self {
+        Error::Upload(e)
+    }
+}
+
 impl From<TlsError> for Error {
     fn from(e: TlsError) -> Self {
         Error::Tls(e)
@@ -154,6 +216,8 @@ pub enum ProtocolError {
     NoTls,
     /// Invalid response to resource binding
     InvalidBindResponse,
+    /// Invalid response to session establishment
+    InvalidSessionResponse,
     /// No xmlns attribute in <stream:stream>
     NoStreamNamespace,
     /// No id attribute in <stream:stream>
@@ -162,6 +226,18 @@ pub enum ProtocolError {
     InvalidToken,
     /// Unexpected <stream:stream> (shouldn't occur)
     InvalidStreamStart,
+    /// SASL mechanisms with channel binding were stripped
+    SaslDowngrade,
+    /// None of the SASL mechanisms offered by the server, given here, is
+    /// supported by this client
+    NoSupportedMechanism(Vec<String>),
+    /// Invalid host-meta document, or HTTP response carrying it
+    InvalidHostMeta,
+    /// In-Band Bytestream negotiated with `<iq/>` stanzas, which aren't
+    /// supported
+    IbbIqStanza,
+    /// In-Band Bytestream negotiated with a block-size of 0
+    IbbZeroBlockSize,
 }
 
 impl fmt::Display for ProtocolError {
@@ -173,17 +249,43 @@ impl fmt::Display for ProtocolError {
             ProtocolError::InvalidBindResponse => {
                 write!(fmt, "invalid response to resource binding")
             }
+            ProtocolError::InvalidSessionResponse => {
+                write!(fmt, "invalid response to session establishment")
+            }
             ProtocolError::NoStreamNamespace => {
                 write!(fmt, "no xmlns attribute in <stream:stream>")
             }
             ProtocolError::NoStreamId => write!(fmt, "no id attribute in <stream:stream>"),
             ProtocolError::InvalidToken => write!(fmt, "encountered an unexpected XML token"),
             ProtocolError::InvalidStreamStart => write!(fmt, "unexpected <stream:stream>"),
+            ProtocolError::SaslDowngrade => {
+                write!(fmt, "SASL mechanisms with channel binding were stripped")
+            }
+            ProtocolError::NoSupportedMechanism(offered) => write!(
+                fmt,
+                "the server only offers unsupported SASL mechanisms: {}",
+                offered.join(", ")
+            ),
+            ProtocolError::InvalidHostMeta => write!(fmt, "invalid host-meta document"),
+            ProtocolError::IbbIqStanza => {
+                write!(fmt, "In-Band Bytestreams over <iq/> aren't supported")
+            }
+            ProtocolError::IbbZeroBlockSize => {
+                write!(fmt, "In-Band Bytestream with a block-size of 0")
+            }
         }
     }
 }
 
-impl StdError for ProtocolError {}
+impl StdError for ProtocolError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ProtocolError::Parser(e) => Some(e),
+            ProtocolError::Parsers(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl From<minidom::Error> for ProtocolError {
     fn from(e: minidom::Error) -> Self {
@@ -216,19 +318,145 @@ pub enum AuthError {
     ComponentFail,
 }
 
-impl StdError for AuthError {}
+impl AuthError {
+    /// Whether the same credentials may succeed later, e.g. to decide
+    /// between retrying and asking the user for another password
+    pub fn is_temporary(&self) -> bool {
+        matches!(
+            self,
+            AuthError::Fail(SaslDefinedCondition::TemporaryAuthFailure)
+        )
+    }
+}
+
+impl StdError for AuthError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            AuthError::Sasl(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for AuthError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
             AuthError::NoMechanism => write!(fmt, "no matching SASL mechanism available"),
             AuthError::Sasl(s) => write!(fmt, "local SASL implementation error: {}", s),
-            AuthError::Fail(c) => write!(fmt, "failure from the server: {:?}", c),
+            AuthError::Fail(c) => {
+                let reason = match c {
+                    SaslDefinedCondition::Aborted => "authentication aborted",
+                    SaslDefinedCondition::AccountDisabled => "account disabled",
+                    SaslDefinedCondition::CredentialsExpired => "credentials expired",
+                    SaslDefinedCondition::EncryptionRequired => "encryption required",
+                    SaslDefinedCondition::IncorrectEncoding => "incorrect encoding",
+                    SaslDefinedCondition::InvalidAuthzid => "invalid authzid",
+                    SaslDefinedCondition::InvalidMechanism => "invalid mechanism",
+                    SaslDefinedCondition::MalformedRequest => "malformed request",
+                    SaslDefinedCondition::MechanismTooWeak => "mechanism too weak",
+                    SaslDefinedCondition::NotAuthorized => "not authorized",
+                    SaslDefinedCondition::TemporaryAuthFailure => {
+                        "temporary failure, try again later"
+                    }
+                };
+                write!(fmt, "failure from the server: {}", reason)
+            }
             AuthError::ComponentFail => write!(fmt, "component authentication failure"),
         }
     }
 }
 
+/// HTTP File Upload error (XEP-0363)
+///
+/// The XMPP side of the upload fails with `Refused`, the HTTP side with
+/// `Http` or `HttpStatus`.
+#[derive(Debug)]
+pub enum UploadError {
+    /// The service doesn’t advertise HTTP File Upload
+    NotSupported,
+    /// The file is larger than the max-file-size the service advertises
+    TooLarge {
+        /// Size of the file
+        size: u64,
+        /// Largest size the service accepts
+        max: u64,
+    },
+    /// The service answered with an error, e.g. when refusing the slot
+    Refused(Box<StanzaError>),
+    /// The HTTP PUT failed without a response
+    Http(Box<dyn StdError + Send + Sync>),
+    /// The HTTP PUT got answered with this unsuccessful status code
+    HttpStatus(u16),
+}
+
+impl StdError for UploadError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            UploadError::Refused(e) => Some(&**e),
+            UploadError::Http(e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UploadError::NotSupported => write!(fmt, "the service doesn’t support HTTP upload"),
+            UploadError::TooLarge { size, max } => write!(
+                fmt,
+                "the file is {} bytes large, the service accepts at most {}",
+                size, max
+            ),
+            UploadError::Refused(e) => write!(fmt, "refused by the service: {}", e),
+            UploadError::Http(e) => write!(fmt, "HTTP error: {}", e),
+            UploadError::HttpStatus(status) => write!(fmt, "HTTP status {}", status),
+        }
+    }
+}
+
+/// Invalid client configuration, as refused by `AsyncConfigBuilder::build()`
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The JID has no node, which is needed as the SASL username
+    NoNode,
+    /// The resource isn’t a valid resourcepart
+    InvalidResource(JidParseError),
+    /// The manually configured server host is empty
+    EmptyHost,
+    /// The manually configured server port is 0
+    InvalidPort,
+    /// The language isn’t a valid language tag
+    InvalidLang(String),
+    /// The maximum number of unacknowledged stanzas is 0, which would drop
+    /// every stanza as soon as it is sent
+    NoUnackedStanzas,
+}
+
+impl StdError for ConfigError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ConfigError::InvalidResource(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::NoNode => write!(fmt, "the JID has no node to use as username"),
+            ConfigError::InvalidResource(e) => write!(fmt, "invalid resource: {}", e),
+            ConfigError::EmptyHost => write!(fmt, "the server host is empty"),
+            ConfigError::InvalidPort => write!(fmt, "the server port is 0"),
+            ConfigError::InvalidLang(lang) => write!(fmt, "invalid language tag: {:?}", lang),
+            ConfigError::NoUnackedStanzas => {
+                write!(fmt, "the maximum number of unacknowledged stanzas is 0")
+            }
+        }
+    }
+}
+
 /// Error establishing connection
 #[derive(Debug)]
 pub enum ConnecterError {
@@ -240,10 +468,22 @@ pub enum ConnecterError {
     Resolve(ResolveError),
 }
 
-impl StdError for ConnecterError {}
+impl StdError for ConnecterError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ConnecterError::AllFailed => None,
+            ConnecterError::Dns(e) => Some(e),
+            ConnecterError::Resolve(e) => Some(e),
+        }
+    }
+}
 
 impl std::fmt::Display for ConnecterError {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(fmt, "{:?}", self)
+        match self {
+            ConnecterError::AllFailed => write!(fmt, "all connection attempts failed"),
+            ConnecterError::Dns(e) => write!(fmt, "DNS protocol error: {}", e),
+            ConnecterError::Resolve(e) => write!(fmt, "DNS resolution error: {}", e),
+        }
     }
 }
@@ -17,10 +17,40 @@ pub enum Event {
         /// Not yet implemented for the Client
         resumed: bool,
     },
+    /// Connecting to the server, emitted by the Client every time it
+    /// (re)connects
+    Connecting,
+    /// Connected, now authenticating (Client only)
+    Authenticating,
+    /// Authenticated, now binding a resource (Client only)
+    Binding,
     /// Stream end
     Disconnected(Error),
     /// Received stanza/nonza
     Stanza(Element),
+    /// The server acknowledged the stanzas we sent (XEP-0198)
+    ///
+    /// Only emitted by the Client, when the server supports Stream
+    /// Management.
+    StanzaAcked {
+        /// Number of stanzas sent on this session which the server has
+        /// handled so far
+        ///
+        /// Compare with `Client::sent_stanzas()` right after sending a
+        /// stanza to know when that stanza got acknowledged.
+        h: u32,
+    },
+    /// Stanzas the server didn’t acknowledge yet got dropped from the
+    /// buffer kept to send them again, which was full (XEP-0198)
+    ///
+    /// See `Config::sm_max_unacked`; the session isn’t resumable anymore.
+    /// Also emitted right after `Event::Online` for the stanzas the previous
+    /// session left unacknowledged, when a new session replaced it, with or
+    /// without Stream Management.
+    UnackedDropped {
+        /// Number of stanzas dropped, the oldest ones
+        count: u32,
+    },
 }
 
 impl Event {
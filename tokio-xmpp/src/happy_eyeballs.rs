@@ -1,24 +1,25 @@
-use crate::{ConnecterError, Error};
+use crate::resolver::{Resolver, SrvRecord};
+use crate::Error;
 use idna;
 use log::debug;
+use socket2::{SockRef, TcpKeepalive};
 use std::net::SocketAddr;
+use std::time::Duration;
 use tokio::net::TcpStream;
-use trust_dns_resolver::{IntoName, TokioAsyncResolver};
 
-pub async fn connect_to_host(domain: &str, port: u16) -> Result<TcpStream, Error> {
+pub async fn connect_to_host(
+    resolver: &dyn Resolver,
+    domain: &str,
+    port: u16,
+) -> Result<TcpStream, Error> {
     let ascii_domain = idna::domain_to_ascii(&domain).map_err(|_| Error::Idna)?;
 
     if let Ok(ip) = ascii_domain.parse() {
         return Ok(TcpStream::connect(&SocketAddr::new(ip, port)).await?);
     }
 
-    let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(ConnecterError::Resolve)?;
-
-    let ips = resolver
-        .lookup_ip(ascii_domain)
-        .await
-        .map_err(ConnecterError::Resolve)?;
-    for ip in ips.iter() {
+    let ips = resolver.lookup_ip(&ascii_domain).await?;
+    for ip in ips {
         match TcpStream::connect(&SocketAddr::new(ip, port)).await {
             Ok(stream) => return Ok(stream),
             Err(_) => {}
@@ -27,7 +28,33 @@ pub async fn connect_to_host(domain: &str, port: u16) -> Result<TcpStream, Error
     Err(Error::Disconnected)
 }
 
+/// Disables Nagle’s algorithm if `nodelay` is set, and enables TCP
+/// keepalive probes after `keepalive` of idle time if given, leaving the
+/// system defaults otherwise.
+pub fn set_tcp_options(
+    stream: &TcpStream,
+    nodelay: bool,
+    keepalive: Option<Duration>,
+) -> Result<(), Error> {
+    if nodelay {
+        stream.set_nodelay(true)?;
+    }
+    if let Some(idle) = keepalive {
+        SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+    }
+    Ok(())
+}
+
+/// Sorts SRV records in the order they should be tried in: by ascending
+/// priority, then by descending weight.
+fn sort_srv_records(records: &mut [SrvRecord]) {
+    // TODO: pick records of the same priority at random, proportionally to
+    // their weight, as RFC 2782 requires.
+    records.sort_by_key(|srv| (srv.priority, std::cmp::Reverse(srv.weight)));
+}
+
 pub async fn connect_with_srv(
+    resolver: &dyn Resolver,
     domain: &str,
     srv: &str,
     fallback_port: u16,
@@ -39,21 +66,19 @@ pub async fn connect_with_srv(
         return Ok(TcpStream::connect(&SocketAddr::new(ip, fallback_port)).await?);
     }
 
-    let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(ConnecterError::Resolve)?;
-
-    let srv_domain = format!("{}.{}.", srv, ascii_domain)
-        .into_name()
-        .map_err(ConnecterError::Dns)?;
-    let srv_records = resolver.srv_lookup(srv_domain.clone()).await.ok();
+    let srv_domain = format!("{}.{}.", srv, ascii_domain);
+    let srv_records = resolver.lookup_srv(&srv_domain).await.ok();
 
     match srv_records {
-        Some(lookup) => {
-            // TODO: sort lookup records by priority/weight
-            for srv in lookup.iter() {
-                debug!("Attempting connection to {srv_domain} {srv}");
-                match connect_to_host(&srv.target().to_ascii(), srv.port()).await {
-                    Ok(stream) => return Ok(stream),
-                    Err(_) => {}
+        Some(mut records) => {
+            sort_srv_records(&mut records);
+            for srv in records {
+                debug!(
+                    "Attempting connection to {srv_domain} {} {} {} {}",
+                    srv.priority, srv.weight, srv.port, srv.target
+                );
+                if let Ok(stream) = connect_to_host(resolver, &srv.target, srv.port).await {
+                    return Ok(stream);
                 }
             }
             Err(Error::Disconnected)
@@ -61,7 +86,92 @@ pub async fn connect_with_srv(
         None => {
             // SRV lookup error, retry with hostname
             debug!("Attempting connection to {domain}:{fallback_port}");
-            connect_to_host(domain, fallback_port).await
+            connect_to_host(resolver, domain, fallback_port).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::BoxFuture;
+    use std::net::{IpAddr, Ipv4Addr};
+    use tokio::net::TcpListener;
+
+    #[derive(Debug)]
+    struct FakeResolver {
+        records: Vec<SrvRecord>,
+    }
+
+    impl Resolver for FakeResolver {
+        fn lookup_srv<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<SrvRecord>, Error>> {
+            assert_eq!(name, "_xmpp-client._tcp.example.org.");
+            Box::pin(async move { Ok(self.records.clone()) })
+        }
+
+        fn lookup_ip<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>, Error>> {
+            Box::pin(async move {
+                match host {
+                    "good.example.org" => Ok(vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]),
+                    _ => Ok(vec![]),
+                }
+            })
+        }
+    }
+
+    fn srv(priority: u16, weight: u16, port: u16, target: &str) -> SrvRecord {
+        SrvRecord {
+            priority,
+            weight,
+            port,
+            target: target.to_owned(),
         }
     }
+
+    #[test]
+    fn test_sort_srv_records() {
+        let mut records = vec![
+            srv(20, 0, 5222, "c"),
+            srv(10, 5, 5222, "b"),
+            srv(10, 50, 5222, "a"),
+        ];
+        sort_srv_records(&mut records);
+        let targets: Vec<_> = records.iter().map(|srv| srv.target.as_str()).collect();
+        assert_eq!(targets, ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_srv() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let resolver = FakeResolver {
+            records: vec![
+                srv(20, 0, port, "good.example.org"),
+                srv(10, 0, port, "bad.example.org"),
+            ],
+        };
+        let stream = connect_with_srv(&resolver, "example.org", "_xmpp-client._tcp", 5222)
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap().port(), port);
+    }
+
+    #[tokio::test]
+    async fn test_set_tcp_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        set_tcp_options(&stream, false, None).unwrap();
+        assert!(!stream.nodelay().unwrap());
+
+        set_tcp_options(&stream, true, Some(Duration::from_secs(30))).unwrap();
+        assert!(stream.nodelay().unwrap());
+        let socket = SockRef::from(&stream);
+        assert!(socket.keepalive().unwrap());
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            socket.tcp_keepalive_time().unwrap(),
+            Duration::from_secs(30)
+        );
+    }
 }
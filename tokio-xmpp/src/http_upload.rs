@@ -0,0 +1,260 @@
+//! Uploading files to a slot given by the server, over HTTP (XEP-0363)
+
+use futures::future::BoxFuture;
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use xmpp_parsers::{
+    disco::{DiscoInfoQuery, DiscoInfoResult},
+    http_upload::{Header, SlotRequest, SlotResult},
+    iq::{Iq, IqType},
+    ns,
+    stanza_id::generate_id,
+    Element, Error as ParsersError, Jid,
+};
+
+use crate::client::split::ClientSender;
+use crate::{Error, ProtocolError, UploadError};
+
+/// Performs the HTTP requests of uploads, so that any HTTP library can be
+/// used
+pub trait HttpClient: Send + Sync {
+    /// PUTs `body` to `url` with the given headers, and returns the status
+    /// code of the response
+    fn put<'a>(
+        &'a self,
+        url: &'a str,
+        headers: &'a [(&'static str, String)],
+        body: Vec<u8>,
+    ) -> BoxFuture<'a, Result<u16, Box<dyn StdError + Send + Sync>>>;
+}
+
+/// The result of an iq we sent, or the error it got answered with.
+fn result(iq: Iq) -> Result<Element, Error> {
+    match iq.payload {
+        IqType::Result(Some(payload)) => Ok(payload),
+        IqType::Error(error) => Err(UploadError::Refused(Box::new(error)).into()),
+        _ => Err(
+            ProtocolError::Parsers(ParsersError::ParseError("Empty result to an upload iq."))
+                .into(),
+        ),
+    }
+}
+
+/// The max-file-size advertised in this disco#info, if any.
+fn max_file_size(info: &DiscoInfoResult) -> Option<u64> {
+    info.extensions
+        .iter()
+        .filter(|form| form.form_type.as_deref() == Some(ns::HTTP_UPLOAD))
+        .flat_map(|form| form.fields.iter())
+        .find(|field| field.var == "max-file-size")
+        .and_then(|field| field.values.first())
+        .and_then(|value| value.parse().ok())
+}
+
+impl ClientSender {
+    /// Upload a file to the HTTP File Upload `service`, performing the PUT
+    /// with `http`, and return the URL it can be downloaded from.
+    ///
+    /// The disco#info of the service is queried first, and the upload fails
+    /// with `UploadError::TooLarge` without requesting a slot when the file
+    /// is larger than what the service accepts.
+    pub async fn upload_file(
+        &mut self,
+        service: Jid,
+        http: &dyn HttpClient,
+        data: Vec<u8>,
+        filename: &str,
+        content_type: Option<&str>,
+    ) -> Result<String, Error> {
+        let size = data.len() as u64;
+        let query =
+            Iq::from_get(generate_id(), DiscoInfoQuery { node: None }).with_to(service.clone());
+        let info = DiscoInfoResult::try_from(result(self.send_iq(query).await?)?)
+            .map_err(ProtocolError::Parsers)?;
+        if !info
+            .features
+            .iter()
+            .any(|feature| feature.var == ns::HTTP_UPLOAD)
+        {
+            return Err(UploadError::NotSupported.into());
+        }
+        if let Some(max) = max_file_size(&info) {
+            if size > max {
+                return Err(UploadError::TooLarge { size, max }.into());
+            }
+        }
+
+        let request = SlotRequest {
+            filename: String::from(filename),
+            size,
+            content_type: content_type.map(String::from),
+        };
+        let request = Iq::from_get(generate_id(), request).with_to(service);
+        let slot = SlotResult::try_from(result(self.send_iq(request).await?)?)
+            .map_err(ProtocolError::Parsers)?;
+
+        let mut headers: Vec<(&'static str, String)> = slot
+            .put
+            .headers
+            .into_iter()
+            .map(|header| match header {
+                Header::Authorization(value) => ("Authorization", value),
+                Header::Cookie(value) => ("Cookie", value),
+                Header::Expires(value) => ("Expires", value),
+            })
+            .collect();
+        if let Some(content_type) = content_type {
+            headers.push(("Content-Type", String::from(content_type)));
+        }
+        let status = http
+            .put(&slot.put.url, &headers, data)
+            .await
+            .map_err(UploadError::Http)?;
+        if !(200..300).contains(&status) {
+            return Err(UploadError::HttpStatus(status).into());
+        }
+        Ok(slot.get.url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use xmpp_parsers::{
+        data_forms::{DataForm, DataFormType, Field, FieldType},
+        disco::{Feature, Identity},
+        http_upload::{Get, Put},
+        FullJid,
+    };
+
+    use crate::test_util::online_client_and_server;
+
+    type Put_ = (String, Vec<(&'static str, String)>, Vec<u8>);
+
+    struct FakeHttp {
+        status: u16,
+        puts: Mutex<Vec<Put_>>,
+    }
+
+    impl HttpClient for FakeHttp {
+        fn put<'a>(
+            &'a self,
+            url: &'a str,
+            headers: &'a [(&'static str, String)],
+            body: Vec<u8>,
+        ) -> BoxFuture<'a, Result<u16, Box<dyn StdError + Send + Sync>>> {
+            self.puts
+                .lock()
+                .unwrap()
+                .push((String::from(url), headers.to_vec(), body));
+            Box::pin(async move { Ok(self.status) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_file() {
+        let (mut client, mut server) = online_client_and_server(|builder| builder).await;
+        client.set_reconnect(false);
+        let (mut sender, _receiver) = client.split(4);
+        let jid = FullJid::new("juliet@capulet.example/orchard").unwrap();
+        let service = Jid::new("upload.capulet.example").unwrap();
+        let server_service = service.clone();
+        let server = tokio::spawn(async move {
+            // Three uploads: successful, too large, refused over HTTP.
+            for slots in [1, 0, 1] {
+                let iq = Iq::try_from(server.recv().await.unwrap()).unwrap();
+                assert_eq!(iq.to.as_ref(), Some(&server_service));
+                let info = DiscoInfoResult {
+                    node: None,
+                    identities: vec![Identity::new("store", "file", "en", "HTTP File Upload")],
+                    features: vec![Feature::new(ns::DISCO_INFO), Feature::new(ns::HTTP_UPLOAD)],
+                    extensions: vec![DataForm::new(
+                        DataFormType::Result_,
+                        ns::HTTP_UPLOAD,
+                        vec![Field::new("max-file-size", FieldType::TextSingle).with_value("16")],
+                    )],
+                };
+                let answer = Iq::from_result(iq.id, Some(info))
+                    .with_from(server_service.clone())
+                    .with_to(Jid::Full(jid.clone()));
+                server.send(answer).await.unwrap();
+
+                for _ in 0..slots {
+                    let iq = Iq::try_from(server.recv().await.unwrap()).unwrap();
+                    let request = match iq.payload {
+                        IqType::Get(payload) => SlotRequest::try_from(payload).unwrap(),
+                        _ => panic!(),
+                    };
+                    assert_eq!(request.filename, "balcony.txt");
+                    assert_eq!(request.size, 14);
+                    let slot = SlotResult {
+                        put: Put {
+                            url: String::from("https://upload.capulet.example/put/balcony.txt"),
+                            headers: vec![Header::Authorization(String::from("Basic cm9zZQ=="))],
+                        },
+                        get: Get {
+                            url: String::from("https://upload.capulet.example/get/balcony.txt"),
+                        },
+                    };
+                    let answer = Iq::from_result(iq.id, Some(slot))
+                        .with_from(server_service.clone())
+                        .with_to(Jid::Full(jid.clone()));
+                    server.send(answer).await.unwrap();
+                }
+            }
+            server.close().await.unwrap();
+        });
+
+        let data = b"O Romeo, Romeo".to_vec();
+        let http = FakeHttp {
+            status: 201,
+            puts: Mutex::new(Vec::new()),
+        };
+        let url = sender
+            .upload_file(
+                service.clone(),
+                &http,
+                data.clone(),
+                "balcony.txt",
+                Some("text/plain"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(url, "https://upload.capulet.example/get/balcony.txt");
+        let puts = http.puts.lock().unwrap().clone();
+        assert_eq!(
+            puts,
+            [(
+                String::from("https://upload.capulet.example/put/balcony.txt"),
+                vec![
+                    ("Authorization", String::from("Basic cm9zZQ==")),
+                    ("Content-Type", String::from("text/plain")),
+                ],
+                data.clone(),
+            )]
+        );
+
+        let error = sender
+            .upload_file(service.clone(), &http, vec![0; 17], "balcony.txt", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Upload(UploadError::TooLarge { size: 17, max: 16 })
+        ));
+
+        let http = FakeHttp {
+            status: 403,
+            puts: Mutex::new(Vec::new()),
+        };
+        let error = sender
+            .upload_file(service, &http, data, "balcony.txt", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Upload(UploadError::HttpStatus(403))));
+        assert_eq!(error.to_string(), "upload error: HTTP status 403");
+        assert_eq!(error.source().unwrap().to_string(), "HTTP status 403");
+        server.await.unwrap();
+    }
+}
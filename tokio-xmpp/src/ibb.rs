@@ -0,0 +1,229 @@
+//! Sending data over In-Band Bytestreams (XEP-0047)
+
+use futures::{ready, Sink};
+use std::io::{Error as IoError, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::AsyncWrite;
+use xmpp_parsers::{
+    ibb::{Close, Data, Open, Stanza, StreamId},
+    iq::Iq,
+    message::{Message, MessageType},
+    stanza_id::generate_id,
+    Jid,
+};
+
+use crate::{Error, Packet, ProtocolError};
+
+/// Writes to an already opened In-Band Bytestream, one `<message/>` stanza
+/// per chunk.
+///
+/// Only streams opened with `stanza='message'` are supported, as the
+/// `<iq/>` framing requires waiting for the result of each chunk.
+///
+/// A chunk is only produced once `sink` is ready to accept it, so writing a
+/// large buffer never holds more than what the sink itself buffers, and
+/// writes wait for the stanzas to be sent instead of queueing them.
+///
+/// Shutting the writer down sends the `<close/>` of the stream, without
+/// waiting for the peer's answer.
+pub struct IbbWriter<S> {
+    sink: S,
+    to: Jid,
+    sid: StreamId,
+    block_size: u16,
+    seq: u16,
+    closed: bool,
+}
+
+impl<S: Sink<Packet, Error = Error> + Unpin> IbbWriter<S> {
+    /// Writes to the stream negotiated with `to` through `open`, sending
+    /// the stanzas to `sink`.
+    ///
+    /// Fails with `ProtocolError::IbbIqStanza` if `open` asks for the
+    /// `<iq/>` framing, and with `ProtocolError::IbbZeroBlockSize` if its
+    /// block-size is 0.
+    pub fn new(sink: S, to: Jid, open: &Open) -> Result<Self, Error> {
+        if open.block_size == 0 {
+            return Err(ProtocolError::IbbZeroBlockSize.into());
+        }
+        if open.stanza != Stanza::Message {
+            return Err(ProtocolError::IbbIqStanza.into());
+        }
+        Ok(IbbWriter {
+            sink,
+            to,
+            sid: open.sid.clone(),
+            block_size: open.block_size,
+            seq: 0,
+            closed: false,
+        })
+    }
+
+    /// Gives the sink back.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+fn io_error(e: Error) -> IoError {
+    match e {
+        Error::Io(e) => e,
+        e => IoError::other(e),
+    }
+}
+
+impl<S: Sink<Packet, Error = Error> + Unpin> AsyncWrite for IbbWriter<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, IoError>> {
+        let this = &mut *self;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        if this.closed {
+            return Poll::Ready(Err(ErrorKind::BrokenPipe.into()));
+        }
+        ready!(Pin::new(&mut this.sink).poll_ready(cx)).map_err(io_error)?;
+        let len = buf.len().min(this.block_size as usize);
+        let data = Data {
+            seq: this.seq,
+            sid: this.sid.clone(),
+            data: buf[..len].to_vec(),
+        };
+        let mut message = Message::new(this.to.clone()).with_payload(data);
+        message.type_ = MessageType::Normal;
+        message.id = Some(generate_id());
+        Pin::new(&mut this.sink)
+            .start_send(Packet::Stanza(message.into()))
+            .map_err(io_error)?;
+        this.seq = this.seq.wrapping_add(1);
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), IoError>> {
+        Pin::new(&mut self.sink).poll_flush(cx).map_err(io_error)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), IoError>> {
+        let this = &mut *self;
+        if !this.closed {
+            ready!(Pin::new(&mut this.sink).poll_ready(cx)).map_err(io_error)?;
+            let close = Close {
+                sid: this.sid.clone(),
+            };
+            let iq = Iq::from_set(generate_id(), close).with_to(this.to.clone());
+            Pin::new(&mut this.sink)
+                .start_send(Packet::Stanza(iq.into()))
+                .map_err(io_error)?;
+            this.closed = true;
+        }
+        Pin::new(&mut this.sink).poll_flush(cx).map_err(io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{channel::mpsc::channel, SinkExt, StreamExt};
+    use std::convert::TryFrom;
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+    use xmpp_parsers::ns;
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        const BLOCK_SIZE: u16 = 4096;
+        let (tx, mut rx) = channel(2);
+        let sink = tx.sink_map_err(|_| Error::Disconnected);
+        let to = Jid::new("romeo@montague.example/orchard").unwrap();
+        let sid = StreamId(String::from("i781hf64"));
+        let open = Open {
+            block_size: BLOCK_SIZE,
+            sid: sid.clone(),
+            stanza: Stanza::Message,
+        };
+        let mut writer = IbbWriter::new(sink, to, &open).unwrap();
+        let payload: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+
+        // Nobody reads the stanzas, so writing stops once the channel is
+        // full instead of chunking the whole payload up front.
+        let mut written = 0;
+        while let Ok(n) =
+            tokio::time::timeout(Duration::from_millis(50), writer.write(&payload[written..])).await
+        {
+            written += n.unwrap();
+        }
+        // The channel holds its buffer plus one stanza per sender.
+        assert!(written > 0);
+        assert!(written <= 3 * BLOCK_SIZE as usize);
+
+        let write = async move {
+            writer.write_all(&payload[written..]).await.unwrap();
+            writer.shutdown().await.unwrap();
+            payload
+        };
+        let read = async {
+            let mut received = Vec::new();
+            let mut seq = 0u16;
+            while let Some(Packet::Stanza(stanza)) = rx.next().await {
+                if stanza.is("iq", ns::DEFAULT_NS) {
+                    let iq = Iq::try_from(stanza).unwrap();
+                    let close = match iq.payload {
+                        xmpp_parsers::iq::IqType::Set(payload) => Close::try_from(payload).unwrap(),
+                        _ => panic!(),
+                    };
+                    assert_eq!(close.sid, sid);
+                    break;
+                }
+                let message = Message::try_from(stanza).unwrap();
+                assert!(message.id.is_some());
+                assert_eq!(message.type_, MessageType::Normal);
+                let data = Data::try_from(message.payloads[0].clone()).unwrap();
+                assert_eq!(data.seq, seq);
+                assert_eq!(data.sid, sid);
+                assert!(data.data.len() <= BLOCK_SIZE as usize);
+                seq = seq.wrapping_add(1);
+                received.extend(data.data);
+            }
+            (received, seq)
+        };
+        let (payload, (received, seq)) = tokio::join!(write, read);
+        assert_eq!(received, payload);
+        assert_eq!(seq as usize, payload.len() / BLOCK_SIZE as usize);
+    }
+
+    #[test]
+    fn test_iq_stanza() {
+        let (tx, _rx) = channel(1);
+        let sink = tx.sink_map_err(|_| Error::Disconnected);
+        let open = Open {
+            block_size: 4096,
+            sid: StreamId(String::from("i781hf64")),
+            stanza: Stanza::Iq,
+        };
+        let to = Jid::new("romeo@montague.example/orchard").unwrap();
+        assert!(matches!(
+            IbbWriter::new(sink, to, &open),
+            Err(Error::Protocol(ProtocolError::IbbIqStanza))
+        ));
+    }
+
+    #[test]
+    fn test_zero_block_size() {
+        let (tx, _rx) = channel(1);
+        let sink = tx.sink_map_err(|_| Error::Disconnected);
+        let open = Open {
+            block_size: 0,
+            sid: StreamId(String::from("i781hf64")),
+            stanza: Stanza::Message,
+        };
+        let to = Jid::new("romeo@montague.example/orchard").unwrap();
+        assert!(matches!(
+            IbbWriter::new(sink, to, &open),
+            Err(Error::Protocol(ProtocolError::IbbZeroBlockSize))
+        ));
+    }
+}
@@ -2,26 +2,70 @@
 
 #![deny(unsafe_code, missing_docs, bare_trait_objects)]
 
+/// Emits a debug `tracing` event, only with the tracing feature
+macro_rules! trace_debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    }};
+}
+
+/// Records fields of the current `tracing` span, declared empty by
+/// `#[instrument(fields(…))]`, only with the tracing feature
+macro_rules! trace_record {
+    ($($field:ident = $value:expr),+) => {{
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            $(span.record(stringify!($field), $value);)+
+        }
+    }};
+    ($field:ident = %$value:expr) => {{
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record(stringify!($field), tracing::field::display(&$value));
+    }};
+}
+
 mod starttls;
 mod stream_start;
 mod xmpp_codec;
-pub use crate::xmpp_codec::Packet;
+pub use crate::xmpp_codec::{Framing, Packet, XMPPCodec};
 mod event;
 pub use event::Event;
 mod client;
+pub mod connection_discovery;
 mod happy_eyeballs;
+pub mod http_upload;
+mod ibb;
+pub use crate::ibb::IbbWriter;
+pub mod resolver;
 pub mod stream_features;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 pub mod xmpp_stream;
 pub use client::{
-    async_client::Client as AsyncClient, async_client::Config as AsyncConfig,
-    async_client::ServerConfig as AsyncServerConfig, simple_client::Client as SimpleClient,
+    async_client::Client as AsyncClient,
+    async_client::Config as AsyncConfig,
+    async_client::ConfigBuilder as AsyncConfigBuilder,
+    async_client::MessageOptions,
+    async_client::ServerConfig as AsyncServerConfig,
+    simple_client::Client as SimpleClient,
+    split::{ClientReceiver, ClientSender},
 };
 mod component;
 pub use crate::component::Component;
 mod error;
-pub use crate::error::{AuthError, ConnecterError, Error, ParseError, ProtocolError};
+pub use crate::error::{
+    AuthError, ConfigError, ConnecterError, Error, ParseError, ProtocolError, UploadError,
+};
 pub use starttls::starttls;
 
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Any transport an XMPP stream can run on, TLS-encrypted or not
+pub trait AsyncReadAndWrite: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncReadAndWrite for T {}
+
 // Re-exports
 pub use minidom::Element;
 pub use xmpp_parsers as parsers;
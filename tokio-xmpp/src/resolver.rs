@@ -0,0 +1,77 @@
+//! DNS resolution used to find and connect to the server
+
+use futures::future::BoxFuture;
+use std::fmt;
+use std::net::IpAddr;
+use trust_dns_resolver::{IntoName, TokioAsyncResolver};
+
+use crate::{ConnecterError, Error};
+
+/// A SRV record, as returned by `Resolver::lookup_srv()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvRecord {
+    /// Lower values are tried first
+    pub priority: u16,
+    /// Among records of the same priority, higher values are tried first
+    pub weight: u16,
+    /// Port to connect to
+    pub port: u16,
+    /// Host name to resolve with `Resolver::lookup_ip()`
+    pub target: String,
+}
+
+/// Performs the DNS lookups needed to connect to a server, so that e.g.
+/// DNS-over-HTTPS or a fake resolver for tests can be used instead of
+/// the system one
+pub trait Resolver: fmt::Debug + Send + Sync {
+    /// Looks up the SRV records of `name`, e.g.
+    /// `_xmpp-client._tcp.example.org.`
+    ///
+    /// An error makes the client fall back to the domain itself on the
+    /// default port.
+    fn lookup_srv<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<SrvRecord>, Error>>;
+
+    /// Looks up the A and AAAA records of `host`
+    fn lookup_ip<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>, Error>>;
+}
+
+/// The default `Resolver`, using the system configuration
+#[derive(Debug, Clone, Default)]
+pub struct SystemResolver;
+
+impl SystemResolver {
+    fn resolver() -> Result<TokioAsyncResolver, Error> {
+        TokioAsyncResolver::tokio_from_system_conf().map_err(|e| ConnecterError::Resolve(e).into())
+    }
+}
+
+impl Resolver for SystemResolver {
+    fn lookup_srv<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<SrvRecord>, Error>> {
+        Box::pin(async move {
+            let name = name.into_name().map_err(ConnecterError::Dns)?;
+            let lookup = Self::resolver()?
+                .srv_lookup(name)
+                .await
+                .map_err(ConnecterError::Resolve)?;
+            Ok(lookup
+                .iter()
+                .map(|srv| SrvRecord {
+                    priority: srv.priority(),
+                    weight: srv.weight(),
+                    port: srv.port(),
+                    target: srv.target().to_ascii(),
+                })
+                .collect())
+        })
+    }
+
+    fn lookup_ip<'a>(&'a self, host: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>, Error>> {
+        Box::pin(async move {
+            let lookup = Self::resolver()?
+                .lookup_ip(host)
+                .await
+                .map_err(ConnecterError::Resolve)?;
+            Ok(lookup.iter().collect())
+        })
+    }
+}
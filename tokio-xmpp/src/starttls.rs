@@ -6,7 +6,7 @@ use {
     std::sync::Arc,
     tokio_rustls::{
         client::TlsStream,
-        rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName},
+        rustls::{ClientConfig, OwnedTrustAnchor, ProtocolVersion, RootCertStore, ServerName},
         TlsConnector,
     },
     webpki_roots,
@@ -18,6 +18,7 @@ use {
     tokio_native_tls::{TlsConnector, TlsStream},
 };
 
+use sasl::common::ChannelBinding;
 use tokio::io::{AsyncRead, AsyncWrite};
 use xmpp_parsers::{ns, Element};
 
@@ -26,26 +27,24 @@ use crate::xmpp_stream::XMPPStream;
 use crate::{Error, ProtocolError};
 
 #[cfg(feature = "tls-native")]
-async fn get_tls_stream<S: AsyncRead + AsyncWrite + Unpin>(
-    xmpp_stream: XMPPStream<S>,
+pub(crate) async fn tls_connect<S: AsyncRead + AsyncWrite + Unpin>(
+    domain: &str,
+    stream: S,
 ) -> Result<TlsStream<S>, Error> {
-    let domain = xmpp_stream.jid.domain_str().to_owned();
-    let stream = xmpp_stream.into_inner();
     let tls_stream = TlsConnector::from(NativeTlsConnector::builder().build().unwrap())
-        .connect(&domain, stream)
+        .connect(domain, stream)
         .await?;
     Ok(tls_stream)
 }
 
 #[cfg(feature = "tls-rust")]
-async fn get_tls_stream<S: AsyncRead + AsyncWrite + Unpin>(
-    xmpp_stream: XMPPStream<S>,
+pub(crate) async fn tls_connect<S: AsyncRead + AsyncWrite + Unpin>(
+    domain: &str,
+    stream: S,
 ) -> Result<TlsStream<S>, Error> {
-    let domain = xmpp_stream.jid.domain().to_owned();
-    let domain = ServerName::try_from(domain.as_str())?;
-    let stream = xmpp_stream.into_inner();
+    let domain = ServerName::try_from(domain)?;
     let mut root_store = RootCertStore::empty();
-    root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+    root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
         OwnedTrustAnchor::from_subject_spki_name_constraints(
             ta.subject,
             ta.spki,
@@ -62,6 +61,26 @@ async fn get_tls_stream<S: AsyncRead + AsyncWrite + Unpin>(
     Ok(tls_stream)
 }
 
+#[cfg(feature = "tls-native")]
+pub(crate) fn channel_binding<S>(_tls_stream: &TlsStream<S>) -> ChannelBinding {
+    // native-tls doesn’t expose the exporter, nor the Finished message tls-unique needs.
+    ChannelBinding::None
+}
+
+/// Exports the tls-exporter channel binding (RFC 9266) when TLS 1.3 has been
+/// negotiated, tls-unique being undefined there.
+#[cfg(feature = "tls-rust")]
+pub(crate) fn channel_binding<S>(tls_stream: &TlsStream<S>) -> ChannelBinding {
+    let (_, connection) = tls_stream.get_ref();
+    if connection.protocol_version() != Some(ProtocolVersion::TLSv1_3) {
+        return ChannelBinding::None;
+    }
+    match connection.export_keying_material([0u8; 32], b"EXPORTER-Channel-Binding", None) {
+        Ok(data) => ChannelBinding::TlsExporter(data.to_vec()),
+        Err(_) => ChannelBinding::None,
+    }
+}
+
 /// Performs `<starttls/>` on an XMPPStream and returns a binary
 /// TlsStream.
 pub async fn starttls<S: AsyncRead + AsyncWrite + Unpin>(
@@ -82,5 +101,6 @@ pub async fn starttls<S: AsyncRead + AsyncWrite + Unpin>(
         }
     }
 
-    get_tls_stream(xmpp_stream).await
+    let domain = xmpp_stream.jid.domain_str().to_owned();
+    tls_connect(&domain, xmpp_stream.into_inner()).await
 }
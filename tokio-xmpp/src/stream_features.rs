@@ -1,7 +1,8 @@
 //! Contains wrapper for `<stream:features/>`
 
 use crate::error::AuthError;
-use xmpp_parsers::{ns, Element};
+use std::convert::TryFrom;
+use xmpp_parsers::{ns, stream_features::StreamFeatures as ParsedStreamFeatures, Element};
 
 /// Wraps `<stream:features/>`, usually the very first nonza of an
 /// XMPPStream.
@@ -32,8 +33,39 @@ impl StreamFeatures {
             .map(|mech_el| mech_el.text()))
     }
 
+    /// Does server support Stream Management (XEP-0198)?
+    pub fn can_sm(&self) -> bool {
+        self.0.get_child("sm", ns::SM).is_some()
+    }
+
+    /// Does server support Client State Indication (XEP-0352)?
+    pub fn can_csi(&self) -> bool {
+        self.0.get_child("csi", ns::CSI).is_some()
+    }
+
     /// Does server support user resource binding?
     pub fn can_bind(&self) -> bool {
         self.0.get_child("bind", ns::BIND).is_some()
     }
+
+    /// Does server require session establishment (RFC 3921) after
+    /// binding, i.e. advertise it without marking it `<optional/>`?
+    pub fn session_required(&self) -> bool {
+        self.0
+            .get_child("session", ns::SESSION)
+            .is_some_and(|session| !session.has_child("optional", ns::SESSION))
+    }
+
+    /// Iterate over the features marked `<required/>` by the server
+    pub fn required<'a>(&'a self) -> impl Iterator<Item = &'a Element> + 'a {
+        self.0
+            .children()
+            .filter(|feature| feature.has_child("required", feature.ns().as_str()))
+    }
+
+    /// Parse the nonza, to inspect every feature advertised by the server
+    pub fn parse(&self) -> ParsedStreamFeatures {
+        // Only `<stream:features/>` is ever wrapped, which always parses.
+        ParsedStreamFeatures::try_from(self.0.clone()).unwrap_or_default()
+    }
 }
@@ -1,4 +1,5 @@
 use futures::{sink::SinkExt, stream::StreamExt};
+use std::collections::HashMap;
 use std::marker::Unpin;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::codec::Framed;
@@ -14,8 +15,9 @@ pub async fn start<S: AsyncRead + AsyncWrite + Unpin>(
     mut stream: Framed<S, XMPPCodec>,
     jid: Jid,
     ns: String,
+    lang: Option<&str>,
 ) -> Result<XMPPStream<S>, Error> {
-    let attrs = [
+    let mut attrs: HashMap<String, String> = [
         ("to".to_owned(), jid.domain_str().to_owned()),
         ("version".to_owned(), "1.0".to_owned()),
         ("xmlns".to_owned(), ns.clone()),
@@ -24,6 +26,9 @@ pub async fn start<S: AsyncRead + AsyncWrite + Unpin>(
     .iter()
     .cloned()
     .collect();
+    if let Some(lang) = lang {
+        attrs.insert("xml:lang".to_owned(), lang.to_owned());
+    }
     stream.send(Packet::StreamStart(attrs)).await?;
 
     let stream_attrs;
@@ -47,7 +52,8 @@ pub async fn start<S: AsyncRead + AsyncWrite + Unpin>(
         .get("id")
         .ok_or(ProtocolError::NoStreamId)?
         .clone();
-    let stream = if stream_ns == "jabber:client" && stream_attrs.get("version").is_some() {
+    let stream_lang = stream_attrs.get("xml:lang").cloned();
+    let mut stream = if stream_ns == ns::JABBER_CLIENT && stream_attrs.get("version").is_some() {
         let stream_features;
         loop {
             match stream.next().await {
@@ -71,5 +77,6 @@ pub async fn start<S: AsyncRead + AsyncWrite + Unpin>(
             Element::builder(stream_id, ns::STREAM).build(),
         )
     };
+    stream.lang = stream_lang;
     Ok(stream)
 }
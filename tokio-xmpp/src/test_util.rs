@@ -0,0 +1,559 @@
+//! Helpers to test code built on tokio-xmpp without a real server
+//!
+//! `FakeServer` plays the server side of a stream, on an in-memory
+//! `duplex()` transport or on a socket accepted from a local
+//! `TcpListener`. An `AsyncClient` connects to the former when configured
+//! with the server of a `DuplexListener`, and to the latter with
+//! `LocalResolver`.
+//!
+//! `client_and_listener()` and `online_client_and_server()` set up the
+//! client most tests start with.
+
+use futures::channel::mpsc;
+use futures::future::BoxFuture;
+use futures::{sink::SinkExt, stream::StreamExt};
+use sasl::common::scram::{Sha1, Sha256};
+use sasl::common::{ChannelBinding, Identity};
+use sasl::secret::{Pbkdf2Sha1, Pbkdf2Sha256};
+use sasl::server::mechanisms::Scram;
+use sasl::server::{Mechanism, Provider, ProviderError, Response as SaslResponse};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr};
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream};
+use tokio_util::codec::{Framed, FramedParts};
+use xmpp_parsers::iq::{Iq, IqType};
+use xmpp_parsers::sasl::{
+    Auth, Challenge, DefinedCondition, Failure, Mechanism as XMPPMechanism, Response, Success,
+};
+use xmpp_parsers::{ns, Element, FullJid, Jid};
+
+use crate::resolver::{Resolver, SrvRecord};
+use crate::xmpp_codec::{Packet, XMPPCodec};
+use crate::{
+    AsyncClient, AsyncConfigBuilder, AsyncServerConfig, ConnecterError, Error, Event, ProtocolError,
+};
+
+/// Size of the buffers of the in-memory transports
+const DUPLEX_BUFFER_SIZE: usize = 65536;
+
+/// Creates an in-memory transport, returning the client end and a
+/// `FakeServer` on the other end
+pub fn duplex() -> (DuplexStream, FakeServer<DuplexStream>) {
+    let (client, server) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+    (client, FakeServer::new(server))
+}
+
+/// Accepts the in-memory connections of the `AsyncClient`s configured
+/// with its `server_config()`, like a `TcpListener` would
+pub struct DuplexListener {
+    connector: DuplexConnector,
+    incoming: mpsc::UnboundedReceiver<DuplexStream>,
+}
+
+impl DuplexListener {
+    /// Creates a listener nobody connected to yet
+    pub fn new() -> Self {
+        let (tx, incoming) = mpsc::unbounded();
+        DuplexListener {
+            connector: DuplexConnector { incoming: tx },
+            incoming,
+        }
+    }
+
+    /// The server configuration connecting a client to this listener
+    pub fn server_config(&self) -> AsyncServerConfig {
+        AsyncServerConfig::InMemory(self.connector.clone())
+    }
+
+    /// Waits for the next connection of a client, returning the
+    /// `FakeServer` on its other end
+    pub async fn accept(&mut self) -> FakeServer<DuplexStream> {
+        // Never ends, as we hold a sender ourselves.
+        FakeServer::new(self.incoming.next().await.unwrap())
+    }
+}
+
+impl Default for DuplexListener {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The client side of a `DuplexListener`, see
+/// `AsyncServerConfig::InMemory`
+#[derive(Clone, Debug)]
+pub struct DuplexConnector {
+    incoming: mpsc::UnboundedSender<DuplexStream>,
+}
+
+impl DuplexConnector {
+    /// Opens a new in-memory transport to the listener, failing like a
+    /// refused connection once it's gone
+    pub(crate) fn connect(&self) -> Result<DuplexStream, Error> {
+        let (client, server) = tokio::io::duplex(DUPLEX_BUFFER_SIZE);
+        self.incoming
+            .unbounded_send(server)
+            .map_err(|_| ConnecterError::AllFailed)?;
+        Ok(client)
+    }
+}
+
+/// A `Resolver` sending every connection to `127.0.0.1`, without SRV
+/// records, so that a client connects to a local `FakeServer`
+#[derive(Debug, Clone, Default)]
+pub struct LocalResolver;
+
+impl Resolver for LocalResolver {
+    fn lookup_srv<'a>(&'a self, _name: &'a str) -> BoxFuture<'a, Result<Vec<SrvRecord>, Error>> {
+        Box::pin(async { Ok(vec![]) })
+    }
+
+    fn lookup_ip<'a>(&'a self, _host: &'a str) -> BoxFuture<'a, Result<Vec<IpAddr>, Error>> {
+        Box::pin(async { Ok(vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]) })
+    }
+}
+
+/// The server side of a stream, replying to the client as scripted by
+/// the test
+///
+/// Every method waits for what the client is expected to send first, and
+/// fails with `Error::Disconnected` if the client closed the stream.
+pub struct FakeServer<S> {
+    stream: Option<Framed<S, XMPPCodec>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> FakeServer<S> {
+    /// Plays the server on the given transport
+    pub fn new(stream: S) -> Self {
+        FakeServer {
+            stream: Some(Framed::new(stream, XMPPCodec::new())),
+        }
+    }
+
+    fn stream(&mut self) -> &mut Framed<S, XMPPCodec> {
+        self.stream.as_mut().unwrap()
+    }
+
+    /// Waits for the `<stream:stream>` of the client, then replies with
+    /// ours and `<stream:features/>` containing `features`
+    ///
+    /// The children in `features` must carry their own `xmlns`.
+    pub async fn open_stream(&mut self, features: &str) -> Result<(), Error> {
+        // Every stream restart begins a new XML document.
+        let parts = self.stream.take().unwrap().into_parts();
+        let mut new_parts = FramedParts::new(parts.io, XMPPCodec::new());
+        new_parts.read_buf = parts.read_buf;
+        self.stream = Some(Framed::from_parts(new_parts));
+
+        let client_attrs = loop {
+            match self.stream().next().await {
+                Some(Ok(Packet::StreamStart(attrs))) => break attrs,
+                Some(Ok(_)) => (),
+                Some(Err(e)) => return Err(e),
+                None => return Err(Error::Disconnected),
+            }
+        };
+        let mut attrs = HashMap::new();
+        attrs.insert("xmlns".to_owned(), ns::JABBER_CLIENT.to_owned());
+        attrs.insert("xmlns:stream".to_owned(), ns::STREAM.to_owned());
+        attrs.insert("id".to_owned(), "fake-stream".to_owned());
+        attrs.insert("version".to_owned(), "1.0".to_owned());
+        if let Some(to) = client_attrs.get("to") {
+            attrs.insert("from".to_owned(), to.clone());
+        }
+        self.stream().send(Packet::StreamStart(attrs)).await?;
+
+        let features: Element = format!("<features xmlns='{}'>{}</features>", ns::STREAM, features)
+            .parse()
+            .map_err(ProtocolError::Parser)?;
+        self.send(features).await
+    }
+
+    /// Waits for the next stanza or nonza from the client
+    pub async fn recv(&mut self) -> Result<Element, Error> {
+        loop {
+            match self.stream().next().await {
+                Some(Ok(Packet::Stanza(stanza))) => return Ok(stanza),
+                Some(Ok(Packet::Text(_))) => (),
+                Some(Ok(Packet::StreamStart(_))) => {
+                    return Err(ProtocolError::InvalidStreamStart.into())
+                }
+                Some(Ok(Packet::StreamEnd)) | None => return Err(Error::Disconnected),
+                Some(Err(e)) => return Err(e),
+            }
+        }
+    }
+
+    /// Sends a stanza or nonza to the client
+    pub async fn send<E: Into<Element>>(&mut self, stanza: E) -> Result<(), Error> {
+        self.stream().send(Packet::Stanza(stanza.into())).await
+    }
+
+    /// Sends a stanza or nonza given as XML, which must carry its `xmlns`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `xml` isn’t a valid element.
+    pub async fn send_xml(&mut self, xml: &str) -> Result<(), Error> {
+        let stanza: Element = xml.parse().expect("invalid XML given to FakeServer");
+        self.send(stanza).await
+    }
+
+    /// Closes the stream with `</stream:stream>`
+    pub async fn close(&mut self) -> Result<(), Error> {
+        self.stream().send(Packet::StreamEnd).await
+    }
+
+    /// Runs SCRAM-SHA-256 or SCRAM-SHA-1, whichever the client picked,
+    /// against the given credentials
+    ///
+    /// Returns whether the client authenticated successfully, after
+    /// having sent it `<success/>` or `<failure/>`.
+    pub async fn authenticate(&mut self, username: &str, password: &str) -> Result<bool, Error> {
+        let auth = Auth::try_from(self.recv().await?).map_err(ProtocolError::Parsers)?;
+        let provider = PasswordProvider {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        };
+        let mut mechanism: Box<dyn Mechanism + Send> = match auth.mechanism {
+            XMPPMechanism::ScramSha256 => {
+                Box::new(Scram::<Sha256, _>::new(provider, ChannelBinding::None))
+            }
+            XMPPMechanism::ScramSha1 => {
+                Box::new(Scram::<Sha1, _>::new(provider, ChannelBinding::None))
+            }
+            _ => {
+                self.fail(DefinedCondition::InvalidMechanism).await?;
+                return Ok(false);
+            }
+        };
+
+        let mut data = auth.data;
+        loop {
+            match mechanism.respond(&data) {
+                Ok(SaslResponse::Proceed(challenge)) => {
+                    self.send(Challenge { data: challenge }).await?;
+                    let response =
+                        Response::try_from(self.recv().await?).map_err(ProtocolError::Parsers)?;
+                    data = response.data;
+                }
+                Ok(SaslResponse::Success(_, additional)) => {
+                    self.send(Success { data: additional }).await?;
+                    return Ok(true);
+                }
+                Err(_) => {
+                    self.fail(DefinedCondition::NotAuthorized).await?;
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    async fn fail(&mut self, defined_condition: DefinedCondition) -> Result<(), Error> {
+        self.send(Failure {
+            defined_condition,
+            texts: Default::default(),
+        })
+        .await
+    }
+
+    /// Waits for the resource binding request, and binds `jid`
+    /// whatever the client asked for
+    pub async fn bind(&mut self, jid: &FullJid) -> Result<(), Error> {
+        let iq = Iq::try_from(self.recv().await?).map_err(ProtocolError::Parsers)?;
+        match iq.payload {
+            IqType::Set(ref payload) if payload.is("bind", ns::BIND) => (),
+            _ => return Err(ProtocolError::InvalidBindResponse.into()),
+        }
+        let response = Element::builder("bind", ns::BIND)
+            .append(Element::builder("jid", ns::BIND).append(jid.to_string()))
+            .build();
+        self.send(Iq {
+            from: None,
+            to: None,
+            id: iq.id,
+            payload: IqType::Result(Some(response)),
+        })
+        .await
+    }
+
+    /// Plays a whole login of `jid` with `password`, without TLS: SASL,
+    /// then resource binding
+    ///
+    /// Returns whether the client authenticated successfully, in which
+    /// case the stream is ready for stanzas.
+    pub async fn login(&mut self, jid: &FullJid, password: &str) -> Result<bool, Error> {
+        self.open_stream(&format!(
+            "<mechanisms xmlns='{}'><mechanism>SCRAM-SHA-256</mechanism><mechanism>SCRAM-SHA-1</mechanism></mechanisms>",
+            ns::SASL
+        ))
+        .await?;
+        let username = jid.node_str().unwrap_or("");
+        if !self.authenticate(username, password).await? {
+            return Ok(false);
+        }
+        self.open_stream(&format!("<bind xmlns='{}'/>", ns::BIND))
+            .await?;
+        self.bind(jid).await?;
+        Ok(true)
+    }
+}
+
+/// The password of the clients created by `client_and_listener()`
+pub const PASSWORD: &str = "balcony";
+
+/// Creates a client for `juliet@capulet.example`, connecting without TLS
+/// to the returned listener and authenticating with `PASSWORD`
+///
+/// `configure` sets the other options of the client before it gets built.
+pub fn client_and_listener<F>(configure: F) -> (AsyncClient, DuplexListener)
+where
+    F: FnOnce(AsyncConfigBuilder) -> AsyncConfigBuilder,
+{
+    let listener = DuplexListener::new();
+    let builder = AsyncConfigBuilder::new(Jid::new("juliet@capulet.example").unwrap(), PASSWORD)
+        .set_server(listener.server_config())
+        .set_tls_required(false);
+    let client = AsyncClient::new_with_config(configure(builder).build().unwrap());
+    (client, listener)
+}
+
+/// Waits for the client to be online, returning the JID it got bound to
+/// and whether its session got resumed
+///
+/// Panics if the client got disconnected first.
+pub async fn wait_online(client: &mut AsyncClient) -> (Jid, bool) {
+    loop {
+        match client.next().await {
+            Some(Event::Online { bound_jid, resumed }) => return (bound_jid, resumed),
+            Some(Event::Disconnected(e)) => panic!("{}", e),
+            Some(_) => (),
+            None => panic!("client ended before being online"),
+        }
+    }
+}
+
+/// Creates a client like `client_and_listener()`, and logs it in as
+/// `juliet@capulet.example/orchard`
+///
+/// Returns once the client is online, with the server ready to exchange
+/// stanzas with it.
+pub async fn online_client_and_server<F>(configure: F) -> (AsyncClient, FakeServer<DuplexStream>)
+where
+    F: FnOnce(AsyncConfigBuilder) -> AsyncConfigBuilder,
+{
+    let (mut client, mut listener) = client_and_listener(configure);
+    let mut server = listener.accept().await;
+    let jid = FullJid::new("juliet@capulet.example/orchard").unwrap();
+    let (logged_in, _) =
+        futures::future::join(server.login(&jid, PASSWORD), wait_online(&mut client)).await;
+    assert!(logged_in.unwrap());
+    (client, server)
+}
+
+/// Derives the SCRAM secrets from a single known password
+struct PasswordProvider {
+    username: String,
+    password: String,
+}
+
+impl PasswordProvider {
+    const SALT: &'static [u8] = b"fake-server-salt";
+    const ITERATIONS: u32 = 4096;
+
+    fn check(&self, identity: &Identity) -> Result<(), ProviderError> {
+        match identity {
+            Identity::Username(username) if *username == self.username => Ok(()),
+            _ => Err(ProviderError::AuthenticationFailed),
+        }
+    }
+}
+
+impl Provider<Pbkdf2Sha1> for PasswordProvider {
+    fn provide(&self, identity: &Identity) -> Result<Pbkdf2Sha1, ProviderError> {
+        self.check(identity)?;
+        Ok(Pbkdf2Sha1::derive(
+            &self.password,
+            Self::SALT,
+            Self::ITERATIONS,
+        )?)
+    }
+}
+
+sasl::impl_validator_using_provider!(PasswordProvider, Pbkdf2Sha1);
+
+impl Provider<Pbkdf2Sha256> for PasswordProvider {
+    fn provide(&self, identity: &Identity) -> Result<Pbkdf2Sha256, ProviderError> {
+        self.check(identity)?;
+        Ok(Pbkdf2Sha256::derive(
+            &self.password,
+            Self::SALT,
+            Self::ITERATIONS,
+        )?)
+    }
+}
+
+sasl::impl_validator_using_provider!(PasswordProvider, Pbkdf2Sha256);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::AuthError;
+
+    async fn client_and_server() -> (AsyncClient, FakeServer<DuplexStream>) {
+        let (client, mut listener) = client_and_listener(|builder| builder);
+        (client, listener.accept().await)
+    }
+
+    #[tokio::test]
+    async fn test_login() {
+        let (mut client, mut server) = client_and_server().await;
+        let jid = FullJid::new("juliet@capulet.example/orchard").unwrap();
+        let server = tokio::spawn(async move {
+            assert!(server.login(&jid, PASSWORD).await.unwrap());
+            server
+                .send_xml("<message xmlns='jabber:client' from='romeo@montague.example/garden'><body>Hi!</body></message>")
+                .await
+                .unwrap();
+            server
+        });
+
+        let mut events = vec![];
+        while let Some(event) = client.next().await {
+            let done = event.as_stanza().is_some();
+            events.push(event);
+            if done {
+                break;
+            }
+        }
+        assert!(matches!(events[0], Event::Connecting));
+        assert!(matches!(events[1], Event::Authenticating));
+        assert!(matches!(events[2], Event::Binding));
+        assert_eq!(
+            events[3].get_jid(),
+            Some(&Jid::new("juliet@capulet.example/orchard").unwrap())
+        );
+        assert!(events[4].is_stanza("message"));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password() {
+        let (mut client, mut server) = client_and_server().await;
+        let jid = FullJid::new("juliet@capulet.example/orchard").unwrap();
+        let server =
+            tokio::spawn(async move { assert!(!server.login(&jid, "nightingale").await.unwrap()) });
+
+        loop {
+            match client.next().await {
+                Some(Event::Disconnected(Error::Auth(AuthError::Fail(condition)))) => {
+                    assert_eq!(condition, DefinedCondition::NotAuthorized);
+                    break;
+                }
+                Some(Event::Disconnected(e)) => panic!("{}", e),
+                Some(_) => (),
+                None => panic!(),
+            }
+        }
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_no_supported_mechanism() {
+        let (mut client, mut server) = client_and_server().await;
+        let server = tokio::spawn(async move {
+            server
+                .open_stream(&format!(
+                    "<mechanisms xmlns='{}'><mechanism>PLAIN</mechanism></mechanisms>",
+                    ns::SASL
+                ))
+                .await
+                .unwrap();
+            server
+        });
+
+        loop {
+            match client.next().await {
+                Some(Event::Disconnected(Error::Protocol(
+                    ProtocolError::NoSupportedMechanism(offered),
+                ))) => {
+                    assert_eq!(offered, ["PLAIN"]);
+                    break;
+                }
+                Some(Event::Disconnected(e)) => panic!("{}", e),
+                Some(_) => (),
+                None => panic!(),
+            }
+        }
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_token() {
+        let (mut client, mut listener) = client_and_listener(|builder| {
+            builder.set_token("vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==")
+        });
+        let mut server = listener.accept().await;
+        let server = tokio::spawn(async move {
+            server
+                .open_stream(&format!(
+                    "<mechanisms xmlns='{}'><mechanism>SCRAM-SHA-256</mechanism><mechanism>OAUTHBEARER</mechanism></mechanisms>",
+                    ns::SASL
+                ))
+                .await
+                .unwrap();
+            let auth = Auth::try_from(server.recv().await.unwrap()).unwrap();
+            assert_eq!(auth.mechanism, XMPPMechanism::OAuthBearer);
+            assert_eq!(
+                auth.data,
+                &b"n,a=juliet,\x01auth=Bearer vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==\x01\x01"[..]
+            );
+            server.fail(DefinedCondition::NotAuthorized).await.unwrap();
+        });
+
+        loop {
+            match client.next().await {
+                Some(Event::Disconnected(Error::Auth(AuthError::Fail(condition)))) => {
+                    assert_eq!(condition, DefinedCondition::NotAuthorized);
+                    break;
+                }
+                Some(Event::Disconnected(e)) => panic!("{}", e),
+                Some(_) => (),
+                None => panic!(),
+            }
+        }
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_duplex() {
+        let (client, mut server) = duplex();
+        let mut client = Framed::new(client, XMPPCodec::new());
+        let server = tokio::spawn(async move {
+            server.open_stream("").await.unwrap();
+            let ping = server.recv().await.unwrap();
+            assert!(ping.is("iq", ns::JABBER_CLIENT));
+        });
+
+        let mut attrs = HashMap::new();
+        attrs.insert("xmlns".to_owned(), ns::JABBER_CLIENT.to_owned());
+        attrs.insert("xmlns:stream".to_owned(), ns::STREAM.to_owned());
+        client.send(Packet::StreamStart(attrs)).await.unwrap();
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(Packet::StreamStart(_)))
+        ));
+        match client.next().await {
+            Some(Ok(Packet::Stanza(features))) => assert!(features.is("features", ns::STREAM)),
+            _ => panic!(),
+        }
+        let ping: Element =
+            "<iq xmlns='jabber:client' type='get' id='ping'><ping xmlns='urn:xmpp:ping'/></iq>"
+                .parse()
+                .unwrap();
+        client.send(Packet::Stanza(ping)).await.unwrap();
+        server.await.unwrap();
+    }
+}
@@ -2,7 +2,7 @@
 
 use crate::Error;
 use bytes::{BufMut, BytesMut};
-use log::debug;
+use log::{debug, log_enabled, Level};
 use minidom::tree_builder::TreeBuilder;
 use rxml::{Lexer, PushDriver, RawParser};
 use std;
@@ -13,7 +13,7 @@ use std::io;
 #[cfg(feature = "syntax-highlighting")]
 use std::sync::OnceLock;
 use tokio_util::codec::{Decoder, Encoder};
-use xmpp_parsers::Element;
+use xmpp_parsers::{ns, Element};
 
 #[cfg(feature = "syntax-highlighting")]
 static PS: OnceLock<syntect::parsing::SyntaxSet> = OnceLock::new();
@@ -60,8 +60,21 @@ pub enum Packet {
     StreamEnd,
 }
 
+/// How packets are delimited on the transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// A single XML document, between `<stream:stream>` and
+    /// `</stream:stream>` (RFC 6120)
+    Stream,
+    /// One complete top-level element per WebSocket text frame, the stream
+    /// being opened by `<open/>` and closed by `<close/>` in the
+    /// `urn:ietf:params:xml:ns:xmpp-framing` namespace (RFC 7395)
+    WebSocket,
+}
+
 /// Stateful encoder/decoder for a bytestream from/to XMPP `Packet`
 pub struct XMPPCodec {
+    framing: Framing,
     /// Outgoing
     ns: Option<String>,
     /// Incoming
@@ -72,6 +85,16 @@ pub struct XMPPCodec {
 impl XMPPCodec {
     /// Constructor
     pub fn new() -> Self {
+        Self::with_framing(Framing::Stream)
+    }
+
+    /// Constructor for the given framing
+    ///
+    /// With `Framing::WebSocket`, `<open/>` and `<close/>` get decoded as
+    /// `Packet::StreamStart` and `Packet::StreamEnd`, and every packet gets
+    /// encoded on its own, so that each call to `encode()` produces exactly
+    /// one frame.
+    pub fn with_framing(framing: Framing) -> Self {
         let stanza_builder = TreeBuilder::new();
         let driver = PushDriver::wrap(Lexer::new(), RawParser::new());
         #[cfg(feature = "syntax-highlighting")]
@@ -79,11 +102,51 @@ impl XMPPCodec {
             init_syntect();
         }
         XMPPCodec {
+            framing,
             ns: None,
             driver,
             stanza_builder,
         }
     }
+
+    /// Decodes the next complete top-level element, each frame being an XML
+    /// document of its own.
+    fn decode_framed(&mut self, buf: &mut BytesMut) -> Result<Option<Packet>, Error> {
+        loop {
+            let token = match self.driver.parse(buf, false) {
+                Ok(Some(token)) => token,
+                Ok(None) => break,
+                Err(rxml::Error::IO(e)) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(minidom::Error::from(e).into()),
+            };
+            self.stanza_builder.process_event(token)?;
+
+            if let Some(elem) = self.stanza_builder.root.take() {
+                self.driver = PushDriver::wrap(Lexer::new(), RawParser::new());
+                self.stanza_builder = TreeBuilder::new();
+                debug!("<< {}", highlight_xml(&String::from(&elem)));
+
+                let packet = if elem.is("open", ns::WEBSOCKET) {
+                    let attrs = elem
+                        .attrs()
+                        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                        .chain(std::iter::once((
+                            "xmlns".to_owned(),
+                            ns::WEBSOCKET.to_owned(),
+                        )))
+                        .collect();
+                    Packet::StreamStart(attrs)
+                } else if elem.is("close", ns::WEBSOCKET) {
+                    Packet::StreamEnd
+                } else {
+                    Packet::Stanza(elem)
+                };
+                return Ok(Some(packet));
+            }
+        }
+
+        Ok(None)
+    }
 }
 
 impl Default for XMPPCodec {
@@ -97,6 +160,9 @@ impl Decoder for XMPPCodec {
     type Error = Error;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if self.framing == Framing::WebSocket {
+            return self.decode_framed(buf);
+        }
         loop {
             let token = match self.driver.parse(buf, false) {
                 Ok(Some(token)) => token,
@@ -165,40 +231,58 @@ impl Encoder<Packet> for XMPPCodec {
             io::Error::new(io::ErrorKind::InvalidInput, e)
         }
 
+        // Everything gets serialised straight into dst, only what got
+        // written for this packet is then logged.
+        let start = dst.len();
         match item {
+            Packet::StreamStart(start_attrs) if self.framing == Framing::WebSocket => {
+                write!(dst, "<open xmlns=\"{}\"", ns::WEBSOCKET).map_err(to_io_err)?;
+                for (name, value) in start_attrs {
+                    // Stanzas carry their own namespace instead.
+                    if name == "xmlns" || name.starts_with("xmlns:") {
+                        continue;
+                    }
+                    write!(dst, " {}=\"{}\"", escape(&name), escape(&value)).map_err(to_io_err)?;
+                }
+                write!(dst, "/>").map_err(to_io_err)?;
+            }
             Packet::StreamStart(start_attrs) => {
-                let mut buf = String::new();
-                write!(buf, "<stream:stream").map_err(to_io_err)?;
+                write!(dst, "<stream:stream").map_err(to_io_err)?;
                 for (name, value) in start_attrs {
-                    write!(buf, " {}=\"{}\"", escape(&name), escape(&value)).map_err(to_io_err)?;
+                    write!(dst, " {}=\"{}\"", escape(&name), escape(&value)).map_err(to_io_err)?;
                     if name == "xmlns" {
                         self.ns = Some(value);
                     }
                 }
-                write!(buf, ">").map_err(to_io_err)?;
-
-                write!(dst, "{}", buf)?;
-                let utf8 = std::str::from_utf8(dst)?;
-                debug!(">> {}", highlight_xml(utf8))
+                write!(dst, ">").map_err(to_io_err)?;
             }
             Packet::Stanza(stanza) => {
-                let _ = stanza
-                    .write_to(&mut WriteBytes::new(dst))
-                    .map_err(|e| to_io_err(format!("{}", e)))?;
-                let utf8 = std::str::from_utf8(dst)?;
-                debug!(">> {}", highlight_xml(utf8));
+                if let Err(e) = stanza.write_to(&mut WriteBytes::new(dst)) {
+                    // Don’t leave half a stanza in the buffer, which would
+                    // break the stream for the next ones.
+                    dst.truncate(start);
+                    return Err(to_io_err(e).into());
+                }
+            }
+            Packet::Text(_) if self.framing == Framing::WebSocket => {
+                return Err(to_io_err("text can’t be sent as a WebSocket frame").into());
             }
             Packet::Text(text) => {
                 let _ = write_text(&text, dst).map_err(to_io_err)?;
-                let utf8 = std::str::from_utf8(dst)?;
-                debug!(">> {}", highlight_xml(utf8));
+            }
+            Packet::StreamEnd if self.framing == Framing::WebSocket => {
+                write!(dst, "<close xmlns=\"{}\"/>", ns::WEBSOCKET).map_err(to_io_err)?;
             }
             Packet::StreamEnd => {
                 let _ = write!(dst, "</stream:stream>\n").map_err(to_io_err);
-                debug!(">> {}", highlight_xml("</stream:stream>"));
             }
         }
 
+        if log_enabled!(Level::Debug) {
+            let utf8 = std::str::from_utf8(&dst[start..])?;
+            debug!(">> {}", highlight_xml(utf8));
+        }
+
         Ok(())
     }
 }
@@ -389,6 +473,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_encode_appends() {
+        let mut c = XMPPCodec::new();
+        let mut b = BytesMut::new();
+        let mut attrs = HashMap::new();
+        attrs.insert("xmlns".to_owned(), "jabber:client".to_owned());
+        c.encode(Packet::StreamStart(attrs), &mut b).unwrap();
+        let stanza = Element::builder("presence", "jabber:client").build();
+        c.encode(Packet::Stanza(stanza), &mut b).unwrap();
+        c.encode(Packet::Text(" & ".to_owned()), &mut b).unwrap();
+        assert_eq!(
+            &b[..],
+            &b"<stream:stream xmlns=\"jabber:client\"><presence xmlns='jabber:client'/> &amp; "[..]
+        );
+    }
+
+    #[test]
+    fn test_encode_invalid_char() {
+        let mut c = XMPPCodec::new();
+        let mut b = BytesMut::new();
+        let stanza = Element::builder("message", "jabber:client")
+            .append(
+                Element::builder("body", "jabber:client")
+                    .append("\u{1b}[31mred")
+                    .build(),
+            )
+            .build();
+        assert!(c.encode(Packet::Stanza(stanza), &mut b).is_err());
+        assert!(b.is_empty());
+        let stanza = Element::builder("presence", "jabber:client").build();
+        c.encode(Packet::Stanza(stanza), &mut b).unwrap();
+        assert_eq!(&b[..], &b"<presence xmlns='jabber:client'/>"[..]);
+    }
+
     #[test]
     fn test_cut_out_stanza() {
         let mut c = XMPPCodec::new();
@@ -408,4 +526,64 @@ mod tests {
             _ => false,
         });
     }
+
+    #[test]
+    fn test_websocket_decode() {
+        let mut c = XMPPCodec::with_framing(Framing::WebSocket);
+        let mut b = BytesMut::with_capacity(1024);
+        b.put_slice(b"<open xmlns='urn:ietf:params:xml:ns:xmpp-framing' from='example.com' id='abc' version='1.0'/>");
+        match c.decode(&mut b) {
+            Ok(Some(Packet::StreamStart(attrs))) => {
+                assert_eq!(attrs["from"], "example.com");
+                assert_eq!(attrs["id"], "abc");
+                assert_eq!(attrs["xmlns"], ns::WEBSOCKET);
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+
+        // Two frames received at once, the second one truncated.
+        b.put_slice(b"<message xmlns='jabber:client' type='chat'><body>Foo</body></message>");
+        b.put_slice(b"<iq xmlns='jabber:client' ");
+        match c.decode(&mut b) {
+            Ok(Some(Packet::Stanza(stanza))) => assert!(stanza.is("message", ns::JABBER_CLIENT)),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert!(matches!(c.decode(&mut b), Ok(None)));
+        b.put_slice(b"type='get' id='a'/>");
+        match c.decode(&mut b) {
+            Ok(Some(Packet::Stanza(stanza))) => assert!(stanza.is("iq", ns::JABBER_CLIENT)),
+            r => panic!("unexpected {:?}", r),
+        }
+
+        b.put_slice(b"<close xmlns='urn:ietf:params:xml:ns:xmpp-framing'/>");
+        assert!(matches!(c.decode(&mut b), Ok(Some(Packet::StreamEnd))));
+    }
+
+    #[test]
+    fn test_websocket_encode() {
+        let mut c = XMPPCodec::with_framing(Framing::WebSocket);
+        let mut b = BytesMut::new();
+        let mut attrs = HashMap::new();
+        attrs.insert("to".to_owned(), "example.com".to_owned());
+        attrs.insert("xmlns".to_owned(), ns::JABBER_CLIENT.to_owned());
+        attrs.insert("xmlns:stream".to_owned(), ns::STREAM.to_owned());
+        c.encode(Packet::StreamStart(attrs), &mut b).unwrap();
+        assert_eq!(
+            &b[..],
+            b"<open xmlns=\"urn:ietf:params:xml:ns:xmpp-framing\" to=\"example.com\"/>"
+        );
+
+        let mut b = BytesMut::new();
+        let presence = Element::builder("presence", ns::JABBER_CLIENT).build();
+        c.encode(Packet::Stanza(presence), &mut b).unwrap();
+        assert_eq!(&b[..], b"<presence xmlns='jabber:client'/>");
+
+        let mut b = BytesMut::new();
+        assert!(c.encode(Packet::Text(" ".to_owned()), &mut b).is_err());
+        c.encode(Packet::StreamEnd, &mut b).unwrap();
+        assert_eq!(
+            &b[..],
+            b"<close xmlns=\"urn:ietf:params:xml:ns:xmpp-framing\"/>"
+        );
+    }
 }
@@ -2,30 +2,26 @@
 
 use futures::sink::Send;
 use futures::{sink::SinkExt, task::Poll, Sink, Stream};
-use rand::{thread_rng, Rng};
 use std::pin::Pin;
 use std::task::Context;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::codec::Framed;
-use xmpp_parsers::{Element, Jid};
+use xmpp_parsers::{stanza_id::generate_id, Element, Jid};
 
 use crate::stream_features::StreamFeatures;
 use crate::stream_start;
 use crate::xmpp_codec::{Packet, XMPPCodec};
 use crate::Error;
 
-fn make_id() -> String {
-    let id: u64 = thread_rng().gen();
-    format!("{}", id)
-}
-
+/// Sets a newly generated id on an iq, message or presence stanza, unless it
+/// already has one, e.g. to match the response to an iq.
 pub(crate) fn add_stanza_id(mut stanza: Element, default_ns: &str) -> Element {
     if stanza.is("iq", default_ns)
         || stanza.is("message", default_ns)
         || stanza.is("presence", default_ns)
     {
         if stanza.attr("id").is_none() {
-            stanza.set_attr("id", make_id());
+            stanza.set_attr("id", generate_id());
         }
     }
 
@@ -50,6 +46,9 @@ pub struct XMPPStream<S: AsyncRead + AsyncWrite + Unpin> {
     pub ns: String,
     /// Stream `id` attribute
     pub id: String,
+    /// Stream `xml:lang` attribute, the default language of stanzas
+    /// received on this stream
+    pub lang: Option<String>,
 }
 
 impl<S: AsyncRead + AsyncWrite + Unpin> XMPPStream<S> {
@@ -67,13 +66,25 @@ impl<S: AsyncRead + AsyncWrite + Unpin> XMPPStream<S> {
             stream_features: StreamFeatures::new(stream_features),
             ns,
             id,
+            lang: None,
         }
     }
 
     /// Send a `<stream:stream>` start tag
     pub async fn start(stream: S, jid: Jid, ns: String) -> Result<Self, Error> {
+        Self::start_with_lang(stream, jid, ns, None).await
+    }
+
+    /// Send a `<stream:stream>` start tag, advertising `lang` as the
+    /// default language of the stanzas we send in its `xml:lang`
+    pub async fn start_with_lang(
+        stream: S,
+        jid: Jid,
+        ns: String,
+        lang: Option<&str>,
+    ) -> Result<Self, Error> {
         let xmpp_stream = Framed::new(stream, XMPPCodec::new());
-        stream_start::start(xmpp_stream, jid, ns).await
+        stream_start::start(xmpp_stream, jid, ns, lang).await
     }
 
     /// Unwraps the inner stream
@@ -146,3 +157,69 @@ impl<S: AsyncRead + AsyncWrite + Unpin> Stream for XMPPStream<S> {
             .map(|result| result.map(|result| result.map_err(|e| e.into())))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use xmpp_parsers::ns;
+
+    #[tokio::test]
+    async fn test_send_stanza_keeps_id() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let mut stream = XMPPStream::new(
+            Jid::new("test@example.org").unwrap(),
+            Framed::new(client, XMPPCodec::new()),
+            ns::JABBER_CLIENT.to_owned(),
+            "stream-id".to_owned(),
+            Element::builder("features", ns::STREAM).build(),
+        );
+
+        let iq = Element::builder("iq", ns::JABBER_CLIENT)
+            .attr("type", "get")
+            .attr("id", "my-id")
+            .build();
+        stream
+            .send_stanza(add_stanza_id(iq, ns::JABBER_CLIENT))
+            .await
+            .unwrap();
+
+        let mut buf = vec![0; 4096];
+        let len = server.read(&mut buf).await.unwrap();
+        let received = Element::from_reader(&buf[..len]).unwrap();
+        assert_eq!(received.attr("id"), Some("my-id"));
+    }
+
+    #[tokio::test]
+    async fn test_start_with_lang() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let jid = Jid::new("test@example.org").unwrap();
+        let server = async move {
+            let mut buf = vec![0; 4096];
+            let len = server.read(&mut buf).await.unwrap();
+            let header = String::from_utf8(buf[..len].to_vec()).unwrap();
+            server
+                .write_all(b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' id='abc' version='1.0' xml:lang='fr'><stream:features/>")
+                .await
+                .unwrap();
+            header
+        };
+        let (stream, header) = tokio::join!(
+            XMPPStream::start_with_lang(client, jid, ns::JABBER_CLIENT.to_owned(), Some("fr")),
+            server
+        );
+        assert!(header.contains("xml:lang=\"fr\""));
+        assert_eq!(stream.unwrap().lang.as_deref(), Some("fr"));
+    }
+
+    #[test]
+    fn test_add_stanza_id() {
+        let message = Element::builder("message", ns::JABBER_CLIENT).build();
+        let message = add_stanza_id(message, ns::JABBER_CLIENT);
+        assert!(message.attr("id").is_some());
+
+        let nonza = Element::builder("r", ns::SM).build();
+        let nonza = add_stanza_id(nonza, ns::JABBER_CLIENT);
+        assert_eq!(nonza.attr("id"), None);
+    }
+}
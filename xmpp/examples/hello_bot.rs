@@ -31,7 +31,8 @@ async fn main() -> Result<(), Option<()>> {
         .enable_feature(ClientFeature::Avatars)
         .enable_feature(ClientFeature::ContactList)
         .enable_feature(ClientFeature::JoinRooms)
-        .build();
+        .build()
+        .expect("Invalid client configuration");
 
     while let Some(events) = client.wait_for_events().await {
         for event in events {
@@ -52,7 +53,7 @@ async fn main() -> Result<(), Option<()>> {
                 Event::ContactChanged(contact) => {
                     println!("Contact {} changed.", contact.jid);
                 }
-                Event::ChatMessage(_id, jid, body) => {
+                Event::ChatMessage(_id, jid, body, _carbon) => {
                     println!("Message from {}: {}", jid, body.0);
                 }
                 Event::JoinRoom(jid, conference) => {
@@ -0,0 +1,215 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+use tokio_xmpp::parsers::{
+    caps::{compute_disco, hash_caps, query_caps, Caps},
+    disco::DiscoInfoResult,
+    iq::{Iq, IqType},
+    stanza_id::generate_id,
+    Jid,
+};
+
+/// How many disco#info results to keep, the oldest ones being dropped first.
+const CACHE_SIZE: usize = 256;
+
+/// How long to wait for the answer to a query, before letting the next entity advertising the
+/// same caps be queried instead.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The disco#info of the entities which sent us their caps (XEP-0115), queried only once for
+/// every node and ver.
+#[derive(Default)]
+pub(crate) struct CapsCache {
+    /// The verified results, keyed by node and ver, the oldest first.
+    entries: Vec<((String, String), DiscoInfoResult)>,
+    /// The node and ver last advertised by each entity.
+    jids: HashMap<Jid, (String, String)>,
+    /// Our pending queries, with their id, recipient, the caps they should match and when they
+    /// got sent.
+    requests: Vec<(String, Jid, Caps, Instant)>,
+}
+
+impl CapsCache {
+    pub(crate) fn get(&self, jid: &Jid) -> Option<&DiscoInfoResult> {
+        let key = self.jids.get(jid)?;
+        self.entries
+            .iter()
+            .find(|(other, _)| other == key)
+            .map(|(_, disco)| disco)
+    }
+
+    /// Remembers the caps of an entity, returning the query to send if nobody advertised them
+    /// before.
+    pub(crate) fn handle_caps(&mut self, from: Jid, caps: Caps) -> Option<Iq> {
+        self.expire(Instant::now());
+        let key = (caps.node.clone(), caps.hash.to_base64());
+        self.jids.insert(from.clone(), key.clone());
+        if self.entries.iter().any(|(other, _)| *other == key)
+            || self
+                .requests
+                .iter()
+                .any(|(_, _, other, _)| other.node == caps.node && other.hash == caps.hash)
+        {
+            return None;
+        }
+        let id = generate_id();
+        let iq = Iq::from_get(id.clone(), query_caps(caps.clone())).with_to(from.clone());
+        self.requests.push((id, from, caps, Instant::now()));
+        Some(iq)
+    }
+
+    /// Drops the queries nobody answered in time, an answer coming later being ignored.
+    fn expire(&mut self, now: Instant) {
+        self.requests
+            .retain(|(_, _, _, sent)| now.duration_since(*sent) < REQUEST_TIMEOUT);
+    }
+
+    /// Forgets the caps of an entity which went offline, along with our queries to it, which it
+    /// won't answer anymore.
+    pub(crate) fn forget(&mut self, jid: &Jid) {
+        self.jids.remove(jid);
+        self.requests.retain(|(_, to, _, _)| to != jid);
+    }
+
+    /// Caches the answer to one of our queries if it matches the advertised hash, returning
+    /// whether this iq was such an answer.
+    pub(crate) fn handle_iq(&mut self, from: &Jid, id: &str, payload: &IqType) -> bool {
+        let index = match self
+            .requests
+            .iter()
+            .position(|(other, jid, _, _)| other == id && jid == from)
+        {
+            Some(index) => index,
+            None => return false,
+        };
+        let (_, _, caps, _) = self.requests.remove(index);
+        let payload = match payload {
+            IqType::Result(Some(payload)) => payload.clone(),
+            _ => return true,
+        };
+        let disco = match DiscoInfoResult::try_from(payload) {
+            Ok(disco) => disco,
+            Err(err) => {
+                warn!("Invalid disco#info from {} for its caps: {}", from, err);
+                return true;
+            }
+        };
+        match hash_caps(&compute_disco(&disco), caps.hash.algo.clone()) {
+            Ok(hash) if hash == caps.hash => (),
+            Ok(_) => {
+                warn!(
+                    "The disco#info of {} doesn’t match its caps, not caching it.",
+                    from
+                );
+                return true;
+            }
+            Err(err) => {
+                warn!("Can’t verify the caps of {}: {}", from, err);
+                return true;
+            }
+        }
+        if self.entries.len() >= CACHE_SIZE {
+            self.entries.remove(0);
+        }
+        let key = (caps.node, caps.hash.to_base64());
+        self.entries.push((key, disco));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tokio_xmpp::parsers::{
+        disco::{Feature, Identity},
+        hashes::Algo,
+        ns,
+    };
+
+    #[test]
+    fn test_cache() {
+        let disco = DiscoInfoResult {
+            node: None,
+            identities: vec![Identity::new("client", "pc", "en", "Psi 0.11")],
+            features: vec![Feature::new(ns::DISCO_INFO), Feature::new(ns::CAPS)],
+            extensions: vec![],
+        };
+        let hash = hash_caps(&compute_disco(&disco), Algo::Sha_1).unwrap();
+        let caps = Caps::new("https://psi-im.org", hash);
+        let juliet = Jid::from_str("juliet@capulet.example/balcony").unwrap();
+        let romeo = Jid::from_str("romeo@montague.example/orchard").unwrap();
+
+        let mut cache = CapsCache::default();
+        let iq = cache.handle_caps(juliet.clone(), caps.clone()).unwrap();
+        assert_eq!(iq.to, Some(juliet.clone()));
+        // Already being queried.
+        assert!(cache.handle_caps(romeo.clone(), caps.clone()).is_none());
+        assert!(cache.get(&juliet).is_none());
+
+        // Not from the queried entity.
+        let payload = IqType::Result(Some(disco.clone().into()));
+        assert!(!cache.handle_iq(&romeo, &iq.id, &payload));
+
+        assert!(cache.handle_iq(&juliet, &iq.id, &payload));
+        assert_eq!(cache.get(&juliet).unwrap().features, disco.features);
+        assert_eq!(cache.get(&romeo).unwrap().features, disco.features);
+
+        // Once cached, the same caps don’t trigger a new query.
+        let other = Jid::from_str("nurse@capulet.example/kitchen").unwrap();
+        assert!(cache.handle_caps(other.clone(), caps.clone()).is_none());
+        cache.forget(&other);
+        assert!(cache.get(&other).is_none());
+    }
+
+    #[test]
+    fn test_wrong_hash() {
+        let disco = DiscoInfoResult {
+            node: None,
+            identities: vec![Identity::new("client", "pc", "en", "Psi 0.11")],
+            features: vec![Feature::new(ns::DISCO_INFO)],
+            extensions: vec![],
+        };
+        let hash = hash_caps(b"something else", Algo::Sha_1).unwrap();
+        let caps = Caps::new("https://psi-im.org", hash);
+        let juliet = Jid::from_str("juliet@capulet.example/balcony").unwrap();
+
+        let mut cache = CapsCache::default();
+        let iq = cache.handle_caps(juliet.clone(), caps.clone()).unwrap();
+        let payload = IqType::Result(Some(disco.into()));
+        assert!(cache.handle_iq(&juliet, &iq.id, &payload));
+        assert!(cache.get(&juliet).is_none());
+
+        // It gets queried again next time.
+        assert!(cache.handle_caps(juliet, caps).is_some());
+    }
+
+    #[test]
+    fn test_unanswered() {
+        let hash = hash_caps(b"anything", Algo::Sha_1).unwrap();
+        let caps = Caps::new("https://psi-im.org", hash);
+        let juliet = Jid::from_str("juliet@capulet.example/balcony").unwrap();
+        let romeo = Jid::from_str("romeo@montague.example/orchard").unwrap();
+
+        let mut cache = CapsCache::default();
+        assert!(cache.handle_caps(juliet.clone(), caps.clone()).is_some());
+        assert!(cache.handle_caps(romeo.clone(), caps.clone()).is_none());
+
+        // Gone offline before answering.
+        cache.forget(&juliet);
+        assert!(cache.requests.is_empty());
+        let iq = cache.handle_caps(romeo.clone(), caps.clone()).unwrap();
+        assert_eq!(iq.to, Some(romeo.clone()));
+
+        // Never answered.
+        cache.expire(Instant::now() + REQUEST_TIMEOUT);
+        assert!(cache.requests.is_empty());
+        let payload = IqType::Result(None);
+        assert!(!cache.handle_iq(&romeo, &iq.id, &payload));
+        assert!(cache.handle_caps(juliet, caps).is_some());
+    }
+}
@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeSet;
+use tokio_xmpp::parsers::{
+    caps::{compute_disco, hash_caps, Caps},
+    disco::{DiscoInfoResult, Feature, Identity},
+    hashes::{Algo, Hash},
+    ns,
+};
+
+/// The identities and features we advertise in our own disco#info (XEP-0030), from which our
+/// caps (XEP-0115) are computed.
+///
+/// The Agent registers the namespace of every module as it gets enabled, and answers the
+/// disco#info queries it receives from this registry.
+#[derive(Debug, Clone)]
+pub struct FeatureRegistry {
+    identities: Vec<Identity>,
+    features: BTreeSet<String>,
+    changed: bool,
+}
+
+impl FeatureRegistry {
+    /// A registry advertising this identity, and only disco#info itself as a feature.
+    pub fn new(identity: Identity) -> FeatureRegistry {
+        let mut features = BTreeSet::new();
+        features.insert(String::from(ns::DISCO_INFO));
+        FeatureRegistry {
+            identities: vec![identity],
+            features,
+            changed: true,
+        }
+    }
+
+    /// Advertises this feature, returning whether it wasn’t already.
+    pub fn register<S: Into<String>>(&mut self, var: S) -> bool {
+        let inserted = self.features.insert(var.into());
+        self.changed |= inserted;
+        inserted
+    }
+
+    /// Stops advertising this feature, returning whether it was.
+    pub fn unregister(&mut self, var: &str) -> bool {
+        let removed = self.features.remove(var);
+        self.changed |= removed;
+        removed
+    }
+
+    /// Whether our caps changed since the last call, and should be advertised again.
+    pub(crate) fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// Whether this feature is advertised.
+    pub fn contains(&self, var: &str) -> bool {
+        self.features.contains(var)
+    }
+
+    /// Our disco#info, as answered to a query for this node.
+    pub fn disco_info(&self, node: Option<String>) -> DiscoInfoResult {
+        DiscoInfoResult {
+            node,
+            identities: self.identities.clone(),
+            features: self.features.iter().map(Feature::new).collect(),
+            extensions: vec![],
+        }
+    }
+
+    /// The SHA-1 hash of our disco#info, as advertised in the ver of our caps.
+    pub fn ver(&self) -> Hash {
+        let data = compute_disco(&self.disco_info(None));
+        hash_caps(&data, Algo::Sha_1).unwrap()
+    }
+
+    /// Our caps for this node.
+    pub fn caps(&self, node: &str) -> Caps {
+        Caps::new(node, self.ver())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let mut registry = FeatureRegistry::new(Identity {
+            category: String::from("client"),
+            type_: String::from("pc"),
+            lang: None,
+            name: Some(String::from("Exodus 0.9.1")),
+        });
+        assert!(registry.contains(ns::DISCO_INFO));
+        assert!(registry.register(ns::CAPS));
+        assert!(registry.register(ns::MUC));
+        assert!(!registry.register(ns::MUC));
+        assert!(registry.register(ns::DISCO_ITEMS));
+
+        // The example of XEP-0115, §5.2.
+        let caps = registry.caps("http://code.google.com/p/exodus");
+        assert_eq!(caps.hash.to_base64(), "QgayPKawpkPSDYmwT/WM94uAlu0=");
+        let disco = registry.disco_info(Some(String::from("node")));
+        assert_eq!(disco.node, Some(String::from("node")));
+        assert_eq!(disco.features.len(), 4);
+
+        assert!(registry.unregister(ns::MUC));
+        assert!(!registry.unregister(ns::MUC));
+        assert!(!registry.contains(ns::MUC));
+        assert_ne!(registry.ver().to_base64(), "QgayPKawpkPSDYmwT/WM94uAlu0=");
+    }
+}
@@ -6,6 +6,7 @@
 
 #![deny(bare_trait_objects)]
 
+use caps::CapsCache;
 use futures::stream::StreamExt;
 use reqwest::{
     header::HeaderMap as ReqwestHeaderMap, Body as ReqwestBody, Client as ReqwestClient,
@@ -13,33 +14,50 @@ use reqwest::{
 use std::convert::TryFrom;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tokio::fs::File;
 use tokio_util::codec::{BytesCodec, FramedRead};
 pub use tokio_xmpp::parsers;
+#[cfg(feature = "avatars")]
+use tokio_xmpp::parsers::avatar::Info;
 use tokio_xmpp::parsers::{
     bookmarks2::Conference,
-    caps::{compute_disco, hash_caps, Caps},
-    disco::{DiscoInfoQuery, DiscoInfoResult, Feature, Identity},
-    hashes::Algo,
+    caps::Caps,
+    carbons::{Disable as CarbonsDisable, Enable as CarbonsEnable, Received, Sent},
+    chat_markers::Displayed,
+    chatstates::ChatState,
+    disco::{DiscoInfoQuery, DiscoInfoResult, Identity},
     http_upload::{Header as HttpUploadHeader, SlotRequest, SlotResult},
     iq::{Iq, IqType},
     message::{Body, Message, MessageType},
     muc::{
-        user::{MucUser, Status},
+        admin::{Item as MucAdminItem, MucAdmin},
+        user::{Affiliation, MucUser, Role, Status},
         Muc,
     },
     ns,
+    ping::Ping,
     presence::{Presence, Type as PresenceType},
     pubsub::pubsub::{Items, PubSub},
     roster::{Item as RosterItem, Roster},
     stanza_error::{DefinedCondition, ErrorType, StanzaError},
+    stanza_id::{generate_id, StanzaId},
 };
-use tokio_xmpp::{AsyncClient as TokioXmppClient, Event as TokioXmppEvent};
-pub use tokio_xmpp::{BareJid, Element, FullJid, Jid};
+use tokio_xmpp::{
+    AsyncClient as TokioXmppClient, AsyncConfigBuilder as TokioXmppConfigBuilder,
+    Event as TokioXmppEvent,
+};
+pub use tokio_xmpp::{BareJid, ConfigError, Element, FullJid, Jid};
 #[macro_use]
 extern crate log;
 
+mod caps;
+mod features;
 mod pubsub;
+mod subscription;
+
+pub use features::FeatureRegistry;
+pub use subscription::{SubscriptionEvent, SubscriptionState, SubscriptionTracker};
 
 pub type Error = tokio_xmpp::Error;
 
@@ -70,11 +88,42 @@ pub enum ClientFeature {
     Avatars,
     ContactList,
     JoinRooms,
+    /// Fetches and caches the disco#info of the entities advertising caps (XEP-0115), see
+    /// Agent::features_of.
+    Caps,
+    /// Answers the messages requesting a delivery receipt (XEP-0184), from the contacts subscribed
+    /// to our presence in the roster fetched with ClientFeature::ContactList.
+    Receipts,
+    /// Reports the chat states of our contacts as Event::ChatState (XEP-0085).
+    ChatStates,
 }
 
 pub type Id = Option<String>;
 pub type RoomNick = String;
 
+/// How long to wait for the answer to a MUC self-ping, after which an answer is ignored.
+const SELF_PING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The outcome of a MUC self-ping (XEP-0410).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfPing {
+    /// We are still joined to the room.
+    Joined,
+    /// The room doesn't know us anymore, we should join it again.
+    NotJoined,
+    /// The room couldn't be reached, we may try again later.
+    Inconclusive,
+}
+
+/// Which copy of a message Message Carbons (XEP-0280) delivered to us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarbonDirection {
+    /// A message one of our other resources received.
+    Received,
+    /// A message one of our other resources sent.
+    Sent,
+}
+
 #[derive(Debug)]
 pub enum Event {
     Online,
@@ -84,7 +133,9 @@ pub enum Event {
     ContactChanged(RosterItem),
     #[cfg(feature = "avatars")]
     AvatarRetrieved(Jid, String),
-    ChatMessage(Id, BareJid, Body),
+    /// A chat message, containing its ID, the BareJid of the contact, its body, and whether it is
+    /// a carbon copy; for a message one of our other resources sent, the contact is its recipient.
+    ChatMessage(Id, BareJid, Body, Option<CarbonDirection>),
     JoinRoom(BareJid, Conference),
     LeaveRoom(BareJid),
     LeaveAllRooms,
@@ -96,6 +147,18 @@ pub enum Event {
     RoomPrivateMessage(Id, BareJid, RoomNick, Body),
     ServiceMessage(Id, BareJid, Body),
     HttpUploadedFile(String),
+    /// The outcome of a MUC self-ping (XEP-0410), containing the room's BareJid and whether we
+    /// are still joined to it.
+    RoomSelfPing(BareJid, SelfPing),
+    /// The outcome of Agent::enable_carbons or Agent::disable_carbons: whether carbons are now
+    /// enabled, or the error returned by the server.
+    Carbons(Result<bool, StanzaError>),
+    /// The outcome of Agent::muc_kick, Agent::muc_ban or Agent::muc_set_affiliation, containing
+    /// the room's BareJid and the error returned by the room, if any.
+    RoomAdmin(BareJid, Result<(), StanzaError>),
+    /// A chat state (XEP-0085) sent by a contact, containing its BareJid; requires the
+    /// ClientFeature::ChatStates feature.
+    ChatState(BareJid, ChatState),
 }
 
 pub struct ClientBuilder<'a> {
@@ -107,6 +170,8 @@ pub struct ClientBuilder<'a> {
     disco: (ClientType, String),
     features: Vec<ClientFeature>,
     resource: Option<String>,
+    #[cfg(feature = "avatars")]
+    avatar_max_bytes: usize,
 }
 
 impl ClientBuilder<'_> {
@@ -120,6 +185,8 @@ impl ClientBuilder<'_> {
             disco: (ClientType::default(), String::from("tokio-xmpp")),
             features: vec![],
             resource: None,
+            #[cfg(feature = "avatars")]
+            avatar_max_bytes: u16::MAX.into(),
         }
     }
 
@@ -149,70 +216,109 @@ impl ClientBuilder<'_> {
         self
     }
 
+    /// Sets the largest avatar data to accept, in bytes; the default is the largest size the
+    /// metadata can announce
+    #[cfg(feature = "avatars")]
+    pub fn set_avatar_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.avatar_max_bytes = max_bytes;
+        self
+    }
+
     pub fn enable_feature(mut self, feature: ClientFeature) -> Self {
         self.features.push(feature);
         self
     }
 
-    fn make_disco(&self) -> DiscoInfoResult {
-        let identities = vec![Identity::new(
+    fn make_features(&self) -> FeatureRegistry {
+        let mut features = FeatureRegistry::new(Identity::new(
             "client",
             self.disco.0.to_string(),
             "en",
             self.disco.1.to_string(),
-        )];
-        let mut features = vec![Feature::new(ns::DISCO_INFO)];
+        ));
         #[cfg(feature = "avatars")]
         {
             if self.features.contains(&ClientFeature::Avatars) {
-                features.push(Feature::new(format!("{}+notify", ns::AVATAR_METADATA)));
+                features.register(format!("{}+notify", ns::AVATAR_METADATA));
             }
         }
         if self.features.contains(&ClientFeature::JoinRooms) {
-            features.push(Feature::new(format!("{}+notify", ns::BOOKMARKS2)));
+            features.register(format!("{}+notify", ns::BOOKMARKS2));
         }
-        DiscoInfoResult {
-            node: None,
-            identities,
-            features,
-            extensions: vec![],
+        if self.features.contains(&ClientFeature::Receipts) {
+            features.register(ns::RECEIPTS);
         }
+        if self.features.contains(&ClientFeature::ChatStates) {
+            features.register(ns::CHATSTATES);
+        }
+        features
     }
 
-    pub fn build(self) -> Agent {
-        let jid: Jid = if let Some(resource) = &self.resource {
-            self.jid.with_resource_str(resource).unwrap().into()
-        } else {
-            self.jid.clone().into()
-        };
-
-        let client = TokioXmppClient::new(jid, self.password);
-        self.build_impl(client)
+    /// Builds the Agent, failing if the JID has no node or the resource is invalid
+    pub fn build(self) -> Result<Agent, ConfigError> {
+        let mut config = TokioXmppConfigBuilder::new(self.jid.clone(), self.password)
+            .set_auto_receipts(self.features.contains(&ClientFeature::Receipts));
+        if let Some(resource) = &self.resource {
+            config = config.set_resource(resource);
+        }
+        let client = TokioXmppClient::new_with_config(config.build()?);
+        Ok(self.build_impl(client))
     }
 
     // This function is meant to be used for testing build
     pub(crate) fn build_impl(self, client: TokioXmppClient) -> Agent {
-        let disco = self.make_disco();
+        let features = self.make_features();
         let node = self.website;
+        let caps = if self.features.contains(&ClientFeature::Caps) {
+            Some(CapsCache::default())
+        } else {
+            None
+        };
 
         Agent {
             client,
             default_nick: Arc::new(RwLock::new(self.default_nick)),
             lang: Arc::new(self.lang),
-            disco,
+            features,
             node,
             uploads: Vec::new(),
+            self_pings: Vec::new(),
+            carbons_requests: Vec::new(),
+            admin_requests: Vec::new(),
+            markable: Vec::new(),
+            caps,
+            #[cfg(feature = "avatars")]
+            avatar_requests: Vec::new(),
+            #[cfg(feature = "avatars")]
+            avatar_max_bytes: self.avatar_max_bytes,
         }
     }
 }
 
+/// How many received messages to remember as waiting for a displayed marker (XEP-0333).
+const MAX_MARKABLE: usize = 256;
+
 pub struct Agent {
     client: TokioXmppClient,
     default_nick: Arc<RwLock<String>>,
     lang: Arc<Vec<String>>,
-    disco: DiscoInfoResult,
+    features: FeatureRegistry,
     node: String,
     uploads: Vec<(String, Jid, PathBuf)>,
+    /// Our pending self-pings, with their id, room and when they got sent.
+    self_pings: Vec<(String, BareJid, Instant)>,
+    carbons_requests: Vec<(String, bool)>,
+    admin_requests: Vec<(String, BareJid)>,
+    /// The received messages which asked for chat markers, the oldest first, with their chat,
+    /// id, type and the id to mark them with.
+    markable: Vec<(BareJid, String, MessageType, String)>,
+    caps: Option<CapsCache>,
+    /// Our pending avatar data requests, with their id, recipient, the <info/> announcing the
+    /// avatar and when they got sent.
+    #[cfg(feature = "avatars")]
+    avatar_requests: Vec<(String, Jid, Info, Instant)>,
+    #[cfg(feature = "avatars")]
+    avatar_max_bytes: usize,
 }
 
 impl Agent {
@@ -237,7 +343,12 @@ impl Agent {
         let room_jid = room.with_resource_str(&nick).unwrap();
         let mut presence = Presence::new(PresenceType::None).with_to(room_jid);
         presence.add_payload(muc);
-        presence.set_status(String::from(lang), String::from(status));
+        if presence.set_status(lang, status).is_err() {
+            // Better an untagged status than none at all.
+            presence
+                .statuses
+                .insert(String::new(), String::from(status));
+        }
         let _ = self.client.send_stanza(presence.into()).await;
     }
 
@@ -272,10 +383,147 @@ impl Agent {
         let _ = self.client.send_stanza(message.into()).await;
     }
 
-    fn make_initial_presence(disco: &DiscoInfoResult, node: &str) -> Presence {
-        let caps_data = compute_disco(disco);
-        let hash = hash_caps(&caps_data, Algo::Sha_1).unwrap();
-        let caps = Caps::new(node, hash);
+    /// Pings our own occupant JID in a room (XEP-0410), to check whether we are still joined to
+    /// it; the outcome is reported as an Event::RoomSelfPing once the room answers.
+    ///
+    /// Nothing gets sent if the nickname isn't a valid resource.
+    pub async fn muc_self_ping(&mut self, room: BareJid, nick: Option<String>) {
+        if let Some(iq) = self.make_self_ping(room, nick) {
+            let _ = self.client.send_stanza(iq.into()).await;
+        }
+    }
+
+    fn make_self_ping(&mut self, room: BareJid, nick: Option<String>) -> Option<Iq> {
+        let nick = nick.unwrap_or_else(|| self.default_nick.read().unwrap().clone());
+        let occupant = match room.with_resource_str(&nick) {
+            Ok(occupant) => occupant,
+            Err(err) => {
+                warn!("Can’t self-ping {} as {:?}: {}", room, nick, err);
+                return None;
+            }
+        };
+        let now = Instant::now();
+        self.expire_self_pings(now);
+        let id = generate_id();
+        let iq = Iq::from_get(id.clone(), Ping).with_to(occupant.into());
+        self.self_pings.push((id, room, now));
+        Some(iq)
+    }
+
+    /// Drops the self-pings nobody answered in time, an answer coming later being ignored.
+    fn expire_self_pings(&mut self, now: Instant) {
+        self.self_pings
+            .retain(|(_, _, sent)| now.duration_since(*sent) < SELF_PING_TIMEOUT);
+    }
+
+    /// Kicks the occupant with this nickname out of a room (XEP-0045), by setting their role to
+    /// none; the outcome is reported as an Event::RoomAdmin.
+    pub async fn muc_kick(&mut self, room: BareJid, nick: RoomNick, reason: Option<String>) {
+        let mut item = MucAdminItem::role(nick, Role::None);
+        item.reason = reason;
+        let iq = self.make_admin_request(room, item);
+        let _ = self.client.send_stanza(iq.into()).await;
+    }
+
+    /// Bans a user from a room (XEP-0045), by setting the affiliation of their bare JID to
+    /// outcast; the outcome is reported as an Event::RoomAdmin.
+    pub async fn muc_ban(&mut self, room: BareJid, jid: BareJid, reason: Option<String>) {
+        let mut item = MucAdminItem::affiliation(jid, Affiliation::Outcast);
+        item.reason = reason;
+        let iq = self.make_admin_request(room, item);
+        let _ = self.client.send_stanza(iq.into()).await;
+    }
+
+    /// Changes the affiliation of a user with a room (XEP-0045), e.g. to make them a member, or
+    /// to none to lift a ban; the outcome is reported as an Event::RoomAdmin.
+    pub async fn muc_set_affiliation(
+        &mut self,
+        room: BareJid,
+        jid: BareJid,
+        affiliation: Affiliation,
+    ) {
+        let item = MucAdminItem::affiliation(jid, affiliation);
+        let iq = self.make_admin_request(room, item);
+        let _ = self.client.send_stanza(iq.into()).await;
+    }
+
+    fn make_admin_request(&mut self, room: BareJid, item: MucAdminItem) -> Iq {
+        let id = generate_id();
+        let iq = Iq::from_set(id.clone(), MucAdmin::new(item)).with_to(Jid::Bare(room.clone()));
+        self.admin_requests.push((id, room));
+        iq
+    }
+
+    /// Asks the server to send us a copy of the messages our other resources send and receive
+    /// (XEP-0280); the outcome is reported as an Event::Carbons.
+    pub async fn enable_carbons(&mut self) {
+        let id = generate_id();
+        let iq = Iq::from_set(id.clone(), CarbonsEnable);
+        self.carbons_requests.push((id, true));
+        let _ = self.client.send_stanza(iq.into()).await;
+    }
+
+    /// Asks the server to stop sending us carbon copies; the outcome is reported as an
+    /// Event::Carbons.
+    pub async fn disable_carbons(&mut self) {
+        let id = generate_id();
+        let iq = Iq::from_set(id.clone(), CarbonsDisable);
+        self.carbons_requests.push((id, false));
+        let _ = self.client.send_stanza(iq.into()).await;
+    }
+
+    /// Tells the sender of a message that it, and every message before it in the same chat, got
+    /// displayed to the user (XEP-0333).
+    ///
+    /// The chat is the contact’s or the room’s BareJid, as given in Event::ChatMessage or
+    /// Event::RoomMessage. Nothing is sent if the message didn’t ask for chat markers, if it
+    /// has already been marked, or if too many messages asking for them came since.
+    pub async fn mark_read(&mut self, chat: Jid, message_id: String) {
+        if let Some(marker) = self.take_displayed_marker(&chat.to_bare(), &message_id) {
+            let _ = self.client.send_stanza(marker.into()).await;
+        }
+    }
+
+    fn take_displayed_marker(&mut self, chat: &BareJid, message_id: &str) -> Option<Message> {
+        let position = self
+            .markable
+            .iter()
+            .position(|(jid, id, _, _)| jid == chat && id == message_id)?;
+        let (_, _, type_, marker_id) = self.markable[position].clone();
+        // The earlier messages of this chat are implicitly marked too.
+        let mut index = 0;
+        self.markable.retain(|(jid, _, _, _)| {
+            let keep = index > position || jid != chat;
+            index += 1;
+            keep
+        });
+        let mut marker =
+            Message::new(Jid::Bare(chat.clone())).with_payload(Displayed { id: marker_id });
+        marker.type_ = type_;
+        Some(marker)
+    }
+
+    /// The disco#info of this entity, if it advertised caps (XEP-0115) and we already fetched
+    /// and verified them; requires the ClientFeature::Caps feature.
+    pub fn features_of(&self, jid: &Jid) -> Option<&DiscoInfoResult> {
+        self.caps.as_ref()?.get(jid)
+    }
+
+    /// The identities and features we advertise, and answer disco#info queries with.
+    ///
+    /// Our caps are computed from them, and our presence gets broadcast again with the new caps
+    /// when they change while online.
+    pub fn features(&self) -> &FeatureRegistry {
+        &self.features
+    }
+
+    /// Lets modules outside of this crate advertise their own features.
+    pub fn features_mut(&mut self) -> &mut FeatureRegistry {
+        &mut self.features
+    }
+
+    fn make_initial_presence(features: &FeatureRegistry, node: &str) -> Presence {
+        let caps = features.caps(node);
 
         let mut presence = Presence::new(PresenceType::None);
         presence.add_payload(caps);
@@ -288,13 +536,89 @@ impl Agent {
             .from
             .clone()
             .unwrap_or_else(|| self.client.bound_jid().unwrap().clone());
+        if let Some(index) = self
+            .self_pings
+            .iter()
+            .position(|(id, room, _)| id == &iq.id && from.to_bare() == *room)
+        {
+            let (_, room, _) = self.self_pings.remove(index);
+            let outcome = match iq.payload {
+                IqType::Error(error) => match error.defined_condition {
+                    // The room forwarded the ping to our own client, which doesn't handle it.
+                    DefinedCondition::ServiceUnavailable
+                    | DefinedCondition::FeatureNotImplemented => SelfPing::Joined,
+                    // Our server couldn't reach the room's.
+                    DefinedCondition::RemoteServerNotFound
+                    | DefinedCondition::RemoteServerTimeout => SelfPing::Inconclusive,
+                    _ => SelfPing::NotJoined,
+                },
+                _ => SelfPing::Joined,
+            };
+            events.push(Event::RoomSelfPing(room, outcome));
+            return events;
+        }
+        if let Some(index) = self
+            .admin_requests
+            .iter()
+            .position(|(id, room)| id == &iq.id && from.to_bare() == *room)
+        {
+            let (_, room) = self.admin_requests.remove(index);
+            match iq.payload {
+                IqType::Result(_) => events.push(Event::RoomAdmin(room, Ok(()))),
+                IqType::Error(error) => events.push(Event::RoomAdmin(room, Err(error))),
+                _ => (),
+            }
+            return events;
+        }
+        // Carbons requests are answered by our own server, on behalf of our account.
+        if Some(from.to_bare()) == self.client.bound_jid().map(Jid::to_bare) {
+            if let Some(index) = self
+                .carbons_requests
+                .iter()
+                .position(|(id, _)| id == &iq.id)
+            {
+                let (_, enable) = self.carbons_requests.remove(index);
+                match iq.payload {
+                    IqType::Result(_) => {
+                        if enable {
+                            self.features.register(ns::CARBONS);
+                        } else {
+                            self.features.unregister(ns::CARBONS);
+                        }
+                        events.push(Event::Carbons(Ok(enable)))
+                    }
+                    IqType::Error(error) => events.push(Event::Carbons(Err(error))),
+                    _ => (),
+                }
+                return events;
+            }
+        }
+        if let Some(caps) = &mut self.caps {
+            if caps.handle_iq(&from, &iq.id, &iq.payload) {
+                return events;
+            }
+        }
+        #[cfg(feature = "avatars")]
+        if let IqType::Error(ref error) = iq.payload {
+            if let Some(index) = self
+                .avatar_requests
+                .iter()
+                .position(|(id, jid, _, _)| id == &iq.id && *jid == from)
+            {
+                self.avatar_requests.remove(index);
+                warn!(
+                    "Couldn’t retrieve the avatar of {}: {:?}",
+                    from, error.defined_condition
+                );
+                return events;
+            }
+        }
         if let IqType::Get(payload) = iq.payload {
             if payload.is("query", ns::DISCO_INFO) {
                 let query = DiscoInfoQuery::try_from(payload);
                 match query {
                     Ok(query) => {
-                        let mut disco_info = self.disco.clone();
-                        disco_info.node = query.node;
+                        let disco_info = self.features.disco_info(query.node);
                         let iq = Iq::from_result(iq.id, Some(disco_info))
                             .with_to(iq.from.unwrap())
                             .into();
@@ -335,7 +659,7 @@ impl Agent {
                     events.push(Event::ContactAdded(item));
                 }
             } else if payload.is("pubsub", ns::PUBSUB) {
-                let new_events = pubsub::handle_iq_result(&from, payload);
+                let new_events = pubsub::handle_iq_result(&from, payload, self);
                 events.extend(new_events);
             } else if payload.is("slot", ns::HTTP_UPLOAD) {
                 let new_events = handle_upload_result(&from, iq.id, payload, self).await;
@@ -360,18 +684,44 @@ impl Agent {
 
     async fn handle_message(&mut self, message: Message) -> Vec<Event> {
         let mut events = vec![];
-        let from = message.from.clone().unwrap();
+        let own_jid = self.client.bound_jid().map(Jid::to_bare);
+        let (message, carbon) = match own_jid {
+            Some(own_jid) => unwrap_carbon(&own_jid, message),
+            None => (message, None),
+        };
+        let from = match carbon {
+            Some(CarbonDirection::Sent) => match message.to.clone() {
+                Some(to) => to,
+                None => return events,
+            },
+            _ => message.from.clone().unwrap(),
+        };
+        if self.features.contains(ns::CHATSTATES)
+            && message.type_ != MessageType::Groupchat
+            && carbon != Some(CarbonDirection::Sent)
+        {
+            let state = message
+                .payloads
+                .iter()
+                .find_map(|payload| ChatState::try_from(payload.clone()).ok());
+            if let Some(state) = state {
+                events.push(Event::ChatState(from.to_bare(), state));
+            }
+        }
         let langs: Vec<&str> = self.lang.iter().map(String::as_str).collect();
         match message.get_best_body(langs) {
             Some((_lang, body)) => match message.type_ {
                 MessageType::Groupchat => {
                     let event = match from.clone() {
-                        Jid::Full(full) => Event::RoomMessage(
-                            message.id.clone(),
-                            from.to_bare(),
-                            full.resource_str().to_owned(),
-                            body.clone(),
-                        ),
+                        Jid::Full(full) => {
+                            self.track_markable(&message, from.to_bare());
+                            Event::RoomMessage(
+                                message.id.clone(),
+                                from.to_bare(),
+                                full.resource_str().to_owned(),
+                                body.clone(),
+                            )
+                        }
                         Jid::Bare(bare) => {
                             Event::ServiceMessage(message.id.clone(), bare, body.clone())
                         }
@@ -403,8 +753,15 @@ impl Agent {
                     }
 
                     if !found_special_message {
-                        let event =
-                            Event::ChatMessage(message.id.clone(), from.to_bare(), body.clone());
+                        if carbon != Some(CarbonDirection::Sent) {
+                            self.track_markable(&message, from.to_bare());
+                        }
+                        let event = Event::ChatMessage(
+                            message.id.clone(),
+                            from.to_bare(),
+                            body.clone(),
+                            carbon,
+                        );
                         events.push(event)
                     }
                 }
@@ -412,19 +769,82 @@ impl Agent {
             },
             None => (),
         }
-        for child in message.payloads {
-            if child.is("event", ns::PUBSUB_EVENT) {
-                let new_events = pubsub::handle_event(&from, child, self).await;
-                events.extend(new_events);
+        // PubSub events don’t get carbon-copied.
+        if carbon.is_none() {
+            for child in message.payloads {
+                if child.is("event", ns::PUBSUB_EVENT) {
+                    let new_events = pubsub::handle_event(&from, child, self).await;
+                    events.extend(new_events);
+                }
             }
         }
 
         events
     }
 
+    fn track_markable(&mut self, message: &Message, chat: BareJid) {
+        let id = match &message.id {
+            Some(id) => id,
+            None => return,
+        };
+        if !message
+            .payloads
+            .iter()
+            .any(|payload| payload.is("markable", ns::CHAT_MARKERS))
+        {
+            return;
+        }
+        if self
+            .markable
+            .iter()
+            .any(|(jid, other, _, _)| *jid == chat && other == id)
+        {
+            return;
+        }
+        // In a room, messages are marked by the stanza-id the room stamped on them (XEP-0359),
+        // if it did.
+        let marker_id = match message.type_ {
+            MessageType::Groupchat => message
+                .payloads
+                .iter()
+                .filter(|payload| payload.is("stanza-id", ns::SID))
+                .find_map(|payload| match StanzaId::try_from(payload.clone()) {
+                    Ok(StanzaId { id, by }) if by == Jid::Bare(chat.clone()) => Some(id),
+                    _ => None,
+                })
+                .unwrap_or_else(|| id.clone()),
+            _ => id.clone(),
+        };
+        if self.markable.len() >= MAX_MARKABLE {
+            // Never marked, so most likely never going to be.
+            self.markable.remove(0);
+        }
+        self.markable
+            .push((chat, id.clone(), message.type_.clone(), marker_id));
+    }
+
     async fn handle_presence(&mut self, presence: Presence) -> Vec<Event> {
         let mut events = vec![];
-        let from = presence.from.unwrap().to_bare();
+        let full_from = presence.from.unwrap();
+        if let Some(caps) = &mut self.caps {
+            if presence.type_ == PresenceType::Unavailable {
+                caps.forget(&full_from);
+            } else if let Some(payload) = presence
+                .payloads
+                .iter()
+                .find(|payload| payload.is("c", ns::CAPS))
+            {
+                match Caps::try_from(payload.clone()) {
+                    Ok(payload) => {
+                        if let Some(iq) = caps.handle_caps(full_from.clone(), payload) {
+                            let _ = self.client.send_stanza(iq.into()).await;
+                        }
+                    }
+                    Err(err) => warn!("Invalid caps from {}: {}", full_from, err),
+                }
+            }
+        }
+        let from = full_from.to_bare();
         for payload in presence.payloads.into_iter() {
             let muc_user = match MucUser::try_from(payload) {
                 Ok(muc_user) => muc_user,
@@ -442,12 +862,19 @@ impl Agent {
     }
 
     pub async fn wait_for_events(&mut self) -> Option<Vec<Event>> {
-        if let Some(event) = self.client.next().await {
+        // Features registered since the last call, e.g. carbons once enabled.
+        if self.client.bound_jid().is_some() && self.features.take_changed() {
+            let presence = Self::make_initial_presence(&self.features, &self.node).into();
+            let _ = self.client.send_stanza(presence).await;
+        }
+
+        loop {
             let mut events = Vec::new();
 
-            match event {
+            match self.client.next().await? {
                 TokioXmppEvent::Online { resumed: false, .. } => {
-                    let presence = Self::make_initial_presence(&self.disco, &self.node).into();
+                    self.features.take_changed();
+                    let presence = Self::make_initial_presence(&self.features, &self.node).into();
                     let _ = self.client.send_stanza(presence).await;
                     events.push(Event::Online);
                     // TODO: only send this when the ContactList feature is enabled.
@@ -465,24 +892,33 @@ impl Agent {
                         Iq::from_get("bookmarks", PubSub::Items(Items::new(ns::BOOKMARKS2))).into();
                     let _ = self.client.send_stanza(iq).await;
                 }
-                TokioXmppEvent::Online { resumed: true, .. } => {}
+                // Neither connection progress nor Stream Management is reported by the Agent, so
+                // wait for an actual event.
+                TokioXmppEvent::Online { resumed: true, .. }
+                | TokioXmppEvent::StanzaAcked { .. }
+                | TokioXmppEvent::UnackedDropped { .. }
+                | TokioXmppEvent::Connecting
+                | TokioXmppEvent::Authenticating
+                | TokioXmppEvent::Binding => continue,
                 TokioXmppEvent::Disconnected(_) => {
                     events.push(Event::Disconnected);
                 }
                 TokioXmppEvent::Stanza(elem) => {
-                    if elem.is("iq", "jabber:client") {
+                    if elem.is("iq", ns::JABBER_CLIENT) {
                         let iq = Iq::try_from(elem).unwrap();
                         let new_events = self.handle_iq(iq).await;
                         events.extend(new_events);
-                    } else if elem.is("message", "jabber:client") {
-                        let message = Message::try_from(elem).unwrap();
+                    } else if elem.is("message", ns::JABBER_CLIENT) {
+                        let lang = self.client.stream_lang().unwrap_or("");
+                        let message = Message::parse_with_lang(elem, lang).unwrap();
                         let new_events = self.handle_message(message).await;
                         events.extend(new_events);
-                    } else if elem.is("presence", "jabber:client") {
-                        let presence = Presence::try_from(elem).unwrap();
+                    } else if elem.is("presence", ns::JABBER_CLIENT) {
+                        let lang = self.client.stream_lang().unwrap_or("");
+                        let presence = Presence::parse_with_lang(elem, lang).unwrap();
                         let new_events = self.handle_presence(presence).await;
                         events.extend(new_events);
-                    } else if elem.is("error", "http://etherx.jabber.org/streams") {
+                    } else if elem.is("error", ns::STREAM) {
                         println!("Received a fatal stream error: {}", String::from(&elem));
                     } else {
                         panic!("Unknown stanza: {}", String::from(&elem));
@@ -490,9 +926,7 @@ impl Agent {
                 }
             }
 
-            Some(events)
-        } else {
-            None
+            return Some(events);
         }
     }
 
@@ -513,6 +947,35 @@ impl Agent {
     }
 }
 
+/// Returns the message forwarded in a carbon copy, along with its direction, or the message
+/// itself when it isn’t one.  Only our own bare JID may send us carbons, otherwise anyone could
+/// forge messages from our contacts.
+fn unwrap_carbon(own_jid: &BareJid, message: Message) -> (Message, Option<CarbonDirection>) {
+    match message.from {
+        Some(Jid::Bare(ref from)) if from == own_jid => (),
+        _ => return (message, None),
+    }
+    for payload in message.payloads.iter() {
+        let (forwarded, direction) = if payload.is("received", ns::CARBONS) {
+            match Received::try_from(payload.clone()) {
+                Ok(received) => (received.forwarded, CarbonDirection::Received),
+                Err(_) => continue,
+            }
+        } else if payload.is("sent", ns::CARBONS) {
+            match Sent::try_from(payload.clone()) {
+                Ok(sent) => (sent.forwarded, CarbonDirection::Sent),
+                Err(_) => continue,
+            }
+        } else {
+            continue;
+        };
+        if let Some(inner) = forwarded.stanza {
+            return (inner, Some(direction));
+        }
+    }
+    (message, None)
+}
+
 async fn handle_upload_result(
     from: &Jid,
     iqid: String,
@@ -562,8 +1025,22 @@ async fn handle_upload_result(
 
 #[cfg(test)]
 mod tests {
-    use super::{Agent, BareJid, ClientBuilder, ClientFeature, ClientType, Event};
+    #[cfg(feature = "avatars")]
+    use super::{pubsub, Element, Info};
+    use super::{
+        unwrap_carbon, Agent, BareJid, CarbonDirection, ClientBuilder, ClientFeature, ClientType,
+        Event, Jid, SelfPing, SELF_PING_TIMEOUT,
+    };
+    use std::convert::TryFrom;
     use std::str::FromStr;
+    use std::time::Instant;
+    use tokio_xmpp::parsers::chat_markers::Displayed;
+    use tokio_xmpp::parsers::chatstates::ChatState;
+    use tokio_xmpp::parsers::iq::{Iq, IqType};
+    use tokio_xmpp::parsers::message::{Message, MessageType};
+    use tokio_xmpp::parsers::muc::{admin::MucAdmin, user::Role};
+    use tokio_xmpp::parsers::ns;
+    use tokio_xmpp::parsers::stanza_error::{DefinedCondition, ErrorType, StanzaError};
     use tokio_xmpp::AsyncClient as TokioXmppClient;
 
     #[tokio::test]
@@ -593,4 +1070,365 @@ mod tests {
             break;
         }
     }
+
+    #[cfg(feature = "avatars")]
+    #[tokio::test]
+    async fn test_avatar_data_size() {
+        let jid = BareJid::from_str("foo@bar").unwrap();
+        let client = TokioXmppClient::new(jid.clone(), "meh");
+        let mut agent = ClientBuilder::new(jid, "meh")
+            .enable_feature(ClientFeature::Avatars)
+            .set_avatar_max_bytes(4)
+            .build_impl(client);
+        let from = Jid::from_str("juliet@capulet.example").unwrap();
+        // The SHA-1 of "abc", announced as two bytes.
+        let id = "a9993e364706816aba3e25717850c26c9cd0d89d";
+        let info: Element = format!(
+            "<info xmlns='urn:xmpp:avatar:metadata' bytes='2' id='{}' type='image/png'/>",
+            id
+        )
+        .parse()
+        .unwrap();
+        agent.avatar_requests.push((
+            String::from("data1"),
+            from.clone(),
+            Info::try_from(info).unwrap(),
+            Instant::now(),
+        ));
+        let result = |data: &str| -> Element {
+            format!(
+                "<pubsub xmlns='http://jabber.org/protocol/pubsub'><items node='urn:xmpp:avatar:data'><item id='{}'><data xmlns='urn:xmpp:avatar:data'>{}</data></item></items></pubsub>",
+                id, data
+            )
+            .parse()
+            .unwrap()
+        };
+
+        // Three bytes, more than its <info/> announced.
+        let events: Vec<_> = pubsub::handle_iq_result(&from, result("YWJj"), &mut agent)
+            .into_iter()
+            .collect();
+        assert!(events.is_empty());
+        assert!(agent.avatar_requests.is_empty());
+
+        // Six bytes, more than the configured maximum.
+        let events: Vec<_> = pubsub::handle_iq_result(&from, result("YWJjYWJj"), &mut agent)
+            .into_iter()
+            .collect();
+        assert!(events.is_empty());
+    }
+
+    #[cfg(feature = "avatars")]
+    #[tokio::test]
+    async fn test_avatar_request_error() {
+        let jid = BareJid::from_str("foo@bar").unwrap();
+        let client = TokioXmppClient::new(jid.clone(), "meh");
+        let mut agent = ClientBuilder::new(jid, "meh")
+            .enable_feature(ClientFeature::Avatars)
+            .build_impl(client);
+        let from = Jid::from_str("juliet@capulet.example").unwrap();
+        let info: Element = "<info xmlns='urn:xmpp:avatar:metadata' bytes='3' id='a9993e364706816aba3e25717850c26c9cd0d89d' type='image/png'/>"
+            .parse()
+            .unwrap();
+        let info = Info::try_from(info).unwrap();
+        agent.avatar_requests.push((
+            String::from("data1"),
+            from.clone(),
+            info.clone(),
+            Instant::now(),
+        ));
+        agent
+            .avatar_requests
+            .push((String::from("data2"), from.clone(), info, Instant::now()));
+
+        let error = Iq::from_error(
+            "data1",
+            StanzaError::new(ErrorType::Cancel, DefinedCondition::ItemNotFound, "en", ""),
+        )
+        .with_from(from);
+        assert!(agent.handle_iq(error).await.is_empty());
+        assert_eq!(agent.avatar_requests.len(), 1);
+        assert_eq!(agent.avatar_requests[0].0, "data2");
+
+        // Unanswered requests get forgotten.
+        pubsub::avatar::expire_requests(&mut agent.avatar_requests, Instant::now());
+        assert_eq!(agent.avatar_requests.len(), 1);
+        pubsub::avatar::expire_requests(
+            &mut agent.avatar_requests,
+            Instant::now() + pubsub::avatar::REQUEST_TIMEOUT,
+        );
+        assert!(agent.avatar_requests.is_empty());
+    }
+
+    #[test]
+    fn test_unwrap_carbon() {
+        let own = BareJid::from_str("romeo@montague.example").unwrap();
+        let xml = "<message xmlns='jabber:client' from='romeo@montague.example' to='romeo@montague.example/home' type='chat'>
+            <sent xmlns='urn:xmpp:carbons:2'>
+                <forwarded xmlns='urn:xmpp:forward:0'>
+                    <message xmlns='jabber:client' from='romeo@montague.example/garden' to='juliet@capulet.example/balcony' type='chat' id='abc'>
+                        <body>Wherefore art thou?</body>
+                    </message>
+                </forwarded>
+            </sent>
+        </message>";
+        let message = Message::try_from(xml.parse::<tokio_xmpp::Element>().unwrap()).unwrap();
+        let (inner, direction) = unwrap_carbon(&own, message);
+        assert_eq!(direction, Some(CarbonDirection::Sent));
+        assert_eq!(inner.id, Some(String::from("abc")));
+        assert_eq!(
+            inner.to,
+            Some(Jid::from_str("juliet@capulet.example/balcony").unwrap())
+        );
+
+        // Someone else trying to impersonate our server.
+        let forged = xml.replacen(
+            "from='romeo@montague.example'",
+            "from='mallory@evil.example'",
+            1,
+        );
+        let message = Message::try_from(forged.parse::<tokio_xmpp::Element>().unwrap()).unwrap();
+        let (outer, direction) = unwrap_carbon(&own, message);
+        assert_eq!(direction, None);
+        assert_eq!(
+            outer.from,
+            Some(Jid::from_str("mallory@evil.example").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mark_read() {
+        let jid = BareJid::from_str("romeo@montague.example").unwrap();
+        let client = TokioXmppClient::new(jid.clone(), "meh");
+        let mut agent: Agent = ClientBuilder::new(jid, "meh").build_impl(client);
+
+        let message = |from: &str, id: &str, type_: &str, markable: bool| {
+            let xml = format!(
+                "<message xmlns='jabber:client' from='{}' id='{}' type='{}'><body>Hi</body>{}</message>",
+                from,
+                id,
+                type_,
+                if markable {
+                    "<markable xmlns='urn:xmpp:chat-markers:0'/>"
+                } else {
+                    ""
+                }
+            );
+            Message::try_from(xml.parse::<tokio_xmpp::Element>().unwrap()).unwrap()
+        };
+        let juliet = BareJid::from_str("juliet@capulet.example").unwrap();
+        let room = BareJid::from_str("coven@chat.shakespeare.example").unwrap();
+        agent
+            .handle_message(message("juliet@capulet.example/balcony", "1", "chat", true))
+            .await;
+        agent
+            .handle_message(message(
+                "juliet@capulet.example/balcony",
+                "2",
+                "chat",
+                false,
+            ))
+            .await;
+        agent
+            .handle_message(message("juliet@capulet.example/balcony", "3", "chat", true))
+            .await;
+        agent
+            .handle_message(message(
+                "coven@chat.shakespeare.example/thirdwitch",
+                "4",
+                "groupchat",
+                true,
+            ))
+            .await;
+
+        // Not markable.
+        assert!(agent.take_displayed_marker(&juliet, "2").is_none());
+        // Not from that chat.
+        assert!(agent.take_displayed_marker(&room, "3").is_none());
+
+        let marker = agent.take_displayed_marker(&juliet, "3").unwrap();
+        assert_eq!(marker.to, Some(Jid::Bare(juliet.clone())));
+        assert_eq!(marker.type_, MessageType::Chat);
+        let displayed = Displayed::try_from(marker.payloads[0].clone()).unwrap();
+        assert_eq!(displayed.id, "3");
+
+        // Already marked, explicitly or as an earlier message.
+        assert!(agent.take_displayed_marker(&juliet, "3").is_none());
+        assert!(agent.take_displayed_marker(&juliet, "1").is_none());
+
+        let marker = agent.take_displayed_marker(&room, "4").unwrap();
+        assert_eq!(marker.to, Some(Jid::Bare(room.clone())));
+        assert_eq!(marker.type_, MessageType::Groupchat);
+        let displayed = Displayed::try_from(marker.payloads[0].clone()).unwrap();
+        assert_eq!(displayed.id, "4");
+
+        // In a room, the stanza-id stamped by the room is used, not one by anyone else.
+        let xml = "<message xmlns='jabber:client' from='coven@chat.shakespeare.example/thirdwitch' id='5' type='groupchat'><body>Hi</body><markable xmlns='urn:xmpp:chat-markers:0'/><stanza-id xmlns='urn:xmpp:sid:0' id='forged' by='thirdwitch@shakespeare.example'/><stanza-id xmlns='urn:xmpp:sid:0' id='room-5' by='coven@chat.shakespeare.example'/></message>";
+        let stamped = Message::try_from(xml.parse::<tokio_xmpp::Element>().unwrap()).unwrap();
+        agent.handle_message(stamped).await;
+        let marker = agent.take_displayed_marker(&room, "5").unwrap();
+        let displayed = Displayed::try_from(marker.payloads[0].clone()).unwrap();
+        assert_eq!(displayed.id, "room-5");
+
+        // Only the most recent ones are remembered.
+        for id in 0..=super::MAX_MARKABLE {
+            agent
+                .handle_message(message(
+                    "juliet@capulet.example/balcony",
+                    &id.to_string(),
+                    "chat",
+                    true,
+                ))
+                .await;
+        }
+        assert_eq!(agent.markable.len(), super::MAX_MARKABLE);
+        assert!(agent.take_displayed_marker(&juliet, "0").is_none());
+        assert!(agent.take_displayed_marker(&juliet, "1").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_muc_kick() {
+        let jid = BareJid::from_str("crone1@shakespeare.example").unwrap();
+        let client = TokioXmppClient::new(jid.clone(), "meh");
+        let mut agent: Agent = ClientBuilder::new(jid, "meh").build_impl(client);
+        let room = BareJid::from_str("harfleur@chat.shakespeare.example").unwrap();
+
+        let mut item = super::MucAdminItem::role("pistol", Role::None);
+        item.reason = Some(String::from("Avaunt, you cullion!"));
+        let kick = agent.make_admin_request(room.clone(), item);
+        assert_eq!(kick.to, Some(Jid::Bare(room.clone())));
+        let admin = match kick.payload {
+            IqType::Set(ref payload) => MucAdmin::try_from(payload.clone()).unwrap(),
+            _ => panic!(),
+        };
+        assert_eq!(admin.items[0].nick.as_deref(), Some("pistol"));
+        assert_eq!(admin.items[0].role, Some(Role::None));
+
+        // Not from the room.
+        let other = Iq::empty_result(Jid::Bare(room.clone()), kick.id.clone())
+            .with_from(Jid::from_str("pistol@shakespeare.example").unwrap());
+        assert!(agent.handle_iq(other).await.is_empty());
+
+        let error = StanzaError::new(
+            ErrorType::Cancel,
+            DefinedCondition::NotAllowed,
+            "en",
+            "Not a moderator.",
+        );
+        let answer = Iq::from_error(kick.id.clone(), error).with_from(Jid::Bare(room.clone()));
+        match &agent.handle_iq(answer).await[..] {
+            [Event::RoomAdmin(jid, Err(error))] => {
+                assert_eq!(jid, &room);
+                assert_eq!(error.defined_condition, DefinedCondition::NotAllowed);
+            }
+            events => panic!("{:?}", events),
+        }
+        assert!(agent.admin_requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_muc_self_ping() {
+        let jid = BareJid::from_str("crone1@shakespeare.example").unwrap();
+        let client = TokioXmppClient::new(jid.clone(), "meh");
+        let mut agent: Agent = ClientBuilder::new(jid, "meh").build_impl(client);
+        let room = BareJid::from_str("coven@chat.shakespeare.example").unwrap();
+
+        // Not a valid resourcepart.
+        assert!(agent
+            .make_self_ping(room.clone(), Some(String::new()))
+            .is_none());
+        assert!(agent.self_pings.is_empty());
+
+        let ping = |agent: &mut Agent| {
+            let ping = agent
+                .make_self_ping(room.clone(), Some(String::from("firstwitch")))
+                .unwrap();
+            assert_eq!(
+                ping.to,
+                Some(Jid::from_str("coven@chat.shakespeare.example/firstwitch").unwrap())
+            );
+            ping.id
+        };
+        let error = |condition| StanzaError::new(ErrorType::Cancel, condition, "en", "");
+        for (payload, outcome) in [
+            (IqType::Result(None), SelfPing::Joined),
+            (
+                IqType::Error(error(DefinedCondition::ServiceUnavailable)),
+                SelfPing::Joined,
+            ),
+            (
+                IqType::Error(error(DefinedCondition::FeatureNotImplemented)),
+                SelfPing::Joined,
+            ),
+            (
+                IqType::Error(error(DefinedCondition::NotAcceptable)),
+                SelfPing::NotJoined,
+            ),
+            (
+                IqType::Error(error(DefinedCondition::ItemNotFound)),
+                SelfPing::NotJoined,
+            ),
+            (
+                IqType::Error(error(DefinedCondition::RemoteServerTimeout)),
+                SelfPing::Inconclusive,
+            ),
+            (
+                IqType::Error(error(DefinedCondition::RemoteServerNotFound)),
+                SelfPing::Inconclusive,
+            ),
+        ] {
+            let id = ping(&mut agent);
+            let answer = Iq {
+                from: Some(Jid::from_str("coven@chat.shakespeare.example/firstwitch").unwrap()),
+                to: None,
+                id,
+                payload,
+            };
+            match &agent.handle_iq(answer).await[..] {
+                [Event::RoomSelfPing(jid, result)] => {
+                    assert_eq!(jid, &room);
+                    assert_eq!(*result, outcome);
+                }
+                events => panic!("{:?}", events),
+            }
+        }
+        assert!(agent.self_pings.is_empty());
+
+        // Unanswered pings get forgotten.
+        ping(&mut agent);
+        agent.expire_self_pings(Instant::now());
+        assert_eq!(agent.self_pings.len(), 1);
+        agent.expire_self_pings(Instant::now() + SELF_PING_TIMEOUT);
+        assert!(agent.self_pings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_features() {
+        let jid = BareJid::from_str("romeo@montague.example").unwrap();
+        let client = TokioXmppClient::new(jid.clone(), "meh");
+        let mut agent: Agent = ClientBuilder::new(jid, "meh")
+            .enable_feature(ClientFeature::ChatStates)
+            .enable_feature(ClientFeature::Receipts)
+            .build_impl(client);
+        assert!(agent.features().contains(ns::DISCO_INFO));
+        assert!(agent.features().contains(ns::CHATSTATES));
+        assert!(agent.features().contains(ns::RECEIPTS));
+        assert!(!agent.features().contains(ns::CARBONS));
+
+        let xml = "<message xmlns='jabber:client' from='juliet@capulet.example/balcony' type='chat'><composing xmlns='http://jabber.org/protocol/chatstates'/></message>";
+        let message = Message::try_from(xml.parse::<tokio_xmpp::Element>().unwrap()).unwrap();
+        match &agent.handle_message(message).await[..] {
+            [Event::ChatState(jid, ChatState::Composing)] => {
+                assert_eq!(jid, &BareJid::from_str("juliet@capulet.example").unwrap());
+            }
+            events => panic!("{:?}", events),
+        }
+
+        let before = agent.features().ver();
+        assert!(agent.features.take_changed());
+        assert!(agent.features_mut().register(ns::CARBONS));
+        assert_ne!(agent.features().ver(), before);
+        assert!(agent.features.take_changed());
+        assert!(!agent.features.take_changed());
+    }
 }
@@ -9,8 +9,9 @@ use crate::Event;
 use std::convert::TryFrom;
 use std::fs::{self, File};
 use std::io::{self, Write};
+use std::time::{Duration, Instant};
 use tokio_xmpp::parsers::{
-    avatar::{Data, Metadata},
+    avatar::{Data, Info, Metadata},
     iq::Iq,
     ns,
     pubsub::{
@@ -18,9 +19,18 @@ use tokio_xmpp::parsers::{
         pubsub::{Items, PubSub},
         NodeName,
     },
+    stanza_id::generate_id,
     Jid,
 };
 
+/// How long to wait for avatar data, after which an answer is ignored.
+pub(crate) const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Drops the avatar data requests nobody answered in time.
+pub(crate) fn expire_requests(requests: &mut Vec<(String, Jid, Info, Instant)>, now: Instant) {
+    requests.retain(|(_, _, _, sent)| now.duration_since(*sent) < REQUEST_TIMEOUT);
+}
+
 pub(crate) async fn handle_metadata_pubsub_event(
     from: &Jid,
     agent: &mut Agent,
@@ -41,7 +51,12 @@ pub(crate) async fn handle_metadata_pubsub_event(
                 if info.bytes as u64 == file_length {
                     events.push(Event::AvatarRetrieved(from.clone(), filename));
                 } else {
+                    let now = Instant::now();
+                    expire_requests(&mut agent.avatar_requests, now);
                     let iq = download_avatar(from);
+                    agent
+                        .avatar_requests
+                        .push((iq.id.clone(), from.clone(), info, now));
                     let _ = agent.client.send_stanza(iq.into()).await;
                 }
             }
@@ -52,7 +67,7 @@ pub(crate) async fn handle_metadata_pubsub_event(
 
 fn download_avatar(from: &Jid) -> Iq {
     Iq::from_get(
-        "coucou",
+        generate_id(),
         PubSub::Items(Items {
             max_items: None,
             node: NodeName(String::from(ns::AVATAR_DATA)),
@@ -68,6 +83,8 @@ fn download_avatar(from: &Jid) -> Iq {
 pub(crate) fn handle_data_pubsub_iq<'a>(
     from: &'a Jid,
     items: &'a Items,
+    requests: &'a mut Vec<(String, Jid, Info, Instant)>,
+    max_bytes: usize,
 ) -> impl IntoIterator<Item = Event> + 'a {
     let from = from.clone();
     items
@@ -75,7 +92,17 @@ pub(crate) fn handle_data_pubsub_iq<'a>(
         .iter()
         .filter_map(move |item| match (&item.id, &item.payload) {
             (Some(id), Some(payload)) => {
-                let data = Data::try_from(payload.clone()).unwrap();
+                let info = requests
+                    .iter()
+                    .position(|(_, jid, info, _)| *jid == from && info.id.to_hex() == id.0)
+                    .map(|index| requests.remove(index).2);
+                let data = match Data::parse_with_limit(payload.clone(), max_bytes, info.as_ref()) {
+                    Ok(data) => data,
+                    Err(err) => {
+                        warn!("Invalid avatar data from {}: {}", from, err);
+                        return None;
+                    }
+                };
                 let filename = save_avatar(&from, id.0.clone(), &data.data).unwrap();
                 Some(Event::AvatarRetrieved(from.clone(), filename))
             }
@@ -19,6 +19,7 @@ use tokio_xmpp::parsers::{
 #[cfg(feature = "avatars")]
 pub(crate) mod avatar;
 
+#[cfg_attr(not(feature = "avatars"), allow(unused_variables))]
 pub(crate) async fn handle_event(from: &Jid, elem: Element, agent: &mut Agent) -> Vec<Event> {
     let mut events = Vec::new();
     let event = PubSubEvent::try_from(elem);
@@ -78,7 +79,13 @@ pub(crate) async fn handle_event(from: &Jid, elem: Element, agent: &mut Agent) -
     events
 }
 
-pub(crate) fn handle_iq_result(from: &Jid, elem: Element) -> impl IntoIterator<Item = Event> {
+// Without avatars, nothing needs the sender nor the agent.
+#[cfg_attr(not(feature = "avatars"), allow(unused_variables))]
+pub(crate) fn handle_iq_result(
+    from: &Jid,
+    elem: Element,
+    agent: &mut Agent,
+) -> impl IntoIterator<Item = Event> {
     let mut events = Vec::new();
     let pubsub = PubSub::try_from(elem).unwrap();
     trace!("PubSub: {:#?}", pubsub);
@@ -86,7 +93,12 @@ pub(crate) fn handle_iq_result(from: &Jid, elem: Element) -> impl IntoIterator<I
         match items.node.0.clone() {
             #[cfg(feature = "avatars")]
             ref node if node == ns::AVATAR_DATA => {
-                let new_events = avatar::handle_data_pubsub_iq(&from, &items);
+                let new_events = avatar::handle_data_pubsub_iq(
+                    &from,
+                    &items,
+                    &mut agent.avatar_requests,
+                    agent.avatar_max_bytes,
+                );
                 events.extend(new_events);
             }
             ref node if node == ns::BOOKMARKS2 => {
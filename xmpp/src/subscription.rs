@@ -0,0 +1,296 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use tokio_xmpp::parsers::{
+    presence::{Presence, Type as PresenceType},
+    roster::{Ask, Item, Subscription},
+    BareJid,
+};
+
+/// The presence subscription state with a contact, as defined in RFC 6121
+/// appendix A.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionState {
+    /// The approved subscriptions, either way.
+    pub subscription: Subscription,
+
+    /// Whether we asked to subscribe to this contact, without an answer yet.
+    pub pending_out: bool,
+
+    /// Whether this contact asked to subscribe to us, without an answer yet.
+    pub pending_in: bool,
+}
+
+impl Default for SubscriptionState {
+    fn default() -> SubscriptionState {
+        SubscriptionState {
+            subscription: Subscription::None,
+            pending_out: false,
+            pending_in: false,
+        }
+    }
+}
+
+/// A change reported by a [`SubscriptionTracker`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscriptionEvent {
+    /// The subscription state with this contact is now the given one.
+    Changed(BareJid, SubscriptionState),
+
+    /// This contact got removed from the roster.
+    Removed(BareJid),
+}
+
+/// Keeps the subscription state with every contact, from the roster items
+/// and the subscription presences received from the server.
+///
+/// Every item of the roster result and of later roster pushes must be given
+/// to [`SubscriptionTracker::handle_roster_item`], and every presence to
+/// [`SubscriptionTracker::handle_presence`]; both return an event only when
+/// the state actually changed.
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionTracker {
+    contacts: HashMap<BareJid, SubscriptionState>,
+}
+
+impl SubscriptionTracker {
+    /// Creates a tracker without any contact.
+    pub fn new() -> SubscriptionTracker {
+        SubscriptionTracker::default()
+    }
+
+    /// The subscription state with this contact, if known.
+    pub fn get(&self, jid: &BareJid) -> Option<&SubscriptionState> {
+        self.contacts.get(jid)
+    }
+
+    /// Iterates over every known contact and its subscription state.
+    pub fn iter(&self) -> impl Iterator<Item = (&BareJid, &SubscriptionState)> {
+        self.contacts.iter()
+    }
+
+    /// Updates the state of a contact from a roster item, which the server
+    /// keeps authoritative for the approved subscriptions and our pending
+    /// request.
+    pub fn handle_roster_item(&mut self, item: &Item) -> Option<SubscriptionEvent> {
+        if item.subscription == Subscription::Remove {
+            return self
+                .contacts
+                .remove(&item.jid)
+                .map(|_| SubscriptionEvent::Removed(item.jid.clone()));
+        }
+        let mut state = self.contacts.get(&item.jid).cloned().unwrap_or_default();
+        state.subscription = item.subscription.clone();
+        state.pending_out = item.ask == Ask::Subscribe;
+        // Approving their request gives them a from subscription.
+        if matches!(state.subscription, Subscription::From | Subscription::Both) {
+            state.pending_in = false;
+        }
+        self.update(item.jid.clone(), state)
+    }
+
+    /// Updates the state of a contact from a presence it sent us; only the
+    /// subscription-related types have an effect.
+    pub fn handle_presence(&mut self, presence: &Presence) -> Option<SubscriptionEvent> {
+        let jid = presence.from.as_ref()?.to_bare();
+        let state = match presence.type_ {
+            PresenceType::Subscribe => {
+                // A subscription request can come from someone outside the roster.
+                let mut state = self.contacts.get(&jid).cloned().unwrap_or_default();
+                // The server answers on its own if they are already subscribed.
+                if !matches!(state.subscription, Subscription::From | Subscription::Both) {
+                    state.pending_in = true;
+                }
+                state
+            }
+            PresenceType::Subscribed => {
+                let mut state = self.contacts.get(&jid)?.clone();
+                // Only an answer to our own request counts.
+                if state.pending_out {
+                    state.pending_out = false;
+                    state.subscription = match state.subscription {
+                        Subscription::None => Subscription::To,
+                        Subscription::From => Subscription::Both,
+                        subscription => subscription,
+                    };
+                }
+                state
+            }
+            PresenceType::Unsubscribe => {
+                let mut state = self.contacts.get(&jid)?.clone();
+                state.pending_in = false;
+                state.subscription = match state.subscription {
+                    Subscription::From => Subscription::None,
+                    Subscription::Both => Subscription::To,
+                    subscription => subscription,
+                };
+                state
+            }
+            PresenceType::Unsubscribed => {
+                let mut state = self.contacts.get(&jid)?.clone();
+                state.pending_out = false;
+                state.subscription = match state.subscription {
+                    Subscription::To => Subscription::None,
+                    Subscription::Both => Subscription::From,
+                    subscription => subscription,
+                };
+                state
+            }
+            _ => return None,
+        };
+        self.update(jid, state)
+    }
+
+    fn update(&mut self, jid: BareJid, state: SubscriptionState) -> Option<SubscriptionEvent> {
+        if self.contacts.get(&jid) == Some(&state) {
+            return None;
+        }
+        self.contacts.insert(jid.clone(), state.clone());
+        Some(SubscriptionEvent::Changed(jid, state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tokio_xmpp::parsers::Jid;
+
+    fn item(jid: &str, subscription: Subscription, ask: Ask) -> Item {
+        Item {
+            jid: BareJid::from_str(jid).unwrap(),
+            name: None,
+            subscription,
+            ask,
+            groups: vec![],
+        }
+    }
+
+    fn presence(from: &str, type_: PresenceType) -> Presence {
+        Presence::new(type_).with_from(Jid::from_str(from).unwrap())
+    }
+
+    fn state(subscription: Subscription, pending_out: bool, pending_in: bool) -> SubscriptionState {
+        SubscriptionState {
+            subscription,
+            pending_out,
+            pending_in,
+        }
+    }
+
+    #[test]
+    fn test_outbound_subscription() {
+        let juliet = BareJid::from_str("juliet@example.com").unwrap();
+        let mut tracker = SubscriptionTracker::new();
+
+        // Our request, as pushed by the server.
+        let event = tracker.handle_roster_item(&item(
+            "juliet@example.com",
+            Subscription::None,
+            Ask::Subscribe,
+        ));
+        assert_eq!(
+            event,
+            Some(SubscriptionEvent::Changed(
+                juliet.clone(),
+                state(Subscription::None, true, false)
+            ))
+        );
+
+        // Approved, then pushed again by the server.
+        let event = tracker.handle_presence(&presence(
+            "juliet@example.com/balcony",
+            PresenceType::Subscribed,
+        ));
+        assert_eq!(
+            event,
+            Some(SubscriptionEvent::Changed(
+                juliet.clone(),
+                state(Subscription::To, false, false)
+            ))
+        );
+        let event =
+            tracker.handle_roster_item(&item("juliet@example.com", Subscription::To, Ask::None));
+        assert_eq!(event, None);
+
+        // A second subscribed without a request is ignored.
+        let event =
+            tracker.handle_presence(&presence("juliet@example.com", PresenceType::Subscribed));
+        assert_eq!(event, None);
+
+        // Cancelled by the contact.
+        let event =
+            tracker.handle_presence(&presence("juliet@example.com", PresenceType::Unsubscribed));
+        assert_eq!(
+            event,
+            Some(SubscriptionEvent::Changed(
+                juliet.clone(),
+                state(Subscription::None, false, false)
+            ))
+        );
+
+        let event = tracker.handle_roster_item(&item(
+            "juliet@example.com",
+            Subscription::Remove,
+            Ask::None,
+        ));
+        assert_eq!(event, Some(SubscriptionEvent::Removed(juliet.clone())));
+        assert_eq!(tracker.get(&juliet), None);
+    }
+
+    #[test]
+    fn test_inbound_subscription() {
+        let romeo = BareJid::from_str("romeo@example.net").unwrap();
+        let mut tracker = SubscriptionTracker::new();
+        tracker.handle_roster_item(&item("romeo@example.net", Subscription::To, Ask::None));
+
+        let event = tracker.handle_presence(&presence(
+            "romeo@example.net/orchard",
+            PresenceType::Subscribe,
+        ));
+        assert_eq!(
+            event,
+            Some(SubscriptionEvent::Changed(
+                romeo.clone(),
+                state(Subscription::To, false, true)
+            ))
+        );
+
+        // We approved it.
+        let event =
+            tracker.handle_roster_item(&item("romeo@example.net", Subscription::Both, Ask::None));
+        assert_eq!(
+            event,
+            Some(SubscriptionEvent::Changed(
+                romeo.clone(),
+                state(Subscription::Both, false, false)
+            ))
+        );
+
+        let event =
+            tracker.handle_presence(&presence("romeo@example.net", PresenceType::Unsubscribe));
+        assert_eq!(
+            event,
+            Some(SubscriptionEvent::Changed(
+                romeo.clone(),
+                state(Subscription::To, false, false)
+            ))
+        );
+
+        // Other presences don’t matter, and neither do unknown contacts
+        // unsubscribing.
+        let event = tracker.handle_presence(&presence(
+            "romeo@example.net/orchard",
+            PresenceType::Unavailable,
+        ));
+        assert_eq!(event, None);
+        let event = tracker.handle_presence(&presence(
+            "mercutio@example.org",
+            PresenceType::Unsubscribed,
+        ));
+        assert_eq!(event, None);
+        assert_eq!(tracker.iter().count(), 1);
+    }
+}